@@ -1,22 +1,66 @@
 use crate::CodexThread;
 use crate::agent::AgentStatus;
+use crate::agent::agent_status_from_event;
+use crate::codex::InitialContext;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
+use crate::error::SubagentLimitKind;
+use crate::rollout::RolloutRecorder;
+use crate::rollout::truncation::user_message_positions_in_rollout;
+use crate::thread_manager::CollabLimits;
+use crate::thread_manager::GroupChatPostAdmission;
+use crate::thread_manager::GroupChatPostWindow;
+use crate::thread_manager::MAX_SCRATCHPAD_CHARS;
+use crate::thread_manager::MAX_SHARED_KEY_CHARS;
+use crate::thread_manager::MAX_SHARED_VALUE_CHARS;
+use crate::thread_manager::MAX_SUBAGENT_OUTPUT_TREE_DEPTH;
+use crate::thread_manager::MAX_SUBAGENT_OUTPUT_TREE_NODES;
+use crate::thread_manager::MAX_SUBAGENT_TOOL_EVENTS_LIMIT;
+use crate::thread_manager::MAX_SUBAGENT_TREE_DEPTH;
+use crate::thread_manager::MAX_SUBAGENT_TREE_NODES;
+use crate::thread_manager::PendingSpawnGuard;
+use crate::thread_manager::PinnedContextUsage;
+use crate::thread_manager::ScratchpadUsage;
+use crate::thread_manager::SpawnedSubagent;
+use crate::thread_manager::SubagentCheckpoint;
 use crate::thread_manager::SubagentInfo;
+use crate::thread_manager::SubagentOrder;
 use crate::thread_manager::SubagentOutputSnapshot;
+use crate::thread_manager::SubagentOutputUpdate;
+use crate::thread_manager::SubagentSlots;
 use crate::thread_manager::ThreadManagerState;
+use crate::thread_manager::history_at_user_turns;
+use crate::thread_manager::strip_control_sequences;
 use codex_protocol::ThreadId;
 use codex_protocol::items::AgentMessageContent;
 use codex_protocol::items::TurnItem;
 use codex_protocol::protocol::EventMsg;
 use codex_protocol::protocol::GroupChatSender;
+use codex_protocol::protocol::InitialHistory;
 use codex_protocol::protocol::Op;
 use codex_protocol::protocol::SessionSource;
 use codex_protocol::protocol::SubAgentSource;
+use codex_protocol::protocol::SubagentLineage;
 use codex_protocol::user_input::UserInput;
+use futures::future::join_all;
+use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Weak;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+use tracing::field;
+use tracing::instrument;
+
+/// Default per-descendant character limit for nested output, keeping recursive responses small.
+const NESTED_OUTPUT_MAX_CHARS: usize = 2_000;
+/// `SubAgentSource::Other` label for subagents spawned without an explicit `source_label`.
+const DEFAULT_SUBAGENT_SOURCE_LABEL: &str = "collab";
 
 /// Control-plane handle for multi-agent operations.
 /// `AgentControl` is held by each session (via `SessionServices`). It provides capability to
@@ -36,64 +80,580 @@ impl AgentControl {
     }
 
     #[allow(dead_code)] // Used by upcoming multi-agent tooling.
-    /// Spawn a new agent thread and submit the initial prompt.
+    /// Spawn the headless agent thread described by `spawn` and submit its initial prompt.
     /// `parent_id` is recorded so the orchestrator can discover its subagents.
     ///
-    /// If `headless` is true, a background drain task is spawned to prevent unbounded event growth
-    /// of the channel queue when there is no client actively reading the thread events.
+    /// A background drain task is spawned to prevent unbounded event growth of the channel queue,
+    /// since no client actively reads a subagent's events.
+    ///
+    /// `source_label` becomes the thread's `SubAgentSource::Other` value so hosts can tell kinds
+    /// of subagents apart in telemetry; it defaults to `"collab"`. `attachments` are sent after
+    /// the prompt text as part of the first input, and the parent's pinned context before it.
+    ///
+    /// The subagent starts from `initial_history`, such as a curated context or a continuation
+    /// of another conversation; the pinned context is only prepended with `InitialHistory::New`.
+    ///
+    /// When the parent is at its subagent cap and `evict_idle` is set, the parent's completed
+    /// subagent with the lowest `priority` below this one is shut down to make room instead of
+    /// rejecting the spawn.
+    ///
+    /// `max_tool_events` bounds the tool events buffered for `agent_output`; it defaults to the
+    /// host's `CollabLimits::max_subagent_tool_events` and is capped at
+    /// `MAX_SUBAGENT_TOOL_EVENTS_LIMIT`.
+    ///
+    /// A spawn holds one of the parent's subagent slots while its session starts. With a
+    /// `spawn_token`, [`Self::cancel_spawn`] can abort it until the subagent is registered; a
+    /// session that finished starting in the meantime is shut down and removed.
+    ///
+    /// With `auto_post` unset, the subagent's final messages are recorded for `agent_output`
+    /// but not posted to the parent's group chat.
+    ///
+    /// An `output_schema` is sent with every input to the subagent as the schema its final
+    /// message must follow.
+    ///
+    /// If the initial prompt cannot be sent, the spawn is rolled back: the drain is stopped and
+    /// the subagent unregistered and removed before the error is returned.
+    #[instrument(
+        name = "subagent",
+        skip_all,
+        fields(thread_id = field::Empty, parent_id = %parent_id, persona = spawn.persona.as_deref(), correlation_id = spawn.correlation_id.as_deref())
+    )]
     pub(crate) async fn spawn_agent(
         &self,
         parent_id: ThreadId,
-        config: crate::config::Config,
-        prompt: String,
-        headless: bool,
-        persona: Option<String>,
-        display_name: Option<String>,
+        spawn: SubagentSpawn,
     ) -> CodexResult<ThreadId> {
+        let (thread_id, items) = self.start_agent(parent_id, spawn).await?;
+        self.send_initial_prompt_or_roll_back(thread_id, items)
+            .await?;
+        Ok(thread_id)
+    }
+
+    /// Like [`Self::spawn_agent`], but return once the subagent is registered and send its
+    /// initial prompt in the background; see [`Self::spawn_agent_in_background`], which it waits
+    /// on for the registration, for how failures are reported.
+    pub(crate) async fn spawn_agent_async(
+        &self,
+        parent_id: ThreadId,
+        spawn: SubagentSpawn,
+    ) -> CodexResult<SpawnedSubagent> {
+        self.spawn_agent_in_background(parent_id, spawn)
+            .await?
+            .await?
+    }
+
+    /// Like [`Self::spawn_agent`], but return as soon as the spawn holds one of the parent's
+    /// subagent slots, and start the session and send the initial prompt in the background. The
+    /// caller is free to [`Self::cancel_spawn`] a spawn with a `spawn_token` meanwhile.
+    ///
+    /// The returned handle resolves once the subagent is registered, or to why it was not: a
+    /// spawn that fails or is cancelled before then releases its slot and leaves nothing behind.
+    /// Await [`SpawnedSubagent::ready`] to learn when the initial prompt was submitted. A failed
+    /// send is not rolled back; the subagent stays registered with an `Errored` status instead.
+    #[instrument(
+        name = "subagent",
+        skip_all,
+        fields(thread_id = field::Empty, parent_id = %parent_id, persona = spawn.persona.as_deref(), correlation_id = spawn.correlation_id.as_deref())
+    )]
+    pub(crate) async fn spawn_agent_in_background(
+        &self,
+        parent_id: ThreadId,
+        spawn: SubagentSpawn,
+    ) -> CodexResult<JoinHandle<CodexResult<SpawnedSubagent>>> {
+        let pending = self.reserve_spawn_slot(parent_id, &spawn).await?;
+        let control = self.clone();
+        Ok(tokio::spawn(
+            async move {
+                let (thread_id, items) = control
+                    .start_reserved_agent(parent_id, spawn, pending)
+                    .await?;
+                let ready = tokio::spawn(
+                    async move {
+                        control
+                            .send_initial_prompt_or_mark_errored(thread_id, items)
+                            .await
+                    }
+                    .in_current_span(),
+                );
+                Ok(SpawnedSubagent::new(thread_id, ready))
+            }
+            .in_current_span(),
+        ))
+    }
+
+    /// Everything [`Self::spawn_agent`] does short of sending the initial prompt: returns the
+    /// registered subagent and the first input it should receive.
+    async fn start_agent(
+        &self,
+        parent_id: ThreadId,
+        spawn: SubagentSpawn,
+    ) -> CodexResult<(ThreadId, Vec<UserInput>)> {
+        let pending = self.reserve_spawn_slot(parent_id, &spawn).await?;
+        self.start_reserved_agent(parent_id, spawn, pending).await
+    }
+
+    /// Make room for `spawn` under `parent_id` and reserve the slot it will take.
+    async fn reserve_spawn_slot(
+        &self,
+        parent_id: ThreadId,
+        spawn: &SubagentSpawn,
+    ) -> CodexResult<PendingSpawnGuard> {
+        let state = self.upgrade()?;
+        if spawn.evict_idle {
+            if let Some(evicted) = self
+                .evict_idle_subagent(&state, parent_id, spawn.priority)
+                .await
+            {
+                tracing::info!("evicted idle agent {evicted} to make room under {parent_id}");
+            }
+        }
+        ensure_token_budget(&state, parent_id).await?;
+        state
+            .reserve_subagent_spawn(parent_id, spawn.spawn_token.clone())
+            .await
+    }
+
+    /// Start the session of a spawn that holds the slot `pending` and register it. A subagent
+    /// starting from earlier history already saw the parent's pinned context, so it is only
+    /// prepended for a fresh start.
+    async fn start_reserved_agent(
+        &self,
+        parent_id: ThreadId,
+        spawn: SubagentSpawn,
+        pending: PendingSpawnGuard,
+    ) -> CodexResult<(ThreadId, Vec<UserInput>)> {
+        let SubagentSpawn {
+            config,
+            initial_history,
+            prompt,
+            persona,
+            display_name,
+            source_label,
+            attachments,
+            attachment_paths,
+            priority,
+            evict_idle: _,
+            max_tool_events,
+            spawn_token,
+            auto_post,
+            correlation_id,
+            output_schema,
+        } = spawn;
         let state = self.upgrade()?;
-        let new_thread = state
-            .spawn_new_thread_with_source(
-                config,
-                self.clone(),
-                SessionSource::SubAgent(SubAgentSource::Other("collab".to_string())),
+        let mut config = config;
+        config.subagent_lineage = Some(SubagentLineage {
+            parent_id,
+            persona: persona.clone(),
+            display_name: display_name.clone(),
+        });
+        #[cfg(any(test, feature = "test-support"))]
+        {
+            config.thread_id = state.next_seeded_subagent_id();
+        }
+        let spawn_config = Arc::new(config.clone());
+        let source_label =
+            source_label.unwrap_or_else(|| DEFAULT_SUBAGENT_SOURCE_LABEL.to_string());
+        let fresh_start = matches!(initial_history, InitialHistory::New);
+        // Resuming would reuse the source thread's id and rollout file and so replace that
+        // thread; a subagent always starts from a fork of the history instead.
+        let initial_history = match initial_history {
+            InitialHistory::Resumed(resumed) => InitialHistory::Forked(resumed.history),
+            history => history,
+        };
+        let spawn = state.spawn_new_thread_with_source(
+            config,
+            initial_history,
+            self.clone(),
+            SessionSource::SubAgent(SubAgentSource::Other(source_label.clone())),
+        );
+        // Dropping the spawn future before the thread is stored drops its `Codex`, which closes
+        // the submission channel and ends the half-started session.
+        let new_thread = tokio::select! {
+            biased;
+            _ = pending.cancel_token().cancelled() => {
+                return Err(spawn_cancelled(spawn_token.as_deref()));
+            }
+            result = spawn => result?,
+        };
+        tracing::Span::current().record("thread_id", field::display(new_thread.thread_id));
+        if pending.cancel_token().is_cancelled() {
+            if let Err(err) = new_thread.thread.submit(Op::Shutdown).await {
+                tracing::warn!(
+                    "failed to shut down cancelled agent {}: {err}",
+                    new_thread.thread_id
+                );
+            }
+            state.remove_thread(new_thread.thread_id).await;
+            return Err(spawn_cancelled(spawn_token.as_deref()));
+        }
+
+        if let Err(err) = state
+            .register_subagent(
+                new_thread.thread_id,
+                SubagentInfo {
+                    parent_id,
+                    persona,
+                    display_name,
+                    spawn_config,
+                    initial_prompt: prompt.clone(),
+                    source_label,
+                    attachment_paths,
+                    priority,
+                    max_tool_events: max_tool_events
+                        .unwrap_or(state.collab_limits().max_subagent_tool_events)
+                        .clamp(1, MAX_SUBAGENT_TOOL_EVENTS_LIMIT),
+                    last_input: Vec::new(),
+                    group_chat_posts: GroupChatPostWindow::default(),
+                    tool_echoes: GroupChatPostWindow::default(),
+                    auto_post,
+                    checkpoints: VecDeque::new(),
+                    correlation_id,
+                    output_schema,
+                    paused: false,
+                },
             )
-            .await?;
+            .await
+        {
+            if let Err(err) = new_thread.thread.submit(Op::Shutdown).await {
+                tracing::warn!(
+                    "failed to shut down unregistered agent {}: {err}",
+                    new_thread.thread_id
+                );
+            }
+            state.remove_thread(new_thread.thread_id).await;
+            return Err(err);
+        }
+        drop(pending);
+        state.ensure_deadlock_watchdog(parent_id).await;
+        state.ensure_subagent_reaper().await;
 
+        let drain = spawn_headless_drain(
+            Arc::clone(&new_thread.thread),
+            Arc::clone(&state),
+            new_thread.thread_id,
+        );
         state
-            .register_subagent(parent_id, new_thread.thread_id, persona, display_name)
+            .register_subagent_drain(new_thread.thread_id, drain)
             .await;
+        state.ensure_drain_watchdog().await;
 
-        if headless {
-            spawn_headless_drain(
-                Arc::clone(&new_thread.thread),
-                Arc::clone(&state),
-                new_thread.thread_id,
-            );
+        let mut items: Vec<UserInput> = if fresh_start {
+            state
+                .pinned_context(parent_id)
+                .await
+                .into_iter()
+                .map(pinned_context_input)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        items.push(UserInput::Text { text: prompt });
+        items.extend(attachments);
+        Ok((new_thread.thread_id, items))
+    }
+
+    /// Send a freshly registered subagent its first input, unregistering and removing the
+    /// subagent when that fails.
+    pub(crate) async fn send_initial_prompt_or_roll_back(
+        &self,
+        agent_id: ThreadId,
+        items: Vec<UserInput>,
+    ) -> CodexResult<()> {
+        if let Err(err) = self.send_input_items(agent_id, items).await {
+            tracing::warn!("rolling back agent {agent_id} after its initial prompt failed: {err}");
+            if let Ok(state) = self.upgrade() {
+                state.roll_back_subagent_spawn(agent_id).await;
+            }
+            return Err(err);
         }
+        Ok(())
+    }
 
-        self.send_prompt(new_thread.thread_id, prompt).await?;
+    /// Send a freshly registered subagent its first input, keeping the subagent with an
+    /// `Errored` status when that fails.
+    pub(crate) async fn send_initial_prompt_or_mark_errored(
+        &self,
+        agent_id: ThreadId,
+        items: Vec<UserInput>,
+    ) -> CodexResult<()> {
+        let Err(err) = self.send_input_items(agent_id, items).await else {
+            return Ok(());
+        };
+        tracing::warn!("initial prompt of agent {agent_id} failed: {err}");
+        if let Ok(state) = self.upgrade()
+            && let Ok(thread) = state.get_thread(agent_id).await
+        {
+            thread
+                .set_agent_status(AgentStatus::Errored(format!(
+                    "initial prompt failed: {err}"
+                )))
+                .await;
+        }
+        Err(err)
+    }
 
-        Ok(new_thread.thread_id)
+    /// Spawn several subagents under `parent_id` concurrently, returning one result per spawn in
+    /// order. Each spawn reserves its own slot, so spawns beyond the parent's remaining subagent
+    /// capacity, spawns still starting included, fail individually instead of failing the whole
+    /// batch, unless they opted into evicting idle subagents.
+    pub(crate) async fn spawn_agents(
+        &self,
+        parent_id: ThreadId,
+        spawns: Vec<SubagentSpawn>,
+    ) -> CodexResult<Vec<CodexResult<ThreadId>>> {
+        self.upgrade()?;
+        let results = spawns
+            .into_iter()
+            .map(|spawn| self.spawn_agent(parent_id, spawn));
+        Ok(join_all(results).await)
+    }
+
+    /// Spawn `count` siblings of `source_id` under the same parent, reusing its effective
+    /// spawn config and persona. Clones receive `prompt`, or the source's initial prompt.
+    /// All or nothing: when one clone fails, the clones already spawned are rolled back.
+    pub(crate) async fn clone_agent(
+        &self,
+        parent_id: ThreadId,
+        source_id: ThreadId,
+        count: usize,
+        prompt: Option<String>,
+    ) -> CodexResult<Vec<ThreadId>> {
+        let state = self.upgrade()?;
+        let Some(info) = state
+            .subagent_info(source_id)
+            .await
+            .filter(|info| info.parent_id == parent_id)
+        else {
+            return Err(CodexErr::ThreadNotFound(source_id));
+        };
+        ensure_subagent_capacity(&state, parent_id, count).await?;
+        let base_name = info
+            .display_name
+            .clone()
+            .unwrap_or_else(|| "agent".to_string());
+        let prompt = prompt.unwrap_or_else(|| info.initial_prompt.clone());
+        let attachments = crate::agent::resolve_attachments(
+            &info.spawn_config.cwd,
+            info.attachment_paths.clone(),
+        )
+        .await
+        .map_err(CodexErr::InvalidRequest)?;
+        let mut ids = Vec::with_capacity(count);
+        for index in 1..=count {
+            let spawned = self
+                .spawn_agent(
+                    parent_id,
+                    SubagentSpawn {
+                        persona: info.persona.clone(),
+                        display_name: Some(format!("{base_name} #{index}")),
+                        source_label: Some(info.source_label.clone()),
+                        attachments: attachments.clone(),
+                        attachment_paths: info.attachment_paths.clone(),
+                        priority: info.priority,
+                        max_tool_events: Some(info.max_tool_events),
+                        auto_post: info.auto_post,
+                        correlation_id: info.correlation_id.clone(),
+                        output_schema: info.output_schema.clone(),
+                        ..SubagentSpawn::new((*info.spawn_config).clone(), prompt.clone())
+                    },
+                )
+                .await;
+            match spawned {
+                Ok(id) => ids.push(id),
+                Err(err) => {
+                    tracing::warn!(
+                        "rolling back {} clones of agent {source_id} after clone {index} failed: {err}",
+                        ids.len()
+                    );
+                    for id in ids {
+                        state.roll_back_subagent_spawn(id).await;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Label the current position of `agent_id`, a direct subagent of `parent_id`, so
+    /// [`Self::restore_checkpoint`] can fork from it later. Refused while the agent is mid-turn,
+    /// since its rollout is only flushed once a turn ends.
+    pub(crate) async fn checkpoint_agent(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+        label: &str,
+    ) -> CodexResult<SubagentCheckpoint> {
+        let state = self.upgrade()?;
+        if !state.is_subagent_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(CodexErr::UnsupportedOperation(
+                "checkpoint label must not be empty".to_string(),
+            ));
+        }
+        if matches!(
+            self.get_status(agent_id).await,
+            AgentStatus::PendingInit | AgentStatus::Running
+        ) {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "agent {agent_id} is mid-turn; checkpoint it once its turn ends"
+            )));
+        }
+        let thread = state.get_thread(agent_id).await?;
+        let history = RolloutRecorder::get_rollout_history(&thread.rollout_path()).await?;
+        let user_turns = user_message_positions_in_rollout(&history.get_rollout_items()).len();
+        state
+            .add_subagent_checkpoint(agent_id, label.to_string(), user_turns)
+            .await
+    }
+
+    /// Fork `agent_id`, a direct subagent of `parent_id`, from one of its checkpoints: a new
+    /// sibling with the same config and persona starts from the history up to that checkpoint
+    /// and receives `prompt`. The source agent keeps running untouched.
+    pub(crate) async fn restore_checkpoint(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+        checkpoint_id: u64,
+        prompt: String,
+    ) -> CodexResult<ThreadId> {
+        let state = self.upgrade()?;
+        let Some(info) = state
+            .subagent_info(agent_id)
+            .await
+            .filter(|info| info.parent_id == parent_id)
+        else {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        };
+        let Some(checkpoint) = info
+            .checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.id == checkpoint_id)
+        else {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "agent {agent_id} has no checkpoint {checkpoint_id}"
+            )));
+        };
+        let thread = state.get_thread(agent_id).await?;
+        let history = RolloutRecorder::get_rollout_history(&thread.rollout_path()).await?;
+        let history = history_at_user_turns(history, checkpoint.user_turns);
+        let base_name = info
+            .display_name
+            .clone()
+            .unwrap_or_else(|| "agent".to_string());
+        let (thread_id, items) = self
+            .start_agent(
+                parent_id,
+                SubagentSpawn {
+                    initial_history: history,
+                    persona: info.persona.clone(),
+                    display_name: Some(format!("{base_name} @ {}", checkpoint.label)),
+                    source_label: Some(info.source_label.clone()),
+                    priority: info.priority,
+                    max_tool_events: Some(info.max_tool_events),
+                    auto_post: info.auto_post,
+                    correlation_id: info.correlation_id.clone(),
+                    output_schema: info.output_schema.clone(),
+                    ..SubagentSpawn::new((*info.spawn_config).clone(), prompt)
+                },
+            )
+            .await?;
+        self.send_initial_prompt_or_roll_back(thread_id, items)
+            .await?;
+        Ok(thread_id)
     }
 
-    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
     /// Send a `user` prompt to an existing agent thread.
     pub(crate) async fn send_prompt(
         &self,
         agent_id: ThreadId,
         prompt: String,
+    ) -> CodexResult<String> {
+        self.send_input_items(agent_id, vec![UserInput::Text { text: prompt }])
+            .await
+    }
+
+    /// Send a `user` input made of arbitrary items to an existing agent thread.
+    ///
+    /// Sends to the same agent are serialized and delivered in call order; sends to different
+    /// agents still proceed concurrently.
+    #[instrument(name = "subagent", skip_all, fields(thread_id = %agent_id, parent_id = field::Empty, persona = field::Empty, correlation_id = field::Empty))]
+    async fn send_input_items(
+        &self,
+        agent_id: ThreadId,
+        items: Vec<UserInput>,
     ) -> CodexResult<String> {
         let state = self.upgrade()?;
+        record_subagent_span_fields(&state, agent_id).await;
+        let lock = state.input_lock(agent_id).await;
+        let _guard = lock.lock().await;
+        if state.is_subagent_paused(agent_id).await? {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "agent {agent_id} is paused; resume it first"
+            )));
+        }
         state.reset_subagent_output(agent_id).await;
-        state
+        let final_output_json_schema = state.subagent_output_schema(agent_id).await;
+        let submission_id = state
             .send_op(
                 agent_id,
                 Op::UserInput {
-                    items: vec![UserInput::Text { text: prompt }],
-                    final_output_json_schema: None,
+                    items: items.clone(),
+                    final_output_json_schema,
                 },
             )
+            .await?;
+        state.record_subagent_input(agent_id, items).await;
+        Ok(submission_id)
+    }
+
+    #[allow(dead_code)] // Used by multi-agent orchestration.
+    /// Resend the input most recently sent to `agent_id`, a direct subagent of `parent_id`, for
+    /// example after a transient provider error. Refused while the agent is mid-turn.
+    #[instrument(
+        name = "subagent",
+        skip_all,
+        fields(thread_id = %agent_id, parent_id = %parent_id, persona = field::Empty, correlation_id = field::Empty)
+    )]
+    pub(crate) async fn replay_subagent(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+    ) -> CodexResult<String> {
+        let state = self.upgrade()?;
+        let Some(info) = state
+            .subagent_info(agent_id)
             .await
+            .filter(|info| info.parent_id == parent_id)
+        else {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        };
+        if let Some(persona) = &info.persona {
+            tracing::Span::current().record("persona", persona.as_str());
+        }
+        match self.get_status(agent_id).await {
+            AgentStatus::PendingInit | AgentStatus::Running => {
+                return Err(CodexErr::UnsupportedOperation(format!(
+                    "agent {agent_id} is mid-turn; wait for it to finish before replaying"
+                )));
+            }
+            AgentStatus::Shutdown | AgentStatus::NotFound => {
+                return Err(CodexErr::UnsupportedOperation(format!(
+                    "agent {agent_id} is no longer running"
+                )));
+            }
+            AgentStatus::Completed(_) | AgentStatus::Errored(_) => {}
+        }
+        if info.last_input.is_empty() {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "agent {agent_id} has no prompt to replay"
+            )));
+        }
+        ensure_token_budget(&state, parent_id).await?;
+        self.send_input_items(agent_id, info.last_input).await
     }
 
     #[allow(dead_code)] // Used by multi-agent orchestration.
@@ -101,15 +661,27 @@ impl AgentControl {
         &self,
         parent_id: ThreadId,
         text: String,
+        mentions: Vec<ThreadId>,
         sender: GroupChatSender,
     ) -> CodexResult<()> {
         let state = self.upgrade()?;
+        let text = if let GroupChatSender::SubAgent { id, .. } = &sender {
+            let Some(text) = rate_limited_group_chat_text(&state, *id, text).await else {
+                let limit = state.subagent_group_chat_posts_per_second();
+                return Err(CodexErr::UnsupportedOperation(format!(
+                    "group chat rate limit reached (at most {limit} messages per second); message dropped"
+                )));
+            };
+            text
+        } else {
+            text
+        };
         state
             .send_op(
                 parent_id,
                 Op::GroupChatMessage {
                     text,
-                    mentions: Vec::new(),
+                    mentions,
                     sender,
                 },
             )
@@ -134,16 +706,20 @@ impl AgentControl {
     pub(crate) async fn list_subagents(
         &self,
         parent_id: ThreadId,
+        query: &ListSubagentsQuery,
     ) -> CodexResult<Vec<SubagentSummary>> {
         let state = self.upgrade()?;
         let mut subagents = state.subagents_for_parent(parent_id).await;
-        subagents.sort_by(|(left, _), (right, _)| left.to_string().cmp(&right.to_string()));
+        if query.order == SubagentOrder::Id {
+            subagents.sort_by(|(left, _), (right, _)| left.to_string().cmp(&right.to_string()));
+        }
         let mut out = Vec::with_capacity(subagents.len());
         for (
             id,
             SubagentInfo {
                 persona,
                 display_name,
+                correlation_id,
                 ..
             },
         ) in subagents
@@ -152,29 +728,216 @@ impl AgentControl {
                 Ok(thread) => thread.agent_status().await,
                 Err(_) => AgentStatus::NotFound,
             };
+            if query.status.is_some_and(|filter| !filter.matches(&status)) {
+                continue;
+            }
+            let status = status_preview(status, state.subagent_status_preview_chars());
+            let seconds_since_last_activity = state
+                .subagent_last_activity(id)
+                .await
+                .map(|at| at.elapsed().as_secs());
             out.push(SubagentSummary {
                 id,
                 status,
                 persona,
                 display_name,
+                correlation_id,
+                seconds_since_last_activity,
+                unread_count: None,
             });
         }
-        Ok(out)
+        let out = out.into_iter().skip(query.offset);
+        Ok(match query.limit {
+            Some(limit) => out.take(limit).collect(),
+            None => out.collect(),
+        })
     }
 
-    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
+    /// Buffered output of `subagent_id`. Output lives on the manager rather than the parent's
+    /// turn, so a later parent turn still sees everything the drain recorded.
     pub(crate) async fn subagent_output(
         &self,
         parent_id: ThreadId,
         subagent_id: ThreadId,
         max_chars: Option<usize>,
     ) -> CodexResult<SubagentOutputSnapshot> {
+        let state = self.upgrade()?;
+        if !state.is_descendant_of(parent_id, subagent_id).await {
+            // The subagent may have been closed moments ago; serve its final output if so.
+            return state
+                .subagent_tombstone_snapshot(parent_id, subagent_id, max_chars)
+                .await
+                .ok_or_else(|| CodexErr::ThreadNotFound(subagent_id));
+        }
+        state
+            .subagent_output_snapshot(subagent_id, max_chars)
+            .await
+            .ok_or_else(|| CodexErr::ThreadNotFound(subagent_id))
+    }
+
+    /// Output of every descendant of `subagent_id` (which must sit below `parent_id`), nested by
+    /// parent. Bounded by depth and node count; the flag reports whether any were left out.
+    pub(crate) async fn subagent_output_descendants(
+        &self,
+        parent_id: ThreadId,
+        subagent_id: ThreadId,
+        max_chars: Option<usize>,
+    ) -> CodexResult<(Vec<SubagentOutputNode>, bool)> {
+        let state = self.upgrade()?;
+        if !state.is_descendant_of(parent_id, subagent_id).await {
+            return Err(CodexErr::ThreadNotFound(subagent_id));
+        }
+        let (descendants, truncated) = state
+            .subagent_descendants(
+                subagent_id,
+                MAX_SUBAGENT_OUTPUT_TREE_DEPTH,
+                MAX_SUBAGENT_OUTPUT_TREE_NODES,
+            )
+            .await;
+        let max_chars = Some(max_chars.unwrap_or(NESTED_OUTPUT_MAX_CHARS));
+        let mut nodes = Vec::with_capacity(descendants.len());
+        for (id, node_parent) in &descendants {
+            let output = state
+                .subagent_output_snapshot(*id, max_chars)
+                .await
+                .unwrap_or_default();
+            let display_name = state
+                .subagent_info(*id)
+                .await
+                .and_then(|info| info.display_name);
+            nodes.push((
+                *node_parent,
+                SubagentOutputNode {
+                    id: *id,
+                    display_name,
+                    status: self.get_status(*id).await,
+                    last_message: output.last_message,
+                    partial: output.partial,
+                    children: Vec::new(),
+                },
+            ));
+        }
+        let roots = nest_by_parent(nodes, |node| node.id, |node| &mut node.children);
+        Ok((roots, truncated))
+    }
+
+    /// Every descendant of `parent_id`, or of `root_id` when given (which must sit below
+    /// `parent_id`), nested by parent, with the number of nodes included. Bounded by depth and
+    /// node count; `truncated` reports whether any were left out.
+    pub(crate) async fn subagent_tree(
+        &self,
+        parent_id: ThreadId,
+        root_id: Option<ThreadId>,
+    ) -> CodexResult<SubagentTree> {
+        let state = self.upgrade()?;
+        let root_id = match root_id {
+            Some(root_id) if !state.is_descendant_of(parent_id, root_id).await => {
+                return Err(CodexErr::ThreadNotFound(root_id));
+            }
+            Some(root_id) => root_id,
+            None => parent_id,
+        };
+        let (descendants, truncated) = state
+            .subagent_descendants(root_id, MAX_SUBAGENT_TREE_DEPTH, MAX_SUBAGENT_TREE_NODES)
+            .await;
+        let mut nodes = Vec::with_capacity(descendants.len());
+        for (id, node_parent) in &descendants {
+            let (persona, display_name) = state
+                .subagent_info(*id)
+                .await
+                .map(|info| (info.persona, info.display_name))
+                .unwrap_or_default();
+            nodes.push((
+                *node_parent,
+                SubagentTreeNode {
+                    id: *id,
+                    persona,
+                    display_name,
+                    status: self.get_status(*id).await,
+                    children: Vec::new(),
+                },
+            ));
+        }
+        Ok(SubagentTree {
+            node_count: nodes.len(),
+            truncated,
+            agents: nest_by_parent(nodes, |node| node.id, |node| &mut node.children),
+        })
+    }
+
+    /// Add standing guidance to the developer instructions of `agent_id`, a direct subagent of
+    /// `parent_id`, without restarting it. The update is also kept in the subagent's spawn
+    /// config, so its clones start with it.
+    pub(crate) async fn amend_instructions(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+        text: &str,
+    ) -> CodexResult<()> {
+        let amendment = crate::agent_personas::instruction_amendment(text)
+            .map_err(CodexErr::UnsupportedOperation)?;
+        let state = self.upgrade()?;
+        if !state.is_subagent_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        state
+            .send_op(
+                agent_id,
+                Op::AmendInstructions {
+                    text: amendment.clone(),
+                },
+            )
+            .await?;
+        state
+            .amend_subagent_spawn_config(agent_id, &amendment)
+            .await;
+        Ok(())
+    }
+
+    /// Persona, display name, and the effective model and tool policy `agent_id` was spawned
+    /// with, after any spawn-time overrides. `agent_id` must sit below `parent_id`.
+    pub(crate) async fn inspect_subagent(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+    ) -> CodexResult<SubagentInspection> {
+        let state = self.upgrade()?;
+        if !state.is_descendant_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        let info = state
+            .subagent_info(agent_id)
+            .await
+            .ok_or(CodexErr::ThreadNotFound(agent_id))?;
+        Ok(SubagentInspection::from_info(agent_id, &info))
+    }
+
+    /// The base instructions and initial context items `agent_id` actually started with, for
+    /// checking a handoff against what was intended. `agent_id` must sit below `parent_id`.
+    pub(crate) async fn inspect_agent_context(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+    ) -> CodexResult<InitialContext> {
+        let state = self.upgrade()?;
+        if !state.is_descendant_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        state.get_thread(agent_id).await?.initial_context().await
+    }
+
+    /// Subscribe to live output updates recorded by the drain for `subagent_id`.
+    pub(crate) async fn subscribe_subagent_output(
+        &self,
+        parent_id: ThreadId,
+        subagent_id: ThreadId,
+    ) -> CodexResult<broadcast::Receiver<SubagentOutputUpdate>> {
         let state = self.upgrade()?;
         if !state.is_subagent_of(parent_id, subagent_id).await {
             return Err(CodexErr::ThreadNotFound(subagent_id));
         }
         state
-            .subagent_output_snapshot(subagent_id, max_chars)
+            .subscribe_subagent_output(subagent_id)
             .await
             .ok_or_else(|| CodexErr::ThreadNotFound(subagent_id))
     }
@@ -213,173 +976,1024 @@ impl AgentControl {
         Ok(state.is_subagent_of(parent_id, subagent_id).await)
     }
 
-    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
-    pub(crate) async fn shutdown_agent(&self, agent_id: ThreadId) -> CodexResult<()> {
+    /// Whether `subagent_id` was spawned with an `output_schema` its final messages must follow.
+    pub(crate) async fn expects_structured_output(
+        &self,
+        subagent_id: ThreadId,
+    ) -> CodexResult<bool> {
         let state = self.upgrade()?;
-        state.send_op(agent_id, Op::Shutdown).await?;
-        Ok(())
+        Ok(state.subagent_output_schema(subagent_id).await.is_some())
     }
 
-    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
-    pub(crate) async fn forget_subagent(&self, agent_id: ThreadId) -> CodexResult<()> {
+    /// The parent `subagent_id` shares with `other_id`, when the two are distinct siblings.
+    pub(crate) async fn sibling_parent(
+        &self,
+        subagent_id: ThreadId,
+        other_id: ThreadId,
+    ) -> CodexResult<Option<ThreadId>> {
         let state = self.upgrade()?;
-        state.remove_thread(agent_id).await;
-        Ok(())
+        Ok(state.sibling_parent(subagent_id, other_id).await)
     }
 
-    fn upgrade(&self) -> CodexResult<Arc<ThreadManagerState>> {
-        self.manager
-            .upgrade()
-            .ok_or_else(|| CodexErr::UnsupportedOperation("thread manager dropped".to_string()))
+    /// Set or, when `value` is `None`, remove a shared variable. Every agent in a subagent
+    /// tree writes to the top-level thread's store, so the whole tree sees the change.
+    pub(crate) async fn set_shared(
+        &self,
+        caller_id: ThreadId,
+        key: &str,
+        value: Option<String>,
+    ) -> CodexResult<()> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(CodexErr::UnsupportedOperation(
+                "shared variable key must not be empty".to_string(),
+            ));
+        }
+        if key.chars().count() > MAX_SHARED_KEY_CHARS {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "shared variable key must be at most {MAX_SHARED_KEY_CHARS} characters"
+            )));
+        }
+        if value
+            .as_ref()
+            .is_some_and(|value| value.chars().count() > MAX_SHARED_VALUE_CHARS)
+        {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "shared variable value must be at most {MAX_SHARED_VALUE_CHARS} characters"
+            )));
+        }
+        let state = self.upgrade()?;
+        let scope = state.shared_scope(caller_id).await;
+        state
+            .set_shared_variable(scope, key.to_string(), value)
+            .await
     }
-}
 
-#[derive(Debug, Clone, Serialize)]
-pub(crate) struct SubagentSummary {
-    pub(crate) id: ThreadId,
-    pub(crate) status: AgentStatus,
-    pub(crate) persona: Option<String>,
-    pub(crate) display_name: Option<String>,
-}
+    /// Shared variables visible to `caller_id`: the store of the top-level thread of its tree.
+    pub(crate) async fn shared_variables(
+        &self,
+        caller_id: ThreadId,
+    ) -> CodexResult<BTreeMap<String, String>> {
+        let state = self.upgrade()?;
+        let scope = state.shared_scope(caller_id).await;
+        Ok(state.shared_variables(scope).await)
+    }
 
-/// When an agent is spawned "headless" (no UI/view attached), there may be no consumer polling
-/// `CodexThread::next_event()`. The underlying event channel is unbounded, so the producer can
-/// accumulate events indefinitely. This drain task prevents that memory growth by polling and
-/// discarding events until shutdown.
-fn spawn_headless_drain(
-    thread: Arc<CodexThread>,
-    state: Arc<ThreadManagerState>,
-    agent_id: ThreadId,
-) {
-    tokio::spawn(async move {
+    /// Append a note to the scratchpad `caller_id` shares with the rest of its tree: that of the
+    /// top-level thread. The oldest notes are dropped once the scratchpad outgrows
+    /// [`MAX_SCRATCHPAD_CHARS`].
+    pub(crate) async fn scratchpad_append(
+        &self,
+        caller_id: ThreadId,
+        text: &str,
+    ) -> CodexResult<ScratchpadUsage> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(CodexErr::UnsupportedOperation(
+                "scratchpad entry must not be empty".to_string(),
+            ));
+        }
+        if text.chars().count() > MAX_SCRATCHPAD_CHARS {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "scratchpad entry must be at most {MAX_SCRATCHPAD_CHARS} characters"
+            )));
+        }
+        let state = self.upgrade()?;
+        let scope = state.shared_scope(caller_id).await;
+        state.append_scratchpad(scope, text.to_string()).await
+    }
+
+    /// Scratchpad entries visible to `caller_id`, oldest first.
+    pub(crate) async fn scratchpad(&self, caller_id: ThreadId) -> CodexResult<Vec<String>> {
+        let state = self.upgrade()?;
+        let scope = state.shared_scope(caller_id).await;
+        Ok(state.scratchpad(scope).await)
+    }
+
+    /// Roll-up of `parent_id`'s direct subagents: counts by status, tokens, lifetime, and turns.
+    pub(crate) async fn subagent_stats(&self, parent_id: ThreadId) -> CodexResult<SubagentStats> {
+        let state = self.upgrade()?;
+        let mut stats = SubagentStats {
+            total_tokens: state.subagent_token_usage(parent_id).await,
+            ..SubagentStats::default()
+        };
+        for (id, _) in state.subagents_for_parent(parent_id).await {
+            let status = match state.get_thread(id).await {
+                Ok(thread) => thread.agent_status().await,
+                Err(_) => AgentStatus::NotFound,
+            };
+            stats.total += 1;
+            if matches!(status, AgentStatus::Errored(_)) {
+                stats.errored += 1;
+            }
+            *stats.by_status.entry(status_kind(&status)).or_default() += 1;
+            if let Some((lifetime, turns)) = state.subagent_lifetime(id).await {
+                stats.total_seconds += lifetime.as_secs();
+                stats.turns += u64::from(turns);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// How many more subagents `parent_id` could spawn right now, with the headroom left under
+    /// each limit that spawns are checked against.
+    pub(crate) async fn spawn_capacity(&self, parent_id: ThreadId) -> CodexResult<SpawnCapacity> {
+        let state = self.upgrade()?;
+        let SubagentSlots { active, starting } = state.subagent_slots(parent_id).await;
+        let limit = state.collab_limits().max_subagents_per_parent;
+        let per_parent = PerParentCapacity {
+            limit,
+            active,
+            starting,
+            remaining: limit.saturating_sub(active + starting),
+        };
+        let token_budget = match state.subagent_token_budget() {
+            Some(limit) => {
+                let used = state.subagent_token_usage_up_to_root(parent_id).await;
+                Some(TokenBudgetCapacity {
+                    limit,
+                    used,
+                    remaining: limit.saturating_sub(used),
+                })
+            }
+            None => None,
+        };
+        let budget_exhausted = token_budget
+            .as_ref()
+            .is_some_and(|budget| budget.remaining == 0);
+        Ok(SpawnCapacity {
+            remaining: if budget_exhausted {
+                0
+            } else {
+                per_parent.remaining
+            },
+            per_parent,
+            token_budget,
+        })
+    }
+
+    /// Cancel `parent_id`'s in-flight spawn registered under `token`. Fails when no such spawn
+    /// is still starting.
+    pub(crate) fn cancel_spawn(&self, parent_id: ThreadId, token: &str) -> CodexResult<()> {
+        let state = self.upgrade()?;
+        if state.cancel_pending_spawn(parent_id, token) {
+            Ok(())
+        } else {
+            Err(CodexErr::UnsupportedOperation(format!(
+                "no spawn with token {token} is in progress"
+            )))
+        }
+    }
+
+    /// Pin `text` into the first prompt of every subagent `parent_id` spawns from now on.
+    pub(crate) async fn pin_context(
+        &self,
+        parent_id: ThreadId,
+        text: &str,
+    ) -> CodexResult<PinnedContextUsage> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(CodexErr::UnsupportedOperation(
+                "pinned context must not be empty".to_string(),
+            ));
+        }
+        let state = self.upgrade()?;
+        state.pin_context(parent_id, text.to_string()).await
+    }
+
+    /// Stop pinning context into `parent_id`'s new subagents, returning how many items were
+    /// pinned.
+    pub(crate) async fn clear_pinned_context(&self, parent_id: ThreadId) -> CodexResult<usize> {
+        let state = self.upgrade()?;
+        Ok(state.clear_pinned_context(parent_id).await)
+    }
+
+    /// Shared variables that subagents spawned by `parent_id` will see: the same store
+    /// `parent_id` itself reads and writes.
+    pub(crate) async fn child_shared_variables(
+        &self,
+        parent_id: ThreadId,
+    ) -> CodexResult<BTreeMap<String, String>> {
+        let state = self.upgrade()?;
+        let scope = state.shared_scope(parent_id).await;
+        Ok(state.shared_variables(scope).await)
+    }
+
+    /// Host-configured timeouts and buffer sizes of the collab tools.
+    pub(crate) fn collab_limits(&self) -> CollabLimits {
+        self.upgrade()
+            .map(|state| state.collab_limits())
+            .unwrap_or_default()
+    }
+
+    /// Host-configured prefix and suffix for subagent developer instructions.
+    pub(crate) fn subagent_prompt_affixes(&self) -> (Option<String>, Option<String>) {
+        self.upgrade()
+            .map(|state| state.subagent_prompt_affixes())
+            .unwrap_or_default()
+    }
+
+    /// Fail when the subagent tree of `parent_id` or of any of its ancestors has exhausted the
+    /// host's token budget.
+    pub(crate) async fn ensure_token_budget(&self, parent_id: ThreadId) -> CodexResult<()> {
+        let state = self.upgrade()?;
+        ensure_token_budget(&state, parent_id).await
+    }
+
+    /// Tokens used by the subagent tree of `parent_id`, and the configured budget if any.
+    pub(crate) async fn subagent_token_usage(
+        &self,
+        parent_id: ThreadId,
+    ) -> CodexResult<(u64, Option<u64>)> {
+        let state = self.upgrade()?;
+        Ok((
+            state.subagent_token_usage(parent_id).await,
+            state.subagent_token_budget(),
+        ))
+    }
+
+    /// Report that a `wait` on `agent_id` timed out to the host's metrics hook, if any.
+    pub(crate) fn record_wait_timeout(&self, agent_id: ThreadId) {
+        if let Ok(state) = self.upgrade() {
+            state.record_subagent_wait_timeout(agent_id);
+        }
+    }
+
+    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
+    /// Shut down `agent_id`. A `reason` is first posted to the agent's own group chat so it
+    /// lands in its transcript, and is kept with its output after removal.
+    #[instrument(name = "subagent", skip_all, fields(thread_id = %agent_id, parent_id = field::Empty, persona = field::Empty, correlation_id = field::Empty))]
+    pub(crate) async fn shutdown_agent(
+        &self,
+        agent_id: ThreadId,
+        reason: Option<String>,
+    ) -> CodexResult<()> {
+        let state = self.upgrade()?;
+        record_subagent_span_fields(&state, agent_id).await;
+        // A paused drain would never see the shutdown complete.
+        if let Err(err) = state.set_subagent_paused(agent_id, false).await {
+            tracing::debug!("could not resume agent {agent_id} before shutdown: {err}");
+        }
+        if let Some(reason) = reason.filter(|reason| !reason.trim().is_empty()) {
+            state
+                .record_subagent_shutdown_reason(agent_id, reason.trim())
+                .await;
+            state
+                .send_op(
+                    agent_id,
+                    Op::GroupChatMessage {
+                        text: format!("Shutting down: {}", reason.trim()),
+                        mentions: Vec::new(),
+                        sender: GroupChatSender::TeamLead,
+                    },
+                )
+                .await?;
+        }
+        state.send_op(agent_id, Op::Shutdown).await?;
+        state.cascade_shutdown(agent_id).await;
+        Ok(())
+    }
+
+    /// Remove the agent immediately, aborting its drain task instead of waiting for it. Any
+    /// events the drain is still processing are dropped.
+    pub(crate) async fn forget_subagent(&self, agent_id: ThreadId) -> CodexResult<()> {
+        let state = self.upgrade()?;
+        if let Err(err) = state.retire_subagent(agent_id).await {
+            tracing::debug!("forgotten agent {agent_id} had already stopped: {err}");
+        }
+        Ok(())
+    }
+
+    /// Emergency stop: request shutdown of every subagent owned by `parent_id` and forget them
+    /// without waiting. Individual shutdown failures are collected rather than aborting.
+    pub(crate) async fn kill_all_subagents(
+        &self,
+        parent_id: ThreadId,
+    ) -> CodexResult<KillAllOutcome> {
+        let state = self.upgrade()?;
+        let mut outcome = KillAllOutcome::default();
+        for (id, _) in state.subagents_for_parent(parent_id).await {
+            if let Err(err) = state.retire_subagent(id).await {
+                tracing::warn!("failed to shut down agent {id}: {err}");
+                outcome.shutdown_errors.push(format!("{id}: {err}"));
+            }
+            state.cascade_shutdown(id).await;
+            outcome.terminated += 1;
+        }
+        Ok(outcome)
+    }
+
+    /// Pause `agent_id`, a direct subagent of `parent_id`: its drain stops reading events and
+    /// new inputs are refused until [`Self::resume_agent`]. A turn already running keeps going;
+    /// its events queue until the resume. Returns whether the agent was running unpaused.
+    pub(crate) async fn pause_agent(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+    ) -> CodexResult<bool> {
+        let state = self.upgrade()?;
+        if !state.is_subagent_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        state.set_subagent_paused(agent_id, true).await
+    }
+
+    /// Undo [`Self::pause_agent`], letting the drain catch up on queued events. Returns whether
+    /// the agent was paused.
+    pub(crate) async fn resume_agent(
+        &self,
+        parent_id: ThreadId,
+        agent_id: ThreadId,
+    ) -> CodexResult<bool> {
+        let state = self.upgrade()?;
+        if !state.is_subagent_of(parent_id, agent_id).await {
+            return Err(CodexErr::ThreadNotFound(agent_id));
+        }
+        state.set_subagent_paused(agent_id, false).await
+    }
+
+    /// Pause every subagent owned by `parent_id`, e.g. while the team is rate limited. Returns
+    /// how many were newly paused; subagents already paused are not counted.
+    pub(crate) async fn pause_all(&self, parent_id: ThreadId) -> CodexResult<usize> {
+        self.set_all_paused(parent_id, true).await
+    }
+
+    /// Resume every paused subagent owned by `parent_id`. Returns how many were resumed.
+    pub(crate) async fn resume_all(&self, parent_id: ThreadId) -> CodexResult<usize> {
+        self.set_all_paused(parent_id, false).await
+    }
+
+    async fn set_all_paused(&self, parent_id: ThreadId, paused: bool) -> CodexResult<usize> {
+        let state = self.upgrade()?;
+        let mut affected = 0;
+        for (id, _) in state.subagents_for_parent(parent_id).await {
+            match state.set_subagent_paused(id, paused).await {
+                Ok(true) => affected += 1,
+                Ok(false) => {}
+                // Removed since the listing; nothing left to pause or resume.
+                Err(CodexErr::ThreadNotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(affected)
+    }
+
+    /// Wait up to `timeout` for the agent's headless drain to finish before removing it, so no
+    /// events are recorded after removal. A drain that does not finish in time is aborted.
+    #[instrument(name = "subagent", skip_all, fields(thread_id = %agent_id, parent_id = field::Empty, persona = field::Empty, correlation_id = field::Empty))]
+    pub(crate) async fn forget_subagent_after_drain(
+        &self,
+        agent_id: ThreadId,
+        timeout: Duration,
+    ) -> CodexResult<()> {
+        let state = self.upgrade()?;
+        record_subagent_span_fields(&state, agent_id).await;
+        if let Some(mut drain) = state.take_subagent_drain(agent_id).await
+            && tokio::time::timeout(timeout, &mut drain).await.is_err()
+        {
+            tracing::warn!("drain did not finish in time; aborting");
+            drain.abort();
+        }
+        state.remove_thread(agent_id).await;
+        Ok(())
+    }
+
+    /// Shut down and forget the completed subagent of `parent_id` with the lowest priority below
+    /// `priority`, but only while the parent's live subagents and spawns still starting fill its
+    /// subagent cap. The victim is retired like a reaped subagent, drain included.
+    pub(crate) async fn evict_idle_subagent(
+        &self,
+        state: &ThreadManagerState,
+        parent_id: ThreadId,
+        priority: u8,
+    ) -> Option<ThreadId> {
+        let SubagentSlots { active, starting } = state.subagent_slots(parent_id).await;
+        if active + starting < state.collab_limits().max_subagents_per_parent {
+            return None;
+        }
+        let subagents = state.subagents_for_parent(parent_id).await;
+        let mut candidates = Vec::with_capacity(subagents.len());
+        for (id, info) in subagents {
+            candidates.push((id, info.priority, self.get_status(id).await));
+        }
+        let victim_id = eviction_candidate(&candidates, priority)?;
+        if let Err(err) = state.retire_subagent(victim_id).await {
+            tracing::warn!("failed to shut down evicted agent {victim_id}: {err}");
+        }
+        Some(victim_id)
+    }
+
+    fn upgrade(&self) -> CodexResult<Arc<ThreadManagerState>> {
+        self.manager
+            .upgrade()
+            .ok_or_else(|| CodexErr::UnsupportedOperation("thread manager dropped".to_string()))
+    }
+}
+
+/// Pick the idle subagent to evict for a spawn at `priority` from `(id, priority, status)`
+/// entries in spawn order: the lowest priority below `priority`, oldest first on ties.
+/// Only completed subagents count as idle; errored ones are kept so their failure stays visible.
+fn eviction_candidate(
+    candidates: &[(ThreadId, u8, AgentStatus)],
+    priority: u8,
+) -> Option<ThreadId> {
+    candidates
+        .iter()
+        .filter(|(_, candidate_priority, status)| {
+            *candidate_priority < priority && matches!(status, AgentStatus::Completed(_))
+        })
+        .min_by_key(|(_, candidate_priority, _)| *candidate_priority)
+        .map(|(id, _, _)| *id)
+}
+
+/// Cap a completion message at `max_chars` characters for summaries; `agent_output` serves the
+/// full text.
+fn status_preview(status: AgentStatus, max_chars: usize) -> AgentStatus {
+    match status {
+        AgentStatus::Completed(Some(message)) if message.chars().count() > max_chars => {
+            let mut preview = message.chars().take(max_chars).collect::<String>();
+            preview.push_str("...");
+            AgentStatus::Completed(Some(preview))
+        }
+        status => status,
+    }
+}
+
+/// Reject work on behalf of `parent_id` once its subagent tree, or that of any ancestor, has used
+/// up the token budget, so nested subagents cannot keep working past the root's budget.
+async fn ensure_token_budget(state: &ThreadManagerState, parent_id: ThreadId) -> CodexResult<()> {
+    let Some(budget) = state.subagent_token_budget() else {
+        return Ok(());
+    };
+    let used = state.subagent_token_usage_up_to_root(parent_id).await;
+    if used >= budget {
+        tracing::debug!("subagent token budget of {parent_id} exhausted: {used} of {budget} used");
+        return Err(CodexErr::SubagentLimitExceeded {
+            kind: SubagentLimitKind::TokenBudget,
+            limit: budget,
+        });
+    }
+    Ok(())
+}
+
+/// First-prompt item carrying one piece of the parent's pinned context.
+fn pinned_context_input(text: String) -> UserInput {
+    UserInput::Text {
+        text: format!("<pinned_context>\n{text}\n</pinned_context>"),
+    }
+}
+
+fn spawn_cancelled(token: Option<&str>) -> CodexErr {
+    let message = match token {
+        Some(token) => format!("spawn {token} was cancelled"),
+        None => "spawn was cancelled".to_string(),
+    };
+    CodexErr::UnsupportedOperation(message)
+}
+
+/// Reject spawning `additional` subagents when `parent_id` would exceed its subagent limit,
+/// counting spawns still starting like [`ThreadManagerState::reserve_subagent_spawn`].
+async fn ensure_subagent_capacity(
+    state: &ThreadManagerState,
+    parent_id: ThreadId,
+    additional: usize,
+) -> CodexResult<()> {
+    let limit = state.collab_limits().max_subagents_per_parent;
+    let SubagentSlots { active, starting } = state.subagent_slots(parent_id).await;
+    if (active + starting).saturating_add(additional) > limit {
+        return Err(CodexErr::SubagentLimitExceeded {
+            kind: SubagentLimitKind::PerParent,
+            limit: limit as u64,
+        });
+    }
+    Ok(())
+}
+
+/// One descendant in a nested `agent_output` response.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SubagentOutputNode {
+    pub(crate) id: ThreadId,
+    pub(crate) display_name: Option<String>,
+    pub(crate) status: AgentStatus,
+    pub(crate) last_message: Option<String>,
+    pub(crate) partial: Option<String>,
+    pub(crate) children: Vec<SubagentOutputNode>,
+}
+
+impl SubagentOutputNode {
+    pub(crate) fn strip_control_sequences(self) -> Self {
+        Self {
+            last_message: self
+                .last_message
+                .map(|message| strip_control_sequences(&message)),
+            partial: self
+                .partial
+                .map(|partial| strip_control_sequences(&partial)),
+            children: self
+                .children
+                .into_iter()
+                .map(Self::strip_control_sequences)
+                .collect(),
+            ..self
+        }
+    }
+}
+
+/// One headless subagent to launch via [`AgentControl::spawn_agent`] or
+/// [`AgentControl::spawn_agents`].
+pub(crate) struct SubagentSpawn {
+    pub(crate) config: crate::config::Config,
+    /// History the subagent starts from, such as a curated handoff; `InitialHistory::New` for a
+    /// cold start.
+    pub(crate) initial_history: InitialHistory,
+    pub(crate) prompt: String,
+    pub(crate) persona: Option<String>,
+    pub(crate) display_name: Option<String>,
+    pub(crate) source_label: Option<String>,
+    /// Extra input items sent after `prompt`, already validated by the caller.
+    pub(crate) attachments: Vec<UserInput>,
+    /// Paths `attachments` were read from, resolved against the config's cwd, so clones re-read the
+    /// files instead of reusing stale contents.
+    pub(crate) attachment_paths: Vec<PathBuf>,
+    pub(crate) priority: u8,
+    /// Evict a lower-priority idle subagent when the parent is at its cap.
+    pub(crate) evict_idle: bool,
+    pub(crate) max_tool_events: Option<usize>,
+    /// Caller-chosen token that lets `cancel_spawn` abort the spawn while its session starts.
+    pub(crate) spawn_token: Option<String>,
+    /// Post the subagent's final messages to the parent's group chat.
+    pub(crate) auto_post: bool,
+    /// Host-supplied id tying the subagent to an upstream request, for cross-system tracing.
+    pub(crate) correlation_id: Option<String>,
+    /// JSON schema the subagent's final messages must follow.
+    pub(crate) output_schema: Option<serde_json::Value>,
+}
+
+impl SubagentSpawn {
+    /// A cold start that sends `prompt`, with every other option at its default.
+    pub(crate) fn new(config: crate::config::Config, prompt: String) -> Self {
+        Self {
+            config,
+            initial_history: InitialHistory::New,
+            prompt,
+            persona: None,
+            display_name: None,
+            source_label: None,
+            attachments: Vec::new(),
+            attachment_paths: Vec::new(),
+            priority: 0,
+            evict_idle: false,
+            max_tool_events: None,
+            spawn_token: None,
+            auto_post: true,
+            correlation_id: None,
+            output_schema: None,
+        }
+    }
+}
+
+/// One subagent in a `get_agent_tree` response.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SubagentTreeNode {
+    pub(crate) id: ThreadId,
+    pub(crate) persona: Option<String>,
+    pub(crate) display_name: Option<String>,
+    pub(crate) status: AgentStatus,
+    pub(crate) children: Vec<SubagentTreeNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SubagentTree {
+    pub(crate) node_count: usize,
+    pub(crate) truncated: bool,
+    pub(crate) agents: Vec<SubagentTreeNode>,
+}
+
+/// Nest breadth-first `(parent_id, node)` pairs under their parents, returning the top level.
+fn nest_by_parent<T>(
+    mut nodes: Vec<(ThreadId, T)>,
+    id: impl Fn(&T) -> ThreadId,
+    children: impl Fn(&mut T) -> &mut Vec<T>,
+) -> Vec<T> {
+    // Attaching from the back moves every child into its parent before that parent is itself
+    // attached.
+    let mut roots = Vec::new();
+    while let Some((node_parent, node)) = nodes.pop() {
+        match nodes
+            .iter_mut()
+            .find(|(_, candidate)| id(candidate) == node_parent)
+        {
+            Some((_, parent)) => children(parent).insert(0, node),
+            None => roots.insert(0, node),
+        }
+    }
+    roots
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct KillAllOutcome {
+    pub(crate) terminated: usize,
+    pub(crate) shutdown_errors: Vec<String>,
+}
+
+/// Filtering and paging applied by [`AgentControl::list_subagents`]. Paging is applied after
+/// the status filter.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ListSubagentsQuery {
+    pub(crate) order: SubagentOrder,
+    pub(crate) status: Option<AgentStatusFilter>,
+    pub(crate) offset: usize,
+    pub(crate) limit: Option<usize>,
+}
+
+/// Status kind to match when listing subagents, ignoring any payload on the status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AgentStatusFilter {
+    PendingInit,
+    Running,
+    Completed,
+    Errored,
+    Shutdown,
+    NotFound,
+}
+
+impl AgentStatusFilter {
+    pub(crate) fn matches(self, status: &AgentStatus) -> bool {
+        matches!(
+            (self, status),
+            (Self::PendingInit, AgentStatus::PendingInit)
+                | (Self::Running, AgentStatus::Running)
+                | (Self::Completed, AgentStatus::Completed(_))
+                | (Self::Errored, AgentStatus::Errored(_))
+                | (Self::Shutdown, AgentStatus::Shutdown)
+                | (Self::NotFound, AgentStatus::NotFound)
+        )
+    }
+}
+
+/// Aggregates over a parent's direct subagents, as returned by `agents_summary`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct SubagentStats {
+    pub(crate) total: usize,
+    /// Subagents per status kind; kinds with no subagents are left out.
+    pub(crate) by_status: BTreeMap<&'static str, usize>,
+    pub(crate) errored: usize,
+    /// Tokens used by the whole subagent tree, including subagents already removed.
+    pub(crate) total_tokens: u64,
+    /// Sum of the time each live subagent has existed.
+    pub(crate) total_seconds: u64,
+    /// Turns started across all live subagents.
+    pub(crate) turns: u64,
+}
+
+/// Spawn headroom of a parent, as returned by `spawn_capacity`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct SpawnCapacity {
+    /// Subagents that could be spawned right now: the tightest of the limits below.
+    pub(crate) remaining: usize,
+    pub(crate) per_parent: PerParentCapacity,
+    /// Unset when the host configured no token budget.
+    pub(crate) token_budget: Option<TokenBudgetCapacity>,
+}
+
+/// Slots under [`CollabLimits::max_subagents_per_parent`]; spawns still starting hold one too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct PerParentCapacity {
+    pub(crate) limit: usize,
+    pub(crate) active: usize,
+    pub(crate) starting: usize,
+    pub(crate) remaining: usize,
+}
+
+/// Tokens left for the most-consumed subagent tree the parent sits in, its own or an ancestor's;
+/// new spawns are refused once none remain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct TokenBudgetCapacity {
+    pub(crate) limit: u64,
+    pub(crate) used: u64,
+    pub(crate) remaining: u64,
+}
+
+/// Snake-case name of a status kind, matching the `status_filter` values of `list_agents`.
+fn status_kind(status: &AgentStatus) -> &'static str {
+    match status {
+        AgentStatus::PendingInit => "pending_init",
+        AgentStatus::Running => "running",
+        AgentStatus::Completed(_) => "completed",
+        AgentStatus::Errored(_) => "errored",
+        AgentStatus::Shutdown => "shutdown",
+        AgentStatus::NotFound => "not_found",
+    }
+}
+
+/// Effective settings of one subagent, as returned by `inspect_agent`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SubagentInspection {
+    pub(crate) id: ThreadId,
+    pub(crate) persona: Option<String>,
+    pub(crate) display_name: Option<String>,
+    pub(crate) model: Option<String>,
+    pub(crate) priority: u8,
+    /// `None` when every tool not on the denylist is exposed.
+    pub(crate) tool_allowlist: Option<Vec<String>>,
+    pub(crate) tool_denylist: Vec<String>,
+    pub(crate) shell_command_allowlist: Option<Vec<String>>,
+    pub(crate) shell_command_denylist: Vec<String>,
+}
+
+impl SubagentInspection {
+    fn from_info(id: ThreadId, info: &SubagentInfo) -> Self {
+        fn patterns<T: ToString>(patterns: &[T]) -> Vec<String> {
+            patterns.iter().map(ToString::to_string).collect()
+        }
+        let policy = &info.spawn_config.tool_policy;
+        Self {
+            id,
+            persona: info.persona.clone(),
+            display_name: info.display_name.clone(),
+            model: info.spawn_config.model.clone(),
+            priority: info.priority,
+            tool_allowlist: policy.tool_allowlist.as_deref().map(patterns),
+            tool_denylist: patterns(&policy.tool_denylist),
+            shell_command_allowlist: policy.shell_command_allowlist.as_deref().map(patterns),
+            shell_command_denylist: patterns(&policy.shell_command_denylist),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SubagentSummary {
+    pub(crate) id: ThreadId,
+    pub(crate) status: AgentStatus,
+    pub(crate) persona: Option<String>,
+    pub(crate) display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) correlation_id: Option<String>,
+    /// Seconds since the subagent last produced output or received a prompt. A running agent
+    /// with a large value may be stalled.
+    pub(crate) seconds_since_last_activity: Option<u64>,
+    /// Group-chat messages not yet delivered to the subagent. Filled in from the parent
+    /// session, which owns the chat.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unread_count: Option<usize>,
+}
+
+/// When an agent is spawned "headless" (no UI/view attached), there may be no consumer polling
+/// `CodexThread::next_event()`. The underlying event channel is unbounded, so the producer can
+/// accumulate events indefinitely. This drain task prevents that memory growth by polling and
+/// discarding events until shutdown.
+pub(crate) fn spawn_headless_drain(
+    thread: Arc<CodexThread>,
+    state: Arc<ThreadManagerState>,
+    agent_id: ThreadId,
+) -> JoinHandle<()> {
+    let span = tracing::info_span!(
+        "subagent",
+        thread_id = %agent_id,
+        parent_id = field::Empty,
+        persona = field::Empty,
+        correlation_id = field::Empty,
+    );
+    let drain = async move {
+        record_subagent_span_fields(&state, agent_id).await;
         let mut saw_message_item_completed = false;
+        // The latest message the rate limit held back; posted anyway if it ends the turn.
+        let mut held_back = None;
         loop {
+            state.wait_while_paused(agent_id).await;
             match thread.next_event().await {
-                Ok(event) => match event.msg {
-                    EventMsg::ItemCompleted(event) => {
-                        if let Some(message) = subagent_message_from_item(&event.item) {
-                            saw_message_item_completed = true;
-                            record_and_post_subagent_message(&state, agent_id, message).await;
-                        }
+                Ok(event) => {
+                    if let Some(status) = agent_status_from_event(&event.msg) {
+                        state.record_subagent_status(agent_id, &status);
                     }
-                    EventMsg::AgentMessage(event) => {
-                        if !saw_message_item_completed
-                            && let Some(message) = normalize_subagent_message(&event.message)
-                        {
-                            record_and_post_subagent_message(&state, agent_id, message).await;
+                    match event.msg {
+                        EventMsg::ItemCompleted(event) => {
+                            if let Some(message) = subagent_message_from_item(&event.item) {
+                                saw_message_item_completed = true;
+                                held_back =
+                                    record_and_post_subagent_message(&state, agent_id, message)
+                                        .await;
+                            }
                         }
-                    }
-                    EventMsg::AgentMessageDelta(event) => {
-                        state
-                            .record_subagent_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::AgentMessageContentDelta(event) => {
-                        state
-                            .record_subagent_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::AgentReasoning(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.text.as_str())
-                            .await;
-                    }
-                    EventMsg::AgentReasoningDelta(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::AgentReasoningRawContent(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.text.as_str())
-                            .await;
-                    }
-                    EventMsg::AgentReasoningRawContentDelta(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::ReasoningContentDelta(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::ReasoningRawContentDelta(event) => {
-                        state
-                            .record_subagent_reasoning_delta(agent_id, event.delta.as_str())
-                            .await;
-                    }
-                    EventMsg::ExecCommandBegin(event) => {
-                        let command = event.command.join(" ");
-                        state
-                            .record_subagent_tool_event(agent_id, format!("exec begin: {command}"))
+                        EventMsg::AgentMessage(event) => {
+                            if !saw_message_item_completed
+                                && let Some(message) = normalize_subagent_message(&event.message)
+                            {
+                                held_back =
+                                    record_and_post_subagent_message(&state, agent_id, message)
+                                        .await;
+                            }
+                        }
+                        EventMsg::TurnStarted(_) => {
+                            state.record_subagent_turn_started(agent_id).await;
+                        }
+                        EventMsg::TurnComplete(_) => {
+                            state.record_subagent_turn_complete(agent_id).await;
+                            if let Some(message) = held_back.take() {
+                                post_final_subagent_message(&state, agent_id, message).await;
+                            }
+                        }
+                        EventMsg::TurnAborted(_) => {
+                            if let Some(message) = held_back.take() {
+                                post_final_subagent_message(&state, agent_id, message).await;
+                            }
+                        }
+                        EventMsg::AgentMessageDelta(event) => {
+                            state
+                                .record_subagent_delta(agent_id, event.delta.as_str())
+                                .await;
+                        }
+                        EventMsg::AgentMessageContentDelta(event) => {
+                            state
+                                .record_subagent_delta(agent_id, event.delta.as_str())
+                                .await;
+                        }
+                        EventMsg::AgentReasoning(event) => {
+                            state
+                                .record_subagent_reasoning_summary_delta(
+                                    agent_id,
+                                    event.text.as_str(),
+                                )
+                                .await;
+                        }
+                        EventMsg::AgentReasoningDelta(event) => {
+                            state
+                                .record_subagent_reasoning_summary_delta(
+                                    agent_id,
+                                    event.delta.as_str(),
+                                )
+                                .await;
+                        }
+                        EventMsg::ReasoningContentDelta(event) => {
+                            state
+                                .record_subagent_reasoning_summary_delta(
+                                    agent_id,
+                                    event.delta.as_str(),
+                                )
+                                .await;
+                        }
+                        EventMsg::AgentReasoningRawContent(event) => {
+                            state
+                                .record_subagent_reasoning_raw_delta(agent_id, event.text.as_str())
+                                .await;
+                        }
+                        EventMsg::AgentReasoningRawContentDelta(event) => {
+                            state
+                                .record_subagent_reasoning_raw_delta(agent_id, event.delta.as_str())
+                                .await;
+                        }
+                        EventMsg::ReasoningRawContentDelta(event) => {
+                            state
+                                .record_subagent_reasoning_raw_delta(agent_id, event.delta.as_str())
+                                .await;
+                        }
+                        EventMsg::TokenCount(event) => {
+                            if let Some(info) = event.info {
+                                state
+                                    .record_subagent_token_usage(agent_id, &info.total_token_usage)
+                                    .await;
+                            }
+                            if let Some(rate_limits) = event.rate_limits {
+                                state
+                                    .record_subagent_rate_limits(agent_id, rate_limits)
+                                    .await;
+                            }
+                        }
+                        EventMsg::TurnDiff(event) => {
+                            state
+                                .record_subagent_turn_diff(agent_id, &event.unified_diff)
+                                .await;
+                        }
+                        EventMsg::Warning(event) => {
+                            state.record_subagent_warning(agent_id, event.message).await;
+                        }
+                        EventMsg::StreamError(event) => {
+                            state
+                                .record_subagent_warning(
+                                    agent_id,
+                                    format!("stream error: {}", event.message),
+                                )
+                                .await;
+                        }
+                        EventMsg::ExecCommandBegin(event) => {
+                            let command = event.command.join(" ");
+                            record_and_echo_tool_event(
+                                &state,
+                                agent_id,
+                                format!("exec begin: {command}"),
+                            )
                             .await;
-                    }
-                    EventMsg::ExecCommandEnd(event) => {
-                        let command = event.command.join(" ");
-                        let exit_code = event.exit_code;
-                        state
-                            .record_subagent_tool_event(
+                        }
+                        EventMsg::ExecCommandEnd(event) => {
+                            let command = event.command.join(" ");
+                            let exit_code = event.exit_code;
+                            record_and_echo_tool_event(
+                                &state,
                                 agent_id,
                                 format!("exec end: {command} (exit {exit_code})"),
                             )
                             .await;
-                    }
-                    EventMsg::McpToolCallBegin(event) => {
-                        let server = event.invocation.server;
-                        let tool = event.invocation.tool;
-                        let call_id = event.call_id;
-                        state
-                            .record_subagent_tool_event(
+                        }
+                        EventMsg::PatchApplyEnd(event) => {
+                            let status = if event.success { "ok" } else { "error" };
+                            let files = event.changes.len();
+                            record_and_echo_tool_event(
+                                &state,
+                                agent_id,
+                                format!("patch end: {files} file(s) {status}"),
+                            )
+                            .await;
+                            if event.success {
+                                state
+                                    .record_subagent_file_changes(agent_id, event.changes)
+                                    .await;
+                            }
+                        }
+                        EventMsg::McpToolCallBegin(event) => {
+                            let server = event.invocation.server;
+                            let tool = event.invocation.tool;
+                            let call_id = event.call_id;
+                            record_and_echo_tool_event(
+                                &state,
                                 agent_id,
                                 format!("tool begin: {server}/{tool} ({call_id})"),
                             )
                             .await;
-                    }
-                    EventMsg::McpToolCallEnd(event) => {
-                        let status = if event.is_success() { "ok" } else { "error" };
-                        let server = event.invocation.server;
-                        let tool = event.invocation.tool;
-                        let call_id = event.call_id;
-                        state
-                            .record_subagent_tool_event(
+                        }
+                        EventMsg::McpToolCallEnd(event) => {
+                            let status = if event.is_success() { "ok" } else { "error" };
+                            let server = event.invocation.server;
+                            let tool = event.invocation.tool;
+                            let call_id = event.call_id;
+                            record_and_echo_tool_event(
+                                &state,
                                 agent_id,
                                 format!("tool end: {server}/{tool} ({call_id}) {status}"),
                             )
                             .await;
-                    }
-                    EventMsg::WebSearchBegin(event) => {
-                        let call_id = event.call_id;
-                        state
-                            .record_subagent_tool_event(
+                        }
+                        EventMsg::WebSearchBegin(event) => {
+                            let call_id = event.call_id;
+                            record_and_echo_tool_event(
+                                &state,
                                 agent_id,
                                 format!("web search begin: {call_id}"),
                             )
                             .await;
-                    }
-                    EventMsg::WebSearchEnd(event) => {
-                        let call_id = event.call_id;
-                        let query = event.query;
-                        state
-                            .record_subagent_tool_event(
+                        }
+                        EventMsg::WebSearchEnd(event) => {
+                            let call_id = event.call_id;
+                            let query = event.query;
+                            record_and_echo_tool_event(
+                                &state,
                                 agent_id,
                                 format!("web search end: {call_id} ({query})"),
                             )
                             .await;
+                        }
+                        EventMsg::ShutdownComplete => {
+                            state.remove_thread(agent_id).await;
+                            break;
+                        }
+                        // Reflected in the status recorded above, echoes of the subagent's own
+                        // input, or streaming detail summarized by the matching end event.
+                        EventMsg::Error(_)
+                        | EventMsg::SessionConfigured(_)
+                        | EventMsg::UserMessage(_)
+                        | EventMsg::AgentReasoningSectionBreak(_)
+                        | EventMsg::ExecCommandOutputDelta(_)
+                        | EventMsg::TerminalInteraction(_)
+                        | EventMsg::PatchApplyBegin(_) => {}
+                        other => {
+                            tracing::trace!("drain ignored {other} event from agent {agent_id}");
+                        }
                     }
-                    EventMsg::ShutdownComplete => {
-                        state.remove_thread(agent_id).await;
-                        break;
-                    }
-                    _ => {}
-                },
+                }
                 Err(err) => {
                     tracing::warn!("failed to receive event from agent: {err:?}");
                     break;
                 }
             }
         }
-    });
+    };
+    tokio::spawn(drain.instrument(span))
+}
+
+/// Fill the `parent_id` and `persona` fields of the current subagent span from `agent_id`'s
+/// registration, so control and drain logs carry the same identifying fields.
+async fn record_subagent_span_fields(state: &ThreadManagerState, agent_id: ThreadId) {
+    let Some(info) = state.subagent_info(agent_id).await else {
+        return;
+    };
+    let span = tracing::Span::current();
+    span.record("parent_id", field::display(info.parent_id));
+    if let Some(persona) = &info.persona {
+        span.record("persona", persona.as_str());
+    }
+    if let Some(correlation_id) = &info.correlation_id {
+        span.record("correlation_id", correlation_id.as_str());
+    }
 }
 
 fn normalize_subagent_message(message: &str) -> Option<String> {
@@ -403,34 +2017,105 @@ fn subagent_message_from_item(item: &TurnItem) -> Option<String> {
     normalize_subagent_message(&text)
 }
 
-async fn record_and_post_subagent_message(
+/// Record a tool event for `agent_output` and, when the host opted in, echo it to the parent.
+async fn record_and_echo_tool_event(state: &ThreadManagerState, agent_id: ThreadId, event: String) {
+    if let Some(parent_id) = state.admit_subagent_tool_echo(agent_id).await {
+        let op = Op::SubagentToolActivity {
+            subagent_id: agent_id,
+            description: event.clone(),
+        };
+        if let Err(err) = state.send_op(parent_id, op).await {
+            tracing::debug!("failed to echo tool event to {parent_id}: {err}");
+        }
+    }
+    state.record_subagent_tool_event(agent_id, event).await;
+}
+
+/// Record `message` as the subagent's latest output and post it to its parent's group chat.
+/// Returns the message when the group-chat rate limit held it back, so the drain can still
+/// deliver it with [`post_final_subagent_message`] should it end the turn.
+pub(crate) async fn record_and_post_subagent_message(
     state: &ThreadManagerState,
     agent_id: ThreadId,
     message: String,
-) {
+) -> Option<String> {
     state
         .record_subagent_message(agent_id, message.as_str())
         .await;
-    if let Some(info) = state.subagent_info(agent_id).await {
-        let sender = GroupChatSender::SubAgent {
-            id: agent_id,
-            persona: info.persona.clone(),
-            display_name: info.display_name.clone(),
-        };
-        if let Err(err) = state
-            .send_op(
-                info.parent_id,
-                Op::GroupChatMessage {
-                    text: message,
-                    mentions: Vec::new(),
-                    sender,
-                },
-            )
-            .await
-        {
-            tracing::warn!("failed to post subagent message to group chat: {err}");
+    post_subagent_message(state, agent_id, message, false).await
+}
+
+/// Post the message that ended a subagent's turn after the rate limit held it back. The
+/// orchestrator waits on these, so they are never dropped.
+pub(crate) async fn post_final_subagent_message(
+    state: &ThreadManagerState,
+    agent_id: ThreadId,
+    message: String,
+) {
+    post_subagent_message(state, agent_id, message, true).await;
+}
+
+/// Post `message` to the parent's group chat unless the subagent does not auto-post or the host
+/// transform suppresses it. A `final_message` bypasses the rate limit; any other message over
+/// the limit is returned unposted.
+async fn post_subagent_message(
+    state: &ThreadManagerState,
+    agent_id: ThreadId,
+    message: String,
+    final_message: bool,
+) -> Option<String> {
+    let info = state
+        .subagent_info(agent_id)
+        .await
+        .filter(|info| info.auto_post)?;
+    let sender = GroupChatSender::SubAgent {
+        id: agent_id,
+        persona: info.persona.clone(),
+        display_name: info.display_name.clone(),
+    };
+    let Some(text) = state.transform_subagent_message(&sender, message.clone()) else {
+        tracing::debug!("group chat post from subagent {agent_id} suppressed by host transform");
+        return None;
+    };
+    let text = if final_message {
+        let dropped = state.admit_held_subagent_group_chat_post(agent_id).await;
+        with_dropped_note(text, dropped)
+    } else {
+        match state.admit_subagent_group_chat_post(agent_id).await {
+            GroupChatPostAdmission::Allowed { dropped } => with_dropped_note(text, dropped),
+            GroupChatPostAdmission::Limited => {
+                tracing::debug!("held back group chat post from rate-limited subagent {agent_id}");
+                return Some(message);
+            }
         }
+    };
+    if let Err(err) = state
+        .send_op(
+            info.parent_id,
+            Op::GroupChatMessage {
+                text: text.clone(),
+                mentions: Vec::new(),
+                sender,
+            },
+        )
+        .await
+    {
+        tracing::warn!("failed to post subagent message to group chat: {err}");
+        state
+            .record_subagent_undelivered_message(agent_id, text)
+            .await;
     }
+    None
+}
+
+/// `text` to post, noting the `dropped` posts the rate limit discarded since the last one.
+fn with_dropped_note(text: String, dropped: u32) -> String {
+    if dropped == 0 {
+        return text;
+    }
+    format!(
+        "{text}\n\n({dropped} earlier message(s) from this agent were dropped by the group chat rate limit.)"
+    )
 }
 
 #[cfg(test)]
@@ -457,6 +2142,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn eviction_prefers_lowest_priority_idle_subagent() {
+        let (running, oldest, newer, errored, higher) = (
+            ThreadId::new(),
+            ThreadId::new(),
+            ThreadId::new(),
+            ThreadId::new(),
+            ThreadId::new(),
+        );
+        let candidates = vec![
+            (running, 0, AgentStatus::Running),
+            (oldest, 1, AgentStatus::Completed(None)),
+            (newer, 1, AgentStatus::Completed(Some("done".to_string()))),
+            (errored, 2, AgentStatus::Errored("boom".to_string())),
+            (higher, 5, AgentStatus::Completed(None)),
+        ];
+
+        assert_eq!(eviction_candidate(&candidates, 5), Some(oldest));
+        assert_eq!(eviction_candidate(&candidates[2..], 5), Some(newer));
+        assert_eq!(
+            eviction_candidate(&candidates[3..], 5),
+            None,
+            "errored is kept"
+        );
+        assert_eq!(eviction_candidate(&candidates, 1), None);
+        assert_eq!(eviction_candidate(&candidates[..1], 5), None);
+    }
+
+    #[test]
+    fn status_preview_caps_completion_messages() {
+        let long = "é".repeat(250);
+        assert_eq!(
+            status_preview(AgentStatus::Completed(Some(long)), 200),
+            AgentStatus::Completed(Some(format!("{}...", "é".repeat(200))))
+        );
+        assert_eq!(
+            status_preview(AgentStatus::Completed(Some("done".to_string())), 200),
+            AgentStatus::Completed(Some("done".to_string()))
+        );
+        let error = "x".repeat(250);
+        assert_eq!(
+            status_preview(AgentStatus::Errored(error.clone()), 200),
+            AgentStatus::Errored(error)
+        );
+    }
+
     #[tokio::test]
     async fn get_status_returns_not_found_without_manager() {
         let control = AgentControl::default();
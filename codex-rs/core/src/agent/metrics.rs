@@ -0,0 +1,25 @@
+use codex_protocol::ThreadId;
+use codex_protocol::protocol::AgentStatus;
+
+/// Lifecycle callbacks for subagents, so hosts can export counters (spawns, completions,
+/// errors, timeouts) without forking the orchestration code. Every method defaults to a no-op.
+pub trait SubagentMetrics: Send + Sync {
+    /// A subagent was registered under `parent_id`, carrying the host's `correlation_id` when
+    /// the spawn supplied one.
+    fn subagent_spawned(
+        &self,
+        _parent_id: ThreadId,
+        _subagent_id: ThreadId,
+        _correlation_id: Option<&str>,
+    ) {
+    }
+
+    /// The headless drain observed a status transition for the subagent.
+    fn subagent_status_changed(&self, _subagent_id: ThreadId, _status: &AgentStatus) {}
+
+    /// A `wait` on the subagent timed out before it finished.
+    fn subagent_wait_timed_out(&self, _subagent_id: ThreadId) {}
+
+    /// The subagent was unregistered from its parent.
+    fn subagent_removed(&self, _subagent_id: ThreadId) {}
+}
@@ -1,8 +1,17 @@
 pub(crate) mod control;
+pub(crate) mod metrics;
 pub(crate) mod spawn;
 pub(crate) mod status;
 
 pub(crate) use codex_protocol::protocol::AgentStatus;
 pub(crate) use control::AgentControl;
+pub(crate) use control::AgentStatusFilter;
+pub(crate) use control::ListSubagentsQuery;
+pub(crate) use control::SubagentOutputNode;
+pub(crate) use control::SubagentSpawn;
+pub use metrics::SubagentMetrics;
+pub(crate) use spawn::apply_env_overrides;
+pub(crate) use spawn::apply_model_override;
 pub(crate) use spawn::build_agent_spawn_config;
+pub(crate) use spawn::resolve_attachments;
 pub(crate) use status::agent_status_from_event;
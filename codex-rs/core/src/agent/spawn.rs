@@ -1,11 +1,27 @@
+use crate::ModelProviderInfo;
 use crate::codex::TurnContext;
 use crate::config::Config;
+use crate::config::types::ShellEnvironmentPolicy;
+use crate::models_manager::manager::ModelsManager;
+use codex_protocol::user_input::UserInput;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Largest non-image attachment, in bytes, that is inlined into a subagent's first prompt.
+const MAX_TEXT_ATTACHMENT_BYTES: u64 = 64 * 1024;
+/// Most files a single spawn may attach.
+pub(crate) const MAX_SPAWN_ATTACHMENTS: usize = 16;
+const IMAGE_ATTACHMENT_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
 
 pub(crate) fn build_agent_spawn_config(turn: &TurnContext) -> Result<Config, String> {
     let base_config = turn.client.config();
     let mut config = (*base_config).clone();
-    config.model = Some(turn.client.get_model());
-    config.model_provider = turn.client.get_provider();
+    let model = turn.client.get_model();
+    let provider = turn.client.get_provider();
+    validate_spawn_model(&model, &provider)?;
+    config.model = Some(model);
+    config.model_provider = provider;
     config.model_reasoning_effort = turn.client.get_reasoning_effort();
     config.model_reasoning_summary = turn.client.get_reasoning_summary();
     config.developer_instructions = turn.developer_instructions.clone();
@@ -25,3 +41,399 @@ pub(crate) fn build_agent_spawn_config(turn: &TurnContext) -> Result<Config, Str
         .map_err(|err| format!("sandbox_policy is invalid: {err}"))?;
     Ok(config)
 }
+
+/// Check that the parent's model and provider can be handed to a subagent, so a misconfigured
+/// parent fails the spawn with a clear message instead of a confusing error from the new session.
+fn validate_spawn_model(model: &str, provider: &ModelProviderInfo) -> Result<(), String> {
+    if model.trim().is_empty() {
+        return Err("cannot spawn a subagent: the parent session has no model set".to_string());
+    }
+    if model.chars().any(char::is_whitespace) {
+        return Err(format!(
+            "cannot spawn a subagent: the parent model `{model}` is not a valid model name"
+        ));
+    }
+    if provider.name.trim().is_empty() {
+        return Err(format!(
+            "cannot spawn a subagent: the provider for model `{model}` has no name"
+        ));
+    }
+    if let Some(base_url) = &provider.base_url
+        && url::Url::parse(base_url).is_err()
+    {
+        return Err(format!(
+            "cannot spawn a subagent: provider `{}` has an invalid base_url `{base_url}`",
+            provider.name
+        ));
+    }
+    Ok(())
+}
+
+/// Merge subagent-specific environment variables into `policy`. Variables the policy would
+/// filter out (via `exclude` or `include_only`) are rejected rather than silently dropped.
+pub(crate) fn apply_env_overrides(
+    policy: &mut ShellEnvironmentPolicy,
+    env: HashMap<String, String>,
+) -> Result<(), String> {
+    let mut env: Vec<_> = env.into_iter().collect();
+    env.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (name, _) in &env {
+        if name.is_empty() || name.contains('=') || name.contains('\0') {
+            return Err(format!("invalid environment variable name {name:?}"));
+        }
+        if policy.exclude.iter().any(|pattern| pattern.matches(name)) {
+            return Err(format!(
+                "environment variable {name} is excluded by the shell environment policy"
+            ));
+        }
+        if !policy.include_only.is_empty()
+            && !policy
+                .include_only
+                .iter()
+                .any(|pattern| pattern.matches(name))
+        {
+            return Err(format!(
+                "environment variable {name} is not allowed by the shell environment policy"
+            ));
+        }
+    }
+    policy.r#set.extend(env);
+    Ok(())
+}
+
+/// Apply a spawn-time model override to `config`, which starts out with the parent's model.
+/// The override must name a model the models manager lists. When the listing itself fails, the
+/// override is skipped so the spawn still succeeds on the parent's model, and the returned
+/// warning says why.
+pub(crate) async fn apply_model_override(
+    models_manager: &ModelsManager,
+    config: &mut Config,
+    model: Option<String>,
+) -> Result<Option<String>, String> {
+    let Some(model) = model
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+    else {
+        return Ok(None);
+    };
+    if config.model.as_deref() == Some(model.as_str()) {
+        return Ok(None);
+    }
+    let available = match models_manager.list_models_or_err(config).await {
+        Ok(available) => available,
+        Err(err) => {
+            let parent_model = config.model.as_deref().unwrap_or("the default model");
+            return Ok(Some(format!(
+                "could not validate model `{model}` ({err}); the subagent will use {parent_model} instead"
+            )));
+        }
+    };
+    if !available.iter().any(|preset| preset.model == model) {
+        let names = available
+            .iter()
+            .map(|preset| preset.model.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!(
+            "unknown model `{model}`; available models: {names}"
+        ));
+    }
+    config.model = Some(model);
+    Ok(None)
+}
+
+/// Convert spawn attachments into input items sent with the subagent's first prompt. Paths
+/// resolve against `cwd` and must stay inside it once symlinks are followed. Images become
+/// `LocalImage` items; other files must be UTF-8 text and are inlined. At most
+/// [`MAX_SPAWN_ATTACHMENTS`] paths are accepted.
+pub(crate) async fn resolve_attachments(
+    cwd: &Path,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<UserInput>, String> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    if paths.len() > MAX_SPAWN_ATTACHMENTS {
+        return Err(format!(
+            "at most {MAX_SPAWN_ATTACHMENTS} attachments can be sent to a subagent, got {}",
+            paths.len()
+        ));
+    }
+    let root = canonicalize(cwd).await.map_err(|err| {
+        format!(
+            "unable to resolve working directory `{}`: {err}",
+            cwd.display()
+        )
+    })?;
+    let mut items = Vec::with_capacity(paths.len());
+    for path in paths {
+        items.push(resolve_attachment(&root, &path).await?);
+    }
+    Ok(items)
+}
+
+async fn resolve_attachment(root: &Path, path: &Path) -> Result<UserInput, String> {
+    let resolved = canonicalize(&root.join(path))
+        .await
+        .map_err(|err| format!("unable to locate attachment `{}`: {err}", path.display()))?;
+    if !resolved.starts_with(root) {
+        return Err(format!(
+            "attachment `{}` is outside the working directory",
+            path.display()
+        ));
+    }
+    let metadata = tokio::fs::metadata(&resolved)
+        .await
+        .map_err(|err| format!("unable to read attachment `{}`: {err}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("attachment `{}` is not a file", path.display()));
+    }
+    let is_image = resolved
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_ATTACHMENT_EXTENSIONS
+                .iter()
+                .any(|image_ext| ext.eq_ignore_ascii_case(image_ext))
+        });
+    if is_image {
+        return Ok(UserInput::LocalImage { path: resolved });
+    }
+    if metadata.len() > MAX_TEXT_ATTACHMENT_BYTES {
+        return Err(format!(
+            "attachment `{}` is larger than {MAX_TEXT_ATTACHMENT_BYTES} bytes",
+            path.display()
+        ));
+    }
+    let contents = tokio::fs::read_to_string(&resolved).await.map_err(|err| {
+        format!(
+            "attachment `{}` is not a readable text file: {err}",
+            path.display()
+        )
+    })?;
+    Ok(UserInput::Text {
+        text: format!(
+            "<attachment path=\"{}\">\n{contents}\n</attachment>",
+            resolved.display()
+        ),
+    })
+}
+
+/// `dunce::canonicalize` without blocking the runtime.
+async fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    let path = tokio::fs::canonicalize(path).await?;
+    Ok(dunce::simplified(&path).to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthManager;
+    use crate::CodexAuth;
+    use crate::ModelProviderInfo;
+    use crate::built_in_model_providers;
+    use crate::config::test_config;
+    use crate::config::types::EnvironmentVariablePattern;
+    use crate::features::Feature;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+    use wiremock::MockServer;
+
+    #[test]
+    fn env_overrides_merge_into_policy_set() {
+        let mut policy = ShellEnvironmentPolicy::default();
+        policy.r#set.insert("EXISTING".to_string(), "1".to_string());
+
+        apply_env_overrides(
+            &mut policy,
+            HashMap::from([("WORKER_TOKEN".to_string(), "abc".to_string())]),
+        )
+        .expect("override should be allowed");
+
+        assert_eq!(
+            policy.r#set,
+            HashMap::from([
+                ("EXISTING".to_string(), "1".to_string()),
+                ("WORKER_TOKEN".to_string(), "abc".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn env_overrides_reject_excluded_and_non_included_names() {
+        let mut policy = ShellEnvironmentPolicy {
+            exclude: vec![EnvironmentVariablePattern::new_case_insensitive("AWS_*")],
+            ..Default::default()
+        };
+        let err = apply_env_overrides(
+            &mut policy,
+            HashMap::from([("AWS_SECRET".to_string(), "x".to_string())]),
+        )
+        .expect_err("excluded variable should be rejected");
+        assert_eq!(
+            err,
+            "environment variable AWS_SECRET is excluded by the shell environment policy"
+        );
+
+        let mut policy = ShellEnvironmentPolicy {
+            include_only: vec![EnvironmentVariablePattern::new_case_insensitive("APP_*")],
+            ..Default::default()
+        };
+        let err = apply_env_overrides(
+            &mut policy,
+            HashMap::from([("OTHER".to_string(), "x".to_string())]),
+        )
+        .expect_err("variable outside include_only should be rejected");
+        assert_eq!(
+            err,
+            "environment variable OTHER is not allowed by the shell environment policy"
+        );
+        assert!(policy.r#set.is_empty());
+    }
+
+    #[tokio::test]
+    async fn attachments_inline_text_and_reference_images() {
+        let dir = TempDir::new().expect("tempdir");
+        std::fs::write(dir.path().join("notes.md"), "remember this").expect("write notes");
+        std::fs::write(dir.path().join("diagram.PNG"), [0u8; 4]).expect("write image");
+        let root = dunce::canonicalize(dir.path()).expect("canonical tempdir");
+
+        let items = resolve_attachments(
+            dir.path(),
+            vec![PathBuf::from("notes.md"), root.join("diagram.PNG")],
+        )
+        .await
+        .expect("attachments inside cwd should resolve");
+
+        assert_eq!(
+            items,
+            vec![
+                UserInput::Text {
+                    text: format!(
+                        "<attachment path=\"{}\">\nremember this\n</attachment>",
+                        root.join("notes.md").display()
+                    ),
+                },
+                UserInput::LocalImage {
+                    path: root.join("diagram.PNG"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn attachments_reject_paths_outside_cwd_and_non_files() {
+        let outer = TempDir::new().expect("tempdir");
+        let cwd = outer.path().join("work");
+        std::fs::create_dir(&cwd).expect("create cwd");
+        std::fs::write(outer.path().join("secret.txt"), "nope").expect("write secret");
+
+        let err = resolve_attachments(&cwd, vec![PathBuf::from("../secret.txt")])
+            .await
+            .expect_err("escaping cwd should fail");
+        assert_eq!(
+            err,
+            "attachment `../secret.txt` is outside the working directory"
+        );
+
+        std::fs::create_dir(cwd.join("dir")).expect("create dir");
+        let err = resolve_attachments(&cwd, vec![PathBuf::from("dir")])
+            .await
+            .expect_err("directories should fail");
+        assert_eq!(err, "attachment `dir` is not a file");
+
+        assert!(
+            resolve_attachments(&cwd, vec![PathBuf::from("missing.txt")])
+                .await
+                .is_err()
+        );
+
+        let err = resolve_attachments(&cwd, vec![PathBuf::from("dir"); MAX_SPAWN_ATTACHMENTS + 1])
+            .await
+            .expect_err("too many attachments should fail");
+        assert_eq!(
+            err,
+            format!(
+                "at most {MAX_SPAWN_ATTACHMENTS} attachments can be sent to a subagent, got {}",
+                MAX_SPAWN_ATTACHMENTS + 1
+            )
+        );
+    }
+
+    #[test]
+    fn spawn_model_must_be_set_and_match_a_usable_provider() {
+        let mut provider = built_in_model_providers()["openai"].clone();
+        provider.base_url = Some("https://example.com/v1".to_string());
+        assert_eq!(validate_spawn_model("gpt-5-codex", &provider), Ok(()));
+
+        let err = validate_spawn_model("  ", &provider).expect_err("missing model");
+        assert_eq!(
+            err,
+            "cannot spawn a subagent: the parent session has no model set"
+        );
+        assert!(validate_spawn_model("gpt 5", &provider).is_err());
+
+        let nameless = ModelProviderInfo {
+            name: String::new(),
+            ..provider.clone()
+        };
+        assert!(validate_spawn_model("gpt-5-codex", &nameless).is_err());
+        let bad_url = ModelProviderInfo {
+            base_url: Some("not a url".to_string()),
+            ..provider
+        };
+        let err = validate_spawn_model("gpt-5-codex", &bad_url).expect_err("invalid base_url");
+        assert!(err.contains("invalid base_url `not a url`"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn model_override_must_be_a_listed_model() {
+        let codex_home = TempDir::new().expect("tempdir");
+        let auth_manager =
+            AuthManager::from_auth_for_testing(CodexAuth::from_api_key("Test API Key"));
+        let manager = ModelsManager::new(codex_home.path().to_path_buf(), auth_manager);
+        let mut config = test_config();
+        config.model = Some("parent-model".to_string());
+        let listed = manager.list_models(&config).await[0].model.clone();
+
+        let err = apply_model_override(&manager, &mut config, Some("no-such-model".to_string()))
+            .await
+            .expect_err("unknown models should be rejected");
+        assert!(err.starts_with("unknown model `no-such-model`"), "{err}");
+        assert_eq!(config.model.as_deref(), Some("parent-model"));
+
+        let warning = apply_model_override(&manager, &mut config, Some(listed.clone()))
+            .await
+            .expect("listed models should be accepted");
+        assert_eq!(warning, None);
+        assert_eq!(config.model, Some(listed));
+    }
+
+    #[tokio::test]
+    async fn model_override_falls_back_to_parent_when_models_are_unreachable() {
+        // Nothing is mounted on the server, so every /models request fails.
+        let server = MockServer::start().await;
+        let codex_home = TempDir::new().expect("tempdir");
+        let provider = ModelProviderInfo {
+            base_url: Some(server.uri()),
+            request_max_retries: Some(0),
+            ..ModelProviderInfo::create_openai_provider()
+        };
+        let auth_manager =
+            AuthManager::from_auth_for_testing(CodexAuth::create_dummy_chatgpt_auth_for_testing());
+        let manager =
+            ModelsManager::with_provider(codex_home.path().to_path_buf(), auth_manager, provider);
+        let mut config = test_config();
+        config.features.enable(Feature::RemoteModels);
+        config.model = Some("parent-model".to_string());
+
+        let warning =
+            apply_model_override(&manager, &mut config, Some("no-such-model".to_string()))
+                .await
+                .expect("listing failures should not fail the spawn")
+                .expect("listing failures should produce a warning");
+        assert!(warning.contains("no-such-model"), "{warning}");
+        assert!(warning.ends_with("the subagent will use parent-model instead"));
+        assert_eq!(config.model.as_deref(), Some("parent-model"));
+    }
+}
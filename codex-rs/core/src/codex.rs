@@ -3,15 +3,18 @@ use std::collections::HashSet;
 use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Weak;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use crate::AuthManager;
 use crate::CodexAuth;
 use crate::SandboxState;
 use crate::agent::AgentControl;
 use crate::agent::AgentStatus;
+use crate::agent::SubagentSpawn;
 use crate::agent::agent_status_from_event;
 use crate::agent::control::SubagentSummary;
 use crate::client_common::REVIEW_PROMPT;
@@ -66,6 +69,7 @@ use serde_json;
 use serde_json::Value;
 use tokio::sync::Mutex;
 use tokio::sync::RwLock;
+use tokio::sync::broadcast;
 use tokio::sync::oneshot;
 use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
@@ -87,6 +91,7 @@ use crate::config::Config;
 use crate::config::Constrained;
 use crate::config::ConstraintResult;
 use crate::config::GhostSnapshotConfig;
+use crate::config::types::GroupChatEvictionPolicy;
 use crate::config::types::ShellEnvironmentPolicy;
 use crate::context_manager::ContextManager;
 use crate::environment_context::EnvironmentContext;
@@ -110,7 +115,9 @@ use crate::protocol::ErrorEvent;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
 use crate::protocol::ExecApprovalRequestEvent;
+use crate::protocol::GroupChatCursorEvent;
 use crate::protocol::GroupChatMessageEvent;
+use crate::protocol::GroupChatMessageId;
 use crate::protocol::GroupChatSender;
 use crate::protocol::Op;
 use crate::protocol::RateLimitSnapshot;
@@ -124,6 +131,7 @@ use crate::protocol::SessionConfiguredEvent;
 use crate::protocol::SkillErrorInfo;
 use crate::protocol::SkillMetadata as ProtocolSkillMetadata;
 use crate::protocol::StreamErrorEvent;
+use crate::protocol::SubagentToolActivityEvent;
 use crate::protocol::Submission;
 use crate::protocol::TokenCountEvent;
 use crate::protocol::TokenUsage;
@@ -141,12 +149,14 @@ use crate::skills::SkillMetadata;
 use crate::skills::SkillsManager;
 use crate::skills::build_skill_injections;
 use crate::state::ActiveTurn;
+use crate::state::MAX_GROUP_CHAT_BYTES;
 use crate::state::SessionServices;
 use crate::state::SessionState;
 use crate::tasks::GhostSnapshotTask;
 use crate::tasks::ReviewTask;
 use crate::tasks::SessionTask;
 use crate::tasks::SessionTaskContext;
+use crate::thread_manager::CollabLimits;
 use crate::tools::ToolRouter;
 use crate::tools::context::SharedTurnDiffTracker;
 use crate::tools::parallel::ToolCallRuntime;
@@ -180,6 +190,38 @@ pub struct Codex {
     pub(crate) rx_event: Receiver<Event>,
     // Last known status of the agent.
     pub(crate) agent_status: Arc<RwLock<AgentStatus>>,
+    /// Live feed of messages appended to this session's group chat.
+    pub(crate) group_chat_feed: broadcast::Sender<GroupChatMessageEvent>,
+    /// Session driven by the submission loop, for read-only inspection. It stops upgrading once
+    /// the loop has exited.
+    pub(crate) session: Weak<Session>,
+}
+
+/// What a session started from, as returned by [`Codex::initial_context`].
+#[derive(Debug, Clone)]
+pub(crate) struct InitialContext {
+    /// Instructions sent with every request: the configured override or the model's default.
+    pub(crate) base_instructions: String,
+    /// History items ahead of the first user turn, such as developer instructions (which
+    /// carry a subagent's persona), user instructions, and the environment context.
+    pub(crate) items: Vec<ResponseItem>,
+}
+
+/// Per-recipient outcome of [`Session::broadcast_group_chat_message`], in recipient order.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct GroupChatBroadcast {
+    /// Recipients that accepted the message within the timeout.
+    pub(crate) accepted: Vec<ThreadId>,
+    /// Recipients still busy at the timeout; delivery continues in the background.
+    pub(crate) slow: Vec<ThreadId>,
+    /// Recipients the message could not be delivered to, with the reason.
+    pub(crate) failed: Vec<(ThreadId, String)>,
+}
+
+enum BroadcastDelivery {
+    Accepted,
+    Slow,
+    Failed(String),
 }
 
 /// Wrapper returned by [`Codex::spawn`] containing the spawned [`Codex`],
@@ -278,6 +320,9 @@ impl Codex {
             cwd: config.cwd.clone(),
             original_config_do_not_use: Arc::clone(&config),
             session_source,
+            collab_limits: agent_control.collab_limits(),
+            group_chat_max_bytes: MAX_GROUP_CHAT_BYTES,
+            group_chat_eviction: config.group_chat_eviction,
         };
 
         // Generate a unique ID for the lifetime of this Codex session.
@@ -303,6 +348,8 @@ impl Codex {
             map_session_init_error(&e, &config.codex_home)
         })?;
         let thread_id = session.conversation_id;
+        let group_chat_feed = session.state.lock().await.group_chat.feed();
+        let session_handle = Arc::downgrade(&session);
 
         // This task will run until Op::Shutdown is received.
         tokio::spawn(submission_loop(session, config, rx_sub));
@@ -311,6 +358,8 @@ impl Codex {
             tx_sub,
             rx_event,
             agent_status,
+            group_chat_feed,
+            session: session_handle,
         };
 
         #[allow(deprecated)]
@@ -355,6 +404,33 @@ impl Codex {
         let status = self.agent_status.read().await;
         status.clone()
     }
+
+    pub(crate) async fn set_agent_status(&self, status: AgentStatus) {
+        *self.agent_status.write().await = status;
+    }
+
+    pub(crate) fn subscribe_group_chat(&self) -> broadcast::Receiver<GroupChatMessageEvent> {
+        self.group_chat_feed.subscribe()
+    }
+
+    /// The instructions and context items the session started with.
+    pub(crate) async fn initial_context(&self) -> CodexResult<InitialContext> {
+        let session = self.session.upgrade().ok_or(CodexErr::InternalAgentDied)?;
+        Ok(session.initial_context().await)
+    }
+
+    /// Working directory and sandbox policy the session's next turn runs with.
+    pub(crate) async fn cwd_and_sandbox_policy(&self) -> CodexResult<(PathBuf, SandboxPolicy)> {
+        let session = self.session.upgrade().ok_or(CodexErr::InternalAgentDied)?;
+        Ok(session.cwd_and_sandbox_policy().await)
+    }
+
+    /// Write buffered rollout items to disk. A session that has exited already flushed them.
+    pub(crate) async fn flush_rollout(&self) {
+        if let Some(session) = self.session.upgrade() {
+            session.flush_rollout().await;
+        }
+    }
 }
 
 /// Context for an initialized model agent
@@ -452,6 +528,14 @@ pub(crate) struct SessionConfiguration {
     original_config_do_not_use: Arc<Config>,
     /// Source of the session (cli, vscode, exec, mcp, ...)
     session_source: SessionSource,
+
+    /// Collab tool limits of the thread manager; bounds the group chat's message count and is
+    /// quoted in the collab tool descriptions.
+    pub(crate) collab_limits: CollabLimits,
+    /// Maximum cumulative size, in bytes, of message text retained in the group chat.
+    pub(crate) group_chat_max_bytes: usize,
+    /// Which group-chat messages are evicted first once either limit is exceeded.
+    pub(crate) group_chat_eviction: GroupChatEvictionPolicy,
 }
 
 impl SessionConfiguration {
@@ -541,7 +625,8 @@ impl Session {
             model_info: &model_info,
             features: &per_turn_config.features,
             tool_policy: &per_turn_config.tool_policy,
-        });
+        })
+        .with_collab_limits(session_configuration.collab_limits);
 
         let developer_instructions = if per_turn_config
             .features
@@ -609,16 +694,35 @@ impl Session {
         let should_spawn_default_subagents = config.features.enabled(Feature::AgentOrchestration)
             && !matches!(session_source, SessionSource::SubAgent(_));
 
+        let subagent_rollouts_dir = match session_source {
+            SessionSource::SubAgent(_) => config.subagent_rollouts_dir.clone(),
+            _ => None,
+        };
         let (conversation_id, rollout_params) = match &initial_history {
             InitialHistory::New | InitialHistory::Forked(_) => {
+                // Only collab subagents take a preset id; other subagent sessions, such as
+                // reviews, start from a copy of their parent's config.
+                #[cfg(any(test, feature = "test-support"))]
+                let conversation_id = match (&session_source, config.thread_id) {
+                    (
+                        SessionSource::SubAgent(codex_protocol::protocol::SubAgentSource::Other(_)),
+                        Some(id),
+                    ) => id,
+                    _ => ThreadId::default(),
+                };
+                #[cfg(not(any(test, feature = "test-support")))]
                 let conversation_id = ThreadId::default();
+                let params = RolloutRecorderParams::new(
+                    conversation_id,
+                    session_configuration.user_instructions.clone(),
+                    session_source,
+                );
                 (
                     conversation_id,
-                    RolloutRecorderParams::new(
-                        conversation_id,
-                        session_configuration.user_instructions.clone(),
-                        session_source,
-                    ),
+                    match subagent_rollouts_dir {
+                        Some(dir) => params.with_sessions_dir(dir),
+                        None => params,
+                    },
                 )
             }
             InitialHistory::Resumed(resumed_history) => (
@@ -908,6 +1012,7 @@ impl Session {
     async fn spawn_default_subagents(self: Arc<Self>) {
         let turn = self.new_default_turn().await;
         let parent_id = self.conversation_id();
+        let (prompt_prefix, prompt_suffix) = self.services.agent_control.subagent_prompt_affixes();
         for template in crate::agent_personas::DEFAULT_SUBAGENT_TEMPLATES {
             let mut config = match crate::agent::build_agent_spawn_config(turn.as_ref()) {
                 Ok(config) => config,
@@ -916,21 +1021,29 @@ impl Session {
                     continue;
                 }
             };
-            config.developer_instructions = crate::agent_personas::with_subagent_instructions(
+            config.developer_instructions = match crate::agent_personas::with_subagent_instructions(
                 config.developer_instructions.as_deref(),
                 Some(template.persona),
+                prompt_prefix.as_deref(),
+                prompt_suffix.as_deref(),
                 parent_id,
-            );
+            ) {
+                Ok(instructions) => instructions,
+                Err(err) => {
+                    warn!("failed to build subagent instructions: {err}");
+                    continue;
+                }
+            };
             let result = self
                 .services
                 .agent_control
                 .spawn_agent(
                     parent_id,
-                    config,
-                    template.initial_message.to_string(),
-                    true,
-                    Some(template.persona.to_string()),
-                    Some(template.display_name.to_string()),
+                    SubagentSpawn {
+                        persona: Some(template.persona.to_string()),
+                        display_name: Some(template.display_name.to_string()),
+                        ..SubagentSpawn::new(config, template.initial_message.to_string())
+                    },
                 )
                 .await;
             if let Err(err) = result {
@@ -1184,6 +1297,7 @@ impl Session {
         let mentions = self.resolve_group_chat_mentions(text, &[]).await;
         let display_text = format_group_chat_display_text(text, &mentions);
         let message = GroupChatMessageEvent {
+            id: None,
             sender,
             text: display_text,
             display: true,
@@ -1206,13 +1320,17 @@ impl Session {
         turn_context: &TurnContext,
         message: GroupChatMessageEvent,
     ) {
-        self.append_group_chat_message(&message).await;
+        let Some(message) = self.append_group_chat_message(message).await else {
+            return;
+        };
         self.send_event(turn_context, EventMsg::GroupChatMessage(message))
             .await;
     }
 
     async fn emit_group_chat_message_raw(&self, sub_id: String, message: GroupChatMessageEvent) {
-        self.append_group_chat_message(&message).await;
+        let Some(message) = self.append_group_chat_message(message).await else {
+            return;
+        };
         self.send_event_raw(Event {
             id: sub_id,
             msg: EventMsg::GroupChatMessage(message),
@@ -1220,9 +1338,13 @@ impl Session {
         .await;
     }
 
-    async fn append_group_chat_message(&self, message: &GroupChatMessageEvent) {
+    /// Appends to the in-memory group chat and returns the message stamped with its id.
+    async fn append_group_chat_message(
+        &self,
+        message: GroupChatMessageEvent,
+    ) -> Option<GroupChatMessageEvent> {
         let mut state = self.state.lock().await;
-        state.group_chat.append(message.clone());
+        state.group_chat.append(message)
     }
 
     pub(crate) async fn process_group_chat_message(
@@ -1239,6 +1361,7 @@ impl Session {
         let mentions = self.resolve_group_chat_mentions(trimmed, &mentions).await;
         let display_text = format_group_chat_display_text(trimmed, &mentions);
         let event = GroupChatMessageEvent {
+            id: None,
             sender: sender.clone(),
             text: display_text,
             display: true,
@@ -1274,8 +1397,16 @@ impl Session {
         }
         let mut state = self.state.lock().await;
         for event in events {
-            if let EventMsg::GroupChatMessage(message) = event {
-                state.group_chat.append(message);
+            match event {
+                EventMsg::GroupChatMessage(message) => {
+                    state.group_chat.append(message);
+                }
+                EventMsg::GroupChatCursor(cursor) => {
+                    state
+                        .group_chat
+                        .mark_read(cursor.subagent_id, cursor.last_read);
+                }
+                _ => {}
             }
         }
     }
@@ -1283,14 +1414,110 @@ impl Session {
     async fn unread_group_chat_messages(
         &self,
         subagent_id: ThreadId,
-    ) -> (usize, Vec<GroupChatMessageEvent>) {
+    ) -> (Option<GroupChatMessageId>, Vec<GroupChatMessageEvent>) {
         let state = self.state.lock().await;
         state.group_chat.unread_messages(subagent_id)
     }
 
-    async fn mark_group_chat_read(&self, subagent_id: ThreadId, cursor: usize) {
-        let mut state = self.state.lock().await;
-        state.group_chat.mark_read(subagent_id, cursor);
+    /// Live feed of messages appended to this session's group chat from now on.
+    pub(crate) async fn subscribe_group_chat(&self) -> broadcast::Receiver<GroupChatMessageEvent> {
+        self.state.lock().await.group_chat.feed().subscribe()
+    }
+
+    /// The group-chat messages `subagent_id` last read (up to `read_limit`) and those it has
+    /// not been delivered yet.
+    pub(crate) async fn group_chat_view_for(
+        &self,
+        subagent_id: ThreadId,
+        read_limit: usize,
+    ) -> (Vec<GroupChatMessageEvent>, Vec<GroupChatMessageEvent>) {
+        let state = self.state.lock().await;
+        let last_read = state.group_chat.read_tail(subagent_id, read_limit);
+        let (_, unread) = state.group_chat.unread_messages(subagent_id);
+        (last_read, unread)
+    }
+
+    /// Every message retained in this session's group chat, oldest first.
+    pub(crate) async fn group_chat_history(&self) -> Vec<GroupChatMessageEvent> {
+        self.state.lock().await.group_chat.history()
+    }
+
+    /// Warn the team lead that all of `subagents` have been stalled mid-turn for `idle_seconds`
+    /// with nothing left to read in the group chat, which usually means they are blocked on each
+    /// other. The warning is an event for the host; nothing is added to the history.
+    pub(crate) async fn note_possible_subagent_deadlock(
+        &self,
+        sub_id: String,
+        subagents: &[ThreadId],
+        idle_seconds: u64,
+    ) {
+        for subagent_id in subagents {
+            if self.group_chat_unread_count(*subagent_id).await > 0 {
+                return;
+            }
+        }
+        let message = format!(
+            "Possible deadlock: {} subagent(s) have been mid-turn without activity for {idle_seconds}s with no unread group chat messages. They may be blocked on each other; message one of them or close them.",
+            subagents.len()
+        );
+        self.send_event_raw(Event {
+            id: sub_id,
+            msg: EventMsg::Warning(WarningEvent { message }),
+        })
+        .await;
+    }
+
+    /// Append `text` to the developer instructions of every later turn and record it in history
+    /// as a developer message, so the current conversation sees it without a new session.
+    pub(crate) async fn amend_developer_instructions(&self, text: String) {
+        {
+            let mut state = self.state.lock().await;
+            let configuration = &mut state.session_configuration;
+            configuration.developer_instructions =
+                crate::agent_personas::with_instruction_amendment(
+                    configuration.developer_instructions.as_deref(),
+                    &text,
+                );
+        }
+        let turn_context = self.new_default_turn().await;
+        let item = DeveloperInstructions::new(text).into();
+        self.record_conversation_items(&turn_context, &[item]).await;
+    }
+
+    /// Surface one of `subagent_id`'s tool events in this session's event stream.
+    pub(crate) async fn echo_subagent_tool_activity(
+        &self,
+        sub_id: String,
+        subagent_id: ThreadId,
+        description: String,
+    ) {
+        self.send_event_raw(Event {
+            id: sub_id,
+            msg: EventMsg::SubagentToolActivity(SubagentToolActivityEvent {
+                subagent_id,
+                description,
+            }),
+        })
+        .await;
+    }
+
+    /// Group-chat messages not yet delivered to `subagent_id`.
+    pub(crate) async fn group_chat_unread_count(&self, subagent_id: ThreadId) -> usize {
+        self.state.lock().await.group_chat.unread_count(subagent_id)
+    }
+
+    /// Advance `subagent_id`'s group-chat cursor and persist it so resume does not redeliver.
+    async fn mark_group_chat_read(&self, subagent_id: ThreadId, last_read: GroupChatMessageId) {
+        {
+            let mut state = self.state.lock().await;
+            state.group_chat.mark_read(subagent_id, last_read.clone());
+        }
+        let cursor = EventMsg::GroupChatCursor(GroupChatCursorEvent {
+            subagent_id,
+            last_read,
+        });
+        self.persist_rollout_items(&[RolloutItem::EventMsg(cursor)])
+            .await;
     }
 
     async fn resolve_group_chat_mentions(
@@ -1306,7 +1533,10 @@ impl Session {
         let subagents = match self
             .services
             .agent_control
-            .list_subagents(self.conversation_id)
+            .list_subagents(
+                self.conversation_id,
+                &crate::agent::ListSubagentsQuery::default(),
+            )
             .await
         {
             Ok(subagents) => subagents,
@@ -1353,25 +1583,87 @@ impl Session {
                 continue;
             }
 
-            let (cursor, unread) = self.unread_group_chat_messages(*mention).await;
-            if unread.is_empty() {
-                continue;
+            if let Err(err) = self.deliver_group_chat_prompt(*mention).await {
+                warn!("failed to deliver group chat prompt: {err}");
             }
-            let prompt = format_group_chat_prompt(&unread, true);
-            match self
-                .services
-                .agent_control
-                .send_prompt(*mention, prompt)
-                .await
-            {
-                Ok(_) => {
-                    self.mark_group_chat_read(*mention, cursor).await;
-                }
-                Err(err) => {
-                    warn!("failed to deliver group chat prompt: {err}");
+        }
+    }
+
+    /// Send `subagent_id` every group-chat message it has not been delivered yet, then mark
+    /// them read. Sends to one subagent are serialized by `AgentControl`.
+    async fn deliver_group_chat_prompt(&self, subagent_id: ThreadId) -> CodexResult<()> {
+        let (cursor, unread) = self.unread_group_chat_messages(subagent_id).await;
+        if unread.is_empty() {
+            return Ok(());
+        }
+        let prompt = format_group_chat_prompt(&unread, true);
+        self.services
+            .agent_control
+            .send_prompt(subagent_id, prompt)
+            .await?;
+        if let Some(cursor) = cursor {
+            self.mark_group_chat_read(subagent_id, cursor).await;
+        }
+        Ok(())
+    }
+
+    /// Post `text` from the team lead once and deliver it to each of `recipients` concurrently,
+    /// giving every recipient up to `timeout` to accept it so one wedged subagent cannot hold up
+    /// the rest. A delivery still pending at the deadline keeps going in the background and is
+    /// reported as slow.
+    pub(crate) async fn broadcast_group_chat_message(
+        self: &Arc<Self>,
+        sub_id: String,
+        text: &str,
+        recipients: Vec<ThreadId>,
+        timeout: Duration,
+    ) -> GroupChatBroadcast {
+        let display_text = format_group_chat_display_text(text.trim(), &recipients);
+        let event = GroupChatMessageEvent {
+            id: None,
+            sender: GroupChatSender::TeamLead,
+            text: display_text,
+            display: true,
+        };
+        self.emit_group_chat_message_raw(sub_id, event).await;
+
+        let parent_id = self.conversation_id;
+        let deliveries = recipients.into_iter().map(|recipient| {
+            let session = Arc::clone(self);
+            async move {
+                match session
+                    .services
+                    .agent_control
+                    .is_subagent_of(parent_id, recipient)
+                    .await
+                {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let error = format!("agent with id {recipient} not found");
+                        return (recipient, BroadcastDelivery::Failed(error));
+                    }
+                    Err(err) => return (recipient, BroadcastDelivery::Failed(err.to_string())),
                 }
+                let mut delivery =
+                    tokio::spawn(async move { session.deliver_group_chat_prompt(recipient).await });
+                let outcome = match tokio::time::timeout(timeout, &mut delivery).await {
+                    Ok(Ok(Ok(()))) => BroadcastDelivery::Accepted,
+                    Ok(Ok(Err(err))) => BroadcastDelivery::Failed(err.to_string()),
+                    Ok(Err(err)) => BroadcastDelivery::Failed(err.to_string()),
+                    Err(_) => BroadcastDelivery::Slow,
+                };
+                (recipient, outcome)
+            }
+        });
+        let mut report = GroupChatBroadcast::default();
+        for (recipient, outcome) in futures::future::join_all(deliveries).await {
+            match outcome {
+                BroadcastDelivery::Accepted => report.accepted.push(recipient),
+                BroadcastDelivery::Slow => report.slow.push(recipient),
+                BroadcastDelivery::Failed(error) => report.failed.push((recipient, error)),
             }
         }
+        report
     }
 
     /// Adds an execpolicy amendment to both the in-memory and on-disk policies so future
@@ -1660,6 +1952,37 @@ impl Session {
         }
     }
 
+    /// Working directory and sandbox policy of the session configuration, including changes
+    /// made through `Op::OverrideTurnContext` since startup.
+    pub(crate) async fn cwd_and_sandbox_policy(&self) -> (PathBuf, SandboxPolicy) {
+        let state = self.state.lock().await;
+        (
+            state.session_configuration.cwd.clone(),
+            state.session_configuration.sandbox_policy.get().clone(),
+        )
+    }
+
+    /// Base instructions for the next turn and the history items recorded ahead of the first
+    /// user turn.
+    pub(crate) async fn initial_context(&self) -> InitialContext {
+        let turn_context = self.new_default_turn().await;
+        let base_instructions = turn_context
+            .base_instructions
+            .clone()
+            .unwrap_or_else(|| turn_context.client.get_model_info().base_instructions);
+        let history = self.clone_history().await;
+        let items = history
+            .raw_items()
+            .iter()
+            .take_while(|item| !matches!(parse_turn_item(item), Some(TurnItem::UserMessage(_))))
+            .cloned()
+            .collect();
+        InitialContext {
+            base_instructions,
+            items,
+        }
+    }
+
     pub(crate) async fn clone_history(&self) -> ContextManager {
         let state = self.state.lock().await;
         state.clone_history()
@@ -2002,6 +2325,28 @@ async fn submission_loop(sess: Arc<Session>, config: Arc<Config>, rx_sub: Receiv
             } => {
                 handlers::group_chat_message(&sess, sub.id.clone(), text, mentions, sender).await;
             }
+            Op::PossibleSubagentDeadlock {
+                subagents,
+                idle_seconds,
+            } => {
+                handlers::possible_subagent_deadlock(
+                    &sess,
+                    sub.id.clone(),
+                    subagents,
+                    idle_seconds,
+                )
+                .await;
+            }
+            Op::SubagentToolActivity {
+                subagent_id,
+                description,
+            } => {
+                handlers::subagent_tool_activity(&sess, sub.id.clone(), subagent_id, description)
+                    .await;
+            }
+            Op::AmendInstructions { text } => {
+                handlers::amend_instructions(&sess, text).await;
+            }
             Op::GetHistoryEntryRequest { offset, log_id } => {
                 handlers::get_history_entry_request(&sess, &config, sub.id.clone(), offset, log_id)
                     .await;
@@ -2503,6 +2848,30 @@ mod handlers {
             .await;
     }
 
+    pub async fn possible_subagent_deadlock(
+        sess: &Arc<Session>,
+        sub_id: String,
+        subagents: Vec<ThreadId>,
+        idle_seconds: u64,
+    ) {
+        sess.note_possible_subagent_deadlock(sub_id, &subagents, idle_seconds)
+            .await;
+    }
+
+    pub async fn subagent_tool_activity(
+        sess: &Arc<Session>,
+        sub_id: String,
+        subagent_id: ThreadId,
+        description: String,
+    ) {
+        sess.echo_subagent_tool_activity(sub_id, subagent_id, description)
+            .await;
+    }
+
+    pub async fn amend_instructions(sess: &Arc<Session>, text: String) {
+        sess.amend_developer_instructions(text).await;
+    }
+
     pub async fn get_history_entry_request(
         sess: &Arc<Session>,
         config: &Arc<Config>,
@@ -2772,7 +3141,8 @@ async fn spawn_review_thread(
         model_info: &review_model_info,
         features: &review_features,
         tool_policy: &config.tool_policy,
-    });
+    })
+    .with_collab_limits(parent_turn_context.tools_config.collab_limits);
 
     let base_instructions = REVIEW_PROMPT.to_string();
     let review_prompt = resolved.prompt.clone();
@@ -3638,6 +4008,27 @@ mod tests {
         assert_eq!(expected, history.raw_items());
     }
 
+    #[tokio::test]
+    async fn possible_deadlock_is_a_warning_and_not_a_history_item() {
+        let (sess, _tc, rx) = make_session_and_context_with_rx().await;
+        let before = sess.clone_history().await.raw_items().to_vec();
+
+        handlers::possible_subagent_deadlock(
+            &sess,
+            "sub-1".to_string(),
+            vec![ThreadId::new()],
+            300,
+        )
+        .await;
+
+        let event = rx.recv().await.expect("deadlock warning");
+        let EventMsg::Warning(WarningEvent { message }) = event.msg else {
+            panic!("expected a warning event, got {:?}", event.msg);
+        };
+        assert!(message.starts_with("Possible deadlock"), "{message}");
+        assert_eq!(sess.clone_history().await.raw_items(), before.as_slice());
+    }
+
     #[tokio::test]
     async fn thread_rollback_clears_history_when_num_turns_exceeds_existing_turns() {
         let (sess, tc, rx) = make_session_and_context_with_rx().await;
@@ -3726,6 +4117,9 @@ mod tests {
             cwd: config.cwd.clone(),
             original_config_do_not_use: Arc::clone(&config),
             session_source: SessionSource::Exec,
+            collab_limits: CollabLimits::default(),
+            group_chat_max_bytes: MAX_GROUP_CHAT_BYTES,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
         };
 
         let mut state = SessionState::new(session_configuration);
@@ -3792,6 +4186,9 @@ mod tests {
             cwd: config.cwd.clone(),
             original_config_do_not_use: Arc::clone(&config),
             session_source: SessionSource::Exec,
+            collab_limits: CollabLimits::default(),
+            group_chat_max_bytes: MAX_GROUP_CHAT_BYTES,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
         };
 
         let mut state = SessionState::new(session_configuration);
@@ -4042,6 +4439,9 @@ mod tests {
             cwd: config.cwd.clone(),
             original_config_do_not_use: Arc::clone(&config),
             session_source: SessionSource::Exec,
+            collab_limits: CollabLimits::default(),
+            group_chat_max_bytes: MAX_GROUP_CHAT_BYTES,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
         };
         let per_turn_config = Session::build_per_turn_config(&session_configuration);
         let model_info = ModelsManager::construct_model_info_offline(
@@ -4136,6 +4536,9 @@ mod tests {
             cwd: config.cwd.clone(),
             original_config_do_not_use: Arc::clone(&config),
             session_source: SessionSource::Exec,
+            collab_limits: CollabLimits::default(),
+            group_chat_max_bytes: MAX_GROUP_CHAT_BYTES,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
         };
         let per_turn_config = Session::build_per_turn_config(&session_configuration);
         let model_info = ModelsManager::construct_model_info_offline(
@@ -4222,6 +4625,34 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn amend_developer_instructions_records_and_keeps_the_amendment() {
+        let (session, _turn_context) = make_session_and_context().await;
+
+        session
+            .amend_developer_instructions("Always run the linter.".to_string())
+            .await;
+
+        let history = session.clone_history().await;
+        assert_eq!(
+            history.raw_items().last(),
+            Some(&ResponseItem::Message {
+                id: None,
+                role: "developer".to_string(),
+                content: vec![ContentItem::InputText {
+                    text: "Always run the linter.".to_string(),
+                }],
+            })
+        );
+        let turn_context = session.new_default_turn().await;
+        assert!(
+            turn_context
+                .developer_instructions
+                .as_deref()
+                .is_some_and(|instructions| instructions.ends_with("Always run the linter."))
+        );
+    }
+
     #[derive(Clone, Copy)]
     struct NeverEndingTask {
         kind: TaskKind,
@@ -88,6 +88,8 @@ pub(crate) async fn run_codex_thread_interactive(
         tx_sub: tx_ops,
         rx_event: rx_sub,
         agent_status: Arc::clone(&codex.agent_status),
+        group_chat_feed: codex.group_chat_feed.clone(),
+        session: codex.session.clone(),
     })
 }
 
@@ -130,6 +132,8 @@ pub(crate) async fn run_codex_thread_one_shot(
     let (tx_bridge, rx_bridge) = async_channel::bounded(SUBMISSION_CHANNEL_CAPACITY);
     let ops_tx = io.tx_sub.clone();
     let agent_status = Arc::clone(&io.agent_status);
+    let group_chat_feed = io.group_chat_feed.clone();
+    let session = io.session.clone();
     let io_for_bridge = io;
     tokio::spawn(async move {
         while let Ok(event) = io_for_bridge.next_event().await {
@@ -162,6 +166,8 @@ pub(crate) async fn run_codex_thread_one_shot(
         rx_event: rx_bridge,
         tx_sub: tx_closed,
         agent_status,
+        group_chat_feed,
+        session,
     })
 }
 
@@ -377,6 +383,8 @@ mod tests {
             tx_sub,
             rx_event: rx_events,
             agent_status: Default::default(),
+            group_chat_feed: tokio::sync::broadcast::channel(1).0,
+            session: std::sync::Weak::new(),
         });
 
         let (session, ctx, _rx_evt) = crate::codex::make_session_and_context_with_rx().await;
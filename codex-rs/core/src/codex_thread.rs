@@ -1,23 +1,34 @@
 use crate::agent::AgentStatus;
 use crate::codex::Codex;
+use crate::codex::InitialContext;
 use crate::error::Result as CodexResult;
 use crate::protocol::Event;
 use crate::protocol::Op;
+use crate::protocol::SandboxPolicy;
+use crate::protocol::SessionConfiguredEvent;
 use crate::protocol::Submission;
+use codex_protocol::protocol::GroupChatMessageEvent;
 use std::path::PathBuf;
+use tokio::sync::broadcast;
+
+/// Events buffered per supervisor subscriber before it starts observing `RecvError::Lagged`.
+const EVENT_MIRROR_CAPACITY: usize = 1024;
 
 pub struct CodexThread {
     codex: Codex,
     rollout_path: PathBuf,
+    event_mirror: broadcast::Sender<Event>,
 }
 
 /// Conduit for the bidirectional stream of messages that compose a thread
 /// (formerly called a conversation) in Codex.
 impl CodexThread {
-    pub(crate) fn new(codex: Codex, rollout_path: PathBuf) -> Self {
+    pub(crate) fn new(codex: Codex, session_configured: &SessionConfiguredEvent) -> Self {
+        let (event_mirror, _) = broadcast::channel(EVENT_MIRROR_CAPACITY);
         Self {
             codex,
-            rollout_path,
+            rollout_path: session_configured.rollout_path.clone(),
+            event_mirror,
         }
     }
 
@@ -31,14 +42,59 @@ impl CodexThread {
     }
 
     pub async fn next_event(&self) -> CodexResult<Event> {
-        self.codex.next_event().await
+        let event = self.codex.next_event().await?;
+        // No receivers is the common case; the send error only means nobody is listening.
+        let _ = self.event_mirror.send(event.clone());
+        Ok(event)
+    }
+
+    /// Mirror events to a secondary observer without taking them from the primary consumer.
+    ///
+    /// Events are delivered in the order the primary consumer receives them from
+    /// [`CodexThread::next_event`], starting with the next event after subscribing; nothing is
+    /// mirrored while no one is calling `next_event`. A subscriber that falls more than
+    /// `EVENT_MIRROR_CAPACITY` events behind gets `RecvError::Lagged` with the number of
+    /// skipped events and then resumes from the oldest retained one. The primary consumer is
+    /// never slowed down by subscribers.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.event_mirror.subscribe()
+    }
+
+    /// Live feed of every message appended to this thread's group chat, starting with the next
+    /// one after subscribing. Unlike [`CodexThread::subscribe_events`], this does not depend on
+    /// anyone consuming the thread's events. Subscribers that fall behind observe
+    /// `RecvError::Lagged`.
+    pub fn subscribe_group_chat(&self) -> broadcast::Receiver<GroupChatMessageEvent> {
+        self.codex.subscribe_group_chat()
     }
 
     pub async fn agent_status(&self) -> AgentStatus {
         self.codex.agent_status().await
     }
 
+    /// Override the status derived from events, for failures that happen outside the session.
+    pub(crate) async fn set_agent_status(&self, status: AgentStatus) {
+        self.codex.set_agent_status(status).await;
+    }
+
+    /// The instructions and context items the session started with. Fails once the session
+    /// has shut down.
+    pub(crate) async fn initial_context(&self) -> CodexResult<InitialContext> {
+        self.codex.initial_context().await
+    }
+
     pub fn rollout_path(&self) -> PathBuf {
         self.rollout_path.clone()
     }
+
+    /// Working directory and sandbox policy the session currently runs with, including
+    /// `Op::OverrideTurnContext` changes. Fails once the session has shut down.
+    pub(crate) async fn cwd_and_sandbox_policy(&self) -> CodexResult<(PathBuf, SandboxPolicy)> {
+        self.codex.cwd_and_sandbox_policy().await
+    }
+
+    /// Write the session's buffered rollout items to disk.
+    pub(crate) async fn flush_rollout(&self) {
+        self.codex.flush_rollout().await;
+    }
 }
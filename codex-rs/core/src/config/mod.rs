@@ -1,5 +1,6 @@
 use crate::auth::AuthCredentialsStoreMode;
 use crate::config::types::DEFAULT_OTEL_ENVIRONMENT;
+use crate::config::types::GroupChatEvictionPolicy;
 use crate::config::types::History;
 use crate::config::types::McpServerConfig;
 use crate::config::types::Notice;
@@ -32,6 +33,7 @@ use crate::project_doc::DEFAULT_PROJECT_DOC_FILENAME;
 use crate::project_doc::LOCAL_PROJECT_DOC_FILENAME;
 use crate::protocol::AskForApproval;
 use crate::protocol::SandboxPolicy;
+use crate::rollout::SUBAGENT_SESSIONS_SUBDIR;
 use codex_app_server_protocol::Tools;
 use codex_app_server_protocol::UserSavedConfig;
 use codex_protocol::config_types::AltScreenMode;
@@ -41,6 +43,7 @@ use codex_protocol::config_types::SandboxMode;
 use codex_protocol::config_types::TrustLevel;
 use codex_protocol::config_types::Verbosity;
 use codex_protocol::openai_models::ReasoningEffort;
+use codex_protocol::protocol::SubagentLineage;
 use codex_rmcp_client::OAuthCredentialsStoreMode;
 use codex_utils_absolute_path::AbsolutePathBuf;
 use codex_utils_absolute_path::AbsolutePathBufGuard;
@@ -285,6 +288,21 @@ pub struct Config {
     /// Optional per-session tool restrictions (tool names and shell command patterns).
     pub tool_policy: ToolPolicy,
 
+    /// Set on collab subagent spawn configs; written into the subagent's rollout metadata.
+    pub subagent_lineage: Option<SubagentLineage>,
+
+    /// Id for the collab subagent started from this config instead of a random one. Only set by
+    /// [`crate::ThreadManager::seed_subagent_ids`] so tests get predictable subagent ids.
+    #[cfg(any(test, feature = "test-support"))]
+    pub thread_id: Option<codex_protocol::ThreadId>,
+
+    /// Directory that rollouts of subagent threads are written to instead of
+    /// `~/.kaabil-codex/sessions`. `None` keeps them alongside top-level sessions.
+    pub subagent_rollouts_dir: Option<PathBuf>,
+
+    /// Which group-chat messages are evicted first once the chat is full.
+    pub group_chat_eviction: GroupChatEvictionPolicy,
+
     /// Directory containing all Codex state (defaults to `~/.kaabil-codex` but can be
     /// overridden by the `CODEX_HOME` environment variable).
     pub codex_home: PathBuf,
@@ -772,6 +790,18 @@ pub struct ConfigToml {
     /// Optional per-session tool restrictions (tool names and shell command patterns).
     pub tool_policy: Option<ToolPolicyToml>,
 
+    /// When `true`, subagent rollouts are written under `subagent_rollouts_dir` so they do not
+    /// appear in top-level session listings. Defaults to `false`.
+    pub separate_subagent_rollouts: Option<bool>,
+
+    /// Directory for subagent rollouts when `separate_subagent_rollouts` is enabled. Defaults to
+    /// `~/.kaabil-codex/subagents`.
+    pub subagent_rollouts_dir: Option<AbsolutePathBuf>,
+
+    /// Which group-chat messages are evicted first once the chat is full: `fifo` (default)
+    /// drops the oldest, `retain-pinned` keeps user and team-lead messages over subagent chatter.
+    pub group_chat_eviction: Option<GroupChatEvictionPolicy>,
+
     /// Profile to use from the `profiles` map.
     pub profile: Option<String>,
 
@@ -1335,6 +1365,13 @@ impl Config {
             tool_policy.apply_overrides(tool_policy_overrides);
         }
 
+        let subagent_rollouts_dir = cfg.separate_subagent_rollouts.unwrap_or(false).then(|| {
+            match cfg.subagent_rollouts_dir {
+                Some(dir) => dir.into_path_buf(),
+                None => codex_home.join(SUBAGENT_SESSIONS_SUBDIR),
+            }
+        });
+
         // Ensure that every field of ConfigRequirements is applied to the final
         // Config.
         let ConfigRequirements {
@@ -1391,6 +1428,11 @@ impl Config {
                 .collect(),
             tool_output_token_limit: cfg.tool_output_token_limit,
             tool_policy,
+            subagent_lineage: None,
+            #[cfg(any(test, feature = "test-support"))]
+            thread_id: None,
+            subagent_rollouts_dir,
+            group_chat_eviction: cfg.group_chat_eviction.unwrap_or_default(),
             codex_home,
             config_layer_stack,
             history,
@@ -1946,6 +1988,50 @@ trust_level = "trusted"
         Ok(())
     }
 
+    #[test]
+    fn separate_subagent_rollouts_defaults_under_codex_home() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        let load = |cfg: ConfigToml| {
+            Config::load_from_base_config_with_overrides(
+                cfg,
+                ConfigOverrides::default(),
+                codex_home.path().to_path_buf(),
+            )
+        };
+
+        assert_eq!(load(ConfigToml::default())?.subagent_rollouts_dir, None);
+        let config = load(ConfigToml {
+            separate_subagent_rollouts: Some(true),
+            ..Default::default()
+        })?;
+        assert_eq!(
+            config.subagent_rollouts_dir,
+            Some(codex_home.path().join(SUBAGENT_SESSIONS_SUBDIR))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_chat_eviction_defaults_to_fifo() -> std::io::Result<()> {
+        let codex_home = TempDir::new()?;
+        let config = Config::load_from_base_config_with_overrides(
+            ConfigToml::default(),
+            ConfigOverrides::default(),
+            codex_home.path().to_path_buf(),
+        )?;
+        assert_eq!(config.group_chat_eviction, GroupChatEvictionPolicy::Fifo);
+
+        let cfg: ConfigToml = toml::from_str(r#"group_chat_eviction = "retain-pinned""#)
+            .expect("parse group_chat_eviction");
+        assert_eq!(
+            cfg.group_chat_eviction,
+            Some(GroupChatEvictionPolicy::RetainPinned)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn profile_legacy_toggles_override_base() -> std::io::Result<()> {
         let codex_home = TempDir::new()?;
@@ -3264,6 +3350,11 @@ model_verbosity = "high"
                 project_doc_fallback_filenames: Vec::new(),
                 tool_output_token_limit: None,
                 tool_policy: ToolPolicy::default(),
+                subagent_lineage: None,
+                #[cfg(any(test, feature = "test-support"))]
+                thread_id: None,
+                subagent_rollouts_dir: None,
+                group_chat_eviction: GroupChatEvictionPolicy::default(),
                 codex_home: fixture.codex_home(),
                 config_layer_stack: Default::default(),
                 history: History::default(),
@@ -3351,6 +3442,11 @@ model_verbosity = "high"
             project_doc_fallback_filenames: Vec::new(),
             tool_output_token_limit: None,
             tool_policy: ToolPolicy::default(),
+            subagent_lineage: None,
+            #[cfg(any(test, feature = "test-support"))]
+            thread_id: None,
+            subagent_rollouts_dir: None,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
             codex_home: fixture.codex_home(),
             config_layer_stack: Default::default(),
             history: History::default(),
@@ -3453,6 +3549,11 @@ model_verbosity = "high"
             project_doc_fallback_filenames: Vec::new(),
             tool_output_token_limit: None,
             tool_policy: ToolPolicy::default(),
+            subagent_lineage: None,
+            #[cfg(any(test, feature = "test-support"))]
+            thread_id: None,
+            subagent_rollouts_dir: None,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
             codex_home: fixture.codex_home(),
             config_layer_stack: Default::default(),
             history: History::default(),
@@ -3541,6 +3642,11 @@ model_verbosity = "high"
             project_doc_fallback_filenames: Vec::new(),
             tool_output_token_limit: None,
             tool_policy: ToolPolicy::default(),
+            subagent_lineage: None,
+            #[cfg(any(test, feature = "test-support"))]
+            thread_id: None,
+            subagent_rollouts_dir: None,
+            group_chat_eviction: GroupChatEvictionPolicy::default(),
             codex_home: fixture.codex_home(),
             config_layer_stack: Default::default(),
             history: History::default(),
@@ -274,6 +274,18 @@ pub enum HistoryPersistence {
     None,
 }
 
+/// Which group-chat messages are dropped first once the chat exceeds its size limits.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupChatEvictionPolicy {
+    /// Drop the oldest messages first.
+    #[default]
+    Fifo,
+    /// Drop the oldest subagent messages first, keeping messages from the user and the team
+    /// lead for as long as other messages remain to evict.
+    RetainPinned,
+}
+
 // ===== Analytics configuration =====
 
 /// Analytics settings loaded from config.toml. Fields are optional so we can apply defaults.
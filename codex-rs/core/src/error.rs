@@ -145,6 +145,17 @@ pub enum CodexErr {
     #[error("unsupported operation: {0}")]
     UnsupportedOperation(String),
 
+    /// A subagent spawn or input was refused because it would exceed a subagent limit.
+    #[error("subagent limit exceeded: {kind} (limit {limit})")]
+    SubagentLimitExceeded { kind: SubagentLimitKind, limit: u64 },
+
+    /// Orchestration state stayed locked longer than the host's state lock timeout.
+    #[error("timed out after {timeout:?} waiting for the {lock} lock")]
+    StateLockTimeout {
+        lock: &'static str,
+        timeout: Duration,
+    },
+
     #[error("{0}")]
     RefreshTokenFailed(RefreshTokenFailedError),
 
@@ -194,6 +205,7 @@ impl CodexErr {
             | CodexErr::InvalidRequest(_)
             | CodexErr::RefreshTokenFailed(_)
             | CodexErr::UnsupportedOperation(_)
+            | CodexErr::SubagentLimitExceeded { .. }
             | CodexErr::Sandbox(_)
             | CodexErr::LandlockSandboxExecutableNotProvided
             | CodexErr::RetryLimit(_)
@@ -201,6 +213,7 @@ impl CodexErr {
             | CodexErr::ThreadNotFound(_)
             | CodexErr::Spawn
             | CodexErr::SessionConfiguredNotFirstEvent
+            | CodexErr::StateLockTimeout { .. }
             | CodexErr::UsageLimitReached(_) => false,
             CodexErr::Stream(..)
             | CodexErr::Timeout
@@ -218,6 +231,24 @@ impl CodexErr {
     }
 }
 
+/// Which limit a [`CodexErr::SubagentLimitExceeded`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubagentLimitKind {
+    /// Subagents a single parent may have active or starting at once.
+    PerParent,
+    /// Tokens a parent's subagent tree may spend in total.
+    TokenBudget,
+}
+
+impl std::fmt::Display for SubagentLimitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubagentLimitKind::PerParent => write!(f, "subagents per parent"),
+            SubagentLimitKind::TokenBudget => write!(f, "subagent token budget"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ConnectionFailedError {
     pub source: reqwest::Error,
@@ -497,9 +528,9 @@ impl CodexErr {
             CodexErr::SessionConfiguredNotFirstEvent
             | CodexErr::InternalServerError
             | CodexErr::InternalAgentDied => CodexErrorInfo::InternalServerError,
-            CodexErr::UnsupportedOperation(_) | CodexErr::ThreadNotFound(_) => {
-                CodexErrorInfo::BadRequest
-            }
+            CodexErr::UnsupportedOperation(_)
+            | CodexErr::SubagentLimitExceeded { .. }
+            | CodexErr::ThreadNotFound(_) => CodexErrorInfo::BadRequest,
             CodexErr::Sandbox(_) => CodexErrorInfo::SandboxError,
             _ => CodexErrorInfo::Other,
         }
@@ -66,9 +66,24 @@ mod event_mapping;
 pub mod review_format;
 pub mod review_prompts;
 mod thread_manager;
+pub use agent::SubagentMetrics;
 pub use codex_protocol::protocol::InitialHistory;
+pub use thread_manager::CapacitySnapshot;
+pub use thread_manager::CollabLimits;
 pub use thread_manager::NewThread;
+pub use thread_manager::ReconfigureOutcome;
+#[cfg(any(test, feature = "test-support"))]
+pub use thread_manager::SeededSubagent;
+pub use thread_manager::ShutdownAllSummary;
+pub use thread_manager::SpawnedSubagent;
+pub use thread_manager::SubagentMessageTransform;
+pub use thread_manager::SubagentOrder;
+pub use thread_manager::SubagentOutputFormat;
+pub use thread_manager::SubagentOutputUpdate;
+pub use thread_manager::SubagentSpawnOptions;
 pub use thread_manager::ThreadManager;
+pub use thread_manager::ThreadManagerOptions;
+pub use thread_manager::read_subagent_lineage;
 #[deprecated(note = "use ThreadManager")]
 pub type ConversationManager = ThreadManager;
 #[deprecated(note = "use NewThread")]
@@ -94,6 +109,7 @@ pub use rollout::ARCHIVED_SESSIONS_SUBDIR;
 pub use rollout::INTERACTIVE_SESSION_SOURCES;
 pub use rollout::RolloutRecorder;
 pub use rollout::SESSIONS_SUBDIR;
+pub use rollout::SUBAGENT_SESSIONS_SUBDIR;
 pub use rollout::SessionMeta;
 #[deprecated(note = "use find_thread_path_by_id_str")]
 pub use rollout::find_conversation_path_by_id_str;
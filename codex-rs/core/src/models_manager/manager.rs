@@ -130,6 +130,14 @@ impl ModelsManager {
         self.build_available_models(remote_models)
     }
 
+    /// Like [`Self::list_models`], but surfaces refresh failures instead of logging them so callers
+    /// can tell an unknown model apart from unreachable model metadata.
+    pub(crate) async fn list_models_or_err(&self, config: &Config) -> CoreResult<Vec<ModelPreset>> {
+        self.refresh_available_models_with_cache(config).await?;
+        let remote_models = self.remote_models(config).await;
+        Ok(self.build_available_models(remote_models))
+    }
+
     pub fn try_list_models(&self, config: &Config) -> Result<Vec<ModelPreset>, TryLockError> {
         let remote_models = self.try_get_remote_models(config)?;
         Ok(self.build_available_models(remote_models))
@@ -40,6 +40,7 @@ pub(crate) fn should_persist_event_msg(ev: &EventMsg) -> bool {
         EventMsg::UserMessage(_)
         | EventMsg::AgentMessage(_)
         | EventMsg::GroupChatMessage(_)
+        | EventMsg::GroupChatCursor(_)
         | EventMsg::AgentReasoning(_)
         | EventMsg::AgentReasoningRawContent(_)
         | EventMsg::TokenCount(_)
@@ -51,6 +52,7 @@ pub(crate) fn should_persist_event_msg(ev: &EventMsg) -> bool {
         | EventMsg::TurnAborted(_) => true,
         EventMsg::Error(_)
         | EventMsg::Warning(_)
+        | EventMsg::SubagentToolActivity(_)
         | EventMsg::TurnStarted(_)
         | EventMsg::TurnComplete(_)
         | EventMsg::AgentMessageDelta(_)
@@ -55,6 +55,8 @@ pub enum RolloutRecorderParams {
         conversation_id: ThreadId,
         instructions: Option<String>,
         source: SessionSource,
+        /// Replaces `~/.kaabil-codex/sessions` as the root of the dated rollout directories.
+        sessions_dir: Option<PathBuf>,
     },
     Resume {
         path: PathBuf,
@@ -82,9 +84,19 @@ impl RolloutRecorderParams {
             conversation_id,
             instructions,
             source,
+            sessions_dir: None,
         }
     }
 
+    /// Write a new rollout under `dir` instead of the default sessions directory. Has no effect
+    /// when resuming, which appends to the existing file.
+    pub fn with_sessions_dir(mut self, dir: PathBuf) -> Self {
+        if let Self::Create { sessions_dir, .. } = &mut self {
+            *sessions_dir = Some(dir);
+        }
+        self
+    }
+
     pub fn resume(path: PathBuf) -> Self {
         Self::Resume { path }
     }
@@ -120,13 +132,14 @@ impl RolloutRecorder {
                 conversation_id,
                 instructions,
                 source,
+                sessions_dir,
             } => {
                 let LogFileInfo {
                     file,
                     path,
                     conversation_id: session_id,
                     timestamp,
-                } = create_log_file(config, conversation_id)?;
+                } = create_log_file(config, conversation_id, sessions_dir)?;
 
                 let timestamp_format: &[FormatItem] = format_description!(
                     "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z"
@@ -148,6 +161,7 @@ impl RolloutRecorder {
                         instructions,
                         source,
                         model_provider: Some(config.model_provider_id.clone()),
+                        subagent: config.subagent_lineage.clone(),
                     }),
                 )
             }
@@ -308,12 +322,15 @@ struct LogFileInfo {
     timestamp: OffsetDateTime,
 }
 
-fn create_log_file(config: &Config, conversation_id: ThreadId) -> std::io::Result<LogFileInfo> {
-    // Resolve ~/.kaabil-codex/sessions/YYYY/MM/DD and create it if missing.
+fn create_log_file(
+    config: &Config,
+    conversation_id: ThreadId,
+    sessions_dir: Option<PathBuf>,
+) -> std::io::Result<LogFileInfo> {
+    // Resolve ~/.kaabil-codex/sessions/YYYY/MM/DD (or the override) and create it if missing.
     let timestamp = OffsetDateTime::now_local()
         .map_err(|e| IoError::other(format!("failed to get local time: {e}")))?;
-    let mut dir = config.codex_home.clone();
-    dir.push(SESSIONS_SUBDIR);
+    let mut dir = sessions_dir.unwrap_or_else(|| config.codex_home.join(SESSIONS_SUBDIR));
     dir.push(timestamp.year().to_string());
     dir.push(format!("{:02}", u8::from(timestamp.month())));
     dir.push(format!("{:02}", timestamp.day()));
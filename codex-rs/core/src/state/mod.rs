@@ -3,6 +3,8 @@ mod session;
 mod turn;
 
 pub(crate) use service::SessionServices;
+pub(crate) use session::MAX_GROUP_CHAT_BYTES;
+pub(crate) use session::MAX_GROUP_CHAT_MESSAGES;
 pub(crate) use session::SessionState;
 pub(crate) use turn::ActiveTurn;
 pub(crate) use turn::RunningTask;
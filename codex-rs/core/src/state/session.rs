@@ -3,55 +3,209 @@
 use codex_protocol::ThreadId;
 use codex_protocol::models::ResponseItem;
 use codex_protocol::protocol::GroupChatMessageEvent;
+use codex_protocol::protocol::GroupChatMessageId;
+use codex_protocol::protocol::GroupChatSender;
 
 use crate::codex::SessionConfiguration;
+use crate::config::types::GroupChatEvictionPolicy;
 use crate::context_manager::ContextManager;
 use crate::protocol::RateLimitSnapshot;
 use crate::protocol::TokenUsage;
 use crate::protocol::TokenUsageInfo;
 use crate::truncate::TruncationPolicy;
 use std::collections::HashMap;
+use tokio::sync::broadcast;
 
-const MAX_GROUP_CHAT_MESSAGES: usize = 500;
+pub(crate) const MAX_GROUP_CHAT_MESSAGES: usize = 500;
+pub(crate) const MAX_GROUP_CHAT_BYTES: usize = 512 * 1024;
+/// Messages buffered per group-chat feed subscriber before it observes `RecvError::Lagged`.
+const GROUP_CHAT_FEED_CAPACITY: usize = 256;
 
 #[derive(Debug, Clone)]
 pub(crate) struct GroupChatState {
-    entries: Vec<GroupChatMessageEvent>,
-    cursors: HashMap<ThreadId, usize>,
+    /// Retained messages in append order.
+    entries: Vec<GroupChatEntry>,
+    /// Ordinal of the last entry delivered to each subagent. Ordinals rather than indices keep
+    /// cursors valid across eviction, including selective eviction that leaves older pinned
+    /// entries behind.
+    cursors: HashMap<ThreadId, u64>,
+    max_messages: usize,
+    max_bytes: usize,
+    eviction_policy: GroupChatEvictionPolicy,
+    /// Cumulative size of the text of all retained entries.
+    total_bytes: usize,
+    /// Ordinal assigned to the next appended entry.
+    next_ordinal: u64,
+    /// Next sequence number per sender key. Ids below the high-water mark have
+    /// already been appended, which keeps rehydration from a rollout idempotent.
+    next_seq: HashMap<String, u64>,
+    /// Live feed of every newly appended message, independent of the session's event channel.
+    feed: broadcast::Sender<GroupChatMessageEvent>,
+}
+
+#[derive(Debug, Clone)]
+struct GroupChatEntry {
+    /// Position in the order messages were appended to this chat.
+    ordinal: u64,
+    /// Retained over unpinned entries under [`GroupChatEvictionPolicy::RetainPinned`].
+    pinned: bool,
+    message: GroupChatMessageEvent,
 }
 
 impl GroupChatState {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(max_messages: usize, max_bytes: usize) -> Self {
         Self {
             entries: Vec::new(),
             cursors: HashMap::new(),
+            max_messages,
+            max_bytes,
+            eviction_policy: GroupChatEvictionPolicy::default(),
+            total_bytes: 0,
+            next_ordinal: 0,
+            next_seq: HashMap::new(),
+            feed: broadcast::channel(GROUP_CHAT_FEED_CAPACITY).0,
         }
     }
 
-    pub(crate) fn append(&mut self, message: GroupChatMessageEvent) -> usize {
-        self.entries.push(message);
-        if self.entries.len() > MAX_GROUP_CHAT_MESSAGES {
-            let overflow = self.entries.len().saturating_sub(MAX_GROUP_CHAT_MESSAGES);
-            self.entries.drain(..overflow);
-            for cursor in self.cursors.values_mut() {
-                *cursor = cursor.saturating_sub(overflow);
+    pub(crate) fn with_eviction_policy(mut self, eviction_policy: GroupChatEvictionPolicy) -> Self {
+        self.eviction_policy = eviction_policy;
+        self
+    }
+
+    /// Sender behind the live feed; call `subscribe` on it to receive messages appended from
+    /// then on.
+    pub(crate) fn feed(&self) -> broadcast::Sender<GroupChatMessageEvent> {
+        self.feed.clone()
+    }
+
+    /// Appends `message`, assigning it a stable id when it does not carry one yet. Messages from
+    /// the user and the team lead are pinned. Returns the stored message, or `None` when its id
+    /// was already appended.
+    pub(crate) fn append(
+        &mut self,
+        mut message: GroupChatMessageEvent,
+    ) -> Option<GroupChatMessageEvent> {
+        match message.id.as_ref() {
+            Some(id) => {
+                let next = self.next_seq.entry(id.sender.clone()).or_insert(0);
+                if id.seq < *next {
+                    return None;
+                }
+                *next = id.seq.saturating_add(1);
+            }
+            None => {
+                let sender = group_chat_sender_key(&message.sender);
+                let next = self.next_seq.entry(sender.clone()).or_insert(0);
+                message.id = Some(GroupChatMessageId { sender, seq: *next });
+                *next = next.saturating_add(1);
             }
         }
-        self.entries.len()
+        self.total_bytes = self.total_bytes.saturating_add(message.text.len());
+        self.entries.push(GroupChatEntry {
+            ordinal: self.next_ordinal,
+            pinned: matches!(
+                message.sender,
+                GroupChatSender::Human | GroupChatSender::TeamLead
+            ),
+            message: message.clone(),
+        });
+        self.next_ordinal = self.next_ordinal.saturating_add(1);
+        self.evict_overflow();
+        // No subscribers is the common case; the send error only means nobody is listening.
+        let _ = self.feed.send(message.clone());
+        Some(message)
     }
 
+    /// Evict entries chosen by the eviction policy until both the message-count and byte budgets
+    /// hold. The newest entry is always retained, even when it alone exceeds the byte budget.
+    fn evict_overflow(&mut self) {
+        while self.entries.len() > 1
+            && (self.entries.len() > self.max_messages || self.total_bytes > self.max_bytes)
+        {
+            let victim = match self.eviction_policy {
+                GroupChatEvictionPolicy::Fifo => 0,
+                GroupChatEvictionPolicy::RetainPinned => self.entries[..self.entries.len() - 1]
+                    .iter()
+                    .position(|entry| !entry.pinned)
+                    .unwrap_or(0),
+            };
+            let evicted = self.entries.remove(victim);
+            self.total_bytes = self.total_bytes.saturating_sub(evicted.message.text.len());
+        }
+    }
+
+    /// Index of the first entry `subagent_id` has not been delivered: every retained entry
+    /// appended after its cursor.
+    fn unread_start(&self, subagent_id: ThreadId) -> usize {
+        self.cursors.get(&subagent_id).map_or(0, |cursor| {
+            self.entries
+                .partition_point(|entry| entry.ordinal <= *cursor)
+        })
+    }
+
+    /// Messages not yet delivered to `subagent_id`, plus the cursor to pass to
+    /// [`GroupChatState::mark_read`] once they are delivered.
     pub(crate) fn unread_messages(
         &self,
         subagent_id: ThreadId,
-    ) -> (usize, Vec<GroupChatMessageEvent>) {
-        let start = self.cursors.get(&subagent_id).copied().unwrap_or(0);
-        let start = start.min(self.entries.len());
-        let messages = self.entries[start..].to_vec();
-        (self.entries.len(), messages)
+    ) -> (Option<GroupChatMessageId>, Vec<GroupChatMessageEvent>) {
+        let start = self.unread_start(subagent_id);
+        let cursor = self
+            .entries
+            .last()
+            .and_then(|entry| entry.message.id.clone());
+        (cursor, messages(&self.entries[start..]))
+    }
+
+    /// Number of messages [`GroupChatState::unread_messages`] would deliver to `subagent_id`.
+    pub(crate) fn unread_count(&self, subagent_id: ThreadId) -> usize {
+        self.entries.len() - self.unread_start(subagent_id)
+    }
+
+    /// The last `limit` messages `subagent_id` has already been delivered, oldest first.
+    pub(crate) fn read_tail(
+        &self,
+        subagent_id: ThreadId,
+        limit: usize,
+    ) -> Vec<GroupChatMessageEvent> {
+        let end = self.unread_start(subagent_id);
+        let start = end.saturating_sub(limit);
+        messages(&self.entries[start..end])
+    }
+
+    /// Every retained message, oldest first. Each keeps the sender snapshot taken when it was
+    /// posted, so attribution outlives the subagent that wrote it.
+    pub(crate) fn history(&self) -> Vec<GroupChatMessageEvent> {
+        messages(&self.entries)
     }
 
-    pub(crate) fn mark_read(&mut self, subagent_id: ThreadId, cursor: usize) {
-        self.cursors.insert(subagent_id, cursor);
+    /// Record that `subagent_id` has been delivered every message up to `cursor`. A cursor whose
+    /// message is no longer retained leaves every retained message unread.
+    pub(crate) fn mark_read(&mut self, subagent_id: ThreadId, cursor: GroupChatMessageId) {
+        match self
+            .entries
+            .iter()
+            .find(|entry| entry.message.id.as_ref() == Some(&cursor))
+        {
+            Some(entry) => {
+                self.cursors.insert(subagent_id, entry.ordinal);
+            }
+            None => {
+                self.cursors.remove(&subagent_id);
+            }
+        }
+    }
+}
+
+fn messages(entries: &[GroupChatEntry]) -> Vec<GroupChatMessageEvent> {
+    entries.iter().map(|entry| entry.message.clone()).collect()
+}
+
+fn group_chat_sender_key(sender: &GroupChatSender) -> String {
+    match sender {
+        GroupChatSender::Human => "human".to_string(),
+        GroupChatSender::TeamLead => "team_lead".to_string(),
+        GroupChatSender::SubAgent { id, .. } => format!("subagent:{id}"),
     }
 }
 
@@ -67,11 +221,16 @@ impl SessionState {
     /// Create a new session state mirroring previous `State::default()` semantics.
     pub(crate) fn new(session_configuration: SessionConfiguration) -> Self {
         let history = ContextManager::new();
+        let group_chat = GroupChatState::new(
+            session_configuration.collab_limits.max_group_chat_messages,
+            session_configuration.group_chat_max_bytes,
+        )
+        .with_eviction_policy(session_configuration.group_chat_eviction);
         Self {
             session_configuration,
             history,
             latest_rate_limits: None,
-            group_chat: GroupChatState::new(),
+            group_chat,
         }
     }
 
@@ -144,3 +303,267 @@ fn merge_rate_limit_fields(
     }
     snapshot
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn message(sender: GroupChatSender, text: &str) -> GroupChatMessageEvent {
+        GroupChatMessageEvent {
+            id: None,
+            sender,
+            text: text.to_string(),
+            display: true,
+        }
+    }
+
+    #[test]
+    fn append_assigns_monotonic_ids_per_sender() {
+        let mut chat = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        let first = chat
+            .append(message(GroupChatSender::Human, "one"))
+            .expect("first message appended");
+        let second = chat
+            .append(message(GroupChatSender::Human, "two"))
+            .expect("second message appended");
+        let lead = chat
+            .append(message(GroupChatSender::TeamLead, "three"))
+            .expect("lead message appended");
+
+        assert_eq!(
+            first.id,
+            Some(GroupChatMessageId {
+                sender: "human".to_string(),
+                seq: 0,
+            })
+        );
+        assert_eq!(second.id.map(|id| id.seq), Some(1));
+        assert_eq!(lead.id.map(|id| id.seq), Some(0));
+    }
+
+    #[test]
+    fn append_feeds_new_messages_to_subscribers() {
+        let mut chat = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        let mut feed = chat.feed().subscribe();
+        let first = chat
+            .append(message(GroupChatSender::Human, "one"))
+            .expect("first message appended");
+        assert_eq!(chat.append(first.clone()), None);
+        let second = chat
+            .append(message(GroupChatSender::TeamLead, "two"))
+            .expect("second message appended");
+
+        assert_eq!(feed.try_recv().expect("first message fed"), first);
+        assert_eq!(feed.try_recv().expect("second message fed"), second);
+        assert!(feed.try_recv().is_err(), "duplicates are not fed again");
+    }
+
+    #[test]
+    fn read_tail_returns_messages_before_cursor() {
+        let mut chat = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        let ids = ["one", "two", "three", "four"]
+            .into_iter()
+            .filter_map(|text| chat.append(message(GroupChatSender::Human, text)))
+            .filter_map(|message| message.id)
+            .collect::<Vec<_>>();
+        let subagent_id = ThreadId::new();
+        chat.mark_read(subagent_id, ids[2].clone());
+
+        let texts = |messages: Vec<GroupChatMessageEvent>| {
+            messages
+                .into_iter()
+                .map(|message| message.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(chat.read_tail(subagent_id, 2)), vec!["two", "three"]);
+        assert_eq!(texts(chat.unread_messages(subagent_id).1), vec!["four"]);
+        assert_eq!(chat.unread_count(subagent_id), 1);
+        assert!(chat.read_tail(ThreadId::new(), 2).is_empty());
+        assert_eq!(chat.unread_count(ThreadId::new()), 4);
+    }
+
+    #[test]
+    fn huge_message_triggers_byte_based_eviction() {
+        let mut chat = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, 100);
+        let reader = ThreadId::new();
+        chat.append(message(GroupChatSender::Human, &"a".repeat(40)));
+        chat.append(message(GroupChatSender::TeamLead, &"b".repeat(40)));
+        let (cursor, _) = chat.unread_messages(reader);
+        chat.mark_read(reader, cursor.expect("chat has messages"));
+
+        let newest = chat
+            .append(message(GroupChatSender::Human, &"c".repeat(90)))
+            .expect("message appended");
+
+        let texts: Vec<String> = chat
+            .entries
+            .iter()
+            .map(|entry| entry.message.text.clone())
+            .collect();
+        assert_eq!(texts, vec!["c".repeat(90)]);
+        assert_eq!(chat.total_bytes, 90);
+        let (cursor, unread) = chat.unread_messages(reader);
+        assert_eq!(cursor, newest.id);
+        assert_eq!(unread.len(), 1);
+    }
+
+    #[test]
+    fn cursors_survive_eviction_and_restore() {
+        let mut original = GroupChatState::new(3, MAX_GROUP_CHAT_BYTES);
+        let reader = ThreadId::new();
+        let mut rollout = Vec::new();
+        for text in ["one", "two", "three"] {
+            rollout.extend(original.append(message(GroupChatSender::Human, text)));
+        }
+        let (cursor, _) = original.unread_messages(reader);
+        let cursor = cursor.expect("chat has messages");
+        original.mark_read(reader, cursor.clone());
+        for text in ["four", "five"] {
+            rollout.extend(original.append(message(GroupChatSender::TeamLead, text)));
+        }
+
+        // "one" and "two" were evicted; the cursor still points at "three".
+        let unread_texts = |chat: &GroupChatState| {
+            chat.unread_messages(reader)
+                .1
+                .into_iter()
+                .map(|message| message.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(unread_texts(&original), vec!["four", "five"]);
+
+        // Resume: replay persisted messages with the persisted cursor in rollout order.
+        let mut restored = GroupChatState::new(3, MAX_GROUP_CHAT_BYTES);
+        for message in rollout.iter().take(3).cloned() {
+            restored.append(message);
+        }
+        restored.mark_read(reader, cursor);
+        for message in rollout.iter().skip(3).cloned() {
+            restored.append(message);
+        }
+        assert_eq!(unread_texts(&restored), vec!["four", "five"]);
+
+        // Once the cursor's message is evicted too, everything retained is unread.
+        restored.append(message(GroupChatSender::Human, "six"));
+        assert_eq!(unread_texts(&restored), vec!["four", "five", "six"]);
+    }
+
+    #[test]
+    fn count_cap_still_applies_under_byte_budget() {
+        let mut chat = GroupChatState::new(2, MAX_GROUP_CHAT_BYTES);
+        for text in ["one", "two", "three"] {
+            chat.append(message(GroupChatSender::Human, text));
+        }
+
+        let texts: Vec<&str> = chat
+            .entries
+            .iter()
+            .map(|entry| entry.message.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["two", "three"]);
+        assert_eq!(chat.total_bytes, "twothree".len());
+    }
+
+    #[test]
+    fn retain_pinned_evicts_subagent_chatter_first() {
+        let mut chat = GroupChatState::new(3, MAX_GROUP_CHAT_BYTES)
+            .with_eviction_policy(GroupChatEvictionPolicy::RetainPinned);
+        let subagent = GroupChatSender::SubAgent {
+            id: ThreadId::new(),
+            persona: None,
+            display_name: None,
+        };
+        chat.append(message(GroupChatSender::TeamLead, "plan"));
+        chat.append(message(subagent.clone(), "chatter one"));
+        chat.append(message(GroupChatSender::Human, "constraint"));
+        chat.append(message(subagent.clone(), "chatter two"));
+        chat.append(message(subagent, "chatter three"));
+
+        let texts = |chat: &GroupChatState| {
+            chat.entries
+                .iter()
+                .map(|entry| entry.message.text.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(&chat), vec!["plan", "constraint", "chatter three"]);
+
+        chat.append(message(GroupChatSender::TeamLead, "update"));
+        assert_eq!(texts(&chat), vec!["plan", "constraint", "update"]);
+
+        // Once only pinned entries are left to evict, the oldest pinned one goes.
+        chat.append(message(GroupChatSender::Human, "final"));
+        assert_eq!(texts(&chat), vec!["constraint", "update", "final"]);
+    }
+
+    #[test]
+    fn cursors_stay_correct_under_selective_eviction() {
+        let mut chat = GroupChatState::new(3, MAX_GROUP_CHAT_BYTES)
+            .with_eviction_policy(GroupChatEvictionPolicy::RetainPinned);
+        let reader = ThreadId::new();
+        let subagent = GroupChatSender::SubAgent {
+            id: ThreadId::new(),
+            persona: None,
+            display_name: None,
+        };
+        chat.append(message(GroupChatSender::TeamLead, "plan"));
+        chat.append(message(subagent.clone(), "chatter one"));
+        chat.append(message(subagent.clone(), "chatter two"));
+        let (cursor, _) = chat.unread_messages(reader);
+        chat.mark_read(reader, cursor.expect("chat has messages"));
+
+        // Evicts "chatter one", then the cursor's own "chatter two", leaving the older pinned
+        // "plan" retained ahead of the new messages.
+        chat.append(message(subagent.clone(), "chatter three"));
+        chat.append(message(subagent, "chatter four"));
+
+        let texts = |messages: Vec<GroupChatMessageEvent>| {
+            messages
+                .into_iter()
+                .map(|message| message.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            texts(chat.unread_messages(reader).1),
+            vec!["chatter three", "chatter four"]
+        );
+        assert_eq!(chat.unread_count(reader), 2);
+        assert_eq!(texts(chat.read_tail(reader, 5)), vec!["plan"]);
+    }
+
+    #[test]
+    fn replaying_rollout_twice_is_idempotent() {
+        let mut original = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        let subagent = GroupChatSender::SubAgent {
+            id: ThreadId::new(),
+            persona: None,
+            display_name: Some("Builder".to_string()),
+        };
+        let rollout: Vec<GroupChatMessageEvent> = [
+            message(GroupChatSender::Human, "plan the work"),
+            message(subagent, "done"),
+            message(GroupChatSender::TeamLead, "thanks"),
+        ]
+        .into_iter()
+        .filter_map(|msg| original.append(msg))
+        .collect();
+
+        let mut once = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        for msg in rollout.iter().cloned() {
+            once.append(msg);
+        }
+        let mut twice = GroupChatState::new(MAX_GROUP_CHAT_MESSAGES, MAX_GROUP_CHAT_BYTES);
+        for msg in rollout.iter().chain(rollout.iter()).cloned() {
+            twice.append(msg);
+        }
+
+        assert_eq!(
+            serde_json::to_value(messages(&twice.entries)).unwrap(),
+            serde_json::to_value(messages(&once.entries)).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(messages(&once.entries)).unwrap(),
+            serde_json::to_value(&rollout).unwrap()
+        );
+    }
+}
@@ -4,6 +4,8 @@ use crate::CodexAuth;
 #[cfg(any(test, feature = "test-support"))]
 use crate::ModelProviderInfo;
 use crate::agent::AgentControl;
+use crate::agent::SubagentMetrics;
+use crate::agent::control::SubagentSpawn;
 use crate::codex::Codex;
 use crate::codex::CodexSpawnOk;
 use crate::codex::INITIAL_SUBMIT_ID;
@@ -11,25 +13,57 @@ use crate::codex_thread::CodexThread;
 use crate::config::Config;
 use crate::error::CodexErr;
 use crate::error::Result as CodexResult;
+use crate::error::SubagentLimitKind;
 use crate::models_manager::manager::ModelsManager;
 use crate::protocol::Event;
 use crate::protocol::EventMsg;
+use crate::protocol::SandboxPolicy;
 use crate::protocol::SessionConfiguredEvent;
 use crate::rollout::RolloutRecorder;
 use crate::rollout::truncation;
 use crate::skills::SkillsManager;
+use crate::state::MAX_GROUP_CHAT_MESSAGES;
 use codex_protocol::ThreadId;
 use codex_protocol::openai_models::ModelPreset;
+use codex_protocol::protocol::AgentStatus;
+use codex_protocol::protocol::FileChange;
+use codex_protocol::protocol::GroupChatMessageEvent;
+use codex_protocol::protocol::GroupChatSender;
 use codex_protocol::protocol::InitialHistory;
 use codex_protocol::protocol::Op;
+use codex_protocol::protocol::RateLimitSnapshot;
 use codex_protocol::protocol::RolloutItem;
+use codex_protocol::protocol::RolloutLine;
 use codex_protocol::protocol::SessionSource;
+use codex_protocol::protocol::SubagentLineage;
+use codex_protocol::protocol::TokenUsage;
+use codex_protocol::user_input::UserInput;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
 #[cfg(any(test, feature = "test-support"))]
 use tempfile::TempDir;
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
 use tokio::sync::RwLock;
+use tokio::sync::Semaphore;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 /// Represents a newly created Codex thread (formerly called a conversation), including the first event
 /// (which is [`EventMsg::SessionConfigured`]).
@@ -44,27 +78,627 @@ pub(crate) struct SubagentInfo {
     pub(crate) parent_id: ThreadId,
     pub(crate) persona: Option<String>,
     pub(crate) display_name: Option<String>,
+    /// Effective config the subagent was spawned with, reused by `clone_agent`.
+    pub(crate) spawn_config: Arc<Config>,
+    pub(crate) initial_prompt: String,
+    /// `SubAgentSource::Other` label the subagent was spawned with.
+    pub(crate) source_label: String,
+    /// Paths attached to the initial prompt, re-read by `clone_agent`.
+    pub(crate) attachment_paths: Vec<PathBuf>,
+    /// Scheduling hint; completed subagents with a lower priority may be evicted to make room.
+    pub(crate) priority: u8,
+    /// Tool events buffered for `agent_output` before the oldest are dropped.
+    pub(crate) max_tool_events: usize,
+    /// Input most recently sent to the subagent, resubmitted by `replay_agent`.
+    pub(crate) last_input: Vec<UserInput>,
+    /// Posts this subagent made to group chats in the current rate-limit window.
+    pub(crate) group_chat_posts: GroupChatPostWindow,
+    /// Tool events echoed to the parent's event stream in the current rate-limit window.
+    pub(crate) tool_echoes: GroupChatPostWindow,
+    /// Whether the drain posts the subagent's final messages to the parent's group chat. When
+    /// unset they are only recorded, for the parent to pull with `agent_output`.
+    pub(crate) auto_post: bool,
+    /// Labeled save points recorded by `checkpoint_agent`, oldest first.
+    pub(crate) checkpoints: VecDeque<SubagentCheckpoint>,
+    /// Host-supplied id tying the subagent to an upstream request, for cross-system tracing.
+    pub(crate) correlation_id: Option<String>,
+    /// JSON schema the subagent's final messages must follow, sent with every input.
+    pub(crate) output_schema: Option<serde_json::Value>,
+    /// Set by `pause_agent`: the drain stops reading events and new inputs are refused until the
+    /// subagent is resumed.
+    pub(crate) paused: bool,
 }
 
+/// A labeled position in a subagent's conversation that `restore_checkpoint` can fork from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SubagentCheckpoint {
+    /// Identifier unique among the subagent's checkpoints.
+    pub(crate) id: u64,
+    pub(crate) label: String,
+    /// User turns in the subagent's rollout when the checkpoint was taken.
+    pub(crate) user_turns: usize,
+}
+
+/// A subagent registration and its buffered output, as captured by
+/// [`ThreadManager::snapshot_subagents`] and injected by [`ThreadManager::seed_subagents`].
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeededSubagent {
+    pub id: ThreadId,
+    pub parent_id: ThreadId,
+    pub persona: Option<String>,
+    pub display_name: Option<String>,
+    pub initial_prompt: String,
+    pub partial: Option<String>,
+    pub last_message: Option<String>,
+    pub tool_events: Vec<String>,
+    pub token_usage: Option<TokenUsage>,
+}
+
+/// Outcome of counting a subagent group-chat post against its rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GroupChatPostAdmission {
+    /// The post may go out; `dropped` earlier posts were discarded since the last one allowed.
+    Allowed { dropped: u32 },
+    /// The sender exceeded its limit for the current window and the post must be dropped.
+    Limited,
+}
+
+/// Fixed one-second window used to rate limit a subagent's group-chat posts.
 #[derive(Debug, Clone, Default)]
+pub(crate) struct GroupChatPostWindow {
+    started: Option<Instant>,
+    posts: u32,
+    dropped: u32,
+}
+
+impl GroupChatPostWindow {
+    fn admit(&mut self, now: Instant, max_posts_per_second: u32) -> GroupChatPostAdmission {
+        if self
+            .started
+            .is_none_or(|started| now.duration_since(started) >= GROUP_CHAT_RATE_LIMIT_WINDOW)
+        {
+            self.started = Some(now);
+            self.posts = 0;
+        }
+        if self.posts >= max_posts_per_second {
+            self.dropped = self.dropped.saturating_add(1);
+            return GroupChatPostAdmission::Limited;
+        }
+        self.posts += 1;
+        GroupChatPostAdmission::Allowed {
+            dropped: std::mem::take(&mut self.dropped),
+        }
+    }
+
+    /// Let through a post that was counted as dropped when it was held back, returning how
+    /// many other posts were dropped since the last one allowed.
+    fn admit_held(&mut self) -> u32 {
+        std::mem::take(&mut self.dropped).saturating_sub(1)
+    }
+}
+
+#[derive(Debug, Clone)]
 struct SubagentOutput {
     partial: String,
     last_message: Option<String>,
-    reasoning: String,
+    /// Whether a completed message arrived in the current turn; `last_message` otherwise still
+    /// holds an earlier turn's reply, which the streamed text should replace.
+    turn_message: bool,
+    /// Reasoning summaries, suitable for showing to users.
+    reasoning_summary: String,
+    /// Raw chain-of-thought, which hosts may want to keep internal.
+    reasoning_raw: String,
     tool_events: Vec<String>,
+    /// Set once any buffered output was dropped to stay within the limits since the last prompt.
+    truncated: bool,
+    /// Cumulative usage from the subagent's latest `TokenCount` event; not reset between prompts.
+    token_usage: Option<TokenUsage>,
+    /// Rate limits from the subagent's latest `TokenCount` event; not reset between prompts.
+    rate_limits: Option<RateLimitSnapshot>,
+    /// Latest aggregated diff of the current turn, from `TurnDiff` events.
+    turn_diff: Option<String>,
+    /// Warnings and retried stream errors since the last prompt, oldest first.
+    warnings: Vec<String>,
+    /// Files changed by successfully applied patches since the last prompt, oldest first.
+    file_changes: Vec<SubagentFileChange>,
+    /// When the drain last observed output, a tool event, or a message, or the subagent last
+    /// received a prompt.
+    last_activity: Instant,
+    spawned_at: Instant,
+    turns: u32,
+    max_tool_events: usize,
+    max_output_chars: usize,
+    max_reasoning_chars: usize,
+    /// Reason the parent gave when closing the subagent; kept in its tombstone.
+    shutdown_reason: Option<String>,
+    /// Group-chat posts that could not reach the parent, oldest first; not reset between prompts.
+    undelivered_messages: Vec<String>,
+    updates: broadcast::Sender<SubagentOutputUpdate>,
+    /// Trim whole paragraphs and fenced code blocks instead of cutting through them.
+    preserve_code_blocks: bool,
+}
+
+/// Order in which subagent listings are returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubagentOrder {
+    /// Sorted by the string form of the thread id. Stable but unrelated to spawn time.
+    #[default]
+    Id,
+    /// Sorted by when each subagent was registered, oldest first.
+    SpawnOrder,
+}
+
+/// Rendering for [`ThreadManager::render_subagent_output`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubagentOutputFormat {
+    /// Sections for the message, reasoning summary, tools, and warnings under `##` headings.
+    #[default]
+    Markdown,
+    /// The same sections as markdown, without markup.
+    Text,
+    /// The structured snapshot as pretty-printed JSON.
+    Json,
+}
+
+/// Token usage observed from subagent drains.
+#[derive(Debug, Default)]
+struct SubagentTokenTally {
+    /// Latest cumulative total reported by each live subagent.
+    per_subagent: HashMap<ThreadId, i64>,
+    /// Tokens consumed by each parent's whole subagent tree, including removed subagents.
+    per_parent: HashMap<ThreadId, u64>,
+}
+
+/// A subagent spawn whose session is still starting. It holds one of its parent's subagent
+/// slots until the subagent is registered.
+#[derive(Debug)]
+struct PendingSpawn {
+    id: u64,
+    parent_id: ThreadId,
+    /// Caller-supplied token that `cancel_spawn` can name.
+    token: Option<String>,
+    cancel: CancellationToken,
+}
+
+/// Reservation for an in-flight subagent spawn; dropping it releases the reserved slot.
+pub(crate) struct PendingSpawnGuard {
+    pending: Arc<std::sync::Mutex<Vec<PendingSpawn>>>,
+    id: u64,
+    cancel: CancellationToken,
+}
+
+impl PendingSpawnGuard {
+    pub(crate) fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+}
+
+impl Drop for PendingSpawnGuard {
+    fn drop(&mut self) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|spawn| spawn.id != self.id);
+    }
 }
 
+/// Final output of a removed subagent, kept briefly so a late `agent_output` still sees it.
 #[derive(Debug, Clone)]
+struct SubagentTombstone {
+    parent_id: ThreadId,
+    output: SubagentOutputSnapshot,
+    removed_at: Instant,
+}
+
+/// Live update pushed to subscribers of a subagent's output as the drain records it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubagentOutputUpdate {
+    /// Incremental assistant text.
+    Delta(String),
+    /// A completed assistant message.
+    Message(String),
+}
+
+#[derive(Debug, Clone, Default)]
 pub(crate) struct SubagentOutputSnapshot {
     pub(crate) partial: Option<String>,
     pub(crate) last_message: Option<String>,
-    pub(crate) reasoning: Option<String>,
+    pub(crate) reasoning_summary: Option<String>,
+    pub(crate) reasoning_raw: Option<String>,
     pub(crate) tool_events: Vec<String>,
+    /// Whether older output was dropped from the buffers since the last prompt.
+    pub(crate) truncated: bool,
+    pub(crate) token_usage: Option<TokenUsage>,
+    pub(crate) rate_limits: Option<RateLimitSnapshot>,
+    pub(crate) turn_diff: Option<String>,
+    pub(crate) warnings: Vec<String>,
+    pub(crate) file_changes: Vec<SubagentFileChange>,
+    pub(crate) last_activity: Option<Instant>,
+    /// When the subagent was registered.
+    pub(crate) spawned_at: Option<Instant>,
+    /// Turns the subagent has started since it was spawned.
+    pub(crate) turns: u32,
+    /// Reason the parent gave when closing the subagent.
+    pub(crate) shutdown_reason: Option<String>,
+    /// Group-chat posts that could not reach the parent, oldest first.
+    pub(crate) undelivered_messages: Vec<String>,
+}
+
+/// The host-facing fields of a [`SubagentOutputSnapshot`], as rendered in JSON.
+#[derive(Serialize)]
+struct SubagentOutputView<'a> {
+    partial: &'a Option<String>,
+    last_message: &'a Option<String>,
+    reasoning_summary: &'a Option<String>,
+    reasoning_raw: &'a Option<String>,
+    tool_events: &'a [String],
+    warnings: &'a [String],
+    undelivered_messages: &'a [String],
+    truncated: bool,
+    turns: u32,
+    token_usage: &'a Option<TokenUsage>,
+    rate_limits: &'a Option<RateLimitSnapshot>,
+    turn_diff: &'a Option<String>,
+    file_changes: &'a [SubagentFileChange],
+    shutdown_reason: &'a Option<String>,
+}
+
+impl<'a> From<&'a SubagentOutputSnapshot> for SubagentOutputView<'a> {
+    fn from(snapshot: &'a SubagentOutputSnapshot) -> Self {
+        // Destructure exhaustively so a new snapshot field has to be placed in or out of the view.
+        let SubagentOutputSnapshot {
+            partial,
+            last_message,
+            reasoning_summary,
+            reasoning_raw,
+            tool_events,
+            truncated,
+            token_usage,
+            rate_limits,
+            turn_diff,
+            warnings,
+            file_changes,
+            last_activity: _,
+            spawned_at: _,
+            turns,
+            shutdown_reason,
+            undelivered_messages,
+        } = snapshot;
+        Self {
+            partial,
+            last_message,
+            reasoning_summary,
+            reasoning_raw,
+            tool_events,
+            warnings,
+            undelivered_messages,
+            truncated: *truncated,
+            turns: *turns,
+            token_usage,
+            rate_limits,
+            turn_diff,
+            file_changes,
+            shutdown_reason,
+        }
+    }
 }
 
+/// One file touched by a patch a subagent applied.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SubagentFileChange {
+    pub(crate) path: PathBuf,
+    #[serde(flatten)]
+    pub(crate) change: FileChange,
+}
+
+/// Default for [`CollabLimits::max_subagent_output_chars`].
 const MAX_SUBAGENT_OUTPUT_CHARS: usize = 8000;
+/// Default for [`CollabLimits::max_subagent_reasoning_chars`].
 const MAX_SUBAGENT_REASONING_CHARS: usize = 8000;
-const MAX_SUBAGENT_TOOL_EVENTS: usize = 200;
+/// Default for [`CollabLimits::max_subagent_tool_events`].
+pub(crate) const MAX_SUBAGENT_TOOL_EVENTS: usize = 200;
+/// Upper bound for a per-spawn `max_tool_events` override.
+pub(crate) const MAX_SUBAGENT_TOOL_EVENTS_LIMIT: usize = 2_000;
+const MAX_SUBAGENT_FILE_CHANGES: usize = 100;
+const MAX_SUBAGENT_TURN_DIFF_CHARS: usize = 32_000;
+const MAX_SUBAGENT_WARNINGS: usize = 20;
+const MAX_SUBAGENT_UNDELIVERED_MESSAGES: usize = 20;
+/// Default for [`CollabLimits::max_subagents_per_parent`].
+const MAX_SUBAGENTS_PER_PARENT: usize = 16;
+/// Shared variables a single subagent tree's store may hold.
+pub(crate) const MAX_SHARED_VARIABLES: usize = 32;
+/// Longest shared variable name, in characters.
+pub(crate) const MAX_SHARED_KEY_CHARS: usize = 64;
+/// Longest shared variable value, in characters.
+pub(crate) const MAX_SHARED_VALUE_CHARS: usize = 1_000;
+/// Total characters of context a single parent may pin into its subagents' first prompts.
+pub(crate) const MAX_PINNED_CONTEXT_CHARS: usize = 16_000;
+/// Total characters a single parent's scratchpad keeps before its oldest entries are dropped.
+pub(crate) const MAX_SCRATCHPAD_CHARS: usize = 64_000;
+/// Checkpoints kept per subagent before the oldest is dropped.
+pub(crate) const MAX_SUBAGENT_CHECKPOINTS: usize = 8;
+/// Default for [`CollabLimits::default_wait_timeout_ms`].
+pub(crate) const DEFAULT_WAIT_TIMEOUT_MS: u64 = 30_000;
+/// Default for [`CollabLimits::max_wait_timeout_ms`].
+pub(crate) const DEFAULT_MAX_WAIT_TIMEOUT_MS: u64 = 300_000;
+/// How long orchestration state operations wait for a contended lock unless the host configures
+/// another timeout.
+pub(crate) const DEFAULT_STATE_LOCK_TIMEOUT_MS: u64 = 30_000;
+/// Group-chat posts a single subagent may make per second unless the host configures another
+/// limit.
+pub(crate) const DEFAULT_SUBAGENT_GROUP_CHAT_POSTS_PER_SECOND: u32 = 5;
+/// Shortest interval between possible-deadlock checks, however small the threshold.
+const MIN_DEADLOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the drain watchdog looks for subagent drains that stopped early.
+const DRAIN_WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+/// Shortest interval between completed-subagent reaper passes, however small the TTL.
+const MIN_SUBAGENT_REAPER_INTERVAL: Duration = Duration::from_secs(1);
+/// Subagent sessions that may be set up at the same time unless the host configures another
+/// limit.
+const DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS: usize = 4;
+/// Characters of a completion message kept in subagent summaries unless the host configures
+/// another length.
+pub(crate) const DEFAULT_SUBAGENT_STATUS_PREVIEW_CHARS: usize = 200;
+const GROUP_CHAT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+/// Updates buffered per subscriber before it starts observing `RecvError::Lagged`.
+const SUBAGENT_OUTPUT_CHANNEL_CAPACITY: usize = 256;
+/// Deepest level of descendants included when aggregating nested subagent output.
+pub(crate) const MAX_SUBAGENT_OUTPUT_TREE_DEPTH: usize = 3;
+/// Maximum number of descendants included when aggregating nested subagent output.
+pub(crate) const MAX_SUBAGENT_OUTPUT_TREE_NODES: usize = 32;
+/// Deepest level of descendants included in `get_agent_tree`.
+pub(crate) const MAX_SUBAGENT_TREE_DEPTH: usize = 8;
+/// Maximum number of descendants included in `get_agent_tree`.
+pub(crate) const MAX_SUBAGENT_TREE_NODES: usize = 256;
+/// How long a removed subagent's final output stays readable via `agent_output`.
+const SUBAGENT_TOMBSTONE_TTL: Duration = Duration::from_secs(300);
+/// Upper bound on retained tombstones; the oldest are evicted first.
+const MAX_SUBAGENT_TOMBSTONES: usize = 64;
+
+/// Rewrites a subagent's message before it is posted to its parent's group chat. Returning `None`
+/// suppresses the post.
+pub type SubagentMessageTransform =
+    Arc<dyn Fn(&GroupChatSender, &str) -> Option<String> + Send + Sync>;
+
+/// Host-level settings for [`ThreadManager`] that do not belong in per-thread [`Config`].
+#[derive(Clone, Default)]
+pub struct ThreadManagerOptions {
+    /// Receives subagent lifecycle callbacks (spawns, status transitions, removals).
+    pub subagent_metrics: Option<Arc<dyn SubagentMetrics>>,
+    /// Text placed before the generated subagent instructions on every spawn.
+    pub subagent_prompt_prefix: Option<String>,
+    /// Text placed after the generated subagent instructions on every spawn.
+    pub subagent_prompt_suffix: Option<String>,
+    /// Total tokens a parent's subagent tree may consume before new spawns and inputs are
+    /// refused.
+    pub subagent_token_budget: Option<u64>,
+    /// Timeouts and buffer sizes of the collab tools.
+    pub collab_limits: CollabLimits,
+    /// Keep a thread's subagents running when the host removes the thread or a parent closes
+    /// it. By default those shutdowns take the whole subagent tree down with the thread so
+    /// orchestrators do not leave orphans behind. Eviction, reaping, and restarts never touch
+    /// the subtree.
+    pub keep_orphaned_subagents: bool,
+    /// Group-chat posts each subagent may make per second; excess posts are dropped and noted
+    /// on the sender's next accepted post, except the message that ends a turn, which is always
+    /// posted. Defaults to 5.
+    pub subagent_group_chat_posts_per_second: Option<u32>,
+    /// Trim buffered subagent output by characters only, even through fenced code blocks. By
+    /// default trimming drops whole prose paragraphs first and keeps complete code blocks where
+    /// they fit.
+    pub char_trim_subagent_output: bool,
+    /// Applied to each subagent message before it is posted to the parent's group chat, for
+    /// example to redact secrets or add attribution. Posts pass through unchanged when unset.
+    pub subagent_message_transform: Option<SubagentMessageTransform>,
+    /// Subagent sessions that may be set up at once; further spawns queue until one finishes
+    /// starting. Inputs to running subagents are never throttled. Defaults to 4.
+    pub max_concurrent_subagent_spawns: Option<usize>,
+    /// Milliseconds every mid-turn subagent of a parent must go without activity, with no
+    /// unread group chat messages, before the parent is warned that they may be blocked on each
+    /// other. Subagents that finished their turn are not counted. Disabled when unset or zero.
+    pub subagent_deadlock_threshold_ms: Option<u64>,
+    /// Characters of a completed subagent's final message shown in `list_agents`; the full
+    /// message stays available through `agent_output`. Defaults to 200.
+    pub subagent_status_preview_chars: Option<usize>,
+    /// Restart a subagent's event drain up to this many times when it stops while the subagent
+    /// is still registered and not shut down, so its output keeps being recorded. Disabled when
+    /// unset or zero.
+    pub max_subagent_drain_restarts: Option<u32>,
+    /// Echo each subagent's tool events (exec, patches, MCP calls, web searches) into its
+    /// parent's event stream as `SubagentToolActivity` events, at most this many per subagent
+    /// per second; excess events are not echoed but stay in `agent_output`. Disabled when unset
+    /// or zero.
+    pub subagent_tool_echoes_per_second: Option<u32>,
+    /// Milliseconds thread and subagent bookkeeping operations (looking up a thread, reserving a
+    /// spawn slot, moving a subagent, shared variables, pinned context) wait for a contended
+    /// lock before failing with [`CodexErr::StateLockTimeout`] instead of hanging the caller's
+    /// turn. Defaults to 30 seconds.
+    pub state_lock_timeout_ms: Option<u64>,
+    /// Forget subagents that have sat completed, errored, or shut down for this many
+    /// milliseconds, keeping their final output as a tombstone. Subagents that still have live
+    /// subagents of their own are kept until those are reaped. Disabled when unset or zero.
+    pub completed_subagent_ttl_ms: Option<u64>,
+}
+
+/// Timeouts, buffer sizes, and the subagent cap of the collab tools, tunable by hosts in one
+/// place. The defaults match the limits the tools have always used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollabLimits {
+    /// Milliseconds `wait` and `close_agent` block for when the call names no timeout.
+    pub default_wait_timeout_ms: u64,
+    /// Longest timeout, in milliseconds, that `wait` and `close_agent` may block for.
+    pub max_wait_timeout_ms: u64,
+    /// Characters of streamed, not yet completed output buffered per subagent.
+    pub max_subagent_output_chars: usize,
+    /// Characters buffered per subagent for each of its reasoning summary and raw reasoning.
+    pub max_subagent_reasoning_chars: usize,
+    /// Tool events buffered per subagent unless its spawn sets `max_tool_events`.
+    pub max_subagent_tool_events: usize,
+    /// Messages each session's group chat keeps before evicting older ones.
+    pub max_group_chat_messages: usize,
+    /// Subagents a single parent may own at once, spawns still starting included.
+    pub max_subagents_per_parent: usize,
+}
+
+impl Default for CollabLimits {
+    fn default() -> Self {
+        Self {
+            default_wait_timeout_ms: DEFAULT_WAIT_TIMEOUT_MS,
+            max_wait_timeout_ms: DEFAULT_MAX_WAIT_TIMEOUT_MS,
+            max_subagent_output_chars: MAX_SUBAGENT_OUTPUT_CHARS,
+            max_subagent_reasoning_chars: MAX_SUBAGENT_REASONING_CHARS,
+            max_subagent_tool_events: MAX_SUBAGENT_TOOL_EVENTS,
+            max_group_chat_messages: MAX_GROUP_CHAT_MESSAGES,
+            max_subagents_per_parent: MAX_SUBAGENTS_PER_PARENT,
+        }
+    }
+}
+
+impl CollabLimits {
+    /// Reject limits that would make the collab tools unusable: a group chat that keeps no
+    /// messages, waits that cannot block at all, or parents that may own no subagents.
+    pub fn validate(&self) -> CodexResult<()> {
+        if self.max_group_chat_messages == 0 {
+            return Err(CodexErr::InvalidRequest(
+                "collab_limits.max_group_chat_messages must be greater than zero".to_string(),
+            ));
+        }
+        if self.max_wait_timeout_ms == 0 {
+            return Err(CodexErr::InvalidRequest(
+                "collab_limits.max_wait_timeout_ms must be greater than zero".to_string(),
+            ));
+        }
+        if self.max_subagents_per_parent == 0 {
+            return Err(CodexErr::InvalidRequest(
+                "collab_limits.max_subagents_per_parent must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How long [`ThreadManager::shutdown_all`] sleeps between status checks on threads without a
+/// drain task.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long [`ThreadManager::reconfigure_thread`] waits for a restarted thread's original to shut
+/// down.
+const RECONFIGURE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of [`ThreadManager::shutdown_all`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownAllSummary {
+    /// Threads that finished shutting down (or had already stopped) before the timeout.
+    pub clean: usize,
+    /// Threads still running when the timeout elapsed; their drain tasks were aborted.
+    pub timed_out: usize,
+}
+
+/// Current load against the subagent limits, as reported by [`ThreadManager::capacity_snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacitySnapshot {
+    /// Managed threads, subagents included.
+    pub threads: usize,
+    /// Registered subagents across all parents.
+    pub subagents: usize,
+    /// Subagent spawns whose sessions are still starting; each holds a slot of its parent.
+    pub pending_spawns: usize,
+    /// Live subagents a single parent may own at once.
+    pub max_subagents_per_parent: usize,
+    /// Subagent sessions that may be set up at once.
+    pub max_concurrent_subagent_spawns: usize,
+    /// Session setups that could start right now without queueing.
+    pub available_spawn_permits: usize,
+}
+
+/// How [`ThreadManager::reconfigure_thread`] applied a new config.
+pub enum ReconfigureOutcome {
+    /// Only hot-swappable settings were applied, to the live thread; its id is unchanged.
+    HotApplied,
+    /// The thread was forked under the new config and the original shut down and removed.
+    Restarted(NewThread),
+}
+
+/// Sequence behind [`ThreadManager::seed_subagent_ids`].
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Debug, Clone, Copy)]
+struct SeededThreadIds {
+    seed: u32,
+    next: u64,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl SeededThreadIds {
+    fn next_id(&mut self) -> Option<ThreadId> {
+        let index = self.next & 0xffff_ffff_ffff;
+        self.next += 1;
+        ThreadId::from_string(&format!("{:08x}-0000-7000-8000-{index:012x}", self.seed)).ok()
+    }
+}
+
+/// A parent's pinned context after a `pin_context` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PinnedContextUsage {
+    /// Pinned items, the new one included.
+    pub(crate) items: usize,
+    /// Characters across all pinned items.
+    pub(crate) chars: usize,
+}
+
+/// Optional settings for a subagent spawned through [`ThreadManager::spawn_subagent`] and its
+/// variants.
+#[derive(Debug, Clone, Default)]
+pub struct SubagentSpawnOptions {
+    /// Persona added to the subagent's instructions.
+    pub persona: Option<String>,
+    pub display_name: Option<String>,
+    /// Host-supplied id tying the subagent to an upstream request, for cross-system tracing.
+    pub correlation_id: Option<String>,
+}
+
+/// A subagent whose initial prompt is sent in the background, as returned by
+/// [`ThreadManager::spawn_subagent_async`].
+#[derive(Debug)]
+pub struct SpawnedSubagent {
+    pub thread_id: ThreadId,
+    ready: JoinHandle<CodexResult<()>>,
+}
+
+impl SpawnedSubagent {
+    pub(crate) fn new(thread_id: ThreadId, ready: JoinHandle<CodexResult<()>>) -> Self {
+        Self { thread_id, ready }
+    }
+
+    /// Wait until the initial prompt has been submitted. When that failed, the error is returned
+    /// here and the subagent's status is `Errored`.
+    pub async fn ready(self) -> CodexResult<()> {
+        self.ready.await?
+    }
+}
+
+/// A scratchpad after a `scratchpad_append` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ScratchpadUsage {
+    /// Entries kept, the new one included.
+    pub(crate) entries: usize,
+    /// Characters across the kept entries.
+    pub(crate) chars: usize,
+    /// Oldest entries dropped to stay within [`MAX_SCRATCHPAD_CHARS`].
+    pub(crate) trimmed_entries: usize,
+}
+
+/// Subagent slots a parent currently holds, as counted against
+/// [`CollabLimits::max_subagents_per_parent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SubagentSlots {
+    /// Registered subagents.
+    pub(crate) active: usize,
+    /// Spawns whose sessions are still starting.
+    pub(crate) starting: usize,
+}
 
 /// [`ThreadManager`] is responsible for creating threads and maintaining
 /// them in memory.
@@ -81,10 +715,46 @@ pub(crate) struct ThreadManagerState {
     threads: Arc<RwLock<HashMap<ThreadId, Arc<CodexThread>>>>,
     subagents: Arc<RwLock<HashMap<ThreadId, SubagentInfo>>>,
     subagent_outputs: Arc<RwLock<HashMap<ThreadId, SubagentOutput>>>,
+    /// Headless drain tasks, so callers can wait for a subagent to quiesce before removal.
+    subagent_drains: Arc<RwLock<HashMap<ThreadId, JoinHandle<()>>>>,
+    subagent_tombstones: Arc<RwLock<HashMap<ThreadId, SubagentTombstone>>>,
+    subagent_tokens: Arc<RwLock<SubagentTokenTally>>,
+    /// Registration sequence number per subagent, used for spawn-order listings.
+    subagent_spawn_seq: Arc<RwLock<HashMap<ThreadId, u64>>>,
+    next_subagent_seq: AtomicU64,
+    /// Per-agent locks that keep inputs sent to the same agent in call order.
+    input_locks: Arc<RwLock<HashMap<ThreadId, Arc<Mutex<()>>>>>,
+    /// Permits for in-flight subagent session setups, so batch spawns queue instead of
+    /// starting every session against the provider at once.
+    subagent_spawn_permits: Arc<Semaphore>,
+    /// Times each subagent's drain was restarted after stopping early.
+    drain_restarts: Arc<RwLock<HashMap<ThreadId, u32>>>,
+    drain_watchdog_running: AtomicBool,
+    subagent_reaper_running: AtomicBool,
+    /// Parents with a running possible-deadlock watchdog.
+    deadlock_watchdogs: Arc<RwLock<HashSet<ThreadId>>>,
+    /// Subagent spawns whose sessions are still starting. A std mutex so guards can release
+    /// their entry on drop.
+    pending_spawns: Arc<std::sync::Mutex<Vec<PendingSpawn>>>,
+    next_pending_spawn_id: AtomicU64,
+    /// Key-value store per top-level thread, shared by every agent in its subagent tree at any
+    /// depth; see `shared_scope`.
+    shared_variables: Arc<RwLock<HashMap<ThreadId, BTreeMap<String, String>>>>,
+    /// Context items per parent, injected ahead of the first prompt of every subagent it spawns.
+    pinned_context: Arc<RwLock<HashMap<ThreadId, Vec<String>>>>,
+    /// Append-only notes per top-level thread, oldest first, shared by its whole subagent tree
+    /// like `shared_variables`.
+    scratchpads: Arc<RwLock<HashMap<ThreadId, VecDeque<String>>>>,
+    /// Woken whenever a subagent is resumed or forgotten, so paused drains recheck their flag.
+    subagent_resumed: Notify,
+    /// Predictable ids for new subagents once a test calls `seed_subagent_ids`.
+    #[cfg(any(test, feature = "test-support"))]
+    subagent_id_seed: std::sync::Mutex<Option<SeededThreadIds>>,
     auth_manager: Arc<AuthManager>,
     models_manager: Arc<ModelsManager>,
     skills_manager: Arc<SkillsManager>,
     session_source: SessionSource,
+    options: ThreadManagerOptions,
 }
 
 impl ThreadManager {
@@ -93,11 +763,65 @@ impl ThreadManager {
         auth_manager: Arc<AuthManager>,
         session_source: SessionSource,
     ) -> Self {
+        Self::from_options(
+            codex_home,
+            auth_manager,
+            session_source,
+            ThreadManagerOptions::default(),
+        )
+    }
+
+    /// Like [`Self::new`] with host `options`. Fails when `options.collab_limits` does not pass
+    /// [`CollabLimits::validate`].
+    pub fn new_with_options(
+        codex_home: PathBuf,
+        auth_manager: Arc<AuthManager>,
+        session_source: SessionSource,
+        options: ThreadManagerOptions,
+    ) -> CodexResult<Self> {
+        options.collab_limits.validate()?;
+        Ok(Self::from_options(
+            codex_home,
+            auth_manager,
+            session_source,
+            options,
+        ))
+    }
+
+    fn from_options(
+        codex_home: PathBuf,
+        auth_manager: Arc<AuthManager>,
+        session_source: SessionSource,
+        options: ThreadManagerOptions,
+    ) -> Self {
+        let spawn_permits = options
+            .max_concurrent_subagent_spawns
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS)
+            .max(1);
         Self {
             state: Arc::new(ThreadManagerState {
                 threads: Arc::new(RwLock::new(HashMap::new())),
                 subagents: Arc::new(RwLock::new(HashMap::new())),
                 subagent_outputs: Arc::new(RwLock::new(HashMap::new())),
+                subagent_drains: Arc::new(RwLock::new(HashMap::new())),
+                subagent_tombstones: Arc::new(RwLock::new(HashMap::new())),
+                subagent_tokens: Arc::new(RwLock::new(SubagentTokenTally::default())),
+                subagent_spawn_seq: Arc::new(RwLock::new(HashMap::new())),
+                next_subagent_seq: AtomicU64::new(0),
+                input_locks: Arc::new(RwLock::new(HashMap::new())),
+                subagent_spawn_permits: Arc::new(Semaphore::new(spawn_permits)),
+                drain_restarts: Arc::new(RwLock::new(HashMap::new())),
+                drain_watchdog_running: AtomicBool::new(false),
+                subagent_reaper_running: AtomicBool::new(false),
+                deadlock_watchdogs: Arc::new(RwLock::new(HashSet::new())),
+                pending_spawns: Arc::new(std::sync::Mutex::new(Vec::new())),
+                next_pending_spawn_id: AtomicU64::new(0),
+                shared_variables: Arc::new(RwLock::new(HashMap::new())),
+                pinned_context: Arc::new(RwLock::new(HashMap::new())),
+                scratchpads: Arc::new(RwLock::new(HashMap::new())),
+                subagent_resumed: Notify::new(),
+                #[cfg(any(test, feature = "test-support"))]
+                subagent_id_seed: std::sync::Mutex::new(None),
                 models_manager: Arc::new(ModelsManager::new(
                     codex_home.clone(),
                     auth_manager.clone(),
@@ -105,6 +829,7 @@ impl ThreadManager {
                 skills_manager: Arc::new(SkillsManager::new(codex_home)),
                 auth_manager,
                 session_source,
+                options,
             }),
             #[cfg(any(test, feature = "test-support"))]
             _test_codex_home_guard: None,
@@ -136,6 +861,26 @@ impl ThreadManager {
                 threads: Arc::new(RwLock::new(HashMap::new())),
                 subagents: Arc::new(RwLock::new(HashMap::new())),
                 subagent_outputs: Arc::new(RwLock::new(HashMap::new())),
+                subagent_drains: Arc::new(RwLock::new(HashMap::new())),
+                subagent_tombstones: Arc::new(RwLock::new(HashMap::new())),
+                subagent_tokens: Arc::new(RwLock::new(SubagentTokenTally::default())),
+                subagent_spawn_seq: Arc::new(RwLock::new(HashMap::new())),
+                next_subagent_seq: AtomicU64::new(0),
+                input_locks: Arc::new(RwLock::new(HashMap::new())),
+                subagent_spawn_permits: Arc::new(Semaphore::new(
+                    DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS,
+                )),
+                drain_restarts: Arc::new(RwLock::new(HashMap::new())),
+                drain_watchdog_running: AtomicBool::new(false),
+                subagent_reaper_running: AtomicBool::new(false),
+                deadlock_watchdogs: Arc::new(RwLock::new(HashSet::new())),
+                pending_spawns: Arc::new(std::sync::Mutex::new(Vec::new())),
+                next_pending_spawn_id: AtomicU64::new(0),
+                shared_variables: Arc::new(RwLock::new(HashMap::new())),
+                pinned_context: Arc::new(RwLock::new(HashMap::new())),
+                scratchpads: Arc::new(RwLock::new(HashMap::new())),
+                #[cfg(any(test, feature = "test-support"))]
+                subagent_id_seed: std::sync::Mutex::new(None),
                 models_manager: Arc::new(ModelsManager::with_provider(
                     codex_home.clone(),
                     auth_manager.clone(),
@@ -144,11 +889,115 @@ impl ThreadManager {
                 skills_manager: Arc::new(SkillsManager::new(codex_home)),
                 auth_manager,
                 session_source: SessionSource::Exec,
+                options: ThreadManagerOptions::default(),
             }),
             _test_codex_home_guard: None,
         }
     }
 
+    #[cfg(any(test, feature = "test-support"))]
+    /// Give subagents spawned from now on predictable ids built from `seed` and their spawn
+    /// order, `{seed:08x}-0000-7000-8000-{index:012x}`, so tests can assert on `list_agents`
+    /// output and group-chat transcripts. Calling it again restarts the sequence. Production
+    /// builds always use random ids.
+    pub fn seed_subagent_ids(&self, seed: u32) {
+        *self
+            .state
+            .subagent_id_seed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(SeededThreadIds { seed, next: 0 });
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    /// Register `subagents` with canned output and no backing threads, so orchestration tools
+    /// can be exercised without a live provider. Seeded subagents report `NotFound` status.
+    /// `spawn_config` is recorded as every subagent's spawn config.
+    pub async fn seed_subagents(&self, subagents: Vec<SeededSubagent>, spawn_config: &Config) {
+        let spawn_config = Arc::new(spawn_config.clone());
+        for subagent in subagents {
+            let id = subagent.id;
+            if let Err(err) = self
+                .state
+                .register_subagent(
+                    id,
+                    SubagentInfo {
+                        parent_id: subagent.parent_id,
+                        persona: subagent.persona,
+                        display_name: subagent.display_name,
+                        spawn_config: Arc::clone(&spawn_config),
+                        initial_prompt: subagent.initial_prompt,
+                        source_label: "test".to_string(),
+                        attachment_paths: Vec::new(),
+                        priority: 0,
+                        max_tool_events: self.state.collab_limits().max_subagent_tool_events,
+                        last_input: Vec::new(),
+                        group_chat_posts: GroupChatPostWindow::default(),
+                        tool_echoes: GroupChatPostWindow::default(),
+                        auto_post: true,
+                        checkpoints: VecDeque::new(),
+                        correlation_id: None,
+                        output_schema: None,
+                        paused: false,
+                    },
+                )
+                .await
+            {
+                tracing::warn!("failed to seed subagent {id}: {err}");
+                continue;
+            }
+            if let Some(message) = subagent.last_message {
+                self.state.record_subagent_message(id, &message).await;
+            }
+            if let Some(partial) = subagent.partial {
+                self.state.record_subagent_delta(id, &partial).await;
+            }
+            for event in subagent.tool_events {
+                self.state.record_subagent_tool_event(id, event).await;
+            }
+            if let Some(usage) = subagent.token_usage {
+                self.state.record_subagent_token_usage(id, &usage).await;
+            }
+        }
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    /// Every registered subagent with its buffered output, in spawn order. Passing the result to
+    /// [`Self::seed_subagents`] on a fresh manager rebuilds the same graph.
+    pub async fn snapshot_subagents(&self) -> Vec<SeededSubagent> {
+        let mut subagents: Vec<(ThreadId, SubagentInfo)> = self
+            .state
+            .subagents
+            .read()
+            .await
+            .iter()
+            .map(|(id, info)| (*id, info.clone()))
+            .collect();
+        {
+            let spawn_seq = self.state.subagent_spawn_seq.read().await;
+            subagents.sort_by_key(|(id, _)| spawn_seq.get(id).copied().unwrap_or(u64::MAX));
+        }
+        let mut seeded = Vec::with_capacity(subagents.len());
+        for (id, info) in subagents {
+            let output = self
+                .state
+                .subagent_output_snapshot(id, None)
+                .await
+                .unwrap_or_default();
+            seeded.push(SeededSubagent {
+                id,
+                parent_id: info.parent_id,
+                persona: info.persona,
+                display_name: info.display_name,
+                initial_prompt: info.initial_prompt,
+                partial: output.partial,
+                last_message: output.last_message,
+                tool_events: output.tool_events,
+                token_usage: output.token_usage,
+            });
+        }
+        seeded
+    }
+
     pub fn session_source(&self) -> SessionSource {
         self.state.session_source.clone()
     }
@@ -165,11 +1014,59 @@ impl ThreadManager {
         self.state.models_manager.list_models(config).await
     }
 
+    /// Tail the events of any managed thread without becoming its primary consumer. See
+    /// [`CodexThread::subscribe_events`] for ordering and lag semantics.
+    pub async fn subscribe_events(
+        &self,
+        thread_id: ThreadId,
+    ) -> CodexResult<broadcast::Receiver<Event>> {
+        Ok(self.state.get_thread(thread_id).await?.subscribe_events())
+    }
+
     pub async fn list_thread_ids(&self) -> Vec<ThreadId> {
         self.state.threads.read().await.keys().copied().collect()
     }
 
-    pub async fn list_subagent_ids(&self, parent_id: ThreadId) -> Vec<ThreadId> {
+    /// Number of managed threads, subagents included, without enumerating them.
+    pub async fn thread_count(&self) -> usize {
+        self.state.threads.read().await.len()
+    }
+
+    /// Number of registered subagents across all parents.
+    pub async fn subagent_count(&self) -> usize {
+        self.state.subagents.read().await.len()
+    }
+
+    /// Thread and subagent counts alongside the configured subagent limits, for hosts deciding
+    /// whether to accept more work. Each count is taken under its own brief lock, so the
+    /// snapshot is not atomic across fields.
+    pub async fn capacity_snapshot(&self) -> CapacitySnapshot {
+        let pending_spawns = self
+            .state
+            .pending_spawns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len();
+        CapacitySnapshot {
+            threads: self.thread_count().await,
+            subagents: self.subagent_count().await,
+            pending_spawns,
+            max_subagents_per_parent: self.state.collab_limits().max_subagents_per_parent,
+            max_concurrent_subagent_spawns: self
+                .state
+                .options
+                .max_concurrent_subagent_spawns
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS)
+                .max(1),
+            available_spawn_permits: self.state.subagent_spawn_permits.available_permits(),
+        }
+    }
+
+    pub async fn list_subagent_ids(
+        &self,
+        parent_id: ThreadId,
+        order: SubagentOrder,
+    ) -> Vec<ThreadId> {
         let mut ids = self
             .state
             .subagents_for_parent(parent_id)
@@ -177,10 +1074,26 @@ impl ThreadManager {
             .into_iter()
             .map(|(id, _)| id)
             .collect::<Vec<_>>();
-        ids.sort_by_key(std::string::ToString::to_string);
+        if order == SubagentOrder::Id {
+            ids.sort_by_key(std::string::ToString::to_string);
+        }
         ids
     }
 
+    /// Subagents whose parent is no longer a managed thread, for example because it was removed
+    /// with `keep_orphaned_subagents` set. Sorted by the string form of the id.
+    pub async fn list_orphaned_subagents(&self) -> Vec<ThreadId> {
+        self.state.orphaned_subagents().await
+    }
+
+    /// Parent of `thread_id`, or `None` for top-level threads and unknown ids.
+    pub async fn parent_of(&self, thread_id: ThreadId) -> Option<ThreadId> {
+        self.state
+            .subagent_info(thread_id)
+            .await
+            .map(|info| info.parent_id)
+    }
+
     pub async fn subagent_persona(&self, subagent_id: ThreadId) -> Option<String> {
         self.state
             .subagent_info(subagent_id)
@@ -195,6 +1108,47 @@ impl ThreadManager {
             .and_then(|info| info.display_name)
     }
 
+    /// Output of a subagent below `parent_id`, rendered in `format`. Falls back to the final output
+    /// of a subagent removed within the tombstone TTL.
+    pub async fn render_subagent_output(
+        &self,
+        parent_id: ThreadId,
+        subagent_id: ThreadId,
+        format: SubagentOutputFormat,
+        max_chars: Option<usize>,
+    ) -> CodexResult<String> {
+        self.agent_control()
+            .subagent_output(parent_id, subagent_id, max_chars)
+            .await
+            .map(|output| output.render(format))
+    }
+
+    /// Subscribe to live output updates for a subagent of `parent_id`.
+    /// Receivers that fall more than a bounded number of updates behind observe
+    /// `RecvError::Lagged` instead of buffering without limit.
+    pub async fn subscribe_subagent_output(
+        &self,
+        parent_id: ThreadId,
+        subagent_id: ThreadId,
+    ) -> CodexResult<broadcast::Receiver<SubagentOutputUpdate>> {
+        self.agent_control()
+            .subscribe_subagent_output(parent_id, subagent_id)
+            .await
+    }
+
+    /// Subscribe to every message appended to the group chat of `parent_id`, such as for a
+    /// dedicated chat panel. The feed is separate from the thread's event stream.
+    pub async fn subscribe_group_chat(
+        &self,
+        parent_id: ThreadId,
+    ) -> CodexResult<broadcast::Receiver<GroupChatMessageEvent>> {
+        Ok(self
+            .state
+            .get_thread(parent_id)
+            .await?
+            .subscribe_group_chat())
+    }
+
     pub async fn get_thread(&self, thread_id: ThreadId) -> CodexResult<Arc<CodexThread>> {
         self.state.get_thread(thread_id).await
     }
@@ -214,19 +1168,87 @@ impl ThreadManager {
     pub async fn spawn_subagent(
         &self,
         parent_id: ThreadId,
-        mut config: Config,
+        config: Config,
+        prompt: String,
+        options: SubagentSpawnOptions,
+    ) -> CodexResult<ThreadId> {
+        self.spawn_subagent_with_history(parent_id, config, InitialHistory::New, prompt, options)
+            .await
+    }
+
+    /// Like [`Self::spawn_subagent`], but the subagent starts from `initial_history` instead of
+    /// cold, for example to hand a builder the planner's conversation. The parent's pinned
+    /// context is not prepended, since such a history is expected to carry what it needs.
+    /// A `Resumed` history is forked: the subagent gets its own id and rollout, and the thread
+    /// the history came from is left alone.
+    pub async fn spawn_subagent_with_history(
+        &self,
+        parent_id: ThreadId,
+        config: Config,
+        initial_history: InitialHistory,
         prompt: String,
-        persona: Option<String>,
-        display_name: Option<String>,
+        options: SubagentSpawnOptions,
     ) -> CodexResult<ThreadId> {
+        let spawn = self.subagent_spawn(parent_id, config, initial_history, prompt, options)?;
+        self.agent_control().spawn_agent(parent_id, spawn).await
+    }
+
+    /// Like [`Self::spawn_subagent`], but return once the subagent is registered while its
+    /// initial prompt is sent in the background. Dispatching many subagents this way avoids
+    /// waiting on each prompt in turn; await [`SpawnedSubagent::ready`] where the prompt must
+    /// have been submitted.
+    pub async fn spawn_subagent_async(
+        &self,
+        parent_id: ThreadId,
+        config: Config,
+        prompt: String,
+        options: SubagentSpawnOptions,
+    ) -> CodexResult<SpawnedSubagent> {
+        let spawn = self.subagent_spawn(parent_id, config, InitialHistory::New, prompt, options)?;
+        self.agent_control()
+            .spawn_agent_async(parent_id, spawn)
+            .await
+    }
+
+    fn subagent_spawn(
+        &self,
+        parent_id: ThreadId,
+        mut config: Config,
+        initial_history: InitialHistory,
+        prompt: String,
+        options: SubagentSpawnOptions,
+    ) -> CodexResult<SubagentSpawn> {
+        let SubagentSpawnOptions {
+            persona,
+            display_name,
+            correlation_id,
+        } = options;
+        self.apply_subagent_instructions(&mut config, parent_id, persona.as_deref())?;
+        Ok(SubagentSpawn {
+            initial_history,
+            persona,
+            display_name,
+            correlation_id,
+            ..SubagentSpawn::new(config, prompt)
+        })
+    }
+
+    fn apply_subagent_instructions(
+        &self,
+        config: &mut Config,
+        parent_id: ThreadId,
+        persona: Option<&str>,
+    ) -> CodexResult<()> {
+        let options = &self.state.options;
         config.developer_instructions = crate::agent_personas::with_subagent_instructions(
             config.developer_instructions.as_deref(),
-            persona.as_deref(),
+            persona,
+            options.subagent_prompt_prefix.as_deref(),
+            options.subagent_prompt_suffix.as_deref(),
             parent_id,
-        );
-        self.agent_control()
-            .spawn_agent(parent_id, config, prompt, true, persona, display_name)
-            .await
+        )
+        .map_err(CodexErr::InvalidRequest)?;
+        Ok(())
     }
 
     pub async fn resume_thread_from_rollout(
@@ -254,10 +1276,20 @@ impl ThreadManager {
     /// Removes the thread from the manager's internal map, though the thread is stored
     /// as `Arc<CodexThread>`, it is possible that other references to it exist elsewhere.
     /// Returns the thread if the thread was found and removed.
+    ///
+    /// The thread's subagent tree is shut down and removed with it unless
+    /// [`ThreadManagerOptions::keep_orphaned_subagents`] is set.
     pub async fn remove_thread(&self, thread_id: &ThreadId) -> Option<Arc<CodexThread>> {
+        self.state.cascade_shutdown(*thread_id).await;
         self.state.remove_thread(*thread_id).await
     }
 
+    /// Shut down every managed thread, subagents included, and wait up to `timeout` for each to
+    /// finish so rollouts are flushed. The manager holds no threads or subagents afterwards.
+    pub async fn shutdown_all(&self, timeout: Duration) -> ShutdownAllSummary {
+        self.state.shutdown_all(timeout).await
+    }
+
     /// Fork an existing thread by taking messages up to the given position (not including
     /// the message at the given position) and starting a new thread with identical
     /// configuration (unless overridden by the caller's `config`). The new thread will have
@@ -280,48 +1312,324 @@ impl ThreadManager {
             .await
     }
 
-    fn agent_control(&self) -> AgentControl {
-        AgentControl::new(Arc::downgrade(&self.state))
-    }
-}
-
-impl ThreadManagerState {
-    pub(crate) async fn get_thread(&self, thread_id: ThreadId) -> CodexResult<Arc<CodexThread>> {
-        let threads = self.threads.read().await;
-        threads
-            .get(&thread_id)
-            .cloned()
-            .ok_or_else(|| CodexErr::ThreadNotFound(thread_id))
-    }
-
-    pub(crate) async fn send_op(&self, thread_id: ThreadId, op: Op) -> CodexResult<String> {
-        self.get_thread(thread_id).await?.submit(op).await
-    }
-
-    pub(crate) async fn remove_thread(&self, thread_id: ThreadId) -> Option<Arc<CodexThread>> {
-        self.unregister_subagent(thread_id).await;
-        self.threads.write().await.remove(&thread_id)
-    }
-
-    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
-    pub(crate) async fn spawn_new_thread(
+    /// Like [`Self::fork_thread`], but the fork inherits the team of `source_id`, the thread the
+    /// rollout at `path` belongs to. Subagents are moved, not duplicated: each live direct
+    /// subagent of `source_id` keeps running, together with everything it spawned, and now
+    /// reports to the fork. `source_id` no longer owns them afterwards. The fork also gets a
+    /// copy of the shared variables, scratchpad, and pinned context the team worked with.
+    ///
+    /// The team moves all at once; when that fails, the fork is shut down and the team stays
+    /// with `source_id`.
+    pub async fn fork_thread_with_subagents(
         &self,
+        source_id: ThreadId,
+        nth_user_message: usize,
         config: Config,
-        agent_control: AgentControl,
+        path: PathBuf,
     ) -> CodexResult<NewThread> {
-        self.spawn_new_thread_with_source(config, agent_control, self.session_source.clone())
+        let forked = self.fork_thread(nth_user_message, config, path).await?;
+        if let Err(err) = self
+            .state
+            .reassign_subagents(source_id, forked.thread_id)
             .await
+        {
+            self.discard_fork(source_id, &forked).await;
+            return Err(err);
+        }
+        self.state
+            .copy_team_context(source_id, forked.thread_id)
+            .await;
+        Ok(forked)
     }
 
-    pub(crate) async fn spawn_new_thread_with_source(
+    /// Move a live thread to `config` and report whether that happened in place or needed a
+    /// restart.
+    ///
+    /// Model, reasoning effort and reasoning summary are hot-swappable: they are sent to the
+    /// running session with `Op::OverrideTurnContext` and apply from its next turn. A cwd or
+    /// sandbox policy different from the one the session currently runs with requires a
+    /// restart: the thread's rollout is flushed and forked with its full history under
+    /// `config`, its subagents and their shared state are moved to the fork, and only then is
+    /// the original shut down and removed. When the fork fails the original keeps running
+    /// untouched. Other config
+    /// fields, such as instructions, only take effect on restart. Subagents cannot be
+    /// restarted; spawn a new one instead.
+    pub async fn reconfigure_thread(
         &self,
+        thread_id: ThreadId,
         config: Config,
-        agent_control: AgentControl,
-        session_source: SessionSource,
-    ) -> CodexResult<NewThread> {
-        self.spawn_thread_with_source(
+    ) -> CodexResult<ReconfigureOutcome> {
+        let thread = self.state.get_thread(thread_id).await?;
+        // A session that already exited cannot take an override, so it is restarted.
+        let restart = match thread.cwd_and_sandbox_policy().await {
+            Ok((cwd, sandbox_policy)) => requires_restart(&cwd, &sandbox_policy, &config),
+            Err(_) => true,
+        };
+        if !restart {
+            thread.submit(hot_swap_op(&config)).await?;
+            return Ok(ReconfigureOutcome::HotApplied);
+        }
+        if self.state.subagent_info(thread_id).await.is_some() {
+            return Err(CodexErr::InvalidRequest(format!(
+                "subagent {thread_id} cannot change its cwd or sandbox policy; spawn a new subagent instead"
+            )));
+        }
+
+        thread.flush_rollout().await;
+        let restarted = self
+            .fork_thread(usize::MAX, config, thread.rollout_path())
+            .await?;
+        if let Err(err) = self
+            .state
+            .reassign_subagents(thread_id, restarted.thread_id)
+            .await
+        {
+            self.discard_fork(thread_id, &restarted).await;
+            return Err(err);
+        }
+        self.state
+            .copy_team_context(thread_id, restarted.thread_id)
+            .await;
+        let stopped = match thread.submit(Op::Shutdown).await {
+            Ok(_) => {
+                let deadline = tokio::time::Instant::now() + RECONFIGURE_SHUTDOWN_TIMEOUT;
+                wait_for_thread_shutdown(&thread, deadline).await
+            }
+            Err(err) => {
+                // The submission channel is closed, so the session loop has already exited.
+                tracing::debug!("thread {thread_id} already stopped before restart: {err}");
+                true
+            }
+        };
+        if !stopped {
+            tracing::warn!("thread {thread_id} did not shut down after its restart");
+        }
+        self.state.remove_thread(thread_id).await;
+        Ok(ReconfigureOutcome::Restarted(restarted))
+    }
+
+    /// Shut down and remove `forked`, a fork of `source_id` that could not take over its team.
+    async fn discard_fork(&self, source_id: ThreadId, forked: &NewThread) {
+        if let Err(err) = forked.thread.submit(Op::Shutdown).await {
+            tracing::warn!(
+                "failed to shut down fork {} of {source_id}: {err}",
+                forked.thread_id
+            );
+        }
+        self.state.remove_thread(forked.thread_id).await;
+    }
+
+    fn agent_control(&self) -> AgentControl {
+        AgentControl::new(Arc::downgrade(&self.state))
+    }
+}
+
+/// Copy the entry of `source_id` in a per-thread store to `target_id`, if it has one.
+async fn copy_thread_entry<T: Clone>(
+    store: &RwLock<HashMap<ThreadId, T>>,
+    source_id: ThreadId,
+    target_id: ThreadId,
+) {
+    let mut store = store.write().await;
+    if let Some(entry) = store.get(&source_id).cloned() {
+        store.insert(target_id, entry);
+    }
+}
+
+/// Whether moving a thread that runs with `cwd` and `sandbox_policy` to `config` needs a new
+/// session rather than an `Op::OverrideTurnContext`.
+fn requires_restart(cwd: &Path, sandbox_policy: &SandboxPolicy, config: &Config) -> bool {
+    cwd != config.cwd || sandbox_policy != config.sandbox_policy.get()
+}
+
+/// `Op::OverrideTurnContext` carrying the hot-swappable settings of `config`.
+fn hot_swap_op(config: &Config) -> Op {
+    Op::OverrideTurnContext {
+        cwd: None,
+        approval_policy: None,
+        sandbox_policy: None,
+        model: config.model.clone(),
+        effort: Some(config.model_reasoning_effort),
+        summary: Some(config.model_reasoning_summary),
+    }
+}
+
+/// Poll `thread` until it reports `AgentStatus::Shutdown`, returning false if `deadline` passes
+/// first.
+async fn wait_for_thread_shutdown(thread: &CodexThread, deadline: tokio::time::Instant) -> bool {
+    loop {
+        if matches!(thread.agent_status().await, AgentStatus::Shutdown) {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+}
+
+impl ThreadManagerState {
+    pub(crate) async fn get_thread(&self, thread_id: ThreadId) -> CodexResult<Arc<CodexThread>> {
+        let threads = self.bounded_lock("threads", self.threads.read()).await?;
+        threads
+            .get(&thread_id)
+            .cloned()
+            .ok_or_else(|| CodexErr::ThreadNotFound(thread_id))
+    }
+
+    pub(crate) async fn send_op(&self, thread_id: ThreadId, op: Op) -> CodexResult<String> {
+        self.get_thread(thread_id).await?.submit(op).await
+    }
+
+    /// Forget `thread_id` and the stores it owns, skipping any store whose lock times out. Its
+    /// subagents are left alone; explicit shutdowns go through [`Self::cascade_shutdown`] first.
+    pub(crate) async fn remove_thread(&self, thread_id: ThreadId) -> Option<Arc<CodexThread>> {
+        self.unregister_subagent(thread_id).await;
+        self.forget_thread_entry("shared_variables", &self.shared_variables, thread_id)
+            .await;
+        self.forget_thread_entry("pinned_context", &self.pinned_context, thread_id)
+            .await;
+        self.forget_thread_entry("scratchpads", &self.scratchpads, thread_id)
+            .await;
+        self.forget_thread_entry("threads", &self.threads, thread_id)
+            .await
+    }
+
+    pub(crate) async fn shutdown_all(&self, timeout: Duration) -> ShutdownAllSummary {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Teardown is best effort: a store whose lock times out is skipped, as in
+        // `unregister_subagent`.
+        let threads: Vec<(ThreadId, Arc<CodexThread>)> = self
+            .bounded_lock("threads", self.threads.read())
+            .await
+            .map(|threads| {
+                threads
+                    .iter()
+                    .map(|(id, thread)| (*id, Arc::clone(thread)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut drains = self
+            .bounded_lock("subagent_drains", self.subagent_drains.write())
+            .await
+            .map(|mut drains| std::mem::take(&mut *drains))
+            .unwrap_or_default();
+        let waits = threads.into_iter().map(|(id, thread)| {
+            let drain = drains.remove(&id);
+            async move {
+                if let Err(err) = thread.submit(Op::Shutdown).await {
+                    // The submission channel is closed, so the session loop has already exited.
+                    tracing::debug!("thread {id} already stopped before shutdown: {err}");
+                    return true;
+                }
+                if let Some(mut drain) = drain {
+                    return match tokio::time::timeout_at(deadline, &mut drain).await {
+                        Ok(_) => true,
+                        Err(_) => {
+                            drain.abort();
+                            false
+                        }
+                    };
+                }
+                wait_for_thread_shutdown(&thread, deadline).await
+            }
+        });
+        let results = futures::future::join_all(waits).await;
+        // Drains whose thread was never registered have nothing left to wait for.
+        for (_, drain) in drains {
+            drain.abort();
+        }
+
+        for spawn in self
+            .pending_spawns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            spawn.cancel.cancel();
+        }
+        let subagent_ids: Vec<ThreadId> = self
+            .bounded_lock("subagents", self.subagents.read())
+            .await
+            .map(|subagents| subagents.keys().copied().collect())
+            .unwrap_or_default();
+        for id in subagent_ids {
+            self.unregister_subagent(id).await;
+        }
+        self.clear_thread_store("threads", &self.threads).await;
+        self.clear_thread_store("subagent_outputs", &self.subagent_outputs)
+            .await;
+        self.clear_thread_store("subagent_tombstones", &self.subagent_tombstones)
+            .await;
+        self.clear_thread_store("shared_variables", &self.shared_variables)
+            .await;
+        self.clear_thread_store("pinned_context", &self.pinned_context)
+            .await;
+        self.clear_thread_store("scratchpads", &self.scratchpads)
+            .await;
+
+        let clean = results.iter().filter(|clean| **clean).count();
+        ShutdownAllSummary {
+            clean,
+            timed_out: results.len() - clean,
+        }
+    }
+
+    /// Carry an explicit shutdown of `thread_id` over to its subagent tree, unless the host keeps
+    /// orphaned subagents running.
+    pub(crate) async fn cascade_shutdown(&self, thread_id: ThreadId) {
+        if !self.options.keep_orphaned_subagents {
+            self.shutdown_subagent_tree(thread_id).await;
+        }
+    }
+
+    /// Shut down and remove every descendant of `root_id`, deepest subagents first.
+    async fn shutdown_subagent_tree(&self, root_id: ThreadId) {
+        let mut seen = HashSet::from([root_id]);
+        let mut pending = vec![root_id];
+        let mut descendants = Vec::new();
+        while let Some(parent_id) = pending.pop() {
+            for (id, _) in self.subagents_for_parent(parent_id).await {
+                if seen.insert(id) {
+                    descendants.push(id);
+                    pending.push(id);
+                }
+            }
+        }
+        for id in descendants.into_iter().rev() {
+            if let Err(err) = self.send_op(id, Op::Shutdown).await {
+                tracing::warn!("failed to shut down orphaned agent {id}: {err}");
+            }
+            self.quiesce_subagent(id).await;
+        }
+    }
+
+    #[allow(dead_code)] // Used by upcoming multi-agent tooling.
+    pub(crate) async fn spawn_new_thread(
+        &self,
+        config: Config,
+        agent_control: AgentControl,
+    ) -> CodexResult<NewThread> {
+        self.spawn_new_thread_with_source(
             config,
             InitialHistory::New,
+            agent_control,
+            self.session_source.clone(),
+        )
+        .await
+    }
+
+    /// Spawn a thread with the manager's own auth, starting from `initial_history`.
+    pub(crate) async fn spawn_new_thread_with_source(
+        &self,
+        config: Config,
+        initial_history: InitialHistory,
+        agent_control: AgentControl,
+        session_source: SessionSource,
+    ) -> CodexResult<NewThread> {
+        self.spawn_thread_with_source(
+            config,
+            initial_history,
             Arc::clone(&self.auth_manager),
             agent_control,
             session_source,
@@ -354,19 +1662,35 @@ impl ThreadManagerState {
         agent_control: AgentControl,
         session_source: SessionSource,
     ) -> CodexResult<NewThread> {
-        let CodexSpawnOk {
-            codex, thread_id, ..
-        } = Codex::spawn(
-            config,
-            auth_manager,
-            Arc::clone(&self.models_manager),
-            Arc::clone(&self.skills_manager),
-            initial_history,
-            session_source,
-            agent_control,
-        )
-        .await?;
-        self.finalize_thread_spawn(codex, thread_id).await
+        let is_subagent = matches!(session_source, SessionSource::SubAgent(_));
+        let spawn = async {
+            let CodexSpawnOk {
+                codex, thread_id, ..
+            } = Codex::spawn(
+                config,
+                auth_manager,
+                Arc::clone(&self.models_manager),
+                Arc::clone(&self.skills_manager),
+                initial_history,
+                session_source,
+                agent_control,
+            )
+            .await?;
+            self.finalize_thread_spawn(codex, thread_id).await
+        };
+        if is_subagent {
+            self.throttle_subagent_spawn(spawn).await
+        } else {
+            spawn.await
+        }
+    }
+
+    /// Run a subagent session setup once a spawn permit is free. The permit is held until the
+    /// session is configured.
+    async fn throttle_subagent_spawn<T>(&self, spawn: impl Future<Output = T>) -> T {
+        // The semaphore is never closed, so acquiring cannot fail.
+        let _permit = self.subagent_spawn_permits.acquire().await.ok();
+        spawn.await
     }
 
     async fn finalize_thread_spawn(
@@ -385,10 +1709,7 @@ impl ThreadManagerState {
             }
         };
 
-        let thread = Arc::new(CodexThread::new(
-            codex,
-            session_configured.rollout_path.clone(),
-        ));
+        let thread = Arc::new(CodexThread::new(codex, &session_configured));
         self.threads.write().await.insert(thread_id, thread.clone());
 
         Ok(NewThread {
@@ -398,289 +1719,5810 @@ impl ThreadManagerState {
         })
     }
 
+    /// Register `subagent_id` under its parent. Fails with [`CodexErr::StateLockTimeout`] when a
+    /// registry lock stays held past the state lock timeout.
     pub(crate) async fn register_subagent(
         &self,
-        parent_id: ThreadId,
         subagent_id: ThreadId,
-        persona: Option<String>,
-        display_name: Option<String>,
-    ) {
-        self.subagents.write().await.insert(
-            subagent_id,
-            SubagentInfo {
-                parent_id,
-                persona,
-                display_name,
-            },
-        );
-        self.subagent_outputs
-            .write()
-            .await
+        info: SubagentInfo,
+    ) -> CodexResult<()> {
+        let parent_id = info.parent_id;
+        let correlation_id = info.correlation_id.clone();
+        let max_tool_events = info.max_tool_events;
+        self.bounded_lock("subagents", self.subagents.write())
+            .await?
+            .insert(subagent_id, info);
+        self.bounded_lock("subagent_spawn_seq", self.subagent_spawn_seq.write())
+            .await?
             .entry(subagent_id)
-            .or_insert_with(SubagentOutput::default);
+            .or_insert_with(|| self.next_subagent_seq.fetch_add(1, Ordering::Relaxed));
+        self.bounded_lock("subagent_outputs", self.subagent_outputs.write())
+            .await?
+            .entry(subagent_id)
+            .or_insert_with(|| SubagentOutput {
+                preserve_code_blocks: !self.options.char_trim_subagent_output,
+                max_tool_events,
+                max_output_chars: self.options.collab_limits.max_subagent_output_chars,
+                max_reasoning_chars: self.options.collab_limits.max_subagent_reasoning_chars,
+                ..SubagentOutput::new()
+            });
+        if let Some(metrics) = &self.options.subagent_metrics {
+            metrics.subagent_spawned(parent_id, subagent_id, correlation_id.as_deref());
+        }
+        Ok(())
     }
 
+    /// Forget `subagent_id`, leaving a tombstone of its output. Teardown is best effort: a store
+    /// whose lock stays held past the state lock timeout is skipped rather than hanging the caller.
     pub(crate) async fn unregister_subagent(&self, subagent_id: ThreadId) {
-        self.subagents.write().await.remove(&subagent_id);
-        self.subagent_outputs.write().await.remove(&subagent_id);
+        let info = self
+            .bounded_lock("subagents", self.subagents.write())
+            .await
+            .ok()
+            .and_then(|mut subagents| subagents.remove(&subagent_id));
+        if info.as_ref().is_some_and(|info| info.paused) {
+            self.subagent_resumed.notify_waiters();
+        }
+        if info.is_some()
+            && let Some(metrics) = &self.options.subagent_metrics
+        {
+            metrics.subagent_removed(subagent_id);
+        }
+        if let Ok(mut tokens) = self
+            .bounded_lock("subagent_tokens", self.subagent_tokens.write())
+            .await
+        {
+            tokens.per_subagent.remove(&subagent_id);
+        }
+        self.forget_thread_entry("subagent_spawn_seq", &self.subagent_spawn_seq, subagent_id)
+            .await;
+        self.forget_thread_entry("input_locks", &self.input_locks, subagent_id)
+            .await;
+        self.forget_thread_entry("drain_restarts", &self.drain_restarts, subagent_id)
+            .await;
+        self.forget_thread_entry("shared_variables", &self.shared_variables, subagent_id)
+            .await;
+        self.forget_thread_entry("pinned_context", &self.pinned_context, subagent_id)
+            .await;
+        self.forget_thread_entry("scratchpads", &self.scratchpads, subagent_id)
+            .await;
+        let output = self
+            .forget_thread_entry("subagent_outputs", &self.subagent_outputs, subagent_id)
+            .await;
+        if let (Some(info), Some(output)) = (info, output) {
+            self.insert_subagent_tombstone(subagent_id, info.parent_id, output.snapshot(None))
+                .await;
+        }
+        self.forget_thread_entry("subagent_drains", &self.subagent_drains, subagent_id)
+            .await;
     }
 
-    pub(crate) async fn subagents_for_parent(
+    /// Empty `store`, skipping it if its lock times out.
+    async fn clear_thread_store<T>(
         &self,
-        parent_id: ThreadId,
-    ) -> Vec<(ThreadId, SubagentInfo)> {
-        self.subagents
-            .read()
+        lock: &'static str,
+        store: &RwLock<HashMap<ThreadId, T>>,
+    ) {
+        if let Ok(mut store) = self.bounded_lock(lock, store.write()).await {
+            store.clear();
+        }
+    }
+
+    /// Remove `thread_id` from `store`, skipping the store if its lock times out.
+    async fn forget_thread_entry<T>(
+        &self,
+        lock: &'static str,
+        store: &RwLock<HashMap<ThreadId, T>>,
+        thread_id: ThreadId,
+    ) -> Option<T> {
+        self.bounded_lock(lock, store.write())
             .await
-            .iter()
-            .filter_map(|(id, info)| {
-                if info.parent_id == parent_id {
-                    Some((*id, info.clone()))
-                } else {
-                    None
-                }
-            })
-            .collect()
+            .ok()
+            .and_then(|mut store| store.remove(&thread_id))
     }
 
-    pub(crate) async fn subagent_info(&self, subagent_id: ThreadId) -> Option<SubagentInfo> {
-        self.subagents.read().await.get(&subagent_id).cloned()
+    /// Start the drain watchdog unless it is disabled or already running. It restarts drains
+    /// that stopped early and exits once no drains are left.
+    pub(crate) async fn ensure_drain_watchdog(self: &Arc<Self>) {
+        if self
+            .options
+            .max_subagent_drain_restarts
+            .is_none_or(|max| max == 0)
+            || self.drain_watchdog_running.swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+        let state = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(DRAIN_WATCHDOG_INTERVAL).await;
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                let stopped: Vec<ThreadId> = {
+                    let drains = state.subagent_drains.read().await;
+                    if drains.is_empty() {
+                        // Cleared under the lock so a concurrent drain registration either sees
+                        // this watchdog exit or keeps it running.
+                        state.drain_watchdog_running.store(false, Ordering::Release);
+                        return;
+                    }
+                    drains
+                        .iter()
+                        .filter(|(_, drain)| drain.is_finished())
+                        .map(|(id, _)| *id)
+                        .collect()
+                };
+                for id in stopped {
+                    state.restart_stopped_drain(id).await;
+                }
+            }
+        });
     }
 
-    pub(crate) async fn is_subagent_of(&self, parent_id: ThreadId, subagent_id: ThreadId) -> bool {
-        self.subagents
-            .read()
-            .await
-            .get(&subagent_id)
-            .is_some_and(|info| info.parent_id == parent_id)
+    /// Start the completed-subagent reaper unless it is disabled or already running. It stops
+    /// once no subagents are registered.
+    pub(crate) async fn ensure_subagent_reaper(self: &Arc<Self>) {
+        let Some(ttl) = self
+            .options
+            .completed_subagent_ttl_ms
+            .filter(|ttl| *ttl > 0)
+            .map(Duration::from_millis)
+        else {
+            return;
+        };
+        if self.subagent_reaper_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let poll_interval = (ttl / 4).max(MIN_SUBAGENT_REAPER_INTERVAL);
+        let state = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(poll_interval).await;
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                {
+                    let Ok(subagents) = state
+                        .bounded_lock("subagents", state.subagents.read())
+                        .await
+                    else {
+                        continue;
+                    };
+                    if subagents.is_empty() {
+                        // Cleared under the lock so a concurrent registration either sees this
+                        // reaper exit or keeps it running.
+                        state
+                            .subagent_reaper_running
+                            .store(false, Ordering::Release);
+                        return;
+                    }
+                }
+                state.reap_completed_subagents(ttl).await;
+            }
+        });
     }
 
-    pub(crate) async fn record_subagent_delta(&self, subagent_id: ThreadId, delta: &str) {
-        if let Some(output) = self.subagent_outputs.write().await.get_mut(&subagent_id) {
-            output.push_delta(delta);
+    /// Retire every subagent in a terminal state whose last activity is at least `ttl` ago and
+    /// that has no live subagents of its own, through [`Self::retire_subagent`]. Returns the
+    /// reaped ids.
+    pub(crate) async fn reap_completed_subagents(&self, ttl: Duration) -> Vec<ThreadId> {
+        let Ok(registered) = self
+            .bounded_lock("subagents", self.subagents.read())
+            .await
+            .map(|subagents| subagents.keys().copied().collect::<Vec<_>>())
+        else {
+            return Vec::new();
+        };
+        let mut reaped = Vec::new();
+        for id in registered {
+            let Ok(thread) = self.get_thread(id).await else {
+                continue;
+            };
+            let terminal = matches!(
+                thread.agent_status().await,
+                AgentStatus::Completed(_) | AgentStatus::Errored(_) | AgentStatus::Shutdown
+            );
+            let expired = self
+                .subagent_last_activity(id)
+                .await
+                .is_some_and(|at| at.elapsed() >= ttl);
+            if terminal && expired && self.subagents_for_parent(id).await.is_empty() {
+                tracing::debug!("reaping subagent {id} after it sat completed for {ttl:?}");
+                if let Err(err) = self.retire_subagent(id).await {
+                    tracing::debug!("reaped agent {id} had already stopped: {err}");
+                }
+                reaped.push(id);
+            }
         }
+        reaped
     }
 
-    pub(crate) async fn record_subagent_message(&self, subagent_id: ThreadId, message: &str) {
-        if let Some(output) = self.subagent_outputs.write().await.get_mut(&subagent_id) {
-            output.set_message(message);
+    /// Replace the finished drain of `subagent_id` with a fresh one while the subagent is still
+    /// live and has restarts left; otherwise forget the finished handle.
+    async fn restart_stopped_drain(self: &Arc<Self>, subagent_id: ThreadId) {
+        let thread = match self.get_thread(subagent_id).await {
+            Ok(thread) if self.subagent_info(subagent_id).await.is_some() => thread,
+            _ => {
+                self.subagent_drains.write().await.remove(&subagent_id);
+                return;
+            }
+        };
+        if matches!(thread.agent_status().await, AgentStatus::Shutdown) {
+            self.subagent_drains.write().await.remove(&subagent_id);
+            return;
         }
+        if !self.claim_drain_restart(subagent_id).await {
+            tracing::warn!("event drain for agent {subagent_id} keeps stopping; not restarting it");
+            self.subagent_drains.write().await.remove(&subagent_id);
+            return;
+        }
+        tracing::warn!("restarting stopped event drain for agent {subagent_id}");
+        let drain =
+            crate::agent::control::spawn_headless_drain(thread, Arc::clone(self), subagent_id);
+        self.register_subagent_drain(subagent_id, drain).await;
     }
 
-    pub(crate) async fn reset_subagent_output(&self, subagent_id: ThreadId) {
-        if let Some(output) = self.subagent_outputs.write().await.get_mut(&subagent_id) {
-            output.reset_for_prompt();
+    /// Count one drain restart for `subagent_id`, or return false once it has used all of them.
+    async fn claim_drain_restart(&self, subagent_id: ThreadId) -> bool {
+        let max = self.options.max_subagent_drain_restarts.unwrap_or(0);
+        let mut restarts = self.drain_restarts.write().await;
+        let count = restarts.entry(subagent_id).or_default();
+        if *count >= max {
+            return false;
         }
+        *count += 1;
+        true
     }
 
-    pub(crate) async fn record_subagent_reasoning_delta(&self, subagent_id: ThreadId, delta: &str) {
-        if let Some(output) = self.subagent_outputs.write().await.get_mut(&subagent_id) {
-            output.push_reasoning_delta(delta);
+    /// Start the possible-deadlock watchdog for `parent_id` if the host enabled it and none is
+    /// running yet. It warns the parent once per blocked stretch and stops when the parent has
+    /// no subagents left.
+    pub(crate) async fn ensure_deadlock_watchdog(self: &Arc<Self>, parent_id: ThreadId) {
+        let Some(threshold_ms) = self
+            .options
+            .subagent_deadlock_threshold_ms
+            .filter(|threshold_ms| *threshold_ms > 0)
+        else {
+            return;
+        };
+        if !self.deadlock_watchdogs.write().await.insert(parent_id) {
+            return;
         }
+        let threshold = Duration::from_millis(threshold_ms);
+        let poll_interval = (threshold / 4).max(MIN_DEADLOCK_POLL_INTERVAL);
+        let state = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut warned = false;
+            loop {
+                tokio::time::sleep(poll_interval).await;
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                let subagents = {
+                    // Checked under the lock so a concurrent spawn either sees this watchdog
+                    // exit or keeps it running.
+                    let mut watchdogs = state.deadlock_watchdogs.write().await;
+                    let subagents = state.subagents_for_parent(parent_id).await;
+                    if subagents.is_empty() {
+                        watchdogs.remove(&parent_id);
+                        return;
+                    }
+                    subagents
+                };
+                let mut team = Vec::with_capacity(subagents.len());
+                for (id, _) in &subagents {
+                    let status = match state.get_thread(*id).await {
+                        Ok(thread) => thread.agent_status().await,
+                        Err(_) => AgentStatus::NotFound,
+                    };
+                    let idle_for = state
+                        .subagent_last_activity(*id)
+                        .await
+                        .map_or(Duration::ZERO, |at| at.elapsed());
+                    team.push((*id, status, idle_for));
+                }
+                match blocked_team(&team) {
+                    Some((blocked, idle_for)) if idle_for >= threshold => {
+                        if warned {
+                            continue;
+                        }
+                        warned = true;
+                        let op = Op::PossibleSubagentDeadlock {
+                            subagents: blocked,
+                            idle_seconds: idle_for.as_secs(),
+                        };
+                        if let Err(err) = state.send_op(parent_id, op).await {
+                            tracing::debug!("failed to warn {parent_id} of idle subagents: {err}");
+                        }
+                    }
+                    _ => warned = false,
+                }
+            }
+        });
     }
 
-    pub(crate) async fn record_subagent_tool_event(&self, subagent_id: ThreadId, event: String) {
-        if let Some(output) = self.subagent_outputs.write().await.get_mut(&subagent_id) {
-            output.push_tool_event(event);
+    /// Lock serializing inputs to `agent_id`. Tokio mutexes queue waiters in FIFO order, so
+    /// holding it across a send delivers concurrent sends in the order they were issued.
+    pub(crate) async fn input_lock(&self, agent_id: ThreadId) -> Arc<Mutex<()>> {
+        if let Some(lock) = self.input_locks.read().await.get(&agent_id) {
+            return Arc::clone(lock);
         }
+        Arc::clone(self.input_locks.write().await.entry(agent_id).or_default())
     }
 
-    pub(crate) async fn subagent_output_snapshot(
+    pub(crate) async fn register_subagent_drain(
         &self,
         subagent_id: ThreadId,
-        max_chars: Option<usize>,
-    ) -> Option<SubagentOutputSnapshot> {
-        self.subagent_outputs
-            .read()
+        handle: JoinHandle<()>,
+    ) {
+        self.subagent_drains
+            .write()
             .await
-            .get(&subagent_id)
-            .map(|output| output.snapshot(max_chars))
+            .insert(subagent_id, handle);
     }
-}
 
-impl SubagentOutput {
-    fn push_delta(&mut self, delta: &str) {
-        self.partial.push_str(delta);
-        trim_to_max_chars(&mut self.partial, MAX_SUBAGENT_OUTPUT_CHARS);
+    pub(crate) async fn take_subagent_drain(
+        &self,
+        subagent_id: ThreadId,
+    ) -> Option<JoinHandle<()>> {
+        self.forget_thread_entry("subagent_drains", &self.subagent_drains, subagent_id)
+            .await
     }
 
-    fn push_reasoning_delta(&mut self, delta: &str) {
-        self.reasoning.push_str(delta);
-        trim_to_max_chars(&mut self.reasoning, MAX_SUBAGENT_REASONING_CHARS);
+    /// Shut `subagent_id` down and forget it. The drain is aborted and awaited before the
+    /// subagent is unregistered, so neither its session loop nor its drain outlives the
+    /// registration. The teardown runs even when the session had already stopped; the error only
+    /// reports that the shutdown could not be sent. Its own subagents are left alone; callers
+    /// that shut down a whole tree go through [`Self::cascade_shutdown`] as well.
+    pub(crate) async fn retire_subagent(&self, subagent_id: ThreadId) -> CodexResult<()> {
+        let shutdown = self.send_op(subagent_id, Op::Shutdown).await.map(drop);
+        self.quiesce_subagent(subagent_id).await;
+        shutdown
     }
 
-    fn push_tool_event(&mut self, event: String) {
-        self.tool_events.push(event);
-        if self.tool_events.len() > MAX_SUBAGENT_TOOL_EVENTS {
-            let overflow = self
-                .tool_events
-                .len()
-                .saturating_sub(MAX_SUBAGENT_TOOL_EVENTS);
-            self.tool_events.drain(..overflow);
+    /// Abort and await the drain of `subagent_id`, then forget it.
+    async fn quiesce_subagent(&self, subagent_id: ThreadId) {
+        if let Some(drain) = self.take_subagent_drain(subagent_id).await {
+            drain.abort();
+            // Only a panic or the abort itself can end the wait; either way the task is gone.
+            let _ = drain.await;
         }
+        self.remove_thread(subagent_id).await;
     }
 
-    fn set_message(&mut self, message: &str) {
-        self.last_message = Some(message.to_string());
-        self.partial.clear();
+    /// Next seeded subagent id, or `None` when ids are random.
+    #[cfg(any(test, feature = "test-support"))]
+    pub(crate) fn next_seeded_subagent_id(&self) -> Option<ThreadId> {
+        self.subagent_id_seed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+            .and_then(SeededThreadIds::next_id)
     }
 
-    fn reset_for_prompt(&mut self) {
-        self.partial.clear();
-        self.reasoning.clear();
-        self.tool_events.clear();
+    /// Undo a subagent spawn that failed after registration: stop its drain, shut its session
+    /// down and forget it. No tombstone is kept since the subagent never ran.
+    pub(crate) async fn roll_back_subagent_spawn(&self, subagent_id: ThreadId) {
+        if let Err(err) = self.retire_subagent(subagent_id).await {
+            tracing::debug!("rolled back agent {subagent_id} had already stopped: {err}");
+        }
+        self.forget_thread_entry(
+            "subagent_tombstones",
+            &self.subagent_tombstones,
+            subagent_id,
+        )
+        .await;
     }
 
-    fn snapshot(&self, max_chars: Option<usize>) -> SubagentOutputSnapshot {
-        let partial = max_chars
-            .and_then(|limit| trim_snapshot(self.partial.as_str(), limit))
-            .or_else(|| {
-                if self.partial.is_empty() {
-                    None
-                } else {
-                    Some(self.partial.clone())
+    /// Subagents owned by `parent_id`, in spawn order. Empty when the graph lock times out.
+    pub(crate) async fn subagents_for_parent(
+        &self,
+        parent_id: ThreadId,
+    ) -> Vec<(ThreadId, SubagentInfo)> {
+        let Ok(mut subagents) = self
+            .bounded_lock("subagents", self.subagents.read())
+            .await
+            .map(|subagents| {
+                subagents
+                    .iter()
+                    .filter_map(|(id, info)| {
+                        if info.parent_id == parent_id {
+                            Some((*id, info.clone()))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+        else {
+            return Vec::new();
+        };
+        if let Ok(spawn_seq) = self
+            .bounded_lock("subagent_spawn_seq", self.subagent_spawn_seq.read())
+            .await
+        {
+            subagents.sort_by_key(|(id, _)| spawn_seq.get(id).copied().unwrap_or(u64::MAX));
+        }
+        subagents
+    }
+
+    /// Descendants of `root_id` in breadth-first spawn order, as `(id, parent_id)` pairs, walking
+    /// at most `max_depth` levels and `max_nodes` entries. The flag reports whether anything was
+    /// left out because of those bounds.
+    pub(crate) async fn subagent_descendants(
+        &self,
+        root_id: ThreadId,
+        max_depth: usize,
+        max_nodes: usize,
+    ) -> (Vec<(ThreadId, ThreadId)>, bool) {
+        let subagents = self.subagents.read().await;
+        let spawn_seq = self.subagent_spawn_seq.read().await;
+        let mut children: HashMap<ThreadId, Vec<ThreadId>> = HashMap::new();
+        for (id, info) in subagents.iter() {
+            children.entry(info.parent_id).or_default().push(*id);
+        }
+        for ids in children.values_mut() {
+            ids.sort_by_key(|id| spawn_seq.get(id).copied().unwrap_or(u64::MAX));
+        }
+
+        let mut out = Vec::new();
+        let mut truncated = false;
+        let mut level = vec![root_id];
+        for depth in 0.. {
+            let next = level
+                .iter()
+                .flat_map(|parent| {
+                    children
+                        .get(parent)
+                        .into_iter()
+                        .flatten()
+                        .map(move |child| (*child, *parent))
+                })
+                .collect::<Vec<_>>();
+            if next.is_empty() {
+                break;
+            }
+            if depth >= max_depth {
+                truncated = true;
+                break;
+            }
+            level.clear();
+            for (child, parent) in next {
+                // Guard against malformed graphs; reassignment keeps them acyclic.
+                if child == root_id || out.iter().any(|(id, _)| *id == child) {
+                    continue;
                 }
-            });
-        let reasoning = max_chars
-            .and_then(|limit| trim_snapshot(self.reasoning.as_str(), limit))
-            .or_else(|| {
-                if self.reasoning.is_empty() {
-                    None
-                } else {
-                    Some(self.reasoning.clone())
+                if out.len() >= max_nodes {
+                    truncated = true;
+                    break;
                 }
-            });
-        SubagentOutputSnapshot {
-            partial,
-            last_message: self.last_message.clone(),
-            reasoning,
-            tool_events: self.tool_events.clone(),
+                out.push((child, parent));
+                level.push(child);
+            }
+            if truncated {
+                break;
+            }
         }
+        (out, truncated)
     }
-}
 
-fn trim_to_max_chars(value: &mut String, max_chars: usize) {
-    let total = value.chars().count();
-    if total <= max_chars {
-        return;
+    /// Move every direct subagent of `old_parent_id` under `new_parent_id` at once, so a failure
+    /// leaves the whole team where it was. Returns the moved subagents.
+    pub(crate) async fn reassign_subagents(
+        &self,
+        old_parent_id: ThreadId,
+        new_parent_id: ThreadId,
+    ) -> CodexResult<Vec<ThreadId>> {
+        let mut subagents = self
+            .bounded_lock("subagents", self.subagents.write())
+            .await?;
+        let moved: Vec<ThreadId> = subagents
+            .iter()
+            .filter(|(_, info)| info.parent_id == old_parent_id)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut ancestor = new_parent_id;
+        // Bound the walk by the graph size so a pre-existing cycle cannot hang us.
+        for _ in 0..=subagents.len() {
+            if moved.contains(&ancestor) {
+                return Err(CodexErr::InvalidRequest(format!(
+                    "cannot move the subagents of {old_parent_id} under {new_parent_id}: agent {ancestor} would become its own ancestor"
+                )));
+            }
+            match subagents.get(&ancestor) {
+                Some(info) => ancestor = info.parent_id,
+                None => break,
+            }
+        }
+        for id in &moved {
+            if let Some(info) = subagents.get_mut(id) {
+                info.parent_id = new_parent_id;
+            }
+        }
+        Ok(moved)
     }
-    let trim_chars = total.saturating_sub(max_chars);
-    let start = value
-        .char_indices()
-        .nth(trim_chars)
-        .map(|(idx, _)| idx)
-        .unwrap_or(0);
-    value.drain(..start);
-}
 
-fn trim_snapshot(value: &str, max_chars: usize) -> Option<String> {
-    if value.is_empty() {
-        return None;
+    /// Give `target_id` a copy of the shared variables, scratchpad, and pinned context of
+    /// `source_id`, for a thread that takes over its team.
+    pub(crate) async fn copy_team_context(&self, source_id: ThreadId, target_id: ThreadId) {
+        copy_thread_entry(&self.shared_variables, source_id, target_id).await;
+        copy_thread_entry(&self.scratchpads, source_id, target_id).await;
+        copy_thread_entry(&self.pinned_context, source_id, target_id).await;
+    }
+
+    pub(crate) async fn orphaned_subagents(&self) -> Vec<ThreadId> {
+        let parents = self
+            .subagents
+            .read()
+            .await
+            .iter()
+            .map(|(id, info)| (*id, info.parent_id))
+            .collect::<Vec<_>>();
+        let threads = self.threads.read().await;
+        let mut orphans = parents
+            .into_iter()
+            .filter(|(_, parent_id)| !threads.contains_key(parent_id))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        orphans.sort_by_key(std::string::ToString::to_string);
+        orphans
+    }
+
+    /// Registration of `subagent_id`, or `None` when it is unknown or the graph lock times out.
+    pub(crate) async fn subagent_info(&self, subagent_id: ThreadId) -> Option<SubagentInfo> {
+        self.bounded_lock("subagents", self.subagents.read())
+            .await
+            .ok()?
+            .get(&subagent_id)
+            .cloned()
+    }
+
+    /// Schema `subagent_id` was asked to answer with, if it was spawned with one.
+    pub(crate) async fn subagent_output_schema(
+        &self,
+        subagent_id: ThreadId,
+    ) -> Option<serde_json::Value> {
+        self.subagents
+            .read()
+            .await
+            .get(&subagent_id)
+            .and_then(|info| info.output_schema.clone())
+    }
+
+    /// Record a checkpoint of `subagent_id` at `user_turns`, dropping the oldest one beyond
+    /// [`MAX_SUBAGENT_CHECKPOINTS`].
+    pub(crate) async fn add_subagent_checkpoint(
+        &self,
+        subagent_id: ThreadId,
+        label: String,
+        user_turns: usize,
+    ) -> CodexResult<SubagentCheckpoint> {
+        let mut subagents = self
+            .bounded_lock("subagents", self.subagents.write())
+            .await?;
+        let info = subagents
+            .get_mut(&subagent_id)
+            .ok_or(CodexErr::ThreadNotFound(subagent_id))?;
+        let id = info
+            .checkpoints
+            .back()
+            .map_or(1, |checkpoint| checkpoint.id.saturating_add(1));
+        let checkpoint = SubagentCheckpoint {
+            id,
+            label,
+            user_turns,
+        };
+        info.checkpoints.push_back(checkpoint.clone());
+        while info.checkpoints.len() > MAX_SUBAGENT_CHECKPOINTS {
+            info.checkpoints.pop_front();
+        }
+        Ok(checkpoint)
+    }
+
+    pub(crate) async fn record_subagent_input(&self, subagent_id: ThreadId, items: Vec<UserInput>) {
+        if let Some(info) = self.subagents.write().await.get_mut(&subagent_id) {
+            info.last_input = items;
+        }
+    }
+
+    /// Subagent slots of `parent_id` taken by live subagents and by spawns still starting,
+    /// counted the same way [`Self::reserve_subagent_spawn`] does.
+    pub(crate) async fn subagent_slots(&self, parent_id: ThreadId) -> SubagentSlots {
+        let subagents = self.subagents.read().await;
+        let pending = self
+            .pending_spawns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        count_subagent_slots(&subagents, &pending, parent_id)
+    }
+
+    /// Reserve one of `parent_id`'s subagent slots for a spawn whose session is still starting.
+    /// Fails when live and pending subagents already fill the parent's limit, or when `token` is
+    /// already in use by another pending spawn of the same parent.
+    pub(crate) async fn reserve_subagent_spawn(
+        &self,
+        parent_id: ThreadId,
+        token: Option<String>,
+    ) -> CodexResult<PendingSpawnGuard> {
+        // Registration needs the write lock, so no spawn can move from pending to live while the
+        // two counts are taken.
+        let subagents = self
+            .bounded_lock("subagents", self.subagents.read())
+            .await?;
+        let mut pending = self
+            .pending_spawns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let SubagentSlots { active, starting } =
+            count_subagent_slots(&subagents, &pending, parent_id);
+        let limit = self.collab_limits().max_subagents_per_parent;
+        if active + starting >= limit {
+            tracing::debug!(
+                "subagent spawn under {parent_id} rejected: {active} subagents are active and {starting} more are starting"
+            );
+            return Err(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                limit: limit as u64,
+            });
+        }
+        if let Some(token) = token.as_deref()
+            && pending
+                .iter()
+                .any(|spawn| spawn.parent_id == parent_id && spawn.token.as_deref() == Some(token))
+        {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "a spawn with token {token} is already in progress"
+            )));
+        }
+        let id = self.next_pending_spawn_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancellationToken::new();
+        pending.push(PendingSpawn {
+            id,
+            parent_id,
+            token,
+            cancel: cancel.clone(),
+        });
+        Ok(PendingSpawnGuard {
+            pending: Arc::clone(&self.pending_spawns),
+            id,
+            cancel,
+        })
+    }
+
+    /// Cancel `parent_id`'s pending spawn registered under `token`, returning whether one was
+    /// found. The spawn itself releases its slot and cleans up.
+    pub(crate) fn cancel_pending_spawn(&self, parent_id: ThreadId, token: &str) -> bool {
+        let pending = self
+            .pending_spawns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(spawn) = pending
+            .iter()
+            .find(|spawn| spawn.parent_id == parent_id && spawn.token.as_deref() == Some(token))
+        else {
+            return false;
+        };
+        spawn.cancel.cancel();
+        true
+    }
+
+    /// The store `thread_id` shares: that of the top-level thread its subagent tree grows from,
+    /// so every agent in a tree, at any depth, reads and writes the same one.
+    pub(crate) async fn shared_scope(&self, thread_id: ThreadId) -> ThreadId {
+        let subagents = self.subagents.read().await;
+        let mut scope = thread_id;
+        // Bound the walk by the graph size so a pre-existing cycle cannot hang us.
+        for _ in 0..subagents.len() {
+            match subagents.get(&scope) {
+                Some(info) => scope = info.parent_id,
+                None => break,
+            }
+        }
+        scope
+    }
+
+    pub(crate) async fn shared_variables(&self, scope: ThreadId) -> BTreeMap<String, String> {
+        self.shared_variables
+            .read()
+            .await
+            .get(&scope)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Set `key` in the store for `scope`, or remove it when `value` is `None`. New keys are
+    /// refused once the store holds [`MAX_SHARED_VARIABLES`] entries.
+    pub(crate) async fn set_shared_variable(
+        &self,
+        scope: ThreadId,
+        key: String,
+        value: Option<String>,
+    ) -> CodexResult<()> {
+        let mut stores = self
+            .bounded_lock("shared variables", self.shared_variables.write())
+            .await?;
+        let Some(value) = value else {
+            if let Some(store) = stores.get_mut(&scope) {
+                store.remove(&key);
+                if store.is_empty() {
+                    stores.remove(&scope);
+                }
+            }
+            return Ok(());
+        };
+        let store = stores.entry(scope).or_default();
+        if !store.contains_key(&key) && store.len() >= MAX_SHARED_VARIABLES {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "shared variable limit reached: {MAX_SHARED_VARIABLES} variables are set"
+            )));
+        }
+        store.insert(key, value);
+        Ok(())
+    }
+
+    /// Context pinned by `parent_id`, oldest first.
+    pub(crate) async fn pinned_context(&self, parent_id: ThreadId) -> Vec<String> {
+        self.pinned_context
+            .read()
+            .await
+            .get(&parent_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Append `text` to `parent_id`'s pinned context. Refused when the parent's pinned items
+    /// would exceed [`MAX_PINNED_CONTEXT_CHARS`] in total.
+    pub(crate) async fn pin_context(
+        &self,
+        parent_id: ThreadId,
+        text: String,
+    ) -> CodexResult<PinnedContextUsage> {
+        let mut pinned = self
+            .bounded_lock("pinned context", self.pinned_context.write())
+            .await?;
+        let items = pinned.entry(parent_id).or_default();
+        let used: usize = items.iter().map(|item| item.chars().count()).sum();
+        let chars = used.saturating_add(text.chars().count());
+        if chars > MAX_PINNED_CONTEXT_CHARS {
+            return Err(CodexErr::UnsupportedOperation(format!(
+                "pinned context is limited to {MAX_PINNED_CONTEXT_CHARS} characters and {used} are already pinned; clear it or pin less"
+            )));
+        }
+        items.push(text);
+        Ok(PinnedContextUsage {
+            items: items.len(),
+            chars,
+        })
+    }
+
+    /// Drop everything `parent_id` pinned, returning how many items were removed.
+    pub(crate) async fn clear_pinned_context(&self, parent_id: ThreadId) -> usize {
+        self.pinned_context
+            .write()
+            .await
+            .remove(&parent_id)
+            .map_or(0, |items| items.len())
+    }
+
+    /// Entries in the scratchpad of `scope`, oldest first.
+    pub(crate) async fn scratchpad(&self, scope: ThreadId) -> Vec<String> {
+        self.scratchpads
+            .read()
+            .await
+            .get(&scope)
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Append `text` to the scratchpad of `scope`, dropping the oldest entries while the total
+    /// exceeds [`MAX_SCRATCHPAD_CHARS`]. `text` itself must fit within that limit.
+    pub(crate) async fn append_scratchpad(
+        &self,
+        scope: ThreadId,
+        text: String,
+    ) -> CodexResult<ScratchpadUsage> {
+        let mut scratchpads = self
+            .bounded_lock("scratchpads", self.scratchpads.write())
+            .await?;
+        let entries = scratchpads.entry(scope).or_default();
+        entries.push_back(text);
+        let mut chars: usize = entries.iter().map(|entry| entry.chars().count()).sum();
+        let mut trimmed_entries = 0;
+        while chars > MAX_SCRATCHPAD_CHARS {
+            let Some(oldest) = entries.pop_front() else {
+                break;
+            };
+            chars -= oldest.chars().count();
+            trimmed_entries += 1;
+        }
+        Ok(ScratchpadUsage {
+            entries: entries.len(),
+            chars,
+            trimmed_entries,
+        })
+    }
+
+    /// Fold an instruction update into the config `subagent_id` was spawned with.
+    pub(crate) async fn amend_subagent_spawn_config(&self, subagent_id: ThreadId, amendment: &str) {
+        if let Some(info) = self.subagents.write().await.get_mut(&subagent_id) {
+            let config = Arc::make_mut(&mut info.spawn_config);
+            config.developer_instructions = crate::agent_personas::with_instruction_amendment(
+                config.developer_instructions.as_deref(),
+                amendment,
+            );
+        }
+    }
+
+    /// Whether `subagent_id` is a direct subagent of `parent_id`. A graph lock timeout reads as
+    /// `false`, so callers refuse the operation rather than hang.
+    pub(crate) async fn is_subagent_of(&self, parent_id: ThreadId, subagent_id: ThreadId) -> bool {
+        self.bounded_lock("subagents", self.subagents.read())
+            .await
+            .is_ok_and(|subagents| {
+                subagents
+                    .get(&subagent_id)
+                    .is_some_and(|info| info.parent_id == parent_id)
+            })
+    }
+
+    /// Set or clear the paused flag of `subagent_id`. Returns whether the flag changed, so
+    /// repeating a pause or resume counts nothing. Resuming wakes the subagent's drain.
+    pub(crate) async fn set_subagent_paused(
+        &self,
+        subagent_id: ThreadId,
+        paused: bool,
+    ) -> CodexResult<bool> {
+        let changed = {
+            let mut subagents = self
+                .bounded_lock("subagents", self.subagents.write())
+                .await?;
+            let Some(info) = subagents.get_mut(&subagent_id) else {
+                return Err(CodexErr::ThreadNotFound(subagent_id));
+            };
+            std::mem::replace(&mut info.paused, paused) != paused
+        };
+        if changed && !paused {
+            self.subagent_resumed.notify_waiters();
+        }
+        Ok(changed)
+    }
+
+    /// Whether `subagent_id` is paused. Unknown subagents are not.
+    pub(crate) async fn is_subagent_paused(&self, subagent_id: ThreadId) -> CodexResult<bool> {
+        let subagents = self
+            .bounded_lock("subagents", self.subagents.read())
+            .await?;
+        Ok(subagents.get(&subagent_id).is_some_and(|info| info.paused))
+    }
+
+    /// Return once `subagent_id` is not paused, or is no longer registered. A graph lock timeout
+    /// returns too, so a stuck lock cannot stall the caller's drain indefinitely.
+    pub(crate) async fn wait_while_paused(&self, subagent_id: ThreadId) {
+        loop {
+            let resumed = self.subagent_resumed.notified();
+            tokio::pin!(resumed);
+            resumed.as_mut().enable();
+            if !self.is_subagent_paused(subagent_id).await.unwrap_or(false) {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    /// Whether `descendant_id` sits anywhere below `ancestor_id` in the subagent tree. Unlike
+    /// [`Self::is_subagent_of`], this follows the parent chain past direct children. A graph lock
+    /// timeout reads as `false`.
+    pub(crate) async fn is_descendant_of(
+        &self,
+        ancestor_id: ThreadId,
+        descendant_id: ThreadId,
+    ) -> bool {
+        let Ok(subagents) = self.bounded_lock("subagents", self.subagents.read()).await else {
+            return false;
+        };
+        let mut current = descendant_id;
+        // Bound the walk by the graph size so a pre-existing cycle cannot hang us.
+        for _ in 0..subagents.len() {
+            let Some(info) = subagents.get(&current) else {
+                return false;
+            };
+            if info.parent_id == ancestor_id {
+                return true;
+            }
+            current = info.parent_id;
+        }
+        false
+    }
+
+    /// The parent shared by two distinct subagents, or `None` when they are not siblings.
+    pub(crate) async fn sibling_parent(
+        &self,
+        subagent_id: ThreadId,
+        other_id: ThreadId,
+    ) -> Option<ThreadId> {
+        if subagent_id == other_id {
+            return None;
+        }
+        let subagents = self.subagents.read().await;
+        let parent_id = subagents.get(&subagent_id)?.parent_id;
+        subagents
+            .get(&other_id)
+            .filter(|info| info.parent_id == parent_id)
+            .map(|_| parent_id)
+    }
+
+    /// Apply `update` to the live output of `subagent_id`. The update is dropped when the outputs
+    /// lock stays held past the state lock timeout, so one stuck reader cannot stall every drain.
+    async fn update_subagent_output(
+        &self,
+        subagent_id: ThreadId,
+        update: impl FnOnce(&mut SubagentOutput),
+    ) {
+        if let Ok(mut outputs) = self
+            .bounded_lock("subagent_outputs", self.subagent_outputs.write())
+            .await
+            && let Some(output) = outputs.get_mut(&subagent_id)
+        {
+            update(output);
+        }
+    }
+
+    pub(crate) async fn record_subagent_delta(&self, subagent_id: ThreadId, delta: &str) {
+        self.update_subagent_output(subagent_id, |output| output.push_delta(delta))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_message(&self, subagent_id: ThreadId, message: &str) {
+        self.update_subagent_output(subagent_id, |output| output.set_message(message))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_turn_started(&self, subagent_id: ThreadId) {
+        self.update_subagent_output(subagent_id, |output| output.start_turn())
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_shutdown_reason(
+        &self,
+        subagent_id: ThreadId,
+        reason: &str,
+    ) {
+        self.update_subagent_output(subagent_id, |output| {
+            output.shutdown_reason = Some(reason.to_string())
+        })
+        .await;
+    }
+
+    /// Keep a group-chat post that could not be delivered to the subagent's parent, so a later
+    /// `agent_output` (or the tombstone, once the subagent is removed) still shows it.
+    pub(crate) async fn record_subagent_undelivered_message(
+        &self,
+        subagent_id: ThreadId,
+        message: String,
+    ) {
+        {
+            let Ok(mut outputs) = self
+                .bounded_lock("subagent_outputs", self.subagent_outputs.write())
+                .await
+            else {
+                return;
+            };
+            if let Some(output) = outputs.get_mut(&subagent_id) {
+                output.push_undelivered_message(message);
+                return;
+            }
+        }
+        if let Some(tombstone) = self.subagent_tombstones.write().await.get_mut(&subagent_id) {
+            push_undelivered_message(&mut tombstone.output.undelivered_messages, message);
+        }
+    }
+
+    pub(crate) async fn record_subagent_turn_complete(&self, subagent_id: ThreadId) {
+        self.update_subagent_output(subagent_id, |output| output.complete_turn())
+            .await;
+    }
+
+    pub(crate) async fn reset_subagent_output(&self, subagent_id: ThreadId) {
+        self.update_subagent_output(subagent_id, |output| output.reset_for_prompt())
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_reasoning_summary_delta(
+        &self,
+        subagent_id: ThreadId,
+        delta: &str,
+    ) {
+        self.update_subagent_output(subagent_id, |output| {
+            output.push_reasoning_summary_delta(delta)
+        })
+        .await;
+    }
+
+    pub(crate) async fn record_subagent_reasoning_raw_delta(
+        &self,
+        subagent_id: ThreadId,
+        delta: &str,
+    ) {
+        self.update_subagent_output(subagent_id, |output| output.push_reasoning_raw_delta(delta))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_file_changes(
+        &self,
+        subagent_id: ThreadId,
+        changes: HashMap<PathBuf, FileChange>,
+    ) {
+        self.update_subagent_output(subagent_id, |output| output.push_file_changes(changes))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_rate_limits(
+        &self,
+        subagent_id: ThreadId,
+        rate_limits: RateLimitSnapshot,
+    ) {
+        self.update_subagent_output(subagent_id, |output| output.rate_limits = Some(rate_limits))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_turn_diff(&self, subagent_id: ThreadId, diff: &str) {
+        self.update_subagent_output(subagent_id, |output| output.set_turn_diff(diff))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_warning(&self, subagent_id: ThreadId, warning: String) {
+        self.update_subagent_output(subagent_id, |output| output.push_warning(warning))
+            .await;
+    }
+
+    pub(crate) async fn record_subagent_tool_event(&self, subagent_id: ThreadId, event: String) {
+        self.update_subagent_output(subagent_id, |output| output.push_tool_event(event))
+            .await;
+    }
+
+    pub(crate) async fn subscribe_subagent_output(
+        &self,
+        subagent_id: ThreadId,
+    ) -> Option<broadcast::Receiver<SubagentOutputUpdate>> {
+        self.subagent_outputs
+            .read()
+            .await
+            .get(&subagent_id)
+            .map(|output| output.updates.subscribe())
+    }
+
+    /// Run the host's message transform, if any, over a subagent post bound for group chat.
+    pub(crate) fn transform_subagent_message(
+        &self,
+        sender: &GroupChatSender,
+        text: String,
+    ) -> Option<String> {
+        match &self.options.subagent_message_transform {
+            Some(transform) => transform(sender, &text),
+            None => Some(text),
+        }
+    }
+
+    pub(crate) fn subagent_prompt_affixes(&self) -> (Option<String>, Option<String>) {
+        (
+            self.options.subagent_prompt_prefix.clone(),
+            self.options.subagent_prompt_suffix.clone(),
+        )
+    }
+
+    pub(crate) fn record_subagent_status(&self, subagent_id: ThreadId, status: &AgentStatus) {
+        if let Some(metrics) = &self.options.subagent_metrics {
+            metrics.subagent_status_changed(subagent_id, status);
+        }
+    }
+
+    /// Record a subagent's cumulative token usage and charge any growth to all its ancestors.
+    pub(crate) async fn record_subagent_token_usage(
+        &self,
+        subagent_id: ThreadId,
+        total_usage: &TokenUsage,
+    ) {
+        self.update_subagent_output(subagent_id, |output| {
+            output.token_usage = Some(total_usage.clone())
+        })
+        .await;
+        let total_tokens = total_usage.total_tokens;
+        let subagents = self.subagents.read().await;
+        let Some(info) = subagents.get(&subagent_id) else {
+            return;
+        };
+        let mut tally = self.subagent_tokens.write().await;
+        let previous = tally
+            .per_subagent
+            .insert(subagent_id, total_tokens)
+            .unwrap_or(0);
+        let Ok(delta) = u64::try_from(total_tokens.saturating_sub(previous)) else {
+            return;
+        };
+        if delta == 0 {
+            return;
+        }
+        let mut ancestor = info.parent_id;
+        for _ in 0..=subagents.len() {
+            let used = tally.per_parent.entry(ancestor).or_insert(0);
+            *used = used.saturating_add(delta);
+            match subagents.get(&ancestor) {
+                Some(parent) => ancestor = parent.parent_id,
+                None => break,
+            }
+        }
+    }
+
+    pub(crate) async fn subagent_token_usage(&self, parent_id: ThreadId) -> u64 {
+        self.subagent_tokens
+            .read()
+            .await
+            .per_parent
+            .get(&parent_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Tokens used by the largest subagent tree `thread_id` sits in: its own or that of any
+    /// ancestor up to the root. The budget applies to each of those trees.
+    pub(crate) async fn subagent_token_usage_up_to_root(&self, thread_id: ThreadId) -> u64 {
+        let subagents = self.subagents.read().await;
+        let tally = self.subagent_tokens.read().await;
+        let mut current = thread_id;
+        let mut used = 0;
+        // Bound the walk by the graph size so a pre-existing cycle cannot hang us.
+        for _ in 0..=subagents.len() {
+            used = used.max(tally.per_parent.get(&current).copied().unwrap_or(0));
+            match subagents.get(&current) {
+                Some(info) => current = info.parent_id,
+                None => break,
+            }
+        }
+        used
+    }
+
+    pub(crate) fn subagent_token_budget(&self) -> Option<u64> {
+        self.options.subagent_token_budget
+    }
+
+    pub(crate) fn collab_limits(&self) -> CollabLimits {
+        self.options.collab_limits
+    }
+
+    fn state_lock_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.options
+                .state_lock_timeout_ms
+                .unwrap_or(DEFAULT_STATE_LOCK_TIMEOUT_MS),
+        )
+    }
+
+    /// Await `acquire`, an acquisition of the lock named `lock`, for at most the configured state
+    /// lock timeout, so a stuck holder surfaces as an error instead of a hung turn.
+    async fn bounded_lock<G>(
+        &self,
+        lock: &'static str,
+        acquire: impl Future<Output = G>,
+    ) -> CodexResult<G> {
+        let timeout = self.state_lock_timeout();
+        tokio::time::timeout(timeout, acquire).await.map_err(|_| {
+            tracing::warn!("timed out after {timeout:?} waiting for the {lock} lock");
+            CodexErr::StateLockTimeout { lock, timeout }
+        })
+    }
+
+    pub(crate) fn subagent_status_preview_chars(&self) -> usize {
+        self.options
+            .subagent_status_preview_chars
+            .unwrap_or(DEFAULT_SUBAGENT_STATUS_PREVIEW_CHARS)
+    }
+
+    pub(crate) fn subagent_group_chat_posts_per_second(&self) -> u32 {
+        self.options
+            .subagent_group_chat_posts_per_second
+            .unwrap_or(DEFAULT_SUBAGENT_GROUP_CHAT_POSTS_PER_SECOND)
+    }
+
+    /// Count a group-chat post from `subagent_id` against its per-second limit. Threads that
+    /// are not registered subagents are never limited.
+    pub(crate) async fn admit_subagent_group_chat_post(
+        &self,
+        subagent_id: ThreadId,
+    ) -> GroupChatPostAdmission {
+        let max_posts = self.subagent_group_chat_posts_per_second();
+        let mut subagents = self.subagents.write().await;
+        match subagents.get_mut(&subagent_id) {
+            Some(info) => info.group_chat_posts.admit(Instant::now(), max_posts),
+            None => GroupChatPostAdmission::Allowed { dropped: 0 },
+        }
+    }
+
+    /// Deliver a post from `subagent_id` that the rate limit held back, without counting it
+    /// against the current window. Returns how many of its other posts were dropped since the
+    /// last one allowed.
+    pub(crate) async fn admit_held_subagent_group_chat_post(&self, subagent_id: ThreadId) -> u32 {
+        self.subagents
+            .write()
+            .await
+            .get_mut(&subagent_id)
+            .map_or(0, |info| info.group_chat_posts.admit_held())
+    }
+
+    /// Count a tool-event echo from `subagent_id` against the host's echo limit, returning the
+    /// parent to echo to. `None` when echoes are disabled, the subagent is not registered, or it
+    /// is over its limit for the current window.
+    pub(crate) async fn admit_subagent_tool_echo(&self, subagent_id: ThreadId) -> Option<ThreadId> {
+        let max_echoes = self
+            .options
+            .subagent_tool_echoes_per_second
+            .filter(|max| *max > 0)?;
+        let mut subagents = self.subagents.write().await;
+        let info = subagents.get_mut(&subagent_id)?;
+        match info.tool_echoes.admit(Instant::now(), max_echoes) {
+            GroupChatPostAdmission::Allowed { .. } => Some(info.parent_id),
+            GroupChatPostAdmission::Limited => None,
+        }
+    }
+
+    pub(crate) fn record_subagent_wait_timeout(&self, subagent_id: ThreadId) {
+        if let Some(metrics) = &self.options.subagent_metrics {
+            metrics.subagent_wait_timed_out(subagent_id);
+        }
+    }
+
+    async fn insert_subagent_tombstone(
+        &self,
+        subagent_id: ThreadId,
+        parent_id: ThreadId,
+        output: SubagentOutputSnapshot,
+    ) {
+        let mut tombstones = self.subagent_tombstones.write().await;
+        let now = Instant::now();
+        tombstones.retain(|_, tombstone| {
+            now.duration_since(tombstone.removed_at) < SUBAGENT_TOMBSTONE_TTL
+        });
+        while tombstones.len() >= MAX_SUBAGENT_TOMBSTONES {
+            let Some(oldest) = tombstones
+                .iter()
+                .min_by_key(|(_, tombstone)| tombstone.removed_at)
+                .map(|(id, _)| *id)
+            else {
+                break;
+            };
+            tombstones.remove(&oldest);
+        }
+        tombstones.insert(
+            subagent_id,
+            SubagentTombstone {
+                parent_id,
+                output,
+                removed_at: now,
+            },
+        );
+    }
+
+    /// Final output of a subagent below `ancestor_id` that was removed within the tombstone TTL.
+    /// Ancestry is checked like [`Self::is_descendant_of`], through the removed subagent's parent.
+    pub(crate) async fn subagent_tombstone_snapshot(
+        &self,
+        ancestor_id: ThreadId,
+        subagent_id: ThreadId,
+        max_chars: Option<usize>,
+    ) -> Option<SubagentOutputSnapshot> {
+        let (parent_id, output) = self
+            .subagent_tombstones
+            .read()
+            .await
+            .get(&subagent_id)
+            .filter(|tombstone| tombstone.removed_at.elapsed() < SUBAGENT_TOMBSTONE_TTL)
+            .map(|tombstone| (tombstone.parent_id, tombstone.output.clone()))?;
+        if parent_id != ancestor_id && !self.is_descendant_of(ancestor_id, parent_id).await {
+            return None;
+        }
+        Some(output.limit(max_chars))
+    }
+
+    /// Time since `subagent_id` was registered and the number of turns it has started.
+    pub(crate) async fn subagent_lifetime(&self, subagent_id: ThreadId) -> Option<(Duration, u32)> {
+        self.subagent_outputs
+            .read()
+            .await
+            .get(&subagent_id)
+            .map(|output| (output.spawned_at.elapsed(), output.turns))
+    }
+
+    /// When the drain last saw activity from `subagent_id`, or `None` when it is not tracked.
+    pub(crate) async fn subagent_last_activity(&self, subagent_id: ThreadId) -> Option<Instant> {
+        self.subagent_outputs
+            .read()
+            .await
+            .get(&subagent_id)
+            .map(|output| output.last_activity)
+    }
+
+    pub(crate) async fn subagent_output_snapshot(
+        &self,
+        subagent_id: ThreadId,
+        max_chars: Option<usize>,
+    ) -> Option<SubagentOutputSnapshot> {
+        self.subagent_outputs
+            .read()
+            .await
+            .get(&subagent_id)
+            .map(|output| output.snapshot(max_chars))
+    }
+}
+
+impl SubagentOutput {
+    fn new() -> Self {
+        let (updates, _) = broadcast::channel(SUBAGENT_OUTPUT_CHANNEL_CAPACITY);
+        Self {
+            partial: String::new(),
+            last_message: None,
+            turn_message: false,
+            reasoning_summary: String::new(),
+            reasoning_raw: String::new(),
+            tool_events: Vec::new(),
+            truncated: false,
+            token_usage: None,
+            rate_limits: None,
+            turn_diff: None,
+            warnings: Vec::new(),
+            file_changes: Vec::new(),
+            last_activity: Instant::now(),
+            spawned_at: Instant::now(),
+            turns: 0,
+            max_tool_events: MAX_SUBAGENT_TOOL_EVENTS,
+            max_output_chars: MAX_SUBAGENT_OUTPUT_CHARS,
+            max_reasoning_chars: MAX_SUBAGENT_REASONING_CHARS,
+            shutdown_reason: None,
+            undelivered_messages: Vec::new(),
+            updates,
+            preserve_code_blocks: true,
+        }
+    }
+
+    fn push_delta(&mut self, delta: &str) {
+        self.last_activity = Instant::now();
+        self.partial.push_str(delta);
+        self.truncated |= trim_to_max_chars(
+            &mut self.partial,
+            self.max_output_chars,
+            self.preserve_code_blocks,
+        );
+        // Sending only fails when nobody is subscribed.
+        let _ = self
+            .updates
+            .send(SubagentOutputUpdate::Delta(delta.to_string()));
+    }
+
+    fn push_reasoning_summary_delta(&mut self, delta: &str) {
+        self.last_activity = Instant::now();
+        self.reasoning_summary.push_str(delta);
+        self.truncated |= trim_to_max_chars(
+            &mut self.reasoning_summary,
+            self.max_reasoning_chars,
+            self.preserve_code_blocks,
+        );
+    }
+
+    fn push_reasoning_raw_delta(&mut self, delta: &str) {
+        self.last_activity = Instant::now();
+        self.reasoning_raw.push_str(delta);
+        self.truncated |= trim_to_max_chars(
+            &mut self.reasoning_raw,
+            self.max_reasoning_chars,
+            self.preserve_code_blocks,
+        );
+    }
+
+    fn push_tool_event(&mut self, event: String) {
+        self.last_activity = Instant::now();
+        self.tool_events.push(event);
+        if self.tool_events.len() > self.max_tool_events {
+            let overflow = self.tool_events.len().saturating_sub(self.max_tool_events);
+            self.tool_events.drain(..overflow);
+            self.truncated = true;
+        }
+    }
+
+    fn push_file_changes(&mut self, changes: HashMap<PathBuf, FileChange>) {
+        self.last_activity = Instant::now();
+        let mut changes = changes
+            .into_iter()
+            .map(|(path, change)| SubagentFileChange { path, change })
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        self.file_changes.extend(changes);
+        if self.file_changes.len() > MAX_SUBAGENT_FILE_CHANGES {
+            let overflow = self.file_changes.len() - MAX_SUBAGENT_FILE_CHANGES;
+            self.file_changes.drain(..overflow);
+            self.truncated = true;
+        }
+    }
+
+    fn set_turn_diff(&mut self, diff: &str) {
+        self.last_activity = Instant::now();
+        let mut diff = diff.to_string();
+        self.truncated |= trim_to_max_chars(&mut diff, MAX_SUBAGENT_TURN_DIFF_CHARS, false);
+        self.turn_diff = Some(diff);
+    }
+
+    fn push_warning(&mut self, warning: String) {
+        self.last_activity = Instant::now();
+        self.warnings.push(warning);
+        if self.warnings.len() > MAX_SUBAGENT_WARNINGS {
+            let overflow = self.warnings.len() - MAX_SUBAGENT_WARNINGS;
+            self.warnings.drain(..overflow);
+            self.truncated = true;
+        }
+    }
+
+    fn push_undelivered_message(&mut self, message: String) {
+        self.last_activity = Instant::now();
+        push_undelivered_message(&mut self.undelivered_messages, message);
+    }
+
+    fn set_message(&mut self, message: &str) {
+        self.last_activity = Instant::now();
+        self.last_message = Some(message.to_string());
+        self.turn_message = true;
+        self.partial.clear();
+        let _ = self
+            .updates
+            .send(SubagentOutputUpdate::Message(message.to_string()));
+    }
+
+    fn start_turn(&mut self) {
+        self.last_activity = Instant::now();
+        self.turn_message = false;
+        self.turns = self.turns.saturating_add(1);
+    }
+
+    /// Promote streamed text to `last_message` when the turn produced no completed message (for
+    /// example when only deltas were emitted), so completed agents always report a final message.
+    /// A completed message always wins over text streamed after it.
+    fn complete_turn(&mut self) {
+        if !self.turn_message && !self.partial.trim().is_empty() {
+            let message = std::mem::take(&mut self.partial);
+            self.set_message(&message);
+        }
+    }
+
+    fn reset_for_prompt(&mut self) {
+        self.last_activity = Instant::now();
+        self.turn_message = false;
+        self.partial.clear();
+        self.reasoning_summary.clear();
+        self.reasoning_raw.clear();
+        self.tool_events.clear();
+        self.file_changes.clear();
+        self.turn_diff = None;
+        self.warnings.clear();
+        self.truncated = false;
+    }
+
+    fn snapshot(&self, max_chars: Option<usize>) -> SubagentOutputSnapshot {
+        SubagentOutputSnapshot {
+            partial: snapshot_text(self.partial.as_str(), max_chars),
+            last_message: self.last_message.clone(),
+            reasoning_summary: snapshot_text(self.reasoning_summary.as_str(), max_chars),
+            reasoning_raw: snapshot_text(self.reasoning_raw.as_str(), max_chars),
+            tool_events: self.tool_events.clone(),
+            truncated: self.truncated,
+            token_usage: self.token_usage.clone(),
+            rate_limits: self.rate_limits.clone(),
+            turn_diff: self.turn_diff.clone(),
+            warnings: self.warnings.clone(),
+            file_changes: self.file_changes.clone(),
+            last_activity: Some(self.last_activity),
+            spawned_at: Some(self.spawned_at),
+            turns: self.turns,
+            shutdown_reason: self.shutdown_reason.clone(),
+            undelivered_messages: self.undelivered_messages.clone(),
+        }
+    }
+}
+
+impl SubagentOutputSnapshot {
+    fn limit(self, max_chars: Option<usize>) -> Self {
+        let limit = |value: Option<String>| {
+            value.and_then(|value| snapshot_text(value.as_str(), max_chars))
+        };
+        Self {
+            partial: limit(self.partial),
+            last_message: self.last_message,
+            reasoning_summary: limit(self.reasoning_summary),
+            reasoning_raw: limit(self.reasoning_raw),
+            tool_events: self.tool_events,
+            truncated: self.truncated,
+            token_usage: self.token_usage,
+            rate_limits: self.rate_limits,
+            turn_diff: self.turn_diff,
+            warnings: self.warnings,
+            file_changes: self.file_changes,
+            last_activity: self.last_activity,
+            spawned_at: self.spawned_at,
+            turns: self.turns,
+            shutdown_reason: self.shutdown_reason,
+            undelivered_messages: self.undelivered_messages,
+        }
+    }
+
+    /// Drop ANSI escape sequences and other control characters, which exec output often carries,
+    /// from the captured text. Diffs are left untouched.
+    pub(crate) fn strip_control_sequences(self) -> Self {
+        let strip = |value: Option<String>| value.map(|value| strip_control_sequences(&value));
+        let strip_all = |values: Vec<String>| {
+            values
+                .iter()
+                .map(|value| strip_control_sequences(value))
+                .collect()
+        };
+        Self {
+            partial: strip(self.partial),
+            last_message: strip(self.last_message),
+            reasoning_summary: strip(self.reasoning_summary),
+            reasoning_raw: strip(self.reasoning_raw),
+            tool_events: strip_all(self.tool_events),
+            warnings: strip_all(self.warnings),
+            undelivered_messages: strip_all(self.undelivered_messages),
+            ..self
+        }
+    }
+
+    /// Render the snapshot for hosts; the structured fields stay the source of truth.
+    pub(crate) fn render(&self, format: SubagentOutputFormat) -> String {
+        if format == SubagentOutputFormat::Json {
+            let value = serde_json::to_value(SubagentOutputView::from(self)).unwrap_or_default();
+            return format!("{value:#}");
+        }
+
+        let list = |items: &[String]| {
+            items
+                .iter()
+                .map(|item| format!("- {item}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut sections = Vec::new();
+        if let Some(message) = &self.last_message {
+            sections.push(("Message", message.clone()));
+        }
+        if let Some(partial) = self.partial.as_ref().filter(|partial| !partial.is_empty()) {
+            sections.push(("In progress", partial.clone()));
+        }
+        if let Some(reasoning) = self
+            .reasoning_summary
+            .as_ref()
+            .filter(|reasoning| !reasoning.is_empty())
+        {
+            sections.push(("Reasoning", reasoning.clone()));
+        }
+        if !self.tool_events.is_empty() {
+            sections.push(("Tools", list(&self.tool_events)));
+        }
+        if !self.warnings.is_empty() {
+            sections.push(("Warnings", list(&self.warnings)));
+        }
+        if !self.undelivered_messages.is_empty() {
+            sections.push(("Undelivered messages", list(&self.undelivered_messages)));
+        }
+        if let Some(reason) = &self.shutdown_reason {
+            sections.push(("Shutdown reason", reason.clone()));
+        }
+        if self.truncated {
+            sections.push((
+                "Note",
+                "Older output was dropped since the last prompt.".to_string(),
+            ));
+        }
+        sections
+            .into_iter()
+            .map(|(title, body)| {
+                if format == SubagentOutputFormat::Markdown {
+                    format!("## {title}\n\n{body}")
+                } else {
+                    format!("{title}:\n{body}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+fn push_undelivered_message(messages: &mut Vec<String>, message: String) {
+    messages.push(message);
+    if messages.len() > MAX_SUBAGENT_UNDELIVERED_MESSAGES {
+        let overflow = messages.len() - MAX_SUBAGENT_UNDELIVERED_MESSAGES;
+        messages.drain(..overflow);
+    }
+}
+
+fn count_subagent_slots(
+    subagents: &HashMap<ThreadId, SubagentInfo>,
+    pending: &[PendingSpawn],
+    parent_id: ThreadId,
+) -> SubagentSlots {
+    SubagentSlots {
+        active: subagents
+            .values()
+            .filter(|info| info.parent_id == parent_id)
+            .count(),
+        starting: pending
+            .iter()
+            .filter(|spawn| spawn.parent_id == parent_id)
+            .count(),
+    }
+}
+
+/// Remove ANSI escape sequences (CSI, OSC, and two-character escapes) and every other control
+/// character except newlines and tabs.
+pub(crate) fn strip_control_sequences(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => match chars.next() {
+                // CSI: parameter and intermediate bytes up to a final byte in `@..=~`.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: terminated by BEL or by ST (`ESC \`).
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' {
+                            break;
+                        }
+                        if c == '\u{1b}' && chars.next_if_eq(&'\\').is_some() {
+                            break;
+                        }
+                    }
+                }
+                // Any other escape is two characters long.
+                _ => {}
+            },
+            '\n' | '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn snapshot_text(value: &str, max_chars: Option<usize>) -> Option<String> {
+    match max_chars {
+        Some(limit) => trim_snapshot(value, limit),
+        None if value.is_empty() => None,
+        None => Some(value.to_string()),
+    }
+}
+
+/// Drop the oldest text beyond `max_chars`, returning whether anything was dropped.
+///
+/// With `preserve_code_blocks`, whole blocks are dropped instead: prose paragraphs first, then
+/// fenced code blocks, oldest first within each kind. The newest block is never dropped; when it
+/// alone exceeds the budget, its oldest characters are trimmed instead.
+fn trim_to_max_chars(value: &mut String, max_chars: usize, preserve_code_blocks: bool) -> bool {
+    let mut total = value.chars().count();
+    if total <= max_chars {
+        return false;
+    }
+    if preserve_code_blocks {
+        let blocks = markdown_blocks(value);
+        let mut keep = vec![true; blocks.len()];
+        for drop_code in [false, true] {
+            for (index, (range, is_code)) in blocks.iter().enumerate() {
+                if total <= max_chars {
+                    break;
+                }
+                if *is_code != drop_code || index + 1 == blocks.len() {
+                    continue;
+                }
+                keep[index] = false;
+                total -= value[range.clone()].chars().count();
+            }
+        }
+        *value = blocks
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|((range, _), _)| &value[range.clone()])
+            .collect();
+        if total <= max_chars {
+            return true;
+        }
+    }
+    let trim_chars = total.saturating_sub(max_chars);
+    let start = value
+        .char_indices()
+        .nth(trim_chars)
+        .map(|(idx, _)| idx)
+        .unwrap_or(0);
+    value.drain(..start);
+    true
+}
+
+/// Split markdown into contiguous byte ranges, each flagged when it is a fenced code block.
+/// Prose splits into paragraphs at blank lines, and blank lines stay with the block before
+/// them. A fence that is still open runs to the end of the text.
+fn markdown_blocks(text: &str) -> Vec<(Range<usize>, bool)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut is_code = false;
+    let mut open_fence: Option<(char, usize)> = None;
+    let mut after_break = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+        if let Some((fence_char, fence_len)) = open_fence {
+            if trimmed.len() >= fence_len && trimmed.chars().all(|c| c == fence_char) {
+                open_fence = None;
+                after_break = true;
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            after_break = true;
+            continue;
+        }
+        let fence = code_fence(trimmed);
+        if line_start > start && (fence.is_some() || after_break) {
+            blocks.push((start..line_start, is_code));
+            start = line_start;
+        }
+        is_code = fence.is_some();
+        open_fence = fence;
+        after_break = false;
+    }
+    if start < text.len() {
+        blocks.push((start..text.len(), is_code));
+    }
+    blocks
+}
+
+/// The fence character and length when `line` opens a fenced code block.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let fence_len = line.chars().take_while(|c| *c == fence_char).count();
+    (fence_len >= 3).then_some((fence_char, fence_len))
+}
+
+fn trim_snapshot(value: &str, max_chars: usize) -> Option<String> {
+    if value.is_empty() {
+        return None;
     }
     let total = value.chars().count();
     if total <= max_chars {
         return Some(value.to_string());
     }
-    let trim_chars = total.saturating_sub(max_chars);
-    let start = value
-        .char_indices()
-        .nth(trim_chars)
-        .map(|(idx, _)| idx)
-        .unwrap_or(0);
-    Some(value[start..].to_string())
-}
+    let trim_chars = total.saturating_sub(max_chars);
+    let start = value
+        .char_indices()
+        .nth(trim_chars)
+        .map(|(idx, _)| idx)
+        .unwrap_or(0);
+    Some(value[start..].to_string())
+}
+
+/// The members of a team that are mid-turn, and how long they have all gone without activity:
+/// the shortest time since any of them last did something. `None` when no member is mid-turn,
+/// since a team whose members all finished their turns is done rather than blocked.
+fn blocked_team(team: &[(ThreadId, AgentStatus, Duration)]) -> Option<(Vec<ThreadId>, Duration)> {
+    let blocked: Vec<(ThreadId, Duration)> = team
+        .iter()
+        .filter(|(_, status, _)| matches!(status, AgentStatus::PendingInit | AgentStatus::Running))
+        .map(|(id, _, idle_for)| (*id, *idle_for))
+        .collect();
+    let idle_for = blocked.iter().map(|(_, idle_for)| *idle_for).min()?;
+    Some((blocked.into_iter().map(|(id, _)| id).collect(), idle_for))
+}
+
+/// Scan the rollouts under `codex_home` and map each recorded collab subagent to the parent,
+/// persona, and display name it was spawned with. Both the sessions directory and the default
+/// separate subagent directory are scanned. Rollouts without lineage (top-level threads) and
+/// unreadable files are skipped.
+pub async fn read_subagent_lineage(
+    codex_home: &Path,
+) -> std::io::Result<HashMap<ThreadId, SubagentLineage>> {
+    let mut lineage = HashMap::new();
+    let mut pending = vec![
+        codex_home.join(crate::SESSIONS_SUBDIR),
+        codex_home.join(crate::SUBAGENT_SESSIONS_SUBDIR),
+    ];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "jsonl")
+                && let Some((id, subagent)) = read_rollout_lineage(&path).await
+            {
+                lineage.insert(id, subagent);
+            }
+        }
+    }
+    Ok(lineage)
+}
+
+/// Lineage from the session meta line that starts a rollout file, if it has one.
+async fn read_rollout_lineage(path: &Path) -> Option<(ThreadId, SubagentLineage)> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let first_line = tokio::io::BufReader::new(file)
+        .lines()
+        .next_line()
+        .await
+        .ok()??;
+    match serde_json::from_str::<RolloutLine>(&first_line).ok()?.item {
+        RolloutItem::SessionMeta(meta_line) => {
+            let subagent = meta_line.meta.subagent?;
+            Some((meta_line.meta.id, subagent))
+        }
+        _ => None,
+    }
+}
+
+/// History to fork a checkpoint taken after `user_turns` user turns from: everything before the
+/// next user turn, or the whole history when no turn has started since.
+pub(crate) fn history_at_user_turns(history: InitialHistory, user_turns: usize) -> InitialHistory {
+    let items = history.get_rollout_items();
+    if truncation::user_message_positions_in_rollout(&items).len() > user_turns {
+        truncate_before_nth_user_message(history, user_turns)
+    } else {
+        InitialHistory::Forked(items)
+    }
+}
+
+/// Return a prefix of `items` obtained by cutting strictly before the nth user message
+/// (0-based) and all items that follow it.
+fn truncate_before_nth_user_message(history: InitialHistory, n: usize) -> InitialHistory {
+    let items: Vec<RolloutItem> = history.get_rollout_items();
+    let rolled = truncation::truncate_rollout_before_nth_user_message_from_start(&items, n);
+
+    if rolled.is_empty() {
+        InitialHistory::New
+    } else {
+        InitialHistory::Forked(rolled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::control::PerParentCapacity;
+    use crate::agent::control::SpawnCapacity;
+    use crate::agent::control::SubagentInspection;
+    use crate::agent::control::TokenBudgetCapacity;
+    use crate::agent::control::post_final_subagent_message;
+    use crate::agent::control::record_and_post_subagent_message;
+    use crate::built_in_model_providers;
+    use crate::codex::make_session_and_context;
+    use crate::config::test_config;
+    use crate::config::types::ToolPolicyToml;
+    use assert_matches::assert_matches;
+    use codex_protocol::models::ContentItem;
+    use codex_protocol::models::ReasoningItemReasoningSummary;
+    use codex_protocol::models::ResponseItem;
+    use codex_protocol::openai_models::ReasoningEffort;
+    use codex_protocol::protocol::ResumedHistory;
+    use pretty_assertions::assert_eq;
+
+    fn user_msg(text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: "user".to_string(),
+            content: vec![ContentItem::OutputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+    fn assistant_msg(text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: "assistant".to_string(),
+            content: vec![ContentItem::OutputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn drops_from_last_user_only() {
+        let items = [
+            user_msg("u1"),
+            assistant_msg("a1"),
+            assistant_msg("a2"),
+            user_msg("u2"),
+            assistant_msg("a3"),
+            ResponseItem::Reasoning {
+                id: "r1".to_string(),
+                summary: vec![ReasoningItemReasoningSummary::SummaryText {
+                    text: "s".to_string(),
+                }],
+                content: None,
+                encrypted_content: None,
+            },
+            ResponseItem::FunctionCall {
+                id: None,
+                call_id: "c1".to_string(),
+                name: "tool".to_string(),
+                arguments: "{}".to_string(),
+            },
+            assistant_msg("a4"),
+        ];
+
+        let initial: Vec<RolloutItem> = items
+            .iter()
+            .cloned()
+            .map(RolloutItem::ResponseItem)
+            .collect();
+        let truncated = truncate_before_nth_user_message(InitialHistory::Forked(initial), 1);
+        let got_items = truncated.get_rollout_items();
+        let expected_items = vec![
+            RolloutItem::ResponseItem(items[0].clone()),
+            RolloutItem::ResponseItem(items[1].clone()),
+            RolloutItem::ResponseItem(items[2].clone()),
+        ];
+        assert_eq!(
+            serde_json::to_value(&got_items).unwrap(),
+            serde_json::to_value(&expected_items).unwrap()
+        );
+
+        let initial2: Vec<RolloutItem> = items
+            .iter()
+            .cloned()
+            .map(RolloutItem::ResponseItem)
+            .collect();
+        let truncated2 = truncate_before_nth_user_message(InitialHistory::Forked(initial2), 2);
+        assert_matches!(truncated2, InitialHistory::New);
+    }
+
+    fn test_manager() -> ThreadManager {
+        ThreadManager::with_models_provider(
+            CodexAuth::from_api_key("Test API Key"),
+            built_in_model_providers()["openai"].clone(),
+        )
+    }
+
+    fn test_manager_with_options(options: ThreadManagerOptions) -> ThreadManager {
+        let mut manager = test_manager();
+        let Some(state) = Arc::get_mut(&mut manager.state) else {
+            panic!("fresh manager state should not be shared");
+        };
+        state.options = options;
+        manager
+    }
+
+    fn test_spawn_options(display_name: &str) -> SubagentSpawnOptions {
+        SubagentSpawnOptions {
+            display_name: Some(display_name.to_string()),
+            ..SubagentSpawnOptions::default()
+        }
+    }
+
+    fn test_subagent_info(parent_id: ThreadId, display_name: &str) -> SubagentInfo {
+        SubagentInfo {
+            parent_id,
+            persona: None,
+            display_name: Some(display_name.to_string()),
+            spawn_config: Arc::new(test_config()),
+            initial_prompt: "work".to_string(),
+            source_label: "collab".to_string(),
+            attachment_paths: Vec::new(),
+            priority: 0,
+            max_tool_events: MAX_SUBAGENT_TOOL_EVENTS,
+            last_input: Vec::new(),
+            group_chat_posts: GroupChatPostWindow::default(),
+            tool_echoes: GroupChatPostWindow::default(),
+            auto_post: true,
+            checkpoints: VecDeque::new(),
+            correlation_id: None,
+            output_schema: None,
+            paused: false,
+        }
+    }
+
+    fn usage(total_tokens: i64) -> TokenUsage {
+        TokenUsage {
+            total_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn clone_agent_rejects_exceeding_subagent_limit() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let mut ids = Vec::new();
+        for index in 0..MAX_SUBAGENTS_PER_PARENT {
+            let id = ThreadId::new();
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, &format!("w{index}")))
+                .await
+                .expect("subagent registers");
+            ids.push(id);
+        }
+
+        let err = manager
+            .agent_control()
+            .clone_agent(parent_id, ids[0], 1, None)
+            .await
+            .expect_err("clone should exceed the subagent limit");
+        assert_matches!(
+            err,
+            CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                limit,
+            } if limit == MAX_SUBAGENTS_PER_PARENT as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_cap_comes_from_the_host_collab_limits() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            collab_limits: CollabLimits {
+                max_subagents_per_parent: 2,
+                ..CollabLimits::default()
+            },
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let source = ThreadId::new();
+        manager
+            .state
+            .register_subagent(source, test_subagent_info(parent_id, "source"))
+            .await
+            .expect("subagent registers");
+        let _starting = manager
+            .state
+            .reserve_subagent_spawn(parent_id, None)
+            .await
+            .expect("the second slot is free");
+
+        assert_matches!(
+            manager.state.reserve_subagent_spawn(parent_id, None).await,
+            Err(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                limit: 2,
+            })
+        );
+        assert_matches!(
+            manager
+                .agent_control()
+                .clone_agent(parent_id, source, 1, None)
+                .await,
+            Err(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                limit: 2,
+            })
+        );
+        assert_eq!(
+            manager.capacity_snapshot().await.max_subagents_per_parent,
+            2
+        );
+        let capacity = manager
+            .agent_control()
+            .spawn_capacity(parent_id)
+            .await
+            .expect("capacity");
+        assert_eq!(capacity.per_parent.limit, 2);
+        assert_eq!(capacity.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn pending_spawns_reserve_slots_and_can_be_cancelled() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        for index in 1..MAX_SUBAGENTS_PER_PARENT {
+            manager
+                .state
+                .register_subagent(
+                    ThreadId::new(),
+                    test_subagent_info(parent_id, &format!("w{index}")),
+                )
+                .await
+                .expect("subagent registers");
+        }
+
+        let pending = manager
+            .state
+            .reserve_subagent_spawn(parent_id, Some("slow".to_string()))
+            .await
+            .expect("last slot is free");
+        assert_matches!(
+            manager.state.reserve_subagent_spawn(parent_id, None).await,
+            Err(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                ..
+            })
+        );
+        assert_matches!(
+            manager
+                .state
+                .reserve_subagent_spawn(ThreadId::new(), Some("slow".to_string()))
+                .await,
+            Ok(_)
+        );
+
+        assert!(!manager.state.cancel_pending_spawn(ThreadId::new(), "slow"));
+        manager
+            .agent_control()
+            .cancel_spawn(parent_id, "slow")
+            .expect("spawn is pending");
+        assert!(pending.cancel_token().is_cancelled());
+
+        drop(pending);
+        let err = manager
+            .agent_control()
+            .cancel_spawn(parent_id, "slow")
+            .expect_err("finished spawns cannot be cancelled");
+        assert_eq!(
+            err.to_string(),
+            "unsupported operation: no spawn with token slow is in progress"
+        );
+        assert_matches!(
+            manager.state.reserve_subagent_spawn(parent_id, None).await,
+            Ok(_)
+        );
+    }
+
+    #[tokio::test]
+    async fn background_spawn_can_be_cancelled_while_it_starts() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let spawned = control
+            .spawn_agent_in_background(
+                parent_id,
+                SubagentSpawn {
+                    spawn_token: Some("slow".to_string()),
+                    ..SubagentSpawn::new(test_config(), "work".to_string())
+                },
+            )
+            .await
+            .expect("slot is reserved");
+
+        // The call returned before the session started, so the spawn is still cancellable.
+        control
+            .cancel_spawn(parent_id, "slow")
+            .expect("spawn is pending");
+        let err = spawned
+            .await
+            .expect("spawn task")
+            .map(|spawned| spawned.thread_id)
+            .expect_err("cancelled spawn");
+        assert_eq!(
+            err.to_string(),
+            "unsupported operation: spawn slow was cancelled"
+        );
+        assert_eq!(manager.subagent_count().await, 0);
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn spawn_agents_reports_capacity_failures_per_item() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        for index in 1..MAX_SUBAGENTS_PER_PARENT {
+            manager
+                .state
+                .register_subagent(
+                    ThreadId::new(),
+                    test_subagent_info(parent_id, &format!("w{index}")),
+                )
+                .await
+                .expect("subagent registers");
+        }
+        // A spawn still starting holds the last slot.
+        let _starting = manager
+            .state
+            .reserve_subagent_spawn(parent_id, None)
+            .await
+            .expect("the last slot is free");
+        let spawns = ["planner", "builder"]
+            .into_iter()
+            .map(|name| SubagentSpawn {
+                display_name: Some(name.to_string()),
+                ..SubagentSpawn::new(test_config(), "work".to_string())
+            })
+            .collect();
+
+        let results = manager
+            .agent_control()
+            .spawn_agents(parent_id, spawns)
+            .await
+            .expect("batch should report per-item results");
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_matches!(
+                result,
+                Err(CodexErr::SubagentLimitExceeded {
+                    kind: SubagentLimitKind::PerParent,
+                    ..
+                })
+            );
+        }
+        assert_eq!(
+            manager.state.subagents_for_parent(parent_id).await.len(),
+            MAX_SUBAGENTS_PER_PARENT - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn clone_agent_requires_caller_to_own_source() {
+        let manager = test_manager();
+        let source_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(source_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+
+        let err = manager
+            .agent_control()
+            .clone_agent(ThreadId::new(), source_id, 1, None)
+            .await
+            .expect_err("clone should be scoped to the caller's subagents");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == source_id);
+    }
+
+    #[tokio::test]
+    async fn forget_subagent_after_drain_waits_for_drain_to_finish() {
+        let manager = test_manager();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+        let drained = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let drain = tokio::spawn({
+            let drained = Arc::clone(&drained);
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                drained.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+        manager
+            .state
+            .register_subagent_drain(subagent_id, drain)
+            .await;
+
+        manager
+            .agent_control()
+            .forget_subagent_after_drain(subagent_id, std::time::Duration::from_secs(5))
+            .await
+            .expect("forget should succeed");
+
+        assert!(drained.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(manager.state.subagent_info(subagent_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drain_restarts_are_capped_per_subagent() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            max_subagent_drain_restarts: Some(2),
+            ..ThreadManagerOptions::default()
+        });
+        let (subagent_id, other_id) = (ThreadId::new(), ThreadId::new());
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+
+        assert!(manager.state.claim_drain_restart(subagent_id).await);
+        assert!(manager.state.claim_drain_restart(subagent_id).await);
+        assert!(!manager.state.claim_drain_restart(subagent_id).await);
+        assert!(manager.state.claim_drain_restart(other_id).await);
+
+        manager.state.unregister_subagent(subagent_id).await;
+        assert!(manager.state.claim_drain_restart(subagent_id).await);
+    }
+
+    #[tokio::test]
+    async fn stopped_drain_without_a_thread_is_not_restarted() {
+        let manager = test_manager();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+        let drain = tokio::spawn(async {});
+        while !drain.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        manager
+            .state
+            .register_subagent_drain(subagent_id, drain)
+            .await;
+
+        manager.state.restart_stopped_drain(subagent_id).await;
+
+        assert!(
+            manager
+                .state
+                .take_subagent_drain(subagent_id)
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn forget_subagent_after_drain_aborts_stuck_drain_on_timeout() {
+        let manager = test_manager();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent_drain(subagent_id, tokio::spawn(std::future::pending()))
+            .await;
+
+        manager
+            .agent_control()
+            .forget_subagent_after_drain(subagent_id, std::time::Duration::from_millis(10))
+            .await
+            .expect("forget should succeed");
+
+        assert!(manager.state.subagent_info(subagent_id).await.is_none());
+        assert!(
+            manager
+                .state
+                .take_subagent_drain(subagent_id)
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn list_subagents_filters_by_status_then_pages() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let mut ids = Vec::new();
+        for index in 0..5 {
+            let id = ThreadId::new();
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, &format!("w{index}")))
+                .await
+                .expect("subagent registers");
+            ids.push(id);
+        }
+        ids.sort_by_key(ToString::to_string);
+        let control = manager.agent_control();
+
+        let running = control
+            .list_subagents(
+                parent_id,
+                &crate::agent::ListSubagentsQuery {
+                    status: Some(crate::agent::AgentStatusFilter::Running),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("list should succeed");
+        assert!(running.is_empty());
+
+        let page = control
+            .list_subagents(
+                parent_id,
+                &crate::agent::ListSubagentsQuery {
+                    status: Some(crate::agent::AgentStatusFilter::NotFound),
+                    offset: 1,
+                    limit: Some(2),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("list should succeed");
+        assert_eq!(
+            page.iter().map(|summary| summary.id).collect::<Vec<_>>(),
+            ids[1..3].to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn kill_all_subagents_continues_past_shutdown_failures() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let other_parent = ThreadId::new();
+        for index in 0..3 {
+            manager
+                .state
+                .register_subagent(
+                    ThreadId::new(),
+                    test_subagent_info(parent_id, &format!("w{index}")),
+                )
+                .await
+                .expect("subagent registers");
+        }
+        let survivor = ThreadId::new();
+        manager
+            .state
+            .register_subagent(survivor, test_subagent_info(other_parent, "other"))
+            .await
+            .expect("subagent registers");
+
+        let outcome = manager
+            .agent_control()
+            .kill_all_subagents(parent_id)
+            .await
+            .expect("kill all should succeed");
+
+        assert_eq!(outcome.terminated, 3);
+        assert_eq!(outcome.shutdown_errors.len(), 3);
+        assert!(
+            manager
+                .state
+                .subagents_for_parent(parent_id)
+                .await
+                .is_empty()
+        );
+        assert!(manager.state.is_subagent_of(other_parent, survivor).await);
+    }
+
+    #[tokio::test]
+    async fn collab_limits_reject_zero_group_chat_and_wait_limits() {
+        assert!(CollabLimits::default().validate().is_ok());
+        for (limits, field) in [
+            (
+                CollabLimits {
+                    max_group_chat_messages: 0,
+                    ..CollabLimits::default()
+                },
+                "max_group_chat_messages",
+            ),
+            (
+                CollabLimits {
+                    max_wait_timeout_ms: 0,
+                    ..CollabLimits::default()
+                },
+                "max_wait_timeout_ms",
+            ),
+            (
+                CollabLimits {
+                    max_subagents_per_parent: 0,
+                    ..CollabLimits::default()
+                },
+                "max_subagents_per_parent",
+            ),
+        ] {
+            let err = limits.validate().expect_err("zero limit is rejected");
+            assert_eq!(
+                err.to_string(),
+                format!("collab_limits.{field} must be greater than zero")
+            );
+        }
+
+        let codex_home = tempfile::tempdir().expect("tempdir");
+        let result = ThreadManager::new_with_options(
+            codex_home.path().to_path_buf(),
+            AuthManager::from_auth_for_testing(CodexAuth::from_api_key("Test API Key")),
+            SessionSource::Exec,
+            ThreadManagerOptions {
+                collab_limits: CollabLimits {
+                    max_wait_timeout_ms: 0,
+                    ..CollabLimits::default()
+                },
+                ..ThreadManagerOptions::default()
+            },
+        );
+        assert_matches!(result, Err(CodexErr::InvalidRequest(_)));
+    }
+
+    #[test]
+    fn max_wait_timeout_defaults_and_honors_host_option() {
+        let manager = test_manager();
+        assert_eq!(
+            manager.agent_control().collab_limits().max_wait_timeout_ms,
+            DEFAULT_MAX_WAIT_TIMEOUT_MS
+        );
+
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            collab_limits: CollabLimits {
+                max_wait_timeout_ms: 3_600_000,
+                ..CollabLimits::default()
+            },
+            ..ThreadManagerOptions::default()
+        });
+        assert_eq!(
+            manager.agent_control().collab_limits().max_wait_timeout_ms,
+            3_600_000
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_output_buffers_follow_host_collab_limits() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            collab_limits: CollabLimits {
+                max_subagent_output_chars: 10,
+                max_subagent_reasoning_chars: 5,
+                ..CollabLimits::default()
+            },
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let child = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(parent_id, "child"))
+            .await
+            .expect("subagent registers");
+
+        manager
+            .state
+            .record_subagent_delta(child, &"a".repeat(25))
+            .await;
+        manager
+            .state
+            .record_subagent_reasoning_summary_delta(child, "0123456789")
+            .await;
+        let snapshot = manager
+            .state
+            .subagent_output_snapshot(child, None)
+            .await
+            .expect("snapshot");
+        assert_eq!(snapshot.partial, Some("a".repeat(10)));
+        assert_eq!(snapshot.reasoning_summary.as_deref(), Some("56789"));
+        assert!(snapshot.truncated);
+    }
+
+    #[test]
+    fn group_chat_post_window_limits_posts_per_second() {
+        let start = Instant::now();
+        let mut window = GroupChatPostWindow::default();
+        for _ in 0..2 {
+            assert_eq!(
+                window.admit(start, 2),
+                GroupChatPostAdmission::Allowed { dropped: 0 }
+            );
+        }
+        assert_eq!(window.admit(start, 2), GroupChatPostAdmission::Limited);
+        assert_eq!(
+            window.admit(start + Duration::from_millis(500), 2),
+            GroupChatPostAdmission::Limited
+        );
+
+        assert_eq!(
+            window.admit(start + Duration::from_secs(1), 2),
+            GroupChatPostAdmission::Allowed { dropped: 2 }
+        );
+        assert_eq!(
+            window.admit(start + Duration::from_secs(1), 2),
+            GroupChatPostAdmission::Allowed { dropped: 0 }
+        );
+    }
+
+    #[test]
+    fn subagent_message_transform_can_rewrite_or_suppress_posts() {
+        let manager = test_manager();
+        let sender = GroupChatSender::SubAgent {
+            id: ThreadId::new(),
+            persona: None,
+            display_name: Some("Worker".to_string()),
+        };
+        assert_eq!(
+            manager
+                .state
+                .transform_subagent_message(&sender, "token=abc".to_string()),
+            Some("token=abc".to_string())
+        );
+
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_message_transform: Some(Arc::new(|sender: &GroupChatSender, text: &str| {
+                let GroupChatSender::SubAgent { display_name, .. } = sender else {
+                    return Some(text.to_string());
+                };
+                let name = display_name.as_deref().unwrap_or("agent");
+                (!text.is_empty()).then(|| format!("[{name}] {}", text.replace("abc", "***")))
+            })),
+            ..ThreadManagerOptions::default()
+        });
+        assert_eq!(
+            manager
+                .state
+                .transform_subagent_message(&sender, "token=abc".to_string()),
+            Some("[Worker] token=***".to_string())
+        );
+        assert_eq!(
+            manager
+                .state
+                .transform_subagent_message(&sender, String::new()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn tool_echoes_are_opt_in_and_rate_limited() {
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        let register = |manager: &ThreadManager| {
+            let state = Arc::clone(&manager.state);
+            async move {
+                state
+                    .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+                    .await
+                    .expect("subagent registers");
+            }
+        };
+        let manager = test_manager();
+        register(&manager).await;
+        assert_eq!(
+            manager.state.admit_subagent_tool_echo(subagent_id).await,
+            None
+        );
+
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_tool_echoes_per_second: Some(1),
+            ..ThreadManagerOptions::default()
+        });
+        register(&manager).await;
+        assert_eq!(
+            manager.state.admit_subagent_tool_echo(subagent_id).await,
+            Some(parent_id)
+        );
+        assert_eq!(
+            manager.state.admit_subagent_tool_echo(subagent_id).await,
+            None
+        );
+        assert_eq!(
+            manager.state.admit_subagent_tool_echo(parent_id).await,
+            None
+        );
+        // Echoes have their own window; group-chat posts are unaffected.
+        assert_eq!(
+            manager
+                .state
+                .admit_subagent_group_chat_post(subagent_id)
+                .await,
+            GroupChatPostAdmission::Allowed { dropped: 0 }
+        );
+    }
+
+    #[tokio::test]
+    async fn group_chat_rate_limit_is_per_subagent_and_configurable() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_group_chat_posts_per_second: Some(1),
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let first = ThreadId::new();
+        let second = ThreadId::new();
+        for (id, name) in [(first, "first"), (second, "second")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        let allowed = GroupChatPostAdmission::Allowed { dropped: 0 };
+        assert_eq!(
+            manager.state.admit_subagent_group_chat_post(first).await,
+            allowed
+        );
+        assert_eq!(
+            manager.state.admit_subagent_group_chat_post(first).await,
+            GroupChatPostAdmission::Limited
+        );
+        assert_eq!(
+            manager.state.admit_subagent_group_chat_post(second).await,
+            allowed
+        );
+        assert_eq!(
+            manager
+                .state
+                .admit_subagent_group_chat_post(parent_id)
+                .await,
+            allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_final_messages_are_still_posted() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_group_chat_posts_per_second: Some(1),
+            ..ThreadManagerOptions::default()
+        });
+        // The parent thread was never started, so every post attempt lands in
+        // `undelivered_messages`, which shows what was posted.
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "chatty"))
+            .await
+            .expect("subagent registers");
+
+        let mut held_back = Vec::new();
+        for message in ["progress", "still going", "final answer"] {
+            held_back.push(
+                record_and_post_subagent_message(&manager.state, subagent_id, message.to_string())
+                    .await,
+            );
+        }
+        assert_eq!(
+            held_back,
+            vec![
+                None,
+                Some("still going".to_string()),
+                Some("final answer".to_string())
+            ]
+        );
+        post_final_subagent_message(&manager.state, subagent_id, "final answer".to_string()).await;
+
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(
+            snapshot.undelivered_messages,
+            vec![
+                "progress".to_string(),
+                "final answer\n\n(1 earlier message(s) from this agent were dropped by the group chat rate limit.)".to_string(),
+            ]
+        );
+        // The dropped count was reported with the final post and is not repeated.
+        assert_eq!(
+            manager
+                .state
+                .admit_held_subagent_group_chat_post(subagent_id)
+                .await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn disabling_auto_post_records_messages_without_posting() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_group_chat_posts_per_second: Some(1),
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(
+                subagent_id,
+                SubagentInfo {
+                    auto_post: false,
+                    ..test_subagent_info(parent_id, "quiet")
+                },
+            )
+            .await
+            .expect("subagent registers");
+
+        for message in ["first result", "second result"] {
+            record_and_post_subagent_message(&manager.state, subagent_id, message.to_string())
+                .await;
+        }
+
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(snapshot.last_message.as_deref(), Some("second result"));
+        // Unposted messages never count against the subagent's group chat rate limit.
+        assert_eq!(
+            manager
+                .state
+                .admit_subagent_group_chat_post(subagent_id)
+                .await,
+            GroupChatPostAdmission::Allowed { dropped: 0 }
+        );
+    }
+
+    #[tokio::test]
+    async fn undeliverable_group_chat_posts_are_kept_in_the_tombstone() {
+        let manager = test_manager();
+        // The parent thread was never started, so posting to its group chat fails.
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "orphan"))
+            .await
+            .expect("subagent registers");
+
+        record_and_post_subagent_message(&manager.state, subagent_id, "final result".to_string())
+            .await;
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(
+            snapshot.undelivered_messages,
+            vec!["final result".to_string()]
+        );
+
+        manager.state.unregister_subagent(subagent_id).await;
+        let tombstone = manager
+            .state
+            .subagent_tombstone_snapshot(parent_id, subagent_id, None)
+            .await
+            .expect("tombstone");
+        assert_eq!(tombstone.last_message.as_deref(), Some("final result"));
+        assert_eq!(
+            tombstone.undelivered_messages,
+            vec!["final result".to_string()]
+        );
+    }
+
+    #[test]
+    fn reconfigure_restarts_only_for_cwd_or_sandbox_changes() {
+        let config = test_config();
+        let cwd = config.cwd.clone();
+        let sandbox_policy = config.sandbox_policy.get().clone();
+
+        let mut hot = config.clone();
+        hot.model = Some("gpt-5.1-codex".to_string());
+        hot.model_reasoning_effort = Some(ReasoningEffort::High);
+        assert!(!requires_restart(&cwd, &sandbox_policy, &hot));
+        assert_matches!(
+            hot_swap_op(&hot),
+            Op::OverrideTurnContext {
+                cwd: None,
+                approval_policy: None,
+                sandbox_policy: None,
+                model: Some(model),
+                effort: Some(Some(ReasoningEffort::High)),
+                ..
+            } if model == "gpt-5.1-codex"
+        );
+
+        let mut moved = config.clone();
+        moved.cwd = cwd.join("elsewhere");
+        assert!(requires_restart(&cwd, &sandbox_policy, &moved));
+
+        let other_sandbox = if sandbox_policy == SandboxPolicy::DangerFullAccess {
+            SandboxPolicy::new_read_only_policy()
+        } else {
+            SandboxPolicy::DangerFullAccess
+        };
+        assert!(requires_restart(&cwd, &other_sandbox, &config));
+    }
+
+    /// Shut `thread`'s session down and wait until it stops taking submissions, so the next
+    /// input sent to it fails.
+    async fn stop_session(thread: &CodexThread) {
+        thread.submit(Op::Shutdown).await.expect("submit shutdown");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while thread.submit(Op::Interrupt).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("session stops");
+    }
+
+    #[tokio::test]
+    async fn failed_initial_prompt_rolls_back_spawned_subagent() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let doomed = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("doomed"),
+            )
+            .await
+            .expect("spawn subagent");
+        let thread = manager.get_thread(doomed).await.expect("subagent thread");
+        stop_session(&thread).await;
+
+        let err = manager
+            .agent_control()
+            .send_initial_prompt_or_roll_back(
+                doomed,
+                vec![UserInput::Text {
+                    text: "work".to_string(),
+                }],
+            )
+            .await
+            .expect_err("the initial prompt cannot reach a stopped session");
+
+        assert_matches!(err, CodexErr::InternalAgentDied);
+        assert_eq!(manager.thread_count().await, 0);
+        assert_eq!(manager.subagent_count().await, 0);
+        assert!(manager.state.subagent_drains.read().await.is_empty());
+        assert!(manager.state.subagent_tombstones.read().await.is_empty());
+        assert_eq!(manager.capacity_snapshot().await.pending_spawns, 0);
+    }
+
+    #[tokio::test]
+    async fn subagent_spawned_with_a_resumed_history_forks_instead_of_replacing_the_source() {
+        let manager = test_manager();
+        let NewThread {
+            thread_id: planner,
+            thread,
+            ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start planner thread");
+        let resumed = ResumedHistory {
+            conversation_id: planner,
+            history: vec![RolloutItem::ResponseItem(user_msg(
+                "Plan the parser refactor",
+            ))],
+            rollout_path: thread.rollout_path(),
+        };
+
+        let builder = manager
+            .spawn_subagent_with_history(
+                planner,
+                test_config(),
+                InitialHistory::Resumed(resumed),
+                "Implement the plan".to_string(),
+                test_spawn_options("builder"),
+            )
+            .await
+            .expect("spawn subagent with history");
+
+        assert_ne!(builder, planner);
+        assert!(Arc::ptr_eq(
+            &manager.get_thread(planner).await.expect("planner thread"),
+            &thread
+        ));
+        assert_ne!(
+            manager
+                .get_thread(builder)
+                .await
+                .expect("builder thread")
+                .rollout_path(),
+            thread.rollout_path()
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn subagent_spawned_with_history_starts_from_it() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let handoff = vec![
+            RolloutItem::ResponseItem(user_msg("Plan the parser refactor")),
+            RolloutItem::ResponseItem(assistant_msg("Plan: split lexer.rs out of parser.rs")),
+        ];
+        let builder = manager
+            .spawn_subagent_with_history(
+                parent_id,
+                test_config(),
+                InitialHistory::Forked(handoff.clone()),
+                "Implement the plan".to_string(),
+                test_spawn_options("builder"),
+            )
+            .await
+            .expect("spawn subagent with history");
+
+        let rollout_path = manager
+            .get_thread(builder)
+            .await
+            .expect("builder thread")
+            .rollout_path();
+        let history = RolloutRecorder::get_rollout_history(&rollout_path)
+            .await
+            .expect("read builder rollout");
+        let items = serde_json::to_value(history.get_rollout_items()).expect("serialize rollout");
+        for item in &handoff {
+            let item = serde_json::to_value(item).expect("serialize handoff item");
+            assert!(
+                items.as_array().is_some_and(|items| items.contains(&item)),
+                "rollout should start from the handoff: {item}"
+            );
+        }
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn forking_with_subagents_moves_the_team_to_the_fork() {
+        let manager = test_manager();
+        let NewThread {
+            thread_id: lead,
+            thread,
+            ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start lead thread");
+        let worker = ThreadId::new();
+        let helper = ThreadId::new();
+        manager
+            .state
+            .register_subagent(worker, test_subagent_info(lead, "worker"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(helper, test_subagent_info(worker, "helper"))
+            .await
+            .expect("subagent registers");
+        let control = manager.agent_control();
+        control
+            .set_shared(worker, "target_branch", Some("main".to_string()))
+            .await
+            .expect("set shared");
+        control
+            .scratchpad_append(helper, "core builds cleanly")
+            .await
+            .expect("append note");
+        control
+            .pin_context(lead, "Never push to main.")
+            .await
+            .expect("pin context");
+
+        let forked = manager
+            .fork_thread_with_subagents(lead, usize::MAX, test_config(), thread.rollout_path())
+            .await
+            .expect("fork lead with its subagents");
+        // The moved team still sees what it shared before the fork.
+        assert_eq!(
+            control.shared_variables(helper).await.unwrap(),
+            BTreeMap::from([("target_branch".to_string(), "main".to_string())])
+        );
+        assert_eq!(
+            control.scratchpad(worker).await.unwrap(),
+            vec!["core builds cleanly"]
+        );
+        assert_eq!(
+            manager.state.pinned_context(forked.thread_id).await,
+            vec!["Never push to main.".to_string()]
+        );
+
+        assert_eq!(
+            manager
+                .list_subagent_ids(forked.thread_id, SubagentOrder::SpawnOrder)
+                .await,
+            vec![worker]
+        );
+        assert!(
+            manager
+                .list_subagent_ids(lead, SubagentOrder::SpawnOrder)
+                .await
+                .is_empty()
+        );
+        assert_eq!(manager.parent_of(helper).await, Some(worker));
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn reconfigure_compares_against_live_settings_and_survives_a_failed_fork() {
+        let manager = test_manager();
+        let NewThread {
+            thread_id: lead,
+            thread,
+            ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start lead thread");
+        let worker = ThreadId::new();
+        manager
+            .state
+            .register_subagent(worker, test_subagent_info(lead, "worker"))
+            .await
+            .expect("subagent registers");
+        let elsewhere = tempfile::tempdir().expect("tempdir");
+        thread
+            .submit(Op::OverrideTurnContext {
+                cwd: Some(elsewhere.path().to_path_buf()),
+                approval_policy: None,
+                sandbox_policy: None,
+                model: None,
+                effort: None,
+                summary: None,
+            })
+            .await
+            .expect("override cwd");
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while thread
+            .cwd_and_sandbox_policy()
+            .await
+            .expect("live settings")
+            .0
+            .as_path()
+            != elsewhere.path()
+        {
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "override was not applied"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut moved = test_config();
+        moved.cwd = elsewhere.path().to_path_buf();
+        assert!(matches!(
+            manager.reconfigure_thread(lead, moved).await,
+            Ok(ReconfigureOutcome::HotApplied)
+        ));
+
+        // Going back to the startup cwd is a change from the live one, so it needs a restart;
+        // a fork that cannot read the rollout leaves the thread and its team in place.
+        std::fs::remove_file(thread.rollout_path()).expect("remove rollout");
+        assert!(
+            manager
+                .reconfigure_thread(lead, test_config())
+                .await
+                .is_err()
+        );
+        assert!(manager.get_thread(lead).await.is_ok());
+        assert_eq!(manager.parent_of(worker).await, Some(lead));
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn restarting_a_thread_moves_its_team_to_the_restarted_thread() {
+        let manager = test_manager();
+        let NewThread {
+            thread_id: lead, ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start lead thread");
+        let worker = ThreadId::new();
+        manager
+            .state
+            .register_subagent(worker, test_subagent_info(lead, "worker"))
+            .await
+            .expect("subagent registers");
+        let elsewhere = tempfile::tempdir().expect("tempdir");
+        let mut moved = test_config();
+        moved.cwd = elsewhere.path().to_path_buf();
+
+        let Ok(ReconfigureOutcome::Restarted(restarted)) =
+            manager.reconfigure_thread(lead, moved).await
+        else {
+            panic!("changing cwd should restart the thread");
+        };
+
+        assert!(manager.get_thread(lead).await.is_err());
+        assert_eq!(manager.parent_of(worker).await, Some(restarted.thread_id));
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn inspect_agent_context_shows_the_persona_the_subagent_received() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let persona = "Reviews diffs for unsafe code";
+        let reviewer = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "Review the patch".to_string(),
+                SubagentSpawnOptions {
+                    persona: Some(persona.to_string()),
+                    ..test_spawn_options("reviewer")
+                },
+            )
+            .await
+            .expect("spawn subagent");
+
+        let control = manager.agent_control();
+        let context = control
+            .inspect_agent_context(parent_id, reviewer)
+            .await
+            .expect("inspect context");
+        assert!(!context.base_instructions.is_empty());
+        assert!(
+            context.items.iter().any(|item| matches!(
+                item,
+                ResponseItem::Message { role, content, .. }
+                    if role == "developer"
+                        && content.iter().any(|content| matches!(
+                            content,
+                            ContentItem::InputText { text } if text.contains(persona)
+                        ))
+            )),
+            "developer instructions should carry the persona: {:?}",
+            context.items
+        );
+        let err = control
+            .inspect_agent_context(ThreadId::new(), reviewer)
+            .await
+            .expect_err("only ancestors may inspect");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == reviewer);
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn async_spawn_registers_before_the_prompt_and_reports_failures_via_status() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let spawned = manager
+            .spawn_subagent_async(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("fast"),
+            )
+            .await
+            .expect("spawn subagent");
+        let ready_id = spawned.thread_id;
+        assert_eq!(
+            manager
+                .list_subagent_ids(parent_id, SubagentOrder::SpawnOrder)
+                .await,
+            vec![ready_id]
+        );
+        spawned.ready().await.expect("initial prompt sent");
+
+        let doomed = manager
+            .spawn_subagent_async(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("doomed"),
+            )
+            .await
+            .expect("spawn subagent");
+        let failed_id = doomed.thread_id;
+        doomed.ready().await.expect("initial prompt sent");
+        let thread = manager
+            .get_thread(failed_id)
+            .await
+            .expect("subagent thread");
+        stop_session(&thread).await;
+        let err = manager
+            .agent_control()
+            .send_initial_prompt_or_mark_errored(
+                failed_id,
+                vec![UserInput::Text {
+                    text: "work".to_string(),
+                }],
+            )
+            .await
+            .expect_err("the initial prompt cannot reach a stopped session");
+        assert_matches!(err, CodexErr::InternalAgentDied);
+        let thread = manager
+            .get_thread(failed_id)
+            .await
+            .expect("failed subagent is kept");
+        assert_matches!(thread.agent_status().await, AgentStatus::Errored(_));
+        assert_eq!(manager.subagent_count().await, 2);
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn amended_instructions_are_kept_in_the_spawn_config() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let subagent_id = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                SubagentSpawnOptions {
+                    persona: Some("Reviewer".to_string()),
+                    ..test_spawn_options("reviewer")
+                },
+            )
+            .await
+            .expect("spawn subagent");
+
+        control
+            .amend_instructions(parent_id, subagent_id, "Run the linter before reporting.")
+            .await
+            .expect("amend instructions");
+        let instructions = manager
+            .state
+            .subagent_info(subagent_id)
+            .await
+            .and_then(|info| info.spawn_config.developer_instructions.clone())
+            .unwrap_or_default();
+        assert!(instructions.contains("<persona>\nReviewer\n</persona>"));
+        assert!(instructions.ends_with(
+            "<instruction_update>\nRun the linter before reporting.\n</instruction_update>"
+        ));
+
+        assert_matches!(
+            control
+                .amend_instructions(ThreadId::new(), subagent_id, "Be brief.")
+                .await,
+            Err(CodexErr::ThreadNotFound(_))
+        );
+        assert_matches!(
+            control
+                .amend_instructions(parent_id, subagent_id, "  ")
+                .await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn pinned_context_is_bounded_per_parent_and_clearable() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+
+        assert_eq!(
+            control
+                .pin_context(parent_id, "  Follow the spec.  ")
+                .await
+                .expect("pin"),
+            PinnedContextUsage {
+                items: 1,
+                chars: 16,
+            }
+        );
+        assert_matches!(
+            control.pin_context(parent_id, "   ").await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        assert_matches!(
+            control
+                .pin_context(parent_id, &"x".repeat(MAX_PINNED_CONTEXT_CHARS - 15))
+                .await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        control
+            .pin_context(parent_id, &"x".repeat(MAX_PINNED_CONTEXT_CHARS - 16))
+            .await
+            .expect("pinning up to the limit is allowed");
+        assert!(
+            manager
+                .state
+                .pinned_context(ThreadId::new())
+                .await
+                .is_empty()
+        );
+
+        assert_eq!(control.clear_pinned_context(parent_id).await.unwrap(), 2);
+        assert!(manager.state.pinned_context(parent_id).await.is_empty());
+
+        control
+            .pin_context(parent_id, "Use tabs.")
+            .await
+            .expect("pin");
+        manager.state.remove_thread(parent_id).await;
+        assert!(manager.state.pinned_context(parent_id).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn spawned_subagents_receive_pinned_context_before_the_prompt() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        manager
+            .agent_control()
+            .pin_context(parent_id, "Conventions: use tabs.")
+            .await
+            .expect("pin");
+
+        let subagent_id = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("pinned"),
+            )
+            .await
+            .expect("spawn subagent");
+
+        let info = manager
+            .state
+            .subagent_info(subagent_id)
+            .await
+            .expect("registered subagent");
+        assert_eq!(
+            info.last_input,
+            vec![
+                UserInput::Text {
+                    text: "<pinned_context>\nConventions: use tabs.\n</pinned_context>".to_string(),
+                },
+                UserInput::Text {
+                    text: "work".to_string(),
+                },
+            ]
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn seeded_subagent_ids_follow_spawn_order() {
+        let manager = test_manager();
+        manager.seed_subagent_ids(0x2a);
+        let parent_id = ThreadId::new();
+        let mut ids = Vec::new();
+        for name in ["first", "second"] {
+            let id = manager
+                .spawn_subagent(
+                    parent_id,
+                    test_config(),
+                    "work".to_string(),
+                    test_spawn_options(name),
+                )
+                .await
+                .expect("spawn subagent");
+            ids.push(id);
+        }
+
+        assert_eq!(
+            ids.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "0000002a-0000-7000-8000-000000000000",
+                "0000002a-0000-7000-8000-000000000001",
+            ]
+        );
+        assert_eq!(
+            manager
+                .list_subagent_ids(parent_id, SubagentOrder::SpawnOrder)
+                .await,
+            ids
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn subagent_output_reports_latest_token_usage() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+
+        for total in [120, 480] {
+            manager
+                .state
+                .record_subagent_token_usage(subagent_id, &usage(total))
+                .await;
+        }
+        // A new prompt clears buffered text but keeps cumulative usage.
+        manager.state.reset_subagent_output(subagent_id).await;
+
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(snapshot.token_usage, Some(usage(480)));
+    }
+
+    #[tokio::test]
+    async fn seeded_subagent_graph_round_trips_through_snapshot() {
+        let manager = test_manager();
+        let (parent_id, lead, worker) = (ThreadId::new(), ThreadId::new(), ThreadId::new());
+        let seeded = vec![
+            SeededSubagent {
+                id: lead,
+                parent_id,
+                display_name: Some("lead".to_string()),
+                last_message: Some("Plan ready.".to_string()),
+                token_usage: Some(usage(40)),
+                ..SeededSubagent::default()
+            },
+            SeededSubagent {
+                id: worker,
+                parent_id: lead,
+                display_name: Some("worker".to_string()),
+                partial: Some("Running tests".to_string()),
+                tool_events: vec!["exec begin: cargo test".to_string()],
+                ..SeededSubagent::default()
+            },
+        ];
+        manager.seed_subagents(seeded.clone(), &test_config()).await;
+
+        let summaries = manager
+            .agent_control()
+            .list_subagents(parent_id, &crate::agent::ListSubagentsQuery::default())
+            .await
+            .expect("list subagents");
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].display_name.as_deref(), Some("lead"));
+        assert!(manager.state.is_descendant_of(parent_id, worker).await);
+        assert_eq!(manager.state.subagent_token_usage(parent_id).await, 40);
+
+        let snapshot = manager.snapshot_subagents().await;
+        assert_eq!(snapshot, seeded);
+        let restored = test_manager();
+        restored.seed_subagents(snapshot, &test_config()).await;
+        assert_eq!(restored.snapshot_subagents().await, seeded);
+    }
+
+    #[tokio::test]
+    async fn clones_re_read_attachments() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let control = manager.agent_control();
+        let dir = tempfile::tempdir().expect("tempdir");
+        let notes = dir.path().join("notes.md");
+        std::fs::write(&notes, "first draft").expect("write notes");
+        let mut config = test_config();
+        config.cwd = dir.path().to_path_buf();
+        let paths = vec![PathBuf::from("notes.md")];
+        let attachments = crate::agent::resolve_attachments(&config.cwd, paths.clone())
+            .await
+            .expect("resolve attachments");
+        let source = control
+            .spawn_agent(
+                parent_id,
+                SubagentSpawn {
+                    display_name: Some("writer".to_string()),
+                    attachments,
+                    attachment_paths: paths,
+                    ..SubagentSpawn::new(config, "work".to_string())
+                },
+            )
+            .await
+            .expect("spawn subagent");
+
+        std::fs::write(&notes, "second draft").expect("rewrite notes");
+        let clones = control
+            .clone_agent(parent_id, source, 1, None)
+            .await
+            .expect("clone subagent");
+
+        let info = manager
+            .state
+            .subagent_info(clones[0])
+            .await
+            .expect("registered clone");
+        let attachment = info
+            .last_input
+            .iter()
+            .find_map(|item| match item {
+                UserInput::Text { text } if text.starts_with("<attachment") => Some(text),
+                _ => None,
+            })
+            .expect("clone receives the attachment");
+        assert!(attachment.contains("second draft"), "{attachment}");
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn correlation_id_is_carried_into_listings_and_clones() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let control = manager.agent_control();
+        let traced = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                SubagentSpawnOptions {
+                    correlation_id: Some("req-42".to_string()),
+                    ..test_spawn_options("traced")
+                },
+            )
+            .await
+            .expect("spawn traced subagent");
+        let clones = control
+            .clone_agent(parent_id, traced, 1, None)
+            .await
+            .expect("clone traced subagent");
+
+        let summaries = control
+            .list_subagents(parent_id, &crate::agent::ListSubagentsQuery::default())
+            .await
+            .expect("list subagents");
+        assert_eq!(summaries.len(), 2);
+        for summary in &summaries {
+            assert!(summary.id == traced || clones.contains(&summary.id));
+            assert_eq!(summary.correlation_id.as_deref(), Some("req-42"));
+        }
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn subagent_stats_roll_up_a_parents_team() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let (a, b) = (ThreadId::new(), ThreadId::new());
+        for (id, name) in [(a, "a"), (b, "b")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, name))
+                .await
+                .expect("subagent registers");
+        }
+        manager
+            .state
+            .register_subagent(
+                ThreadId::new(),
+                test_subagent_info(ThreadId::new(), "other"),
+            )
+            .await
+            .expect("subagent registers");
+        manager.state.record_subagent_turn_started(a).await;
+        manager.state.record_subagent_turn_started(b).await;
+        manager.state.record_subagent_turn_started(b).await;
+        manager
+            .state
+            .record_subagent_token_usage(a, &usage(100))
+            .await;
+        manager
+            .state
+            .record_subagent_token_usage(b, &usage(50))
+            .await;
+
+        let stats = manager
+            .agent_control()
+            .subagent_stats(parent_id)
+            .await
+            .expect("subagent stats");
+        // Neither subagent has a live thread in this test.
+        assert_eq!(stats.total, 2);
+        assert_eq!(stats.by_status, BTreeMap::from([("not_found", 2)]));
+        assert_eq!(stats.errored, 0);
+        assert_eq!(stats.total_tokens, 150);
+        assert_eq!(stats.turns, 3);
+    }
+
+    #[tokio::test]
+    async fn subagent_output_tracks_last_activity() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+        let Some(registered) = manager.state.subagent_last_activity(subagent_id).await else {
+            panic!("registered subagents should track activity");
+        };
+
+        let before_delta = Instant::now();
+        manager
+            .state
+            .record_subagent_delta(subagent_id, "working")
+            .await;
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        let Some(last_activity) = snapshot.last_activity else {
+            panic!("snapshot should report last activity");
+        };
+        assert!(last_activity >= before_delta);
+        assert!(last_activity >= registered);
+
+        let summaries = manager
+            .agent_control()
+            .list_subagents(parent_id, &crate::agent::ListSubagentsQuery::default())
+            .await
+            .expect("list subagents");
+        assert!(
+            summaries[0]
+                .seconds_since_last_activity
+                .is_some_and(|secs| secs < 60)
+        );
+        assert_eq!(
+            manager.state.subagent_last_activity(ThreadId::new()).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn spawn_capacity_counts_starting_spawns_and_token_budget() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_token_budget: Some(1_000),
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let worker = ThreadId::new();
+        manager
+            .state
+            .register_subagent(worker, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .record_subagent_token_usage(worker, &usage(400))
+            .await;
+        let _starting = manager
+            .state
+            .reserve_subagent_spawn(parent_id, None)
+            .await
+            .expect("reserve slot");
+
+        let control = manager.agent_control();
+        assert_eq!(
+            control.spawn_capacity(parent_id).await.expect("capacity"),
+            SpawnCapacity {
+                remaining: MAX_SUBAGENTS_PER_PARENT - 2,
+                per_parent: PerParentCapacity {
+                    limit: MAX_SUBAGENTS_PER_PARENT,
+                    active: 1,
+                    starting: 1,
+                    remaining: MAX_SUBAGENTS_PER_PARENT - 2,
+                },
+                token_budget: Some(TokenBudgetCapacity {
+                    limit: 1_000,
+                    used: 400,
+                    remaining: 600,
+                }),
+            }
+        );
+
+        manager
+            .state
+            .record_subagent_token_usage(worker, &usage(1_200))
+            .await;
+        let capacity = control.spawn_capacity(parent_id).await.expect("capacity");
+        assert_eq!(capacity.remaining, 0);
+        assert_eq!(capacity.per_parent.remaining, MAX_SUBAGENTS_PER_PARENT - 2);
+    }
+
+    #[tokio::test]
+    async fn nested_subagents_stop_once_an_ancestor_budget_is_spent() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_token_budget: Some(1_000),
+            ..ThreadManagerOptions::default()
+        });
+        let root = ThreadId::new();
+        let child = ThreadId::new();
+        let grandchild = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(root, "child"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(grandchild, test_subagent_info(child, "grandchild"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .record_subagent_token_usage(child, &usage(900))
+            .await;
+        manager
+            .state
+            .record_subagent_token_usage(grandchild, &usage(200))
+            .await;
+        // The child's own tree is well under budget; the root's is not.
+        assert_eq!(manager.state.subagent_token_usage(child).await, 200);
+
+        let control = manager.agent_control();
+        let err = control
+            .ensure_token_budget(child)
+            .await
+            .expect_err("the root budget should stop the child from spawning or sending");
+        assert_matches!(
+            err,
+            CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::TokenBudget,
+                limit: 1_000,
+            }
+        );
+        let capacity = control.spawn_capacity(child).await.expect("capacity");
+        assert_eq!(capacity.remaining, 0);
+        assert_eq!(
+            capacity.token_budget,
+            Some(TokenBudgetCapacity {
+                limit: 1_000,
+                used: 1_100,
+                remaining: 0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn token_budget_blocks_spawns_once_subagent_tree_exceeds_it() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_token_budget: Some(1_000),
+            ..ThreadManagerOptions::default()
+        });
+        let root = ThreadId::new();
+        let child = ThreadId::new();
+        let grandchild = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(root, "child"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(grandchild, test_subagent_info(child, "grandchild"))
+            .await
+            .expect("subagent registers");
+
+        manager
+            .state
+            .record_subagent_token_usage(child, &usage(400))
+            .await;
+        manager
+            .state
+            .record_subagent_token_usage(grandchild, &usage(300))
+            .await;
+        // Repeated reports of the same cumulative total are not double counted.
+        manager
+            .state
+            .record_subagent_token_usage(grandchild, &usage(300))
+            .await;
+        assert_eq!(manager.state.subagent_token_usage(root).await, 700);
+        assert_eq!(manager.state.subagent_token_usage(child).await, 300);
+        manager
+            .agent_control()
+            .ensure_token_budget(root)
+            .await
+            .expect("budget not yet exhausted");
+
+        manager
+            .state
+            .record_subagent_token_usage(grandchild, &usage(650))
+            .await;
+        manager.state.remove_thread(grandchild).await;
+        assert_eq!(manager.state.subagent_token_usage(root).await, 1_050);
+        let err = manager
+            .agent_control()
+            .ensure_token_budget(root)
+            .await
+            .expect_err("budget should be exhausted");
+        assert_matches!(
+            err,
+            CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::TokenBudget,
+                limit: 1_000,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn list_subagent_ids_supports_spawn_order() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let mut spawned = Vec::new();
+        for index in 0..6 {
+            let id = ThreadId::new();
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, &format!("w{index}")))
+                .await
+                .expect("subagent registers");
+            spawned.push(id);
+        }
+        manager.state.remove_thread(spawned.remove(2)).await;
+
+        assert_eq!(
+            manager
+                .list_subagent_ids(parent_id, SubagentOrder::SpawnOrder)
+                .await,
+            spawned
+        );
+        let mut by_id = spawned.clone();
+        by_id.sort_by_key(ToString::to_string);
+        assert_eq!(
+            manager
+                .list_subagent_ids(parent_id, SubagentOrder::Id)
+                .await,
+            by_id
+        );
+    }
+
+    #[tokio::test]
+    async fn parent_of_walks_up_the_subagent_graph() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let child = ThreadId::new();
+        let grandchild = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(root, "child"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(grandchild, test_subagent_info(child, "grandchild"))
+            .await
+            .expect("subagent registers");
+
+        assert_eq!(manager.parent_of(grandchild).await, Some(child));
+        assert_eq!(manager.parent_of(child).await, Some(root));
+        assert_eq!(manager.parent_of(root).await, None);
+    }
+
+    #[tokio::test]
+    async fn orphaned_subagents_are_those_whose_parent_thread_is_gone() {
+        let manager = test_manager();
+        let NewThread {
+            thread_id: lead, ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start lead thread");
+        let adopted = ThreadId::new();
+        let orphan = ThreadId::new();
+        manager
+            .state
+            .register_subagent(adopted, test_subagent_info(lead, "adopted"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(orphan, test_subagent_info(ThreadId::new(), "orphan"))
+            .await
+            .expect("subagent registers");
+
+        assert_eq!(manager.list_orphaned_subagents().await, vec![orphan]);
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn reaper_forgets_completed_subagents_and_keeps_their_tombstone() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let mut workers = Vec::new();
+        for (name, status) in [
+            ("done", AgentStatus::Completed(Some("finished".to_string()))),
+            ("busy", AgentStatus::Running),
+        ] {
+            let NewThread {
+                thread_id, thread, ..
+            } = manager
+                .start_thread(test_config())
+                .await
+                .expect("start worker thread");
+            manager
+                .state
+                .register_subagent(thread_id, test_subagent_info(parent_id, name))
+                .await
+                .expect("subagent registers");
+            thread.set_agent_status(status).await;
+            workers.push(thread_id);
+        }
+        let (done, busy) = (workers[0], workers[1]);
+
+        assert!(
+            manager
+                .state
+                .reap_completed_subagents(Duration::from_secs(3600))
+                .await
+                .is_empty()
+        );
+        assert_eq!(
+            manager.state.reap_completed_subagents(Duration::ZERO).await,
+            vec![done]
+        );
+        assert_eq!(
+            manager
+                .list_subagent_ids(parent_id, SubagentOrder::SpawnOrder)
+                .await,
+            vec![busy]
+        );
+        assert!(
+            manager
+                .state
+                .subagent_tombstone_snapshot(parent_id, done, None)
+                .await
+                .is_some()
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn reaping_a_subagent_stops_its_drain() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let NewThread {
+            thread_id, thread, ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start worker thread");
+        manager
+            .state
+            .register_subagent(thread_id, test_subagent_info(parent_id, "done"))
+            .await
+            .expect("subagent registers");
+        thread
+            .set_agent_status(AgentStatus::Completed(Some("finished".to_string())))
+            .await;
+        let (drain_alive, mut drain_finished) = tokio::sync::oneshot::channel::<()>();
+        let drain_thread = Arc::clone(&thread);
+        manager
+            .state
+            .register_subagent_drain(
+                thread_id,
+                tokio::spawn(async move {
+                    let _held = (drain_thread, drain_alive);
+                    std::future::pending::<()>().await;
+                }),
+            )
+            .await;
+
+        assert_eq!(
+            manager.state.reap_completed_subagents(Duration::ZERO).await,
+            vec![thread_id]
+        );
+        // The drain was aborted and awaited, so it has already dropped what it held.
+        assert_matches!(
+            drain_finished.try_recv(),
+            Err(tokio::sync::oneshot::error::TryRecvError::Closed)
+        );
+        assert!(manager.state.take_subagent_drain(thread_id).await.is_none());
+        assert!(manager.state.get_thread(thread_id).await.is_err());
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn pause_all_and_resume_all_count_each_subagent_once() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let other_parent_id = ThreadId::new();
+        let first = ThreadId::new();
+        let second = ThreadId::new();
+        let unrelated = ThreadId::new();
+        for (id, parent, name) in [
+            (first, parent_id, "first"),
+            (second, parent_id, "second"),
+            (unrelated, other_parent_id, "unrelated"),
+        ] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        assert!(control.pause_agent(parent_id, first).await.expect("pause"));
+        assert_eq!(control.pause_all(parent_id).await.expect("pause all"), 1);
+        assert_eq!(control.pause_all(parent_id).await.expect("pause all"), 0);
+        for id in [first, second] {
+            assert!(manager.state.is_subagent_paused(id).await.expect("flag"));
+        }
+        assert!(
+            !manager
+                .state
+                .is_subagent_paused(unrelated)
+                .await
+                .expect("flag")
+        );
+        assert_matches!(
+            control.pause_agent(parent_id, unrelated).await,
+            Err(CodexErr::ThreadNotFound(id)) if id == unrelated
+        );
+
+        assert_eq!(control.resume_all(parent_id).await.expect("resume all"), 2);
+        assert_eq!(control.resume_all(parent_id).await.expect("resume all"), 0);
+        assert!(
+            !control
+                .resume_agent(parent_id, first)
+                .await
+                .expect("resume")
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_drain_waits_until_resumed_or_forgotten() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "held"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .set_subagent_paused(subagent_id, true)
+            .await
+            .expect("pause");
+
+        let state = Arc::clone(&manager.state);
+        let gate = tokio::spawn(async move { state.wait_while_paused(subagent_id).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!gate.is_finished());
+        manager
+            .state
+            .set_subagent_paused(subagent_id, false)
+            .await
+            .expect("resume");
+        tokio::time::timeout(Duration::from_secs(5), gate)
+            .await
+            .expect("resume releases the drain")
+            .expect("gate task");
+
+        manager
+            .state
+            .set_subagent_paused(subagent_id, true)
+            .await
+            .expect("pause");
+        let state = Arc::clone(&manager.state);
+        let gate = tokio::spawn(async move { state.wait_while_paused(subagent_id).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!gate.is_finished());
+        manager.state.unregister_subagent(subagent_id).await;
+        tokio::time::timeout(Duration::from_secs(5), gate)
+            .await
+            .expect("forgetting releases the drain")
+            .expect("gate task");
+    }
+
+    #[tokio::test]
+    async fn paused_subagent_refuses_input_until_resumed() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+
+        assert!(
+            control
+                .pause_agent(parent_id, subagent_id)
+                .await
+                .expect("pause")
+        );
+        let Err(CodexErr::UnsupportedOperation(message)) = control
+            .send_prompt(subagent_id, "keep going".to_string())
+            .await
+        else {
+            panic!("input to a paused agent should be refused");
+        };
+        assert_eq!(
+            message,
+            format!("agent {subagent_id} is paused; resume it first")
+        );
+
+        assert!(
+            control
+                .resume_agent(parent_id, subagent_id)
+                .await
+                .expect("resume")
+        );
+        // With no session behind it the input now gets past the pause check to delivery.
+        assert_matches!(
+            control
+                .send_prompt(subagent_id, "keep going".to_string())
+                .await,
+            Err(CodexErr::ThreadNotFound(id)) if id == subagent_id
+        );
+    }
+
+    #[tokio::test]
+    async fn eviction_counts_starting_spawns_and_stops_the_victim_drain() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let NewThread {
+            thread_id: idle,
+            thread,
+            ..
+        } = manager
+            .start_thread(test_config())
+            .await
+            .expect("start worker thread");
+        manager
+            .state
+            .register_subagent(idle, test_subagent_info(parent_id, "idle"))
+            .await
+            .expect("subagent registers");
+        thread
+            .set_agent_status(AgentStatus::Completed(Some("finished".to_string())))
+            .await;
+        let (drain_alive, mut drain_finished) = tokio::sync::oneshot::channel::<()>();
+        let drain_thread = Arc::clone(&thread);
+        manager
+            .state
+            .register_subagent_drain(
+                idle,
+                tokio::spawn(async move {
+                    let _held = (drain_thread, drain_alive);
+                    std::future::pending::<()>().await;
+                }),
+            )
+            .await;
+        for index in 2..MAX_SUBAGENTS_PER_PARENT {
+            manager
+                .state
+                .register_subagent(
+                    ThreadId::new(),
+                    test_subagent_info(parent_id, &format!("w{index}")),
+                )
+                .await
+                .expect("subagent registers");
+        }
+
+        assert_eq!(
+            control
+                .evict_idle_subagent(&manager.state, parent_id, 1)
+                .await,
+            None,
+            "a free slot needs no eviction"
+        );
+        let _starting = manager
+            .state
+            .reserve_subagent_spawn(parent_id, None)
+            .await
+            .expect("the last slot is reserved");
+        assert_eq!(
+            control
+                .evict_idle_subagent(&manager.state, parent_id, 1)
+                .await,
+            Some(idle),
+            "a starting spawn fills the cap"
+        );
+        assert_matches!(
+            drain_finished.try_recv(),
+            Err(tokio::sync::oneshot::error::TryRecvError::Closed)
+        );
+        assert!(manager.state.subagent_info(idle).await.is_none());
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn subagent_output_descendants_nest_grandchildren() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let a = ThreadId::new();
+        let b = ThreadId::new();
+        let c = ThreadId::new();
+        let d = ThreadId::new();
+        for (id, parent, name) in [(a, root, "a"), (b, a, "b"), (c, b, "c"), (d, a, "d")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+        manager.state.record_subagent_message(c, "leaf done").await;
+
+        let (nodes, truncated) = manager
+            .agent_control()
+            .subagent_output_descendants(root, a, None)
+            .await
+            .expect("descendants should resolve");
+
+        assert!(!truncated);
+        assert_eq!(
+            nodes.iter().map(|node| node.id).collect::<Vec<_>>(),
+            vec![b, d]
+        );
+        assert_eq!(
+            nodes[0]
+                .children
+                .iter()
+                .map(|node| node.id)
+                .collect::<Vec<_>>(),
+            vec![c]
+        );
+        assert_eq!(
+            nodes[0].children[0].last_message,
+            Some("leaf done".to_string())
+        );
+
+        let (flat, truncated) = manager.state.subagent_descendants(a, 1, 10).await;
+        assert_eq!(flat, vec![(b, a), (d, a)]);
+        assert!(truncated);
+        let (flat, truncated) = manager.state.subagent_descendants(a, 5, 1).await;
+        assert_eq!(flat, vec![(b, a)]);
+        assert!(truncated);
+    }
+
+    #[tokio::test]
+    async fn subagent_lineage_is_recorded_in_rollout_and_read_back() {
+        let codex_home = tempfile::tempdir().expect("create codex home");
+        let parent_id = ThreadId::new();
+        let lineage = SubagentLineage {
+            parent_id,
+            persona: Some("Reviews diffs".to_string()),
+            display_name: Some("Reviewer".to_string()),
+        };
+        let mut config = test_config();
+        config.codex_home = codex_home.path().to_path_buf();
+
+        let subagent_id = ThreadId::new();
+        let mut subagent_config = config.clone();
+        subagent_config.subagent_lineage = Some(lineage.clone());
+        let separate_dir = codex_home.path().join(crate::SUBAGENT_SESSIONS_SUBDIR);
+        for (id, config, sessions_dir) in [
+            (subagent_id, subagent_config, Some(separate_dir)),
+            (parent_id, config, None),
+        ] {
+            let mut params =
+                crate::rollout::RolloutRecorderParams::new(id, None, SessionSource::Exec);
+            if let Some(dir) = sessions_dir {
+                params = params.with_sessions_dir(dir);
+            }
+            let recorder = RolloutRecorder::new(&config, params)
+                .await
+                .expect("create rollout");
+            recorder.shutdown().await.expect("flush rollout");
+        }
+
+        let found = read_subagent_lineage(codex_home.path())
+            .await
+            .expect("scan rollouts");
+        assert_eq!(found, HashMap::from([(subagent_id, lineage)]));
+    }
+
+    #[tokio::test]
+    async fn subagent_tree_nests_descendants_of_caller() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let a = ThreadId::new();
+        let b = ThreadId::new();
+        let c = ThreadId::new();
+        for (id, parent, name) in [(a, root, "a"), (b, a, "b"), (c, root, "c")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        let tree = manager
+            .agent_control()
+            .subagent_tree(root, None)
+            .await
+            .expect("tree should resolve");
+
+        assert_eq!(tree.node_count, 3);
+        assert!(!tree.truncated);
+        assert_eq!(
+            tree.agents.iter().map(|node| node.id).collect::<Vec<_>>(),
+            vec![a, c]
+        );
+        assert_eq!(
+            tree.agents[0]
+                .children
+                .iter()
+                .map(|node| node.display_name.clone())
+                .collect::<Vec<_>>(),
+            vec![Some("b".to_string())]
+        );
+        assert!(tree.agents[1].children.is_empty());
+    }
+
+    #[tokio::test]
+    async fn subagent_tree_can_root_at_owned_descendant() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let (a, b, c) = (ThreadId::new(), ThreadId::new(), ThreadId::new());
+        for (id, parent, name) in [(a, root, "a"), (b, a, "b"), (c, b, "c")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        let tree = manager
+            .agent_control()
+            .subagent_tree(root, Some(b))
+            .await
+            .expect("grandchild subtree should resolve");
+        assert_eq!(
+            tree.agents.iter().map(|node| node.id).collect::<Vec<_>>(),
+            vec![c]
+        );
+
+        let err = manager
+            .agent_control()
+            .subagent_tree(c, Some(a))
+            .await
+            .expect_err("ancestors are not reachable from below");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == a);
+    }
+
+    #[tokio::test]
+    async fn tool_event_buffer_honors_per_subagent_limit() {
+        let manager = test_manager();
+        let subagent_id = ThreadId::new();
+        let mut info = test_subagent_info(ThreadId::new(), "builder");
+        info.max_tool_events = 2;
+        manager
+            .state
+            .register_subagent(subagent_id, info)
+            .await
+            .expect("subagent registers");
+
+        for command in ["ls", "cargo build", "cargo test"] {
+            manager
+                .state
+                .record_subagent_tool_event(subagent_id, format!("exec begin: {command}"))
+                .await;
+        }
+
+        let snapshot = manager
+            .state
+            .subagent_output_snapshot(subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(
+            snapshot.tool_events,
+            vec![
+                "exec begin: cargo build".to_string(),
+                "exec begin: cargo test".to_string(),
+            ]
+        );
+        assert!(snapshot.truncated);
+    }
+
+    #[tokio::test]
+    async fn shutdown_reason_is_kept_in_tombstone() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let child = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(parent_id, "child"))
+            .await
+            .expect("subagent registers");
+
+        manager
+            .state
+            .record_subagent_shutdown_reason(child, "work no longer needed")
+            .await;
+        manager.state.unregister_subagent(child).await;
+
+        let snapshot = manager
+            .state
+            .subagent_tombstone_snapshot(parent_id, child, None)
+            .await
+            .expect("tombstone");
+        assert_eq!(
+            snapshot.shutdown_reason.as_deref(),
+            Some("work no longer needed")
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_subagent_requires_a_recorded_prompt_and_live_child() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let child = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(parent_id, "child"))
+            .await
+            .expect("subagent registers");
+        let items = vec![UserInput::Text {
+            text: "retry the build".to_string(),
+        }];
+        manager
+            .state
+            .record_subagent_input(child, items.clone())
+            .await;
+        assert_eq!(
+            manager
+                .state
+                .subagent_info(child)
+                .await
+                .map(|info| info.last_input),
+            Some(items)
+        );
+
+        let err = manager
+            .agent_control()
+            .replay_subagent(ThreadId::new(), child)
+            .await
+            .expect_err("only the parent may replay");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == child);
+
+        // Without a running thread the agent reports `NotFound`, which cannot be replayed.
+        let err = manager
+            .agent_control()
+            .replay_subagent(parent_id, child)
+            .await
+            .expect_err("agent is not running");
+        assert_matches!(err, CodexErr::UnsupportedOperation(_));
+    }
+
+    #[test]
+    fn history_at_user_turns_keeps_turns_up_to_the_checkpoint() {
+        let items: Vec<RolloutItem> = [
+            user_msg("u1"),
+            assistant_msg("a1"),
+            user_msg("u2"),
+            assistant_msg("a2"),
+        ]
+        .into_iter()
+        .map(RolloutItem::ResponseItem)
+        .collect();
+
+        let at_first = history_at_user_turns(InitialHistory::Forked(items.clone()), 1);
+        assert_eq!(
+            serde_json::to_value(at_first.get_rollout_items()).expect("serialize history"),
+            serde_json::to_value(&items[..2]).expect("serialize expected history")
+        );
+        // A checkpoint at the end of the history keeps all of it, including the last reply.
+        let at_end = history_at_user_turns(InitialHistory::Forked(items.clone()), 2);
+        assert_eq!(
+            serde_json::to_value(at_end.get_rollout_items()).expect("serialize history"),
+            serde_json::to_value(&items).expect("serialize expected history")
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_checkpoints_are_bounded_and_owned_by_the_parent() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let child = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(parent_id, "explorer"))
+            .await
+            .expect("subagent registers");
+
+        for turn in 0..=MAX_SUBAGENT_CHECKPOINTS {
+            manager
+                .state
+                .add_subagent_checkpoint(child, format!("step {turn}"), turn)
+                .await
+                .expect("registered subagent");
+        }
+        let checkpoints = manager
+            .state
+            .subagent_info(child)
+            .await
+            .map(|info| info.checkpoints)
+            .unwrap_or_default();
+        assert_eq!(checkpoints.len(), MAX_SUBAGENT_CHECKPOINTS);
+        assert_eq!(
+            checkpoints.front(),
+            Some(&SubagentCheckpoint {
+                id: 2,
+                label: "step 1".to_string(),
+                user_turns: 1,
+            })
+        );
+        assert_eq!(
+            checkpoints.back().map(|checkpoint| checkpoint.id),
+            Some(MAX_SUBAGENT_CHECKPOINTS as u64 + 1)
+        );
+        let orphan = ThreadId::new();
+        let err = manager
+            .state
+            .add_subagent_checkpoint(orphan, "orphan".to_string(), 0)
+            .await
+            .expect_err("unregistered subagent");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == orphan);
+
+        let control = manager.agent_control();
+        let err = control
+            .checkpoint_agent(ThreadId::new(), child, "mine")
+            .await
+            .expect_err("only the parent may checkpoint");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == child);
+        let err = control
+            .checkpoint_agent(parent_id, child, "  ")
+            .await
+            .expect_err("labels are required");
+        assert_matches!(err, CodexErr::UnsupportedOperation(_));
+        let err = control
+            .restore_checkpoint(parent_id, child, 1, "retry".to_string())
+            .await
+            .expect_err("checkpoint 1 was dropped");
+        assert_matches!(
+            err,
+            CodexErr::UnsupportedOperation(message) if message.contains("no checkpoint 1")
+        );
+    }
+
+    #[tokio::test]
+    async fn inspect_subagent_reports_effective_overrides() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let (a, b) = (ThreadId::new(), ThreadId::new());
+        let mut config = test_config();
+        config.model = Some("gpt-5-codex".to_string());
+        config.tool_policy.apply_overrides(ToolPolicyToml {
+            tool_allowlist: Some(vec!["shell".to_string()]),
+            tool_denylist: None,
+            shell_command_allowlist: None,
+            shell_command_denylist: Some(vec!["git push*".to_string()]),
+        });
+        let mut info = test_subagent_info(a, "b");
+        info.persona = Some("Reviews diffs".to_string());
+        info.spawn_config = Arc::new(config);
+        manager
+            .state
+            .register_subagent(a, test_subagent_info(root, "a"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(b, info)
+            .await
+            .expect("subagent registers");
+
+        let inspection = manager
+            .agent_control()
+            .inspect_subagent(root, b)
+            .await
+            .expect("nested subagent should be inspectable");
+        assert_eq!(
+            inspection,
+            SubagentInspection {
+                id: b,
+                persona: Some("Reviews diffs".to_string()),
+                display_name: Some("b".to_string()),
+                model: Some("gpt-5-codex".to_string()),
+                priority: 0,
+                tool_allowlist: Some(vec!["shell".to_string()]),
+                tool_denylist: Vec::new(),
+                shell_command_allowlist: None,
+                shell_command_denylist: vec!["git push*".to_string()],
+            }
+        );
+
+        let err = manager
+            .agent_control()
+            .inspect_subagent(b, a)
+            .await
+            .expect_err("ancestors are not inspectable from below");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == a);
+    }
+
+    #[tokio::test]
+    async fn is_descendant_of_follows_multi_level_parent_chain() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let (a, b, c, other) = (
+            ThreadId::new(),
+            ThreadId::new(),
+            ThreadId::new(),
+            ThreadId::new(),
+        );
+        for (id, parent, name) in [(a, root, "a"), (b, a, "b"), (c, b, "c"), (other, a, "o")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        for descendant in [a, b, c, other] {
+            assert!(manager.state.is_descendant_of(root, descendant).await);
+        }
+        assert!(manager.state.is_descendant_of(a, c).await);
+        assert!(!manager.state.is_subagent_of(root, c).await);
+        assert!(!manager.state.is_descendant_of(b, other).await);
+        assert!(!manager.state.is_descendant_of(c, a).await);
+        assert!(!manager.state.is_descendant_of(root, root).await);
+        assert!(!manager.state.is_descendant_of(root, ThreadId::new()).await);
+    }
+
+    #[tokio::test]
+    async fn is_descendant_of_terminates_on_cycles() {
+        let manager = test_manager();
+        let (a, b) = (ThreadId::new(), ThreadId::new());
+        manager
+            .state
+            .register_subagent(a, test_subagent_info(b, "a"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(b, test_subagent_info(a, "b"))
+            .await
+            .expect("subagent registers");
+
+        assert!(manager.state.is_descendant_of(a, b).await);
+        assert!(!manager.state.is_descendant_of(ThreadId::new(), a).await);
+    }
+
+    #[tokio::test]
+    async fn removing_a_thread_cascades_to_its_subagent_tree_unless_disabled() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let (child, grandchild) = (ThreadId::new(), ThreadId::new());
+        let register_tree = |manager: &ThreadManager| {
+            let state = Arc::clone(&manager.state);
+            async move {
+                state
+                    .register_subagent(child, test_subagent_info(root, "child"))
+                    .await
+                    .expect("subagent registers");
+                state
+                    .register_subagent(grandchild, test_subagent_info(child, "grandchild"))
+                    .await
+                    .expect("subagent registers");
+            }
+        };
+
+        register_tree(&manager).await;
+        // Bookkeeping removals such as eviction or reaping leave the subtree alone.
+        manager.state.remove_thread(child).await;
+        assert!(manager.state.is_subagent_of(child, grandchild).await);
+
+        register_tree(&manager).await;
+        manager.remove_thread(&root).await;
+        assert!(manager.state.subagent_info(child).await.is_none());
+        assert!(manager.state.subagent_info(grandchild).await.is_none());
+
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            keep_orphaned_subagents: true,
+            ..ThreadManagerOptions::default()
+        });
+        register_tree(&manager).await;
+        manager.remove_thread(&root).await;
+        assert!(manager.state.is_subagent_of(root, child).await);
+        assert!(manager.state.is_subagent_of(child, grandchild).await);
+    }
+
+    #[tokio::test]
+    async fn capacity_snapshot_counts_subagents_and_pending_spawns() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        for name in ["a", "b"] {
+            manager
+                .state
+                .register_subagent(ThreadId::new(), test_subagent_info(parent_id, name))
+                .await
+                .expect("subagent registers");
+        }
+        let pending = manager
+            .state
+            .reserve_subagent_spawn(parent_id, None)
+            .await
+            .expect("slot is free");
+
+        assert_eq!(manager.thread_count().await, 0);
+        assert_eq!(manager.subagent_count().await, 2);
+        assert_eq!(
+            manager.capacity_snapshot().await,
+            CapacitySnapshot {
+                threads: 0,
+                subagents: 2,
+                pending_spawns: 1,
+                max_subagents_per_parent: MAX_SUBAGENTS_PER_PARENT,
+                max_concurrent_subagent_spawns: DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS,
+                available_spawn_permits: DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS,
+            }
+        );
+
+        drop(pending);
+        assert_eq!(manager.capacity_snapshot().await.pending_spawns, 0);
+    }
+
+    #[tokio::test]
+    async fn forced_close_aborts_the_drain_and_removes_the_agent_at_once() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let subagent_id = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("stuck"),
+            )
+            .await
+            .expect("spawn subagent");
+        // Swap in a drain that never finishes, so only an abort ends it.
+        if let Some(drain) = manager.state.take_subagent_drain(subagent_id).await {
+            drain.abort();
+        }
+        let stuck = tokio::spawn(std::future::pending::<()>());
+        let stuck_drain = stuck.abort_handle();
+        manager
+            .state
+            .register_subagent_drain(subagent_id, stuck)
+            .await;
+
+        control
+            .shutdown_agent(subagent_id, None)
+            .await
+            .expect("request shutdown");
+        tokio::time::timeout(Duration::from_secs(1), control.forget_subagent(subagent_id))
+            .await
+            .expect("a forced close does not wait for the drain")
+            .expect("forget subagent");
+
+        assert!(!manager.state.is_subagent_of(parent_id, subagent_id).await);
+        assert_matches!(
+            manager.get_thread(subagent_id).await,
+            Err(CodexErr::ThreadNotFound(_))
+        );
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !stuck_drain.is_finished() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the drain is aborted");
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_all_clears_subagents_and_drains() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let (a, b) = (ThreadId::new(), ThreadId::new());
+        for (id, name) in [(a, "a"), (b, "b")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, name))
+                .await
+                .expect("subagent registers");
+        }
+        manager
+            .state
+            .register_subagent_drain(a, tokio::spawn(std::future::pending()))
+            .await;
+
+        let live = manager
+            .start_thread(test_config())
+            .await
+            .expect("start live thread");
+        let stopped = manager
+            .start_thread(test_config())
+            .await
+            .expect("start stopped thread");
+        stop_session(&stopped.thread).await;
+        let stuck = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("stuck"),
+            )
+            .await
+            .expect("spawn stuck subagent");
+        if let Some(drain) = manager.state.take_subagent_drain(stuck).await {
+            drain.abort();
+        }
+        manager
+            .state
+            .register_subagent_drain(stuck, tokio::spawn(std::future::pending()))
+            .await;
+
+        let summary = manager.shutdown_all(Duration::from_secs(2)).await;
+
+        // The live and already-stopped threads count as clean; the subagent whose drain never
+        // finishes is forced down when the timeout elapses.
+        assert_eq!(
+            summary,
+            ShutdownAllSummary {
+                clean: 2,
+                timed_out: 1,
+            }
+        );
+        assert_matches!(live.thread.agent_status().await, AgentStatus::Shutdown);
+        assert_eq!(manager.thread_count().await, 0);
+        assert!(
+            manager
+                .state
+                .subagents_for_parent(parent_id)
+                .await
+                .is_empty()
+        );
+        assert!(manager.state.take_subagent_drain(a).await.is_none());
+        assert!(manager.state.subagent_tombstones.read().await.is_empty());
+    }
+
+    #[test]
+    fn blocked_team_counts_only_subagents_stalled_mid_turn() {
+        let secs = Duration::from_secs;
+        let (a, b, c) = (ThreadId::new(), ThreadId::new(), ThreadId::new());
+        assert_eq!(blocked_team(&[]), None);
+        // A team that finished its turns is done, not deadlocked.
+        assert_eq!(
+            blocked_team(&[
+                (a, AgentStatus::Completed(None), secs(300)),
+                (b, AgentStatus::Errored("boom".to_string()), secs(200)),
+            ]),
+            None
+        );
+        assert_eq!(
+            blocked_team(&[
+                (a, AgentStatus::Completed(None), secs(300)),
+                (b, AgentStatus::Running, secs(600)),
+                (c, AgentStatus::Running, secs(400)),
+            ]),
+            Some((vec![b, c], secs(400)))
+        );
+        assert_eq!(
+            blocked_team(&[
+                (a, AgentStatus::Running, secs(600)),
+                (b, AgentStatus::Running, secs(1)),
+            ]),
+            Some((vec![a, b], secs(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_spawns_are_throttled() {
+        let manager = test_manager();
+        let running = AtomicU64::new(0);
+        let peak = AtomicU64::new(0);
+
+        let spawns = (0..DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS * 3).map(|_| {
+            manager.state.throttle_subagent_spawn(async {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        futures::future::join_all(spawns).await;
+
+        assert_eq!(
+            peak.load(Ordering::SeqCst),
+            DEFAULT_MAX_CONCURRENT_SUBAGENT_SPAWNS as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn broadcast_reports_slow_and_unknown_recipients_without_waiting_on_them() {
+        let manager = test_manager();
+        let (mut session, _turn) = make_session_and_context().await;
+        session.services.agent_control = manager.agent_control();
+        let session = Arc::new(session);
+        let parent_id = session.conversation_id();
+        let ready = manager
+            .spawn_subagent(
+                parent_id,
+                test_config(),
+                "work".to_string(),
+                test_spawn_options("ready"),
+            )
+            .await
+            .expect("spawn subagent");
+        let busy = ThreadId::new();
+        manager
+            .state
+            .register_subagent(busy, test_subagent_info(parent_id, "busy"))
+            .await
+            .expect("subagent registers");
+        // Holding the busy agent's input lock stands in for a send that is stuck behind it.
+        let busy_lock = manager.state.input_lock(busy).await;
+        let guard = busy_lock.lock().await;
+        let stranger = ThreadId::new();
+
+        let report = tokio::time::timeout(
+            Duration::from_secs(5),
+            session.broadcast_group_chat_message(
+                "sub".to_string(),
+                "Rebase onto main before continuing",
+                vec![ready, busy, stranger],
+                Duration::from_millis(200),
+            ),
+        )
+        .await
+        .expect("a busy recipient must not hold up the broadcast");
+
+        assert_eq!(report.accepted, vec![ready]);
+        assert_eq!(report.slow, vec![busy]);
+        assert_eq!(
+            report.failed,
+            vec![(stranger, format!("agent with id {stranger} not found"))]
+        );
+        assert_eq!(session.group_chat_history().await.len(), 1);
+        drop(guard);
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn input_locks_are_per_agent() {
+        let manager = test_manager();
+        let (a, b) = (ThreadId::new(), ThreadId::new());
+        let lock_a = manager.state.input_lock(a).await;
+
+        let _guard = lock_a.lock().await;
+        assert!(Arc::ptr_eq(&lock_a, &manager.state.input_lock(a).await));
+        assert!(manager.state.input_lock(b).await.try_lock().is_ok());
+
+        manager.state.unregister_subagent(a).await;
+        assert!(!Arc::ptr_eq(&lock_a, &manager.state.input_lock(a).await));
+    }
+
+    #[tokio::test]
+    async fn shared_variables_are_scoped_to_the_parent() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let (parent_id, other_parent) = (ThreadId::new(), ThreadId::new());
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+
+        control
+            .set_shared(parent_id, "target_branch", Some("main".to_string()))
+            .await
+            .expect("parent set");
+        control
+            .set_shared(subagent_id, "issue", Some("42".to_string()))
+            .await
+            .expect("subagent set");
+        let expected = BTreeMap::from([
+            ("issue".to_string(), "42".to_string()),
+            ("target_branch".to_string(), "main".to_string()),
+        ]);
+        assert_eq!(control.shared_variables(parent_id).await.unwrap(), expected);
+        assert_eq!(
+            control.shared_variables(subagent_id).await.unwrap(),
+            expected
+        );
+        assert!(
+            control
+                .shared_variables(other_parent)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        control
+            .set_shared(subagent_id, "issue", None)
+            .await
+            .unwrap();
+        assert_eq!(control.shared_variables(parent_id).await.unwrap().len(), 1);
+        assert_matches!(
+            control
+                .set_shared(
+                    parent_id,
+                    "notes",
+                    Some("x".repeat(MAX_SHARED_VALUE_CHARS + 1))
+                )
+                .await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        for i in 1..MAX_SHARED_VARIABLES {
+            control
+                .set_shared(parent_id, &format!("key{i}"), Some(i.to_string()))
+                .await
+                .expect("within limit");
+        }
+        assert_matches!(
+            control
+                .set_shared(parent_id, "one_too_many", Some("x".to_string()))
+                .await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        // Overwriting an existing key is still allowed at the limit.
+        control
+            .set_shared(parent_id, "target_branch", Some("dev".to_string()))
+            .await
+            .expect("overwrite at limit");
+
+        manager.state.remove_thread(parent_id).await;
+        assert!(
+            control
+                .shared_variables(parent_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn scratchpad_is_shared_with_subagents_and_drops_oldest_entries() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+
+        control
+            .scratchpad_append(parent_id, "plan: split by crate")
+            .await
+            .expect("parent append");
+        let usage = control
+            .scratchpad_append(subagent_id, "  core builds cleanly\n")
+            .await
+            .expect("subagent append");
+        assert_eq!(
+            usage,
+            ScratchpadUsage {
+                entries: 2,
+                chars: 39,
+                trimmed_entries: 0,
+            }
+        );
+        assert_eq!(
+            control.scratchpad(parent_id).await.unwrap(),
+            vec!["plan: split by crate", "core builds cleanly"]
+        );
+        assert_eq!(
+            control.scratchpad(subagent_id).await.unwrap(),
+            control.scratchpad(parent_id).await.unwrap()
+        );
+        assert_matches!(
+            control.scratchpad_append(parent_id, " ").await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+        assert_matches!(
+            control
+                .scratchpad_append(parent_id, &"x".repeat(MAX_SCRATCHPAD_CHARS + 1))
+                .await,
+            Err(CodexErr::UnsupportedOperation(_))
+        );
+
+        let usage = control
+            .scratchpad_append(parent_id, &"y".repeat(MAX_SCRATCHPAD_CHARS - 20))
+            .await
+            .expect("append large entry");
+        assert_eq!(usage.trimmed_entries, 1);
+        assert_eq!(
+            control.scratchpad(parent_id).await.unwrap()[0],
+            "core builds cleanly"
+        );
+
+        manager.state.remove_thread(parent_id).await;
+        assert!(control.scratchpad(parent_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn nested_subagents_share_the_top_level_store() {
+        let manager = test_manager();
+        let control = manager.agent_control();
+        let (root, mid, leaf) = (ThreadId::new(), ThreadId::new(), ThreadId::new());
+        manager
+            .state
+            .register_subagent(mid, test_subagent_info(root, "lead"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .register_subagent(leaf, test_subagent_info(mid, "worker"))
+            .await
+            .expect("subagent registers");
+
+        control
+            .set_shared(mid, "target_branch", Some("main".to_string()))
+            .await
+            .expect("mid set");
+        control
+            .set_shared(leaf, "issue", Some("42".to_string()))
+            .await
+            .expect("leaf set");
+        let expected = BTreeMap::from([
+            ("issue".to_string(), "42".to_string()),
+            ("target_branch".to_string(), "main".to_string()),
+        ]);
+        for thread_id in [root, mid, leaf] {
+            assert_eq!(control.shared_variables(thread_id).await.unwrap(), expected);
+        }
+        // What mid hands to a new child is what mid itself sees.
+        assert_eq!(control.child_shared_variables(mid).await.unwrap(), expected);
+
+        control
+            .scratchpad_append(mid, "plan: split by crate")
+            .await
+            .expect("mid append");
+        control
+            .scratchpad_append(leaf, "core builds cleanly")
+            .await
+            .expect("leaf append");
+        for thread_id in [root, mid, leaf] {
+            assert_eq!(
+                control.scratchpad(thread_id).await.unwrap(),
+                vec!["plan: split by crate", "core builds cleanly"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn sibling_parent_requires_a_shared_parent() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let (a, b, cousin) = (ThreadId::new(), ThreadId::new(), ThreadId::new());
+        for (id, parent, name) in [
+            (a, parent_id, "a"),
+            (b, parent_id, "b"),
+            (cousin, ThreadId::new(), "cousin"),
+        ] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+
+        assert_eq!(manager.state.sibling_parent(a, b).await, Some(parent_id));
+        assert_eq!(manager.state.sibling_parent(a, a).await, None);
+        assert_eq!(manager.state.sibling_parent(a, cousin).await, None);
+        assert_eq!(manager.state.sibling_parent(a, parent_id).await, None);
+    }
+
+    #[tokio::test]
+    async fn contended_state_locks_time_out_instead_of_hanging() {
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            state_lock_timeout_ms: Some(20),
+            ..ThreadManagerOptions::default()
+        });
+        let parent_id = ThreadId::new();
+        let child = ThreadId::new();
+        manager
+            .state
+            .register_subagent(child, test_subagent_info(parent_id, "child"))
+            .await
+            .expect("subagent registers");
+
+        let held = manager.state.subagents.write().await;
+        let err = manager
+            .state
+            .reassign_subagents(parent_id, ThreadId::new())
+            .await
+            .expect_err("the subagents lock is held");
+        assert!(
+            matches!(
+                err,
+                CodexErr::StateLockTimeout {
+                    lock: "subagents",
+                    ..
+                }
+            ),
+            "unexpected error: {err:?}"
+        );
+        let err = manager
+            .state
+            .register_subagent(ThreadId::new(), test_subagent_info(parent_id, "late"))
+            .await
+            .expect_err("the subagents lock is held");
+        assert!(!err.is_retryable(), "lock timeouts are not retried");
+        // Lookups read a held graph as empty rather than waiting on it.
+        assert!(manager.state.subagent_info(child).await.is_none());
+        assert!(!manager.state.is_subagent_of(parent_id, child).await);
+        assert!(
+            manager
+                .state
+                .subagents_for_parent(parent_id)
+                .await
+                .is_empty()
+        );
+        // Teardown skips the held store instead of hanging.
+        manager.state.remove_thread(child).await;
+        drop(held);
+
+        manager
+            .state
+            .reassign_subagents(parent_id, ThreadId::new())
+            .await
+            .expect("lock is free again");
+    }
+
+    #[tokio::test]
+    async fn reassign_subagents_rejects_cycles() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let a = ThreadId::new();
+        let b = ThreadId::new();
+        let c = ThreadId::new();
+        for (id, parent) in [(a, root), (b, a), (c, b)] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, "worker"))
+                .await
+                .expect("subagent registers");
+        }
+
+        let err = manager
+            .state
+            .reassign_subagents(root, c)
+            .await
+            .expect_err("A under C would create a cycle");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "cannot move the subagents of {root} under {c}: agent {a} would become its own ancestor"
+            )
+        );
+        assert_matches!(manager.state.subagent_info(a).await, Some(info) if info.parent_id == root);
+
+        let moved = manager
+            .state
+            .reassign_subagents(b, a)
+            .await
+            .expect("C under A keeps the graph acyclic");
+        assert_eq!(moved, vec![c]);
+        assert!(manager.state.is_subagent_of(a, c).await);
+    }
 
-/// Return a prefix of `items` obtained by cutting strictly before the nth user message
-/// (0-based) and all items that follow it.
-fn truncate_before_nth_user_message(history: InitialHistory, n: usize) -> InitialHistory {
-    let items: Vec<RolloutItem> = history.get_rollout_items();
-    let rolled = truncation::truncate_rollout_before_nth_user_message_from_start(&items, n);
+    #[tokio::test]
+    async fn removed_subagent_output_remains_readable_by_its_parent() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .record_subagent_tool_event(subagent_id, "exec end: cargo test (exit 0)".to_string())
+            .await;
+        manager
+            .state
+            .record_subagent_message(subagent_id, "all tests pass")
+            .await;
 
-    if rolled.is_empty() {
-        InitialHistory::New
-    } else {
-        InitialHistory::Forked(rolled)
+        manager.state.remove_thread(subagent_id).await;
+
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("tombstone should be readable");
+        assert_eq!(snapshot.last_message, Some("all tests pass".to_string()));
+        assert_eq!(
+            snapshot.tool_events,
+            vec!["exec end: cargo test (exit 0)".to_string()]
+        );
+
+        let err = manager
+            .agent_control()
+            .subagent_output(ThreadId::new(), subagent_id, None)
+            .await
+            .expect_err("tombstones are scoped to the parent");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == subagent_id);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::codex::make_session_and_context;
-    use assert_matches::assert_matches;
-    use codex_protocol::models::ContentItem;
-    use codex_protocol::models::ReasoningItemReasoningSummary;
-    use codex_protocol::models::ResponseItem;
-    use pretty_assertions::assert_eq;
+    #[tokio::test]
+    async fn removed_subagent_output_remains_readable_by_its_ancestors() {
+        let manager = test_manager();
+        let root = ThreadId::new();
+        let (child, grandchild) = (ThreadId::new(), ThreadId::new());
+        for (id, parent, name) in [(child, root, "child"), (grandchild, child, "grandchild")] {
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent, name))
+                .await
+                .expect("subagent registers");
+        }
+        manager
+            .state
+            .record_subagent_message(grandchild, "found the bug")
+            .await;
+        manager.state.remove_thread(grandchild).await;
 
-    fn user_msg(text: &str) -> ResponseItem {
-        ResponseItem::Message {
-            id: None,
-            role: "user".to_string(),
-            content: vec![ContentItem::OutputText {
-                text: text.to_string(),
-            }],
+        for reader in [root, child] {
+            let snapshot = manager
+                .agent_control()
+                .subagent_output(reader, grandchild, None)
+                .await
+                .expect("ancestors read the tombstone");
+            assert_eq!(snapshot.last_message.as_deref(), Some("found the bug"));
         }
+        let err = manager
+            .agent_control()
+            .subagent_output(grandchild, grandchild, None)
+            .await
+            .expect_err("an agent is not its own ancestor");
+        assert_matches!(err, CodexErr::ThreadNotFound(id) if id == grandchild);
     }
-    fn assistant_msg(text: &str) -> ResponseItem {
-        ResponseItem::Message {
-            id: None,
-            role: "assistant".to_string(),
-            content: vec![ContentItem::OutputText {
-                text: text.to_string(),
-            }],
+
+    #[tokio::test]
+    async fn subagent_tombstones_are_size_bounded() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        for _ in 0..MAX_SUBAGENT_TOMBSTONES + 4 {
+            let id = ThreadId::new();
+            manager
+                .state
+                .register_subagent(id, test_subagent_info(parent_id, "worker"))
+                .await
+                .expect("subagent registers");
+            manager.state.remove_thread(id).await;
+        }
+
+        assert_eq!(
+            manager.state.subagent_tombstones.read().await.len(),
+            MAX_SUBAGENT_TOMBSTONES
+        );
+    }
+
+    #[derive(Default)]
+    struct CountingMetrics {
+        spawned: std::sync::atomic::AtomicUsize,
+        completed: std::sync::atomic::AtomicUsize,
+        errored: std::sync::atomic::AtomicUsize,
+        timed_out: std::sync::atomic::AtomicUsize,
+        removed: std::sync::atomic::AtomicUsize,
+    }
+
+    impl SubagentMetrics for CountingMetrics {
+        fn subagent_spawned(
+            &self,
+            _parent_id: ThreadId,
+            _subagent_id: ThreadId,
+            _correlation_id: Option<&str>,
+        ) {
+            self.spawned
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         }
+
+        fn subagent_status_changed(&self, _subagent_id: ThreadId, status: &AgentStatus) {
+            let counter = match status {
+                AgentStatus::Completed(_) => &self.completed,
+                AgentStatus::Errored(_) => &self.errored,
+                _ => return,
+            };
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        fn subagent_wait_timed_out(&self, _subagent_id: ThreadId) {
+            self.timed_out
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        fn subagent_removed(&self, _subagent_id: ThreadId) {
+            self.removed
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn subagent_metrics_observe_lifecycle() {
+        let metrics = Arc::new(CountingMetrics::default());
+        let manager = test_manager_with_options(ThreadManagerOptions {
+            subagent_metrics: Some(metrics.clone()),
+            ..ThreadManagerOptions::default()
+        });
+        let subagent_id = ThreadId::new();
+
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+        manager
+            .state
+            .record_subagent_status(subagent_id, &AgentStatus::Running);
+        manager
+            .state
+            .record_subagent_status(subagent_id, &AgentStatus::Completed(None));
+        manager
+            .state
+            .record_subagent_status(subagent_id, &AgentStatus::Errored("boom".to_string()));
+        manager.agent_control().record_wait_timeout(subagent_id);
+        manager.state.remove_thread(subagent_id).await;
+        manager.state.remove_thread(subagent_id).await;
+
+        let count = |counter: &std::sync::atomic::AtomicUsize| {
+            counter.load(std::sync::atomic::Ordering::SeqCst)
+        };
+        assert_eq!(
+            (
+                count(&metrics.spawned),
+                count(&metrics.completed),
+                count(&metrics.errored),
+                count(&metrics.timed_out),
+                count(&metrics.removed),
+            ),
+            (1, 1, 1, 1, 1)
+        );
     }
 
     #[test]
-    fn drops_from_last_user_only() {
-        let items = [
-            user_msg("u1"),
-            assistant_msg("a1"),
-            assistant_msg("a2"),
-            user_msg("u2"),
-            assistant_msg("a3"),
-            ResponseItem::Reasoning {
-                id: "r1".to_string(),
-                summary: vec![ReasoningItemReasoningSummary::SummaryText {
-                    text: "s".to_string(),
-                }],
-                content: None,
-                encrypted_content: None,
-            },
-            ResponseItem::FunctionCall {
-                id: None,
-                call_id: "c1".to_string(),
-                name: "tool".to_string(),
-                arguments: "{}".to_string(),
-            },
-            assistant_msg("a4"),
-        ];
+    fn subagent_output_keeps_reasoning_summary_and_raw_separate() {
+        let mut output = SubagentOutput::new();
+        output.push_reasoning_summary_delta("Planning the change");
+        output.push_reasoning_raw_delta("raw thoughts");
 
-        let initial: Vec<RolloutItem> = items
-            .iter()
-            .cloned()
-            .map(RolloutItem::ResponseItem)
-            .collect();
-        let truncated = truncate_before_nth_user_message(InitialHistory::Forked(initial), 1);
-        let got_items = truncated.get_rollout_items();
-        let expected_items = vec![
-            RolloutItem::ResponseItem(items[0].clone()),
-            RolloutItem::ResponseItem(items[1].clone()),
-            RolloutItem::ResponseItem(items[2].clone()),
-        ];
+        let snapshot = output.snapshot(None);
         assert_eq!(
-            serde_json::to_value(&got_items).unwrap(),
-            serde_json::to_value(&expected_items).unwrap()
+            snapshot.reasoning_summary,
+            Some("Planning the change".to_string())
         );
+        assert_eq!(snapshot.reasoning_raw, Some("raw thoughts".to_string()));
 
-        let initial2: Vec<RolloutItem> = items
-            .iter()
-            .cloned()
-            .map(RolloutItem::ResponseItem)
-            .collect();
-        let truncated2 = truncate_before_nth_user_message(InitialHistory::Forked(initial2), 2);
-        assert_matches!(truncated2, InitialHistory::New);
+        output.reset_for_prompt();
+        let snapshot = output.snapshot(None);
+        assert_eq!(snapshot.reasoning_summary, None);
+        assert_eq!(snapshot.reasoning_raw, None);
+    }
+
+    #[test]
+    fn markdown_blocks_split_paragraphs_and_fences() {
+        let text = "one\ntwo\n\n```rust\nfn a() {}\n\nfn b() {}\n```\n\nafter\n~~~\nopen";
+        let blocks = markdown_blocks(text)
+            .into_iter()
+            .map(|(range, is_code)| (&text[range], is_code))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                ("one\ntwo\n\n", false),
+                ("```rust\nfn a() {}\n\nfn b() {}\n```\n\n", true),
+                ("after\n", false),
+                ("~~~\nopen", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn trim_drops_prose_before_code_blocks() {
+        let code = "```sh\ncargo test\n```\n";
+        let original = format!("{}\n\n{code}{}\n\nlatest", "a".repeat(20), "b".repeat(20));
+        let budget = code.chars().count() + "latest".len() + 2;
+
+        let mut value = original.clone();
+        assert!(trim_to_max_chars(&mut value, budget, true));
+        assert_eq!(value, format!("{code}latest"));
+
+        let mut value = original.clone();
+        assert!(trim_to_max_chars(&mut value, budget, false));
+        assert_eq!(value, original[original.len() - budget..]);
+    }
+
+    #[test]
+    fn trim_falls_back_to_chars_when_newest_block_is_too_long() {
+        let mut value = format!("intro\n\n```\n{}", "x".repeat(40));
+        assert!(trim_to_max_chars(&mut value, 10, true));
+        assert_eq!(value, "x".repeat(10));
+
+        let mut value = "short".to_string();
+        assert!(!trim_to_max_chars(&mut value, 10, true));
+        assert_eq!(value, "short");
+    }
+
+    #[test]
+    fn subagent_output_flags_truncation_until_next_prompt() {
+        let mut output = SubagentOutput::new();
+        output.push_delta(&"a".repeat(MAX_SUBAGENT_OUTPUT_CHARS));
+        assert!(!output.snapshot(None).truncated);
+
+        output.push_delta("b");
+        let snapshot = output.snapshot(None);
+        assert!(snapshot.truncated);
+        assert_eq!(
+            snapshot.partial.map(|partial| partial.chars().count()),
+            Some(MAX_SUBAGENT_OUTPUT_CHARS)
+        );
+
+        output.reset_for_prompt();
+        assert!(!output.snapshot(None).truncated);
+    }
+
+    #[test]
+    fn subagent_output_collects_applied_file_changes_until_next_prompt() {
+        let mut output = SubagentOutput::new();
+        output.push_file_changes(HashMap::from([
+            (
+                PathBuf::from("src/b.rs"),
+                FileChange::Update {
+                    unified_diff: "@@ -1 +1 @@\n-old\n+new\n".to_string(),
+                    move_path: None,
+                },
+            ),
+            (
+                PathBuf::from("src/a.rs"),
+                FileChange::Add {
+                    content: "fn main() {}\n".to_string(),
+                },
+            ),
+        ]));
+
+        let snapshot = output.snapshot(None);
+        assert_eq!(
+            snapshot
+                .file_changes
+                .iter()
+                .map(|change| change.path.clone())
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]
+        );
+        assert_eq!(
+            serde_json::to_value(&snapshot.file_changes[0]).expect("serialize file change"),
+            serde_json::json!({
+                "path": "src/a.rs",
+                "type": "add",
+                "content": "fn main() {}\n",
+            })
+        );
+
+        output.reset_for_prompt();
+        assert!(output.snapshot(None).file_changes.is_empty());
+    }
+
+    #[test]
+    fn subagent_output_keeps_turn_diff_and_recent_warnings_until_next_prompt() {
+        let mut output = SubagentOutput::new();
+        output.set_turn_diff("@@ -1 +1 @@\n-old\n+new\n");
+        for i in 0..=MAX_SUBAGENT_WARNINGS {
+            output.push_warning(format!("warning {i}"));
+        }
+
+        let snapshot = output.snapshot(None);
+        assert_eq!(
+            snapshot.turn_diff.as_deref(),
+            Some("@@ -1 +1 @@\n-old\n+new\n")
+        );
+        assert_eq!(snapshot.warnings.len(), MAX_SUBAGENT_WARNINGS);
+        assert_eq!(snapshot.warnings[0], "warning 1");
+        assert!(snapshot.truncated);
+
+        output.reset_for_prompt();
+        let snapshot = output.snapshot(None);
+        assert_eq!(snapshot.turn_diff, None);
+        assert!(snapshot.warnings.is_empty());
+    }
+
+    #[test]
+    fn control_sequences_are_stripped_from_captured_output() {
+        assert_eq!(
+            strip_control_sequences(
+                "\u{1b}[1;31merror\u{1b}[0m: build failed\r\n\u{1b}]0;cargo\u{7}\tdone\u{1b}]8;;\u{1b}\\\u{1b}=\u{8}"
+            ),
+            "error: build failed\n\tdone"
+        );
+
+        let mut output = SubagentOutput::new();
+        output.push_tool_event("exec end: \u{1b}[32mcargo test\u{1b}[0m (exit 0)".to_string());
+        output.push_delta("\u{1b}[2KCompiling...");
+        let snapshot = output.snapshot(None).strip_control_sequences();
+        assert_eq!(snapshot.tool_events, vec!["exec end: cargo test (exit 0)"]);
+        assert_eq!(snapshot.partial.as_deref(), Some("Compiling..."));
+    }
+
+    #[test]
+    fn subagent_output_renders_as_markdown_text_or_json() {
+        let mut output = SubagentOutput::new();
+        output.push_reasoning_summary_delta("Checked the tests first.");
+        output.push_tool_event("exec end: cargo test (exit 0)".to_string());
+        output.set_message("All tests pass.");
+        let snapshot = output.snapshot(None);
+
+        assert_eq!(
+            snapshot.render(SubagentOutputFormat::Markdown),
+            "## Message\n\nAll tests pass.\n\n## Reasoning\n\nChecked the tests first.\n\n## Tools\n\n- exec end: cargo test (exit 0)"
+        );
+        assert_eq!(
+            snapshot.render(SubagentOutputFormat::Text),
+            "Message:\nAll tests pass.\n\nReasoning:\nChecked the tests first.\n\nTools:\n- exec end: cargo test (exit 0)"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&snapshot.render(SubagentOutputFormat::Json)).expect("json");
+        assert_eq!(json["last_message"], "All tests pass.");
+        assert_eq!(
+            json["tool_events"],
+            serde_json::json!(["exec end: cargo test (exit 0)"])
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_output_broadcasts_deltas_and_messages() {
+        let mut output = SubagentOutput::new();
+        let mut rx = output.updates.subscribe();
+
+        output.push_delta("hel");
+        output.set_message("hello");
+
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            SubagentOutputUpdate::Delta("hel".to_string())
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            SubagentOutputUpdate::Message("hello".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn subagent_output_survives_parent_turn_boundaries() {
+        let manager = test_manager();
+        let parent_id = ThreadId::new();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(parent_id, "worker"))
+            .await
+            .expect("subagent registers");
+
+        // Recorded while the parent's first turn is still running.
+        manager
+            .state
+            .record_subagent_turn_started(subagent_id)
+            .await;
+        manager
+            .state
+            .record_subagent_tool_event(subagent_id, "exec: cargo test".to_string())
+            .await;
+        manager
+            .state
+            .record_subagent_delta(subagent_id, "Still ")
+            .await;
+
+        // The parent's turn ends here; the subagent keeps streaming into the same buffer.
+        manager
+            .state
+            .record_subagent_delta(subagent_id, "running.")
+            .await;
+        manager
+            .state
+            .record_subagent_turn_complete(subagent_id)
+            .await;
+
+        // Read from the parent's next turn.
+        let snapshot = manager
+            .agent_control()
+            .subagent_output(parent_id, subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(snapshot.last_message.as_deref(), Some("Still running."));
+        assert_eq!(snapshot.tool_events, vec!["exec: cargo test".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn turn_complete_promotes_delta_only_output_to_last_message() {
+        let manager = test_manager();
+        let subagent_id = ThreadId::new();
+        manager
+            .state
+            .register_subagent(subagent_id, test_subagent_info(ThreadId::new(), "worker"))
+            .await
+            .expect("subagent registers");
+
+        for delta in ["All ", "tests ", "pass."] {
+            manager
+                .state
+                .record_subagent_delta(subagent_id, delta)
+                .await;
+        }
+        manager
+            .state
+            .record_subagent_turn_complete(subagent_id)
+            .await;
+
+        let snapshot = manager
+            .state
+            .subagent_output_snapshot(subagent_id, None)
+            .await
+            .expect("subagent output");
+        assert_eq!(snapshot.last_message.as_deref(), Some("All tests pass."));
+        assert_eq!(snapshot.partial, None);
+    }
+
+    #[test]
+    fn turn_complete_keeps_a_completed_message_over_later_deltas() {
+        let mut output = SubagentOutput::new();
+        output.reset_for_prompt();
+        output.push_delta("Final answer.");
+        output.set_message("Final answer.");
+        output.push_delta("Trailing text");
+        output.complete_turn();
+        assert_eq!(output.last_message.as_deref(), Some("Final answer."));
+
+        // The next prompt's delta-only reply still replaces the previous turn's message.
+        output.reset_for_prompt();
+        output.push_delta("Second answer.");
+        output.complete_turn();
+        assert_eq!(output.last_message.as_deref(), Some("Second answer."));
+    }
+
+    #[tokio::test]
+    async fn subagent_output_subscriber_reports_lag() {
+        let mut output = SubagentOutput::new();
+        let mut rx = output.updates.subscribe();
+
+        for _ in 0..=SUBAGENT_OUTPUT_CHANNEL_CAPACITY {
+            output.push_delta("x");
+        }
+
+        assert_matches!(rx.recv().await, Err(broadcast::error::RecvError::Lagged(1)));
     }
 
     #[tokio::test]
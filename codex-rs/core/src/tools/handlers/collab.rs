@@ -1,7 +1,22 @@
+use crate::agent::AgentStatusFilter;
+use crate::agent::ListSubagentsQuery;
+use crate::agent::SubagentOutputNode;
+use crate::agent::SubagentSpawn;
+use crate::agent::control::SubagentSummary;
+use crate::agent_personas::DEFAULT_SUBAGENT_TEMPLATES;
+use crate::agent_personas::ORCHESTRATOR_PROMPT;
+use crate::codex::InitialContext;
 use crate::codex::TurnContext;
 use crate::config::types::ToolPolicyToml;
 use crate::error::CodexErr;
+use crate::error::Result as CodexResult;
+use crate::error::SubagentLimitKind;
 use crate::function_tool::FunctionCallError;
+use crate::thread_manager::MAX_PINNED_CONTEXT_CHARS;
+use crate::thread_manager::MAX_SCRATCHPAD_CHARS;
+use crate::thread_manager::SubagentFileChange;
+use crate::thread_manager::SubagentOrder;
+use crate::thread_manager::SubagentOutputSnapshot;
 use crate::tools::context::ToolInvocation;
 use crate::tools::context::ToolOutput;
 use crate::tools::context::ToolPayload;
@@ -10,30 +25,146 @@ use crate::tools::registry::ToolHandler;
 use crate::tools::registry::ToolKind;
 use async_trait::async_trait;
 use codex_protocol::ThreadId;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ResponseItem;
 use codex_protocol::protocol::AgentStatus;
+use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::GroupChatMessageEvent;
 use codex_protocol::protocol::GroupChatSender;
+use codex_protocol::protocol::InitialHistory;
+use codex_protocol::protocol::RateLimitSnapshot;
 use codex_protocol::protocol::SessionSource;
+use codex_protocol::protocol::TokenUsage;
+use codex_protocol::protocol::WarningEvent;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use tokio::time::sleep;
 
 pub struct CollabHandler;
 
-pub(crate) const DEFAULT_WAIT_TIMEOUT_MS: i64 = 30_000;
-pub(crate) const MAX_WAIT_TIMEOUT_MS: i64 = 300_000;
+/// Version of the `list_agents` envelope; bump it when the agent entries change incompatibly.
+const LIST_AGENTS_SCHEMA_VERSION: u32 = 1;
+/// Already-read group-chat messages included when `agent_output` asks for the chat tail.
+const GROUP_CHAT_TAIL_READ_MESSAGES: usize = 10;
+/// Most recent tool events included in a `report_agent` summary.
+const REPORT_TOOL_EVENTS: usize = 3;
+/// Longest excerpt of the latest message or tool event included in a `report_agent` summary.
+const REPORT_EXCERPT_CHARS: usize = 200;
+/// Characters of base instructions and context items `inspect_agent_context` returns.
+const MAX_AGENT_CONTEXT_CHARS: usize = 48_000;
+/// Time each `broadcast_input` recipient gets to accept the message before it is reported slow.
+const DEFAULT_BROADCAST_TIMEOUT_MS: i64 = 5_000;
 
 #[derive(Debug, Deserialize)]
 struct SpawnAgentArgs {
     message: String,
     display_name: String,
     persona: Option<String>,
+    model: Option<String>,
     tool_allowlist: Option<Vec<String>>,
     tool_denylist: Option<Vec<String>>,
     shell_command_allowlist: Option<Vec<String>>,
     shell_command_denylist: Option<Vec<String>>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    use_template_intro: bool,
+    source_label: Option<String>,
+    #[serde(default)]
+    attachments: Vec<PathBuf>,
+    #[serde(default)]
+    priority: u8,
+    #[serde(default)]
+    evict_idle: bool,
+    max_tool_events: Option<usize>,
+    #[serde(default)]
+    include_shared: bool,
+    spawn_token: Option<String>,
+    /// Post the subagent's final messages to the group chat; defaults to true.
+    auto_post: Option<bool>,
+    correlation_id: Option<String>,
+    /// JSON schema, as a JSON string, the subagent's final messages must follow.
+    output_schema: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpawnAgentBatchArgs {
+    agents: Vec<SpawnAgentArgs>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnAgentBatchResponse {
+    results: Vec<SpawnAgentBatchResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnAgentBatchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent_id: Option<ThreadId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_fallback: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl SpawnAgentBatchResult {
+    fn failed(err: FunctionCallError) -> Self {
+        Self {
+            agent_id: None,
+            model_fallback: None,
+            error: Some(err.to_string()),
+        }
+    }
+}
+
+/// A validated spawn, plus why its requested model was replaced by the parent's, if it was.
+struct PreparedSpawn {
+    spawn: SubagentSpawn,
+    model_fallback: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloneAgentArgs {
+    id: String,
+    count: usize,
+    message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CloneAgentResponse {
+    agent_ids: Vec<ThreadId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckpointAgentArgs {
+    id: String,
+    label: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckpointAgentResponse {
+    checkpoint_id: u64,
+    label: String,
+    user_turns: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreCheckpointArgs {
+    id: String,
+    checkpoint_id: u64,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RestoreCheckpointResponse {
+    agent_id: ThreadId,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,25 +173,316 @@ struct SendInputArgs {
     message: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct SendAndWaitArgs {
+    id: String,
+    message: String,
+    timeout_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SendAndWaitResponse {
+    status: AgentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BroadcastInputArgs {
+    /// Recipients; every direct subagent when omitted.
+    ids: Option<Vec<String>>,
+    message: String,
+    timeout_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct BroadcastInputResponse {
+    accepted: Vec<ThreadId>,
+    slow: Vec<ThreadId>,
+    failed: Vec<BroadcastFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct BroadcastFailure {
+    id: ThreadId,
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectAgentContextArgs {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct InspectAgentContextResponse {
+    /// Context items ahead of the first user turn, in the order the model sees them.
+    context: Vec<AgentContextItem>,
+    base_instructions: String,
+    /// Whether anything was cut to stay within `MAX_AGENT_CONTEXT_CHARS`.
+    truncated: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct AgentContextItem {
+    role: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AmendInstructionsArgs {
+    id: String,
+    text: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct WaitArgs {
     id: String,
     timeout_ms: Option<i64>,
+    /// Answer a timeout with the agent's current status and an output preview instead of an
+    /// error.
+    #[serde(default)]
+    partial_on_timeout: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct WaitTimeoutResponse {
+    timed_out: bool,
+    status: AgentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WaitForMessageArgs {
+    id: String,
+    pattern: String,
+    /// Treat `pattern` as a regular expression instead of a plain substring.
+    #[serde(default)]
+    regex: bool,
+    timeout_ms: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct CloseAgentArgs {
     id: String,
     timeout_ms: Option<i64>,
+    /// Skip waiting for the agent to finish shutting down and forget it immediately.
+    #[serde(default)]
+    force: bool,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAgentsArgs {
+    #[serde(default)]
+    order: SubagentOrder,
+    status_filter: Option<AgentStatusFilter>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListAgentsResponse {
+    version: u32,
+    agents: Vec<SubagentSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetAgentTreeArgs {
+    id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportAgentArgs {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompareAgentsArgs {
+    left: String,
+    right: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplayAgentArgs {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelSpawnArgs {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetSharedArgs {
+    key: String,
+    value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetSharedArgs {
+    key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetSharedResponse {
+    variables: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PinContextArgs {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PinContextResponse {
+    pinned_items: usize,
+    pinned_chars: usize,
+    max_chars: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearPinnedContextArgs {}
+
+#[derive(Debug, Deserialize)]
+struct SpawnCapacityArgs {}
+
+#[derive(Debug, Deserialize)]
+struct ScratchpadAppendArgs {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ScratchpadAppendResponse {
+    entries: usize,
+    chars: usize,
+    max_chars: usize,
+    trimmed_entries: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScratchpadReadArgs {
+    last: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScratchpadReadResponse {
+    entries: Vec<String>,
+    total_entries: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadGroupChatArgs {
+    last: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadGroupChatResponse {
+    messages: Vec<GroupChatMessageEvent>,
+    total_messages: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ClearPinnedContextResponse {
+    cleared_items: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectAgentArgs {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KillAllAgentsArgs {}
+
+#[derive(Debug, Deserialize)]
+struct PauseAgentArgs {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PauseAgentResponse {
+    paused: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ResumeAgentResponse {
+    resumed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PauseAllArgs {}
+
+#[derive(Debug, Serialize)]
+struct PauseAllResponse {
+    paused: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ResumeAllResponse {
+    resumed: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct AgentTokenUsageArgs {}
+
+#[derive(Debug, Deserialize)]
+struct AgentsSummaryArgs {}
+
+#[derive(Debug, Serialize)]
+struct AgentTokenUsageResponse {
+    used_tokens: u64,
+    budget_tokens: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
-struct ListAgentsArgs {}
+struct ListPersonasArgs {
+    #[serde(default)]
+    include_orchestrator_prompt: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct PersonaEntry {
+    name: &'static str,
+    persona: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ListPersonasResponse {
+    personas: Vec<PersonaEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    orchestrator_prompt: Option<&'static str>,
+}
 
 #[derive(Debug, Deserialize)]
 struct AgentOutputArgs {
     id: String,
     max_chars: Option<usize>,
+    #[serde(default)]
+    include_group_chat: bool,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    include_file_changes: bool,
+    #[serde(default)]
+    include_turn_diff: bool,
+    /// Strip ANSI escape sequences and control characters from the output; defaults to true.
+    strip_ansi: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct DescendantOutputs {
+    agents: Vec<SubagentOutputNode>,
+    truncated: bool,
+}
+
+/// Group-chat messages as seen by a subagent: its most recent read messages and what is
+/// still queued for it.
+#[derive(Debug, Serialize)]
+struct GroupChatTail {
+    last_read: Vec<GroupChatMessageEvent>,
+    unread: Vec<GroupChatMessageEvent>,
 }
 
 #[derive(Debug, Serialize)]
@@ -69,8 +491,71 @@ struct AgentOutputResponse {
     status: AgentStatus,
     partial: Option<String>,
     last_message: Option<String>,
-    reasoning: Option<String>,
+    /// Final message parsed as JSON when the subagent answered with a JSON document;
+    /// `last_message` still carries the raw text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    structured_result: Option<serde_json::Value>,
+    /// True when the subagent was spawned with an `output_schema` but its final message is not
+    /// a JSON document.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    structured_result_invalid: bool,
+    reasoning_summary: Option<String>,
+    reasoning_raw: Option<String>,
     tool_events: Option<Vec<String>>,
+    /// True when older output was dropped since the last prompt; poll more often or ask the
+    /// subagent for a summary.
+    truncated: bool,
+    /// Cumulative token usage reported by the subagent so far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_usage: Option<TokenUsage>,
+    /// Latest rate limits the subagent's provider reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limits: Option<RateLimitSnapshot>,
+    /// Warnings and retried stream errors since the last prompt.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    /// Files changed by patches the subagent applied since its last prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_changes: Option<Vec<SubagentFileChange>>,
+    /// Aggregated diff of the subagent's current or last turn.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    turn_diff: Option<String>,
+    /// Reason given when the subagent was closed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shutdown_reason: Option<String>,
+    /// Group-chat posts from the subagent that could not be delivered to its parent.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    undelivered_messages: Vec<String>,
+    /// Seconds since the subagent last produced output or received a prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seconds_since_last_activity: Option<u64>,
+    /// Group-chat messages not yet delivered to the subagent; only set for direct subagents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unread_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_chat: Option<GroupChatTail>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    descendants: Option<DescendantOutputs>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComparedAgent {
+    id: ThreadId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    status: AgentStatus,
+    last_message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompareAgentsResponse {
+    left: ComparedAgent,
+    right: ComparedAgent,
+    /// True when both agents have a last message and the messages match exactly.
+    identical: bool,
+    /// Line diff from `left`'s last message to `right`'s; absent until both have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
 }
 
 #[async_trait]
@@ -103,11 +588,42 @@ impl ToolHandler for CollabHandler {
 
         match tool_name.as_str() {
             "spawn_agent" => handle_spawn_agent(session, turn, arguments).await,
+            "spawn_agent_batch" => handle_spawn_agent_batch(session, turn, arguments).await,
+            "clone_agent" => handle_clone_agent(session, arguments).await,
+            "checkpoint_agent" => handle_checkpoint_agent(session, arguments).await,
+            "restore_checkpoint" => handle_restore_checkpoint(session, arguments).await,
             "send_input" => handle_send_input(session, turn, arguments).await,
+            "send_and_wait" => handle_send_and_wait(session, arguments).await,
+            "broadcast_input" => handle_broadcast_input(session, turn, arguments).await,
             "wait" => handle_wait(session, arguments).await,
+            "wait_for_message" => handle_wait_for_message(session, arguments).await,
             "close_agent" => handle_close_agent(session, arguments).await,
             "list_agents" => handle_list_agents(session, arguments).await,
+            "get_agent_tree" => handle_get_agent_tree(session, arguments).await,
+            "inspect_agent" => handle_inspect_agent(session, arguments).await,
+            "inspect_agent_context" => handle_inspect_agent_context(session, arguments).await,
+            "amend_instructions" => handle_amend_instructions(session, arguments).await,
             "agent_output" => handle_agent_output(session, arguments).await,
+            "report_agent" => handle_report_agent(session, arguments).await,
+            "compare_agents" => handle_compare_agents(session, arguments).await,
+            "replay_agent" => handle_replay_agent(session, arguments).await,
+            "list_personas" => handle_list_personas(arguments),
+            "cancel_spawn" => handle_cancel_spawn(session, arguments),
+            "set_shared" => handle_set_shared(session, arguments).await,
+            "get_shared" => handle_get_shared(session, arguments).await,
+            "pin_context" => handle_pin_context(session, arguments).await,
+            "clear_pinned_context" => handle_clear_pinned_context(session, arguments).await,
+            "scratchpad_append" => handle_scratchpad_append(session, arguments).await,
+            "scratchpad_read" => handle_scratchpad_read(session, arguments).await,
+            "read_group_chat" => handle_read_group_chat(session, arguments).await,
+            "kill_all_agents" => handle_kill_all_agents(session, arguments).await,
+            "pause_agent" => handle_pause_agent(session, arguments).await,
+            "resume_agent" => handle_resume_agent(session, arguments).await,
+            "pause_all" => handle_pause_all(session, arguments).await,
+            "resume_all" => handle_resume_all(session, arguments).await,
+            "agent_token_usage" => handle_agent_token_usage(session, arguments).await,
+            "agents_summary" => handle_agents_summary(session, arguments).await,
+            "spawn_capacity" => handle_spawn_capacity(session, arguments).await,
             other => Err(FunctionCallError::RespondToModel(format!(
                 "unsupported collab tool {other}"
             ))),
@@ -121,6 +637,138 @@ async fn handle_spawn_agent(
     arguments: String,
 ) -> Result<ToolOutput, FunctionCallError> {
     let args: SpawnAgentArgs = parse_arguments(&arguments)?;
+    let PreparedSpawn {
+        spawn,
+        model_fallback,
+    } = prepare_spawn(&session, &turn, args).await?;
+    // A named spawn returns while its session starts so a later cancel_spawn can still reach it.
+    if let Some(token) = spawn.spawn_token.clone() {
+        let spawned = session
+            .services
+            .agent_control
+            .spawn_agent_in_background(session.conversation_id(), spawn)
+            .await
+            .map_err(spawn_error)?;
+        tokio::spawn(async move {
+            let started: CodexResult<()> = async move { spawned.await??.ready().await }.await;
+            let Err(err) = started else {
+                return;
+            };
+            let message = format!("spawn {token} did not start: {err}");
+            session
+                .send_event(&turn, EventMsg::Warning(WarningEvent { message }))
+                .await;
+        });
+        return Ok(ToolOutput::Function {
+            content: spawn_output(
+                format!(
+                    "spawn_token: {token} (starting; find the agent with list_agents once it runs, or abort it with cancel_spawn)"
+                ),
+                model_fallback.as_deref(),
+            ),
+            success: Some(true),
+            content_items: None,
+        });
+    }
+    let result = session
+        .services
+        .agent_control
+        .spawn_agent(session.conversation_id(), spawn)
+        .await
+        .map_err(spawn_error)?;
+
+    Ok(ToolOutput::Function {
+        content: spawn_output(format!("agent_id: {result}"), model_fallback.as_deref()),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+/// `spawn_agent` output: `summary`, then the model fallback notice so the orchestrator knows
+/// which model the subagent actually runs.
+fn spawn_output(summary: String, model_fallback: Option<&str>) -> String {
+    match model_fallback {
+        Some(notice) => format!("{summary}\nmodel_fallback: {notice}"),
+        None => summary,
+    }
+}
+
+async fn handle_spawn_agent_batch(
+    session: std::sync::Arc<crate::codex::Session>,
+    turn: std::sync::Arc<TurnContext>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: SpawnAgentBatchArgs = parse_arguments(&arguments)?;
+    if args.agents.is_empty() {
+        return Err(FunctionCallError::RespondToModel(
+            "agents must contain at least one spawn spec".to_string(),
+        ));
+    }
+
+    // Invalid specs fail on their own; the rest are spawned together so they share the cap.
+    let mut prepared: Vec<Result<PreparedSpawn, FunctionCallError>> =
+        Vec::with_capacity(args.agents.len());
+    for args in args.agents {
+        prepared.push(prepare_spawn(&session, &turn, args).await);
+    }
+    let mut spawns = Vec::new();
+    let mut model_fallbacks = Vec::new();
+    let mut results: Vec<Option<SpawnAgentBatchResult>> = Vec::with_capacity(prepared.len());
+    for prepared in prepared {
+        match prepared {
+            Ok(PreparedSpawn {
+                spawn,
+                model_fallback,
+            }) => {
+                spawns.push(spawn);
+                model_fallbacks.push(model_fallback);
+                results.push(None);
+            }
+            Err(err) => results.push(Some(SpawnAgentBatchResult::failed(err))),
+        }
+    }
+    let mut spawned = session
+        .services
+        .agent_control
+        .spawn_agents(session.conversation_id(), spawns)
+        .await
+        .map_err(spawn_error)?
+        .into_iter()
+        .zip(model_fallbacks);
+    let results: Vec<SpawnAgentBatchResult> = results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| match spawned.next() {
+                Some((Ok(agent_id), model_fallback)) => SpawnAgentBatchResult {
+                    agent_id: Some(agent_id),
+                    model_fallback,
+                    error: None,
+                },
+                Some((Err(err), _)) => SpawnAgentBatchResult::failed(spawn_error(err)),
+                None => SpawnAgentBatchResult::failed(FunctionCallError::Fatal(
+                    "missing spawn result".to_string(),
+                )),
+            })
+        })
+        .collect();
+
+    let success = results.iter().any(|result| result.agent_id.is_some());
+    let content = SpawnAgentBatchResponse { results };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize spawn results: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(success),
+        content_items: None,
+    })
+}
+
+/// Validate spawn arguments and build the config and prompt for a new headless subagent.
+async fn prepare_spawn(
+    session: &crate::codex::Session,
+    turn: &TurnContext,
+    args: SpawnAgentArgs,
+) -> Result<PreparedSpawn, FunctionCallError> {
     if args.message.trim().is_empty() {
         return Err(FunctionCallError::RespondToModel(
             "Empty message can't be send to an agent".to_string(),
@@ -130,11 +778,40 @@ async fn handle_spawn_agent(
         message,
         display_name,
         persona,
+        model,
         tool_allowlist,
         tool_denylist,
         shell_command_allowlist,
         shell_command_denylist,
+        env,
+        use_template_intro,
+        source_label,
+        attachments,
+        priority,
+        evict_idle,
+        max_tool_events,
+        include_shared,
+        spawn_token,
+        auto_post,
+        correlation_id,
+        output_schema,
     } = args;
+    if matches!(max_tool_events, Some(0)) {
+        return Err(FunctionCallError::RespondToModel(
+            "max_tool_events must be greater than zero".to_string(),
+        ));
+    }
+    let output_schema = output_schema
+        .map(|schema| match serde_json::from_str(&schema) {
+            Ok(schema @ serde_json::Value::Object(_)) => Ok(schema),
+            Ok(_) => Err(FunctionCallError::RespondToModel(
+                "output_schema must be a JSON object".to_string(),
+            )),
+            Err(err) => Err(FunctionCallError::RespondToModel(format!(
+                "output_schema is not valid JSON: {err}"
+            ))),
+        })
+        .transpose()?;
     let display_name = display_name.trim();
     if display_name.is_empty() {
         return Err(FunctionCallError::RespondToModel(
@@ -152,41 +829,201 @@ async fn handle_spawn_agent(
             "display_name must be a single non-empty line".to_string(),
         ));
     }
-    let mut config = crate::agent::build_agent_spawn_config(turn.as_ref())
+    let message = if use_template_intro {
+        crate::agent_personas::with_template_intro(&display_name, &message)
+            .map_err(FunctionCallError::RespondToModel)?
+    } else {
+        message
+    };
+    let attachment_paths = attachments.clone();
+    let attachments = crate::agent::resolve_attachments(&turn.cwd, attachments)
+        .await
         .map_err(FunctionCallError::RespondToModel)?;
+    let mut config =
+        crate::agent::build_agent_spawn_config(turn).map_err(FunctionCallError::RespondToModel)?;
+    let model_warning =
+        crate::agent::apply_model_override(&session.services.models_manager, &mut config, model)
+            .await
+            .map_err(FunctionCallError::RespondToModel)?;
+    if let Some(message) = model_warning.clone() {
+        session
+            .send_event(turn, EventMsg::Warning(WarningEvent { message }))
+            .await;
+    }
     let orchestrator_id = session.conversation_id();
+    let (prompt_prefix, prompt_suffix) = session.services.agent_control.subagent_prompt_affixes();
     config.developer_instructions = crate::agent_personas::with_subagent_instructions(
         config.developer_instructions.as_deref(),
         persona.as_deref(),
+        prompt_prefix.as_deref(),
+        prompt_suffix.as_deref(),
         orchestrator_id,
-    );
-    config.tool_policy.apply_overrides(ToolPolicyToml {
-        tool_allowlist,
-        tool_denylist,
+    )
+    .map_err(FunctionCallError::RespondToModel)?;
+    if include_shared {
+        let variables = session
+            .services
+            .agent_control
+            .child_shared_variables(orchestrator_id)
+            .await
+            .map_err(spawn_error)?;
+        config.developer_instructions = crate::agent_personas::with_shared_variables(
+            config.developer_instructions.as_deref(),
+            &variables,
+        );
+    }
+    crate::agent::apply_env_overrides(&mut config.shell_environment_policy, env)
+        .map_err(FunctionCallError::RespondToModel)?;
+    config.tool_policy.apply_overrides(ToolPolicyToml {
+        tool_allowlist,
+        tool_denylist,
         shell_command_allowlist,
         shell_command_denylist,
     });
-    let result = session
+    let spawn = SubagentSpawn {
+        config,
+        initial_history: InitialHistory::New,
+        prompt: message,
+        persona,
+        display_name: Some(display_name),
+        source_label: source_label
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty()),
+        attachments,
+        attachment_paths,
+        priority,
+        evict_idle,
+        max_tool_events,
+        spawn_token: spawn_token
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty()),
+        auto_post: auto_post.unwrap_or(true),
+        correlation_id: correlation_id
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty()),
+        output_schema,
+    };
+    Ok(PreparedSpawn {
+        spawn,
+        model_fallback: model_warning,
+    })
+}
+
+async fn handle_clone_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: CloneAgentArgs = parse_arguments(&arguments)?;
+    let source_id = target_agent_id(session.conversation_id(), &args.id)?;
+    if args.count == 0 {
+        return Err(FunctionCallError::RespondToModel(
+            "count must be greater than zero".to_string(),
+        ));
+    }
+    let message = args.message.filter(|message| !message.trim().is_empty());
+    let agent_ids = session
         .services
         .agent_control
-        .spawn_agent(
-            orchestrator_id,
-            config,
-            message,
-            true,
-            persona,
-            Some(display_name),
-        )
+        .clone_agent(session.conversation_id(), source_id, args.count, message)
         .await
-        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+        .map_err(spawn_error)?;
+    let content = CloneAgentResponse { agent_ids };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize clone result: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_checkpoint_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: CheckpointAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let checkpoint = session
+        .services
+        .agent_control
+        .checkpoint_agent(session.conversation_id(), agent_id, &args.label)
+        .await
+        .map_err(spawn_error)?;
+    let content = CheckpointAgentResponse {
+        checkpoint_id: checkpoint.id,
+        label: checkpoint.label,
+        user_turns: checkpoint.user_turns,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize checkpoint: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
 
+async fn handle_restore_checkpoint(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: RestoreCheckpointArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    if args.message.trim().is_empty() {
+        return Err(FunctionCallError::RespondToModel(
+            "Empty message can't be sent to an agent".to_string(),
+        ));
+    }
+    let agent_id = session
+        .services
+        .agent_control
+        .restore_checkpoint(
+            session.conversation_id(),
+            agent_id,
+            args.checkpoint_id,
+            args.message,
+        )
+        .await
+        .map_err(spawn_error)?;
+    let content = RestoreCheckpointResponse { agent_id };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize restored agent: {content:?}"));
     Ok(ToolOutput::Function {
-        content: format!("agent_id: {result}"),
+        content,
         success: Some(true),
         content_items: None,
     })
 }
 
+/// Map spawn failures the model can act on to `RespondToModel`; everything else is fatal.
+fn spawn_error(err: CodexErr) -> FunctionCallError {
+    match err {
+        CodexErr::ThreadNotFound(id) => {
+            FunctionCallError::RespondToModel(format!("agent with id {id} not found"))
+        }
+        CodexErr::UnsupportedOperation(message) => FunctionCallError::RespondToModel(message),
+        CodexErr::SubagentLimitExceeded { kind, limit } => {
+            FunctionCallError::RespondToModel(subagent_limit_guidance(kind, limit))
+        }
+        err @ CodexErr::StateLockTimeout { .. } => {
+            FunctionCallError::RespondToModel(format!("{err}; the agent state is busy, try again"))
+        }
+        err => FunctionCallError::Fatal(err.to_string()),
+    }
+}
+
+/// What the model should do instead when a subagent limit refuses its request.
+fn subagent_limit_guidance(kind: SubagentLimitKind, limit: u64) -> String {
+    match kind {
+        SubagentLimitKind::PerParent => format!(
+            "subagent limit reached: you already have the maximum of {limit} subagents. Close finished subagents with close_agent, or spawn with evict_idle to replace a completed one."
+        ),
+        SubagentLimitKind::TokenBudget => format!(
+            "subagent token budget exhausted: your subagents have used their {limit} tokens, so no more subagent work can start. Continue with the results you already have."
+        ),
+    }
+}
+
 async fn handle_send_input(
     session: std::sync::Arc<crate::codex::Session>,
     turn: std::sync::Arc<TurnContext>,
@@ -199,7 +1036,7 @@ async fn handle_send_input(
             "Empty message can't be send to an agent".to_string(),
         ));
     }
-    let target_id = agent_id(&args.id)?;
+    let target_id = target_agent_id(session.conversation_id(), &args.id)?;
     if matches!(turn.client.get_session_source(), SessionSource::SubAgent(_)) {
         let subagent_id = session.conversation_id();
         let is_parent = session
@@ -208,11 +1045,24 @@ async fn handle_send_input(
             .is_subagent_of(target_id, subagent_id)
             .await
             .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
-        if !is_parent {
-            return Err(FunctionCallError::RespondToModel(format!(
-                "agent with id {target_id} not found"
-            )));
-        }
+        // Messages to the parent land in its group chat; messages to a sibling are posted to
+        // the shared parent's group chat with the sibling mentioned so it gets pinged.
+        let (chat_id, mentions) = if is_parent {
+            (target_id, Vec::new())
+        } else {
+            let sibling_parent = session
+                .services
+                .agent_control
+                .sibling_parent(subagent_id, target_id)
+                .await
+                .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+            let Some(parent_id) = sibling_parent else {
+                return Err(FunctionCallError::RespondToModel(format!(
+                    "agent with id {target_id} not found"
+                )));
+            };
+            (parent_id, vec![target_id])
+        };
         let persona = session
             .services
             .agent_control
@@ -229,8 +1079,9 @@ async fn handle_send_input(
             .services
             .agent_control
             .post_group_chat_message(
-                target_id,
+                chat_id,
                 message,
+                mentions,
                 GroupChatSender::SubAgent {
                     id: subagent_id,
                     persona,
@@ -238,20 +1089,16 @@ async fn handle_send_input(
                 },
             )
             .await
-            .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+            .map_err(spawn_error)?;
     } else {
         let parent_id = session.conversation_id();
-        let is_subagent = session
+        ensure_direct_subagent(&session, target_id).await?;
+        session
             .services
             .agent_control
-            .is_subagent_of(parent_id, target_id)
+            .ensure_token_budget(parent_id)
             .await
-            .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
-        if !is_subagent {
-            return Err(FunctionCallError::RespondToModel(format!(
-                "agent with id {target_id} not found"
-            )));
-        }
+            .map_err(spawn_error)?;
         session
             .process_group_chat_message(
                 turn.sub_id.clone(),
@@ -269,79 +1116,240 @@ async fn handle_send_input(
     })
 }
 
-async fn handle_wait(
+async fn handle_send_and_wait(
     session: std::sync::Arc<crate::codex::Session>,
     arguments: String,
 ) -> Result<ToolOutput, FunctionCallError> {
-    let args: WaitArgs = parse_arguments(&arguments)?;
-    let agent_id = agent_id(&args.id)?;
-    let timeout_ms = resolve_timeout_ms(args.timeout_ms)?;
-    let status = wait_for_agent(session, agent_id, timeout_ms).await?;
+    let args: SendAndWaitArgs = parse_arguments(&arguments)?;
+    if args.message.trim().is_empty() {
+        return Err(FunctionCallError::RespondToModel(
+            "Empty message can't be sent to an agent".to_string(),
+        ));
+    }
+    let parent_id = session.conversation_id();
+    let agent_id = target_agent_id(parent_id, &args.id)?;
+    let timeout_ms = resolve_timeout_ms(&session, args.timeout_ms)?;
+    ensure_direct_subagent(&session, agent_id).await?;
+    // A message sent mid-turn joins the running turn, so there would be no turn of its own to
+    // wait for.
+    if matches!(
+        session.services.agent_control.get_status(agent_id).await,
+        AgentStatus::PendingInit | AgentStatus::Running
+    ) {
+        return Err(FunctionCallError::RespondToModel(format!(
+            "agent {agent_id} is mid-turn; wait for it to finish, or use send_input"
+        )));
+    }
+    session
+        .services
+        .agent_control
+        .ensure_token_budget(parent_id)
+        .await
+        .map_err(spawn_error)?;
+    let turns_before = subagent_turns(&session, agent_id).await?;
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    session
+        .services
+        .agent_control
+        .send_prompt(agent_id, args.message)
+        .await
+        .map_err(spawn_error)?;
+
+    // Until the new turn starts the status still reports the previous turn's result.
+    loop {
+        if subagent_turns(&session, agent_id).await? > turns_before {
+            break;
+        }
+        if matches!(
+            session.services.agent_control.get_status(agent_id).await,
+            AgentStatus::Shutdown | AgentStatus::NotFound
+        ) {
+            break;
+        }
+        if Instant::now() >= deadline {
+            session.services.agent_control.record_wait_timeout(agent_id);
+            return Err(FunctionCallError::RespondToModel(format!(
+                "send_and_wait timed out before agent {agent_id} started its turn; the message was sent, so use wait to collect the reply"
+            )));
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    let remaining_ms = deadline
+        .saturating_duration_since(Instant::now())
+        .as_millis() as u64;
+    let status = wait_for_agent(Arc::clone(&session), agent_id, remaining_ms).await?;
+    let output = session
+        .services
+        .agent_control
+        .subagent_output(parent_id, agent_id, None)
+        .await
+        .map_err(spawn_error)?;
+    let content = SendAndWaitResponse {
+        status,
+        last_message: output.last_message,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize send_and_wait result: {content:?}"));
     Ok(ToolOutput::Function {
-        content: status_payload(&status),
+        content,
         success: Some(true),
         content_items: None,
     })
 }
 
-fn agent_id(id: &str) -> Result<ThreadId, FunctionCallError> {
-    ThreadId::from_string(id)
-        .map_err(|e| FunctionCallError::RespondToModel(format!("invalid agent id {id}: {e:?}")))
+/// Turns `agent_id` has started, as recorded in its buffered output.
+async fn subagent_turns(
+    session: &crate::codex::Session,
+    agent_id: ThreadId,
+) -> Result<u32, FunctionCallError> {
+    session
+        .services
+        .agent_control
+        .subagent_output(session.conversation_id(), agent_id, None)
+        .await
+        .map(|output| output.turns)
+        .map_err(spawn_error)
 }
 
-async fn handle_close_agent(
+async fn handle_broadcast_input(
     session: std::sync::Arc<crate::codex::Session>,
+    turn: std::sync::Arc<TurnContext>,
     arguments: String,
 ) -> Result<ToolOutput, FunctionCallError> {
-    let args: CloseAgentArgs = parse_arguments(&arguments)?;
-    let agent_id = agent_id(&args.id)?;
+    let args: BroadcastInputArgs = parse_arguments(&arguments)?;
+    if args.message.trim().is_empty() {
+        return Err(FunctionCallError::RespondToModel(
+            "Empty message can't be sent to an agent".to_string(),
+        ));
+    }
+    let timeout_ms = resolve_timeout_ms(
+        &session,
+        Some(args.timeout_ms.unwrap_or(DEFAULT_BROADCAST_TIMEOUT_MS)),
+    )?;
     let parent_id = session.conversation_id();
-    let is_subagent = session
+    let recipients = match args.ids {
+        Some(ids) => ids
+            .iter()
+            .map(|id| target_agent_id(parent_id, id))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => session
+            .services
+            .agent_control
+            .list_subagents(parent_id, &ListSubagentsQuery::default())
+            .await
+            .map_err(spawn_error)?
+            .into_iter()
+            .map(|summary| summary.id)
+            .collect(),
+    };
+    if recipients.is_empty() {
+        return Err(FunctionCallError::RespondToModel(
+            "no subagents to broadcast to".to_string(),
+        ));
+    }
+    session
         .services
         .agent_control
-        .is_subagent_of(parent_id, agent_id)
+        .ensure_token_budget(parent_id)
         .await
-        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
-    if !is_subagent {
-        return Err(FunctionCallError::RespondToModel(format!(
-            "agent with id {agent_id} not found"
-        )));
-    }
+        .map_err(spawn_error)?;
+    let report = session
+        .broadcast_group_chat_message(
+            turn.sub_id.clone(),
+            &args.message,
+            recipients,
+            Duration::from_millis(timeout_ms),
+        )
+        .await;
+    let content = BroadcastInputResponse {
+        accepted: report.accepted,
+        slow: report.slow,
+        failed: report
+            .failed
+            .into_iter()
+            .map(|(id, error)| BroadcastFailure { id, error })
+            .collect(),
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize broadcast result: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_replay_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ReplayAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
     session
         .services
         .agent_control
-        .shutdown_agent(agent_id)
+        .replay_subagent(session.conversation_id(), agent_id)
         .await
-        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
-    let timeout_ms = resolve_timeout_ms(args.timeout_ms)?;
-    let status = wait_for_agent(Arc::clone(&session), agent_id, timeout_ms).await?;
+        .map_err(spawn_error)?;
+    Ok(ToolOutput::Function {
+        content: "ok".to_string(),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+fn handle_cancel_spawn(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: CancelSpawnArgs = parse_arguments(&arguments)?;
+    session
+        .services
+        .agent_control
+        .cancel_spawn(session.conversation_id(), args.token.trim())
+        .map_err(spawn_error)?;
+    Ok(ToolOutput::Function {
+        content: "ok".to_string(),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_set_shared(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: SetSharedArgs = parse_arguments(&arguments)?;
     session
         .services
         .agent_control
-        .forget_subagent(agent_id)
+        .set_shared(session.conversation_id(), &args.key, args.value)
         .await
-        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+        .map_err(spawn_error)?;
     Ok(ToolOutput::Function {
-        content: status_payload(&status),
+        content: "ok".to_string(),
         success: Some(true),
         content_items: None,
     })
 }
 
-async fn handle_list_agents(
+async fn handle_get_shared(
     session: std::sync::Arc<crate::codex::Session>,
     arguments: String,
 ) -> Result<ToolOutput, FunctionCallError> {
-    let _args: ListAgentsArgs = parse_arguments(&arguments)?;
-    let parent_id = session.conversation_id();
-    let summaries = session
+    let args: GetSharedArgs = parse_arguments(&arguments)?;
+    let mut variables = session
         .services
         .agent_control
-        .list_subagents(parent_id)
+        .shared_variables(session.conversation_id())
         .await
-        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
-    let content = serde_json::to_string(&summaries)
-        .unwrap_or_else(|_| format!("failed to serialize agent list: {summaries:?}"));
+        .map_err(spawn_error)?;
+    if let Some(key) = args.key {
+        variables.retain(|name, _| name == key.trim());
+    }
+    let content = GetSharedResponse { variables };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize shared variables: {content:?}"));
     Ok(ToolOutput::Function {
         content,
         success: Some(true),
@@ -349,45 +1357,24 @@ async fn handle_list_agents(
     })
 }
 
-async fn handle_agent_output(
+async fn handle_pin_context(
     session: std::sync::Arc<crate::codex::Session>,
     arguments: String,
 ) -> Result<ToolOutput, FunctionCallError> {
-    let args: AgentOutputArgs = parse_arguments(&arguments)?;
-    let agent_id = agent_id(&args.id)?;
-    if matches!(args.max_chars, Some(0)) {
-        return Err(FunctionCallError::RespondToModel(
-            "max_chars must be greater than zero".to_string(),
-        ));
-    }
-    let parent_id = session.conversation_id();
-    let output = session
+    let args: PinContextArgs = parse_arguments(&arguments)?;
+    let usage = session
         .services
         .agent_control
-        .subagent_output(parent_id, agent_id, args.max_chars)
+        .pin_context(session.conversation_id(), &args.text)
         .await
-        .map_err(|err| match err {
-            CodexErr::ThreadNotFound(id) => {
-                FunctionCallError::RespondToModel(format!("agent with id {id} not found"))
-            }
-            err => FunctionCallError::Fatal(err.to_string()),
-        })?;
-    let status = session.services.agent_control.get_status(agent_id).await;
-    let tool_events = if output.tool_events.is_empty() {
-        None
-    } else {
-        Some(output.tool_events)
-    };
-    let content = AgentOutputResponse {
-        id: agent_id,
-        status,
-        partial: output.partial,
-        last_message: output.last_message,
-        reasoning: output.reasoning,
-        tool_events,
+        .map_err(spawn_error)?;
+    let content = PinContextResponse {
+        pinned_items: usage.items,
+        pinned_chars: usage.chars,
+        max_chars: MAX_PINNED_CONTEXT_CHARS,
     };
     let content = serde_json::to_string(&content)
-        .unwrap_or_else(|_| format!("failed to serialize agent output: {content:?}"));
+        .unwrap_or_else(|_| format!("failed to serialize pinned context: {content:?}"));
     Ok(ToolOutput::Function {
         content,
         success: Some(true),
@@ -395,37 +1382,1700 @@ async fn handle_agent_output(
     })
 }
 
-async fn wait_for_agent(
+async fn handle_clear_pinned_context(
     session: std::sync::Arc<crate::codex::Session>,
-    agent_id: ThreadId,
-    timeout_ms: u64,
-) -> Result<AgentStatus, FunctionCallError> {
-    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
-
-    loop {
-        let status = session.services.agent_control.get_status(agent_id).await;
-        if !matches!(status, AgentStatus::PendingInit | AgentStatus::Running) {
-            return Ok(status);
-        }
-        if Instant::now() >= deadline {
-            return Err(FunctionCallError::RespondToModel(format!(
-                "wait timed out; last status was {status:?}"
-            )));
-        }
-        sleep(Duration::from_millis(200)).await;
-    }
-}
-
-fn resolve_timeout_ms(timeout_ms: Option<i64>) -> Result<u64, FunctionCallError> {
-    let timeout_ms = timeout_ms.unwrap_or(DEFAULT_WAIT_TIMEOUT_MS);
-    if timeout_ms <= 0 {
-        return Err(FunctionCallError::RespondToModel(
-            "timeout_ms must be greater than zero".to_string(),
-        ));
-    }
-    Ok(timeout_ms.min(MAX_WAIT_TIMEOUT_MS) as u64)
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: ClearPinnedContextArgs = parse_arguments(&arguments)?;
+    let cleared_items = session
+        .services
+        .agent_control
+        .clear_pinned_context(session.conversation_id())
+        .await
+        .map_err(spawn_error)?;
+    let content = ClearPinnedContextResponse { cleared_items };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize cleared context: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
 }
 
-fn status_payload(status: &AgentStatus) -> String {
-    serde_json::to_string(status).unwrap_or_else(|_| format!("{status:?}"))
+async fn handle_scratchpad_append(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ScratchpadAppendArgs = parse_arguments(&arguments)?;
+    let usage = session
+        .services
+        .agent_control
+        .scratchpad_append(session.conversation_id(), &args.text)
+        .await
+        .map_err(spawn_error)?;
+    let content = ScratchpadAppendResponse {
+        entries: usage.entries,
+        chars: usage.chars,
+        max_chars: MAX_SCRATCHPAD_CHARS,
+        trimmed_entries: usage.trimmed_entries,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize scratchpad usage: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_scratchpad_read(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ScratchpadReadArgs = parse_arguments(&arguments)?;
+    let mut entries = session
+        .services
+        .agent_control
+        .scratchpad(session.conversation_id())
+        .await
+        .map_err(spawn_error)?;
+    let total_entries = entries.len();
+    if let Some(last) = args.last {
+        entries.drain(..total_entries.saturating_sub(last));
+    }
+    let content = ScratchpadReadResponse {
+        entries,
+        total_entries,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize scratchpad: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+/// Senders are the snapshot taken at post time, so a subagent's display name and persona stay
+/// attached to its messages after it has been closed or forgotten.
+async fn handle_read_group_chat(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ReadGroupChatArgs = parse_arguments(&arguments)?;
+    let mut messages = session.group_chat_history().await;
+    let total_messages = messages.len();
+    if let Some(last) = args.last {
+        messages.drain(..total_messages.saturating_sub(last));
+    }
+    let content = ReadGroupChatResponse {
+        messages,
+        total_messages,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize group chat: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_wait(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: WaitArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let timeout_ms = resolve_timeout_ms(&session, args.timeout_ms)?;
+    if !args.partial_on_timeout {
+        let status = wait_for_agent(session, agent_id, timeout_ms).await?;
+        return Ok(ToolOutput::Function {
+            content: status_payload(&status),
+            success: Some(true),
+            content_items: None,
+        });
+    }
+    let content = match poll_agent_status(&session, agent_id, timeout_ms).await {
+        WaitOutcome::Finished(status) => status_payload(&status),
+        WaitOutcome::TimedOut(status) => {
+            // The preview is best effort; an agent that is not a subagent of the caller has none.
+            let preview = session
+                .services
+                .agent_control
+                .subagent_output(session.conversation_id(), agent_id, None)
+                .await
+                .ok()
+                .and_then(|output| output.partial.or(output.last_message))
+                .map(|text| excerpt(&text));
+            let content = WaitTimeoutResponse {
+                timed_out: true,
+                status,
+                preview,
+            };
+            serde_json::to_string(&content)
+                .unwrap_or_else(|_| format!("failed to serialize wait result: {content:?}"))
+        }
+    };
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_wait_for_message(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: WaitForMessageArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let pattern = MessagePattern::parse(&args.pattern, args.regex)?;
+    let timeout_ms = resolve_timeout_ms(&session, args.timeout_ms)?;
+    ensure_direct_subagent(&session, agent_id).await?;
+    let is_match = |message: &GroupChatMessageEvent| {
+        matches!(
+            &message.sender,
+            GroupChatSender::SubAgent { id, .. } if *id == agent_id
+        ) && pattern.matches(&message.text)
+    };
+    // Subscribe before looking back so a message posted in between is not missed.
+    let mut feed = session.subscribe_group_chat().await;
+    let posted = session
+        .group_chat_history()
+        .await
+        .into_iter()
+        .rev()
+        .find(|message| is_match(message));
+    let wait = async {
+        if let Some(message) = posted {
+            return Ok(message);
+        }
+        loop {
+            match feed.recv().await {
+                Ok(message) => {
+                    if is_match(&message) {
+                        return Ok(message);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("wait_for_message skipped {skipped} group chat messages");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(FunctionCallError::RespondToModel(
+                        "group chat closed before a matching message arrived".to_string(),
+                    ));
+                }
+            }
+        }
+    };
+    let message = tokio::time::timeout(Duration::from_millis(timeout_ms), wait)
+        .await
+        .map_err(|_| {
+            FunctionCallError::RespondToModel(format!(
+                "wait_for_message timed out without a matching message from agent {agent_id}"
+            ))
+        })??;
+    let content = serde_json::to_string(&message)
+        .unwrap_or_else(|_| format!("failed to serialize group chat message: {message:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+/// What `wait_for_message` looks for in a subagent's group-chat messages.
+#[derive(Debug)]
+enum MessagePattern {
+    Substring(String),
+    Regex(regex_lite::Regex),
+}
+
+impl MessagePattern {
+    fn parse(pattern: &str, regex: bool) -> Result<Self, FunctionCallError> {
+        if pattern.is_empty() {
+            return Err(FunctionCallError::RespondToModel(
+                "pattern must not be empty".to_string(),
+            ));
+        }
+        if !regex {
+            return Ok(Self::Substring(pattern.to_string()));
+        }
+        regex_lite::Regex::new(pattern)
+            .map(Self::Regex)
+            .map_err(|err| {
+                FunctionCallError::RespondToModel(format!("invalid regex {pattern}: {err}"))
+            })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Substring(pattern) => text.contains(pattern.as_str()),
+            Self::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+fn agent_id(id: &str) -> Result<ThreadId, FunctionCallError> {
+    ThreadId::from_string(id)
+        .map_err(|e| FunctionCallError::RespondToModel(format!("invalid agent id {id}: {e:?}")))
+}
+
+/// Parse the id of the agent a collab tool called from `caller_id` acts on. Every tool that
+/// targets another agent goes through here, so pointing one at the caller's own thread is
+/// refused the same way everywhere.
+fn target_agent_id(caller_id: ThreadId, id: &str) -> Result<ThreadId, FunctionCallError> {
+    let target_id = agent_id(id)?;
+    if target_id == caller_id {
+        return Err(FunctionCallError::RespondToModel(format!(
+            "agent id {id} is your own thread; pass the id of another agent"
+        )));
+    }
+    Ok(target_id)
+}
+
+/// Refuse `agent_id` unless it is a direct subagent of the calling session.
+async fn ensure_direct_subagent(
+    session: &crate::codex::Session,
+    agent_id: ThreadId,
+) -> Result<(), FunctionCallError> {
+    let is_subagent = session
+        .services
+        .agent_control
+        .is_subagent_of(session.conversation_id(), agent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    if !is_subagent {
+        return Err(FunctionCallError::RespondToModel(format!(
+            "agent with id {agent_id} not found"
+        )));
+    }
+    Ok(())
+}
+
+async fn handle_close_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: CloseAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    ensure_direct_subagent(&session, agent_id).await?;
+    session
+        .services
+        .agent_control
+        .shutdown_agent(agent_id, args.reason)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    if args.force {
+        let status = session.services.agent_control.get_status(agent_id).await;
+        session
+            .services
+            .agent_control
+            .forget_subagent(agent_id)
+            .await
+            .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+        return Ok(ToolOutput::Function {
+            content: status_payload(&status),
+            success: Some(true),
+            content_items: None,
+        });
+    }
+    let timeout_ms = resolve_timeout_ms(&session, args.timeout_ms)?;
+    let status = wait_for_agent(Arc::clone(&session), agent_id, timeout_ms).await?;
+    session
+        .services
+        .agent_control
+        .forget_subagent_after_drain(agent_id, Duration::from_millis(timeout_ms))
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    Ok(ToolOutput::Function {
+        content: status_payload(&status),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_kill_all_agents(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: KillAllAgentsArgs = parse_arguments(&arguments)?;
+    let parent_id = session.conversation_id();
+    let outcome = session
+        .services
+        .agent_control
+        .kill_all_subagents(parent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let content = serde_json::to_string(&outcome)
+        .unwrap_or_else(|_| format!("failed to serialize kill outcome: {outcome:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_pause_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: PauseAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let paused = session
+        .services
+        .agent_control
+        .pause_agent(session.conversation_id(), agent_id)
+        .await
+        .map_err(spawn_error)?;
+    let content = PauseAgentResponse { paused };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize pause outcome: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_resume_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: PauseAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let resumed = session
+        .services
+        .agent_control
+        .resume_agent(session.conversation_id(), agent_id)
+        .await
+        .map_err(spawn_error)?;
+    let content = ResumeAgentResponse { resumed };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize resume outcome: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_pause_all(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: PauseAllArgs = parse_arguments(&arguments)?;
+    let paused = session
+        .services
+        .agent_control
+        .pause_all(session.conversation_id())
+        .await
+        .map_err(spawn_error)?;
+    let content = PauseAllResponse { paused };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize pause outcome: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_resume_all(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: PauseAllArgs = parse_arguments(&arguments)?;
+    let resumed = session
+        .services
+        .agent_control
+        .resume_all(session.conversation_id())
+        .await
+        .map_err(spawn_error)?;
+    let content = ResumeAllResponse { resumed };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize resume outcome: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_agent_token_usage(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: AgentTokenUsageArgs = parse_arguments(&arguments)?;
+    let parent_id = session.conversation_id();
+    let (used_tokens, budget_tokens) = session
+        .services
+        .agent_control
+        .subagent_token_usage(parent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let content = AgentTokenUsageResponse {
+        used_tokens,
+        budget_tokens,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize token usage: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_agents_summary(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: AgentsSummaryArgs = parse_arguments(&arguments)?;
+    let content = session
+        .services
+        .agent_control
+        .subagent_stats(session.conversation_id())
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize agents summary: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_spawn_capacity(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let _args: SpawnCapacityArgs = parse_arguments(&arguments)?;
+    let content = session
+        .services
+        .agent_control
+        .spawn_capacity(session.conversation_id())
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize spawn capacity: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_list_agents(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ListAgentsArgs = parse_arguments(&arguments)?;
+    if matches!(args.limit, Some(0)) {
+        return Err(FunctionCallError::RespondToModel(
+            "limit must be greater than zero".to_string(),
+        ));
+    }
+    let query = ListSubagentsQuery {
+        order: args.order,
+        status: args.status_filter,
+        offset: args.offset,
+        limit: args.limit,
+    };
+    let parent_id = session.conversation_id();
+    let mut summaries = session
+        .services
+        .agent_control
+        .list_subagents(parent_id, &query)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    for summary in &mut summaries {
+        summary.unread_count = Some(session.group_chat_unread_count(summary.id).await);
+    }
+    let content = ListAgentsResponse {
+        version: LIST_AGENTS_SCHEMA_VERSION,
+        agents: summaries,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize agent list: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_get_agent_tree(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: GetAgentTreeArgs = parse_arguments(&arguments)?;
+    let root_id = args
+        .id
+        .as_deref()
+        .map(|id| target_agent_id(session.conversation_id(), id))
+        .transpose()?;
+    let tree = session
+        .services
+        .agent_control
+        .subagent_tree(session.conversation_id(), root_id)
+        .await
+        .map_err(spawn_error)?;
+    let content = serde_json::to_string(&tree)
+        .unwrap_or_else(|_| format!("failed to serialize agent tree: {tree:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_inspect_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: InspectAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let inspection = session
+        .services
+        .agent_control
+        .inspect_subagent(session.conversation_id(), agent_id)
+        .await
+        .map_err(spawn_error)?;
+    let content = serde_json::to_string(&inspection)
+        .unwrap_or_else(|_| format!("failed to serialize agent inspection: {inspection:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_inspect_agent_context(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: InspectAgentContextArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let context = session
+        .services
+        .agent_control
+        .inspect_agent_context(session.conversation_id(), agent_id)
+        .await
+        .map_err(|err| match err {
+            CodexErr::InternalAgentDied => {
+                FunctionCallError::RespondToModel(format!("agent {agent_id} is no longer running"))
+            }
+            err => spawn_error(err),
+        })?;
+    let content = agent_context_response(context, MAX_AGENT_CONTEXT_CHARS);
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize agent context: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+/// Render `context` as text, spending at most `max_chars` characters across the context items
+/// and then the base instructions. Nothing is redacted; text beyond the budget is cut.
+fn agent_context_response(
+    context: InitialContext,
+    max_chars: usize,
+) -> InspectAgentContextResponse {
+    let mut budget = max_chars;
+    let mut truncated = false;
+    let mut take = |text: &str| {
+        let kept = text.chars().take(budget).collect::<String>();
+        let kept_chars = kept.chars().count();
+        truncated |= kept_chars < text.chars().count();
+        budget -= kept_chars;
+        kept
+    };
+    let mut items = Vec::with_capacity(context.items.len());
+    for item in &context.items {
+        let (role, text) = match item {
+            ResponseItem::Message { role, content, .. } => {
+                let text = content
+                    .iter()
+                    .map(|content| match content {
+                        ContentItem::InputText { text } | ContentItem::OutputText { text } => {
+                            text.as_str()
+                        }
+                        ContentItem::InputImage { .. } => "[image]",
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                (role.clone(), text)
+            }
+            other => (
+                "item".to_string(),
+                serde_json::to_string(other).unwrap_or_else(|_| format!("{other:?}")),
+            ),
+        };
+        items.push(AgentContextItem {
+            role,
+            text: take(&text),
+        });
+    }
+    let base_instructions = take(&context.base_instructions);
+    InspectAgentContextResponse {
+        context: items,
+        base_instructions,
+        truncated,
+    }
+}
+
+async fn handle_amend_instructions(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: AmendInstructionsArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    session
+        .services
+        .agent_control
+        .amend_instructions(session.conversation_id(), agent_id, &args.text)
+        .await
+        .map_err(spawn_error)?;
+    Ok(ToolOutput::Function {
+        content: "ok".to_string(),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+fn handle_list_personas(arguments: String) -> Result<ToolOutput, FunctionCallError> {
+    let args: ListPersonasArgs = parse_arguments(&arguments)?;
+    let content = list_personas_response(args.include_orchestrator_prompt);
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize persona list: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+fn list_personas_response(include_orchestrator_prompt: bool) -> ListPersonasResponse {
+    let personas = DEFAULT_SUBAGENT_TEMPLATES
+        .iter()
+        .map(|template| PersonaEntry {
+            name: template.display_name,
+            persona: template.persona,
+        })
+        .collect();
+    ListPersonasResponse {
+        personas,
+        orchestrator_prompt: include_orchestrator_prompt.then_some(ORCHESTRATOR_PROMPT),
+    }
+}
+
+async fn handle_agent_output(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: AgentOutputArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    if matches!(args.max_chars, Some(0)) {
+        return Err(FunctionCallError::RespondToModel(
+            "max_chars must be greater than zero".to_string(),
+        ));
+    }
+    let parent_id = session.conversation_id();
+    let output = session
+        .services
+        .agent_control
+        .subagent_output(parent_id, agent_id, args.max_chars)
+        .await
+        .map_err(|err| match err {
+            CodexErr::ThreadNotFound(id) => {
+                FunctionCallError::RespondToModel(format!("agent with id {id} not found"))
+            }
+            err => FunctionCallError::Fatal(err.to_string()),
+        })?;
+    let strip_ansi = args.strip_ansi.unwrap_or(true);
+    let output = if strip_ansi {
+        output.strip_control_sequences()
+    } else {
+        output
+    };
+    let status = session.services.agent_control.get_status(agent_id).await;
+    let tool_events = if output.tool_events.is_empty() {
+        None
+    } else {
+        Some(output.tool_events)
+    };
+    // Nested subagents read their own parent's chat, so only direct subagents have a count here.
+    let is_direct_subagent = session
+        .services
+        .agent_control
+        .is_subagent_of(parent_id, agent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let unread_count = if is_direct_subagent {
+        Some(session.group_chat_unread_count(agent_id).await)
+    } else {
+        None
+    };
+    let group_chat = if args.include_group_chat {
+        let (last_read, unread) = session
+            .group_chat_view_for(agent_id, GROUP_CHAT_TAIL_READ_MESSAGES)
+            .await;
+        Some(GroupChatTail { last_read, unread })
+    } else {
+        None
+    };
+    let descendants = if args.recursive {
+        let (agents, truncated) = session
+            .services
+            .agent_control
+            .subagent_output_descendants(parent_id, agent_id, args.max_chars)
+            .await
+            .map_err(spawn_error)?;
+        let agents = if strip_ansi {
+            agents
+                .into_iter()
+                .map(SubagentOutputNode::strip_control_sequences)
+                .collect()
+        } else {
+            agents
+        };
+        Some(DescendantOutputs { agents, truncated })
+    } else {
+        None
+    };
+    let expects_structured_output = session
+        .services
+        .agent_control
+        .expects_structured_output(agent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let (structured_result, structured_result_invalid) =
+        structured_result(output.last_message.as_deref(), expects_structured_output);
+    let content = AgentOutputResponse {
+        id: agent_id,
+        status,
+        partial: output.partial,
+        last_message: output.last_message,
+        structured_result,
+        structured_result_invalid,
+        reasoning_summary: output.reasoning_summary,
+        reasoning_raw: output.reasoning_raw,
+        tool_events,
+        truncated: output.truncated,
+        token_usage: output.token_usage,
+        rate_limits: output.rate_limits,
+        warnings: output.warnings,
+        file_changes: args.include_file_changes.then_some(output.file_changes),
+        turn_diff: output.turn_diff.filter(|_| args.include_turn_diff),
+        shutdown_reason: output.shutdown_reason,
+        undelivered_messages: output.undelivered_messages,
+        seconds_since_last_activity: output.last_activity.map(|at| at.elapsed().as_secs()),
+        unread_count,
+        group_chat,
+        descendants,
+    };
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize agent output: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_report_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: ReportAgentArgs = parse_arguments(&arguments)?;
+    let agent_id = target_agent_id(session.conversation_id(), &args.id)?;
+    let agent_control = &session.services.agent_control;
+    let output = agent_control
+        .subagent_output(session.conversation_id(), agent_id, None)
+        .await
+        .map_err(spawn_error)?;
+    let status = agent_control.get_status(agent_id).await;
+    let display_name = agent_control
+        .subagent_display_name(agent_id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    let name = display_name.map_or_else(
+        || agent_id.to_string(),
+        |name| format!("{name} ({agent_id})"),
+    );
+    Ok(ToolOutput::Function {
+        content: agent_report(&name, &status, &output),
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn handle_compare_agents(
+    session: std::sync::Arc<crate::codex::Session>,
+    arguments: String,
+) -> Result<ToolOutput, FunctionCallError> {
+    let args: CompareAgentsArgs = parse_arguments(&arguments)?;
+    let caller_id = session.conversation_id();
+    let (left_id, right_id) = (
+        target_agent_id(caller_id, &args.left)?,
+        target_agent_id(caller_id, &args.right)?,
+    );
+    if left_id == right_id {
+        return Err(FunctionCallError::RespondToModel(
+            "compare_agents needs two different agent ids".to_string(),
+        ));
+    }
+    let left = compared_agent(&session, left_id).await?;
+    let right = compared_agent(&session, right_id).await?;
+    let content = compare_agents(left, right);
+    let content = serde_json::to_string(&content)
+        .unwrap_or_else(|_| format!("failed to serialize agent comparison: {content:?}"));
+    Ok(ToolOutput::Function {
+        content,
+        success: Some(true),
+        content_items: None,
+    })
+}
+
+async fn compared_agent(
+    session: &crate::codex::Session,
+    id: ThreadId,
+) -> Result<ComparedAgent, FunctionCallError> {
+    let agent_control = &session.services.agent_control;
+    let output = agent_control
+        .subagent_output(session.conversation_id(), id, None)
+        .await
+        .map_err(spawn_error)?;
+    let display_name = agent_control
+        .subagent_display_name(id)
+        .await
+        .map_err(|err| FunctionCallError::Fatal(err.to_string()))?;
+    Ok(ComparedAgent {
+        id,
+        display_name,
+        status: agent_control.get_status(id).await,
+        last_message: output.last_message,
+    })
+}
+
+fn compare_agents(left: ComparedAgent, right: ComparedAgent) -> CompareAgentsResponse {
+    let (identical, diff) = match (&left.last_message, &right.last_message) {
+        (Some(left_message), Some(right_message)) => {
+            let diff = similar::TextDiff::from_lines(left_message, right_message)
+                .unified_diff()
+                .context_radius(3)
+                .header(&left.id.to_string(), &right.id.to_string())
+                .to_string();
+            (left_message == right_message, Some(diff))
+        }
+        _ => (false, None),
+    };
+    CompareAgentsResponse {
+        left,
+        right,
+        identical,
+        diff,
+    }
+}
+
+/// One-paragraph, deterministic summary of a subagent: status, age, turns, latest message, and
+/// its most recent tool events.
+fn agent_report(name: &str, status: &AgentStatus, output: &SubagentOutputSnapshot) -> String {
+    let state = match status {
+        AgentStatus::PendingInit => "is starting".to_string(),
+        AgentStatus::Running => "is running".to_string(),
+        AgentStatus::Completed(_) => "has completed".to_string(),
+        AgentStatus::Errored(err) => format!("errored ({})", excerpt(err)),
+        AgentStatus::Shutdown => "has shut down".to_string(),
+        AgentStatus::NotFound => "is no longer running".to_string(),
+    };
+    let mut report = format!("{name} {state}");
+    if let Some(spawned_at) = output.spawned_at {
+        let secs = spawned_at.elapsed().as_secs();
+        report.push_str(&format!(
+            " after {}m{:02}s and {} turn(s)",
+            secs / 60,
+            secs % 60,
+            output.turns
+        ));
+    }
+    report.push('.');
+    let latest = match status {
+        AgentStatus::Completed(Some(message)) => Some(("Final message", message.as_str())),
+        _ => output
+            .partial
+            .as_deref()
+            .map(|partial| ("Writing", partial))
+            .or_else(|| {
+                output
+                    .last_message
+                    .as_deref()
+                    .map(|message| ("Last message", message))
+            }),
+    };
+    if let Some((label, text)) = latest {
+        report.push_str(&format!(" {label}: \"{}\".", excerpt(text)));
+    }
+    let recent = output
+        .tool_events
+        .iter()
+        .rev()
+        .take(REPORT_TOOL_EVENTS)
+        .rev()
+        .map(|event| excerpt(event.as_str()))
+        .collect::<Vec<_>>();
+    if !recent.is_empty() {
+        report.push_str(&format!(" Recent tools: {}.", recent.join("; ")));
+    }
+    report
+}
+
+/// The JSON document in `last_message`, if any, and whether it is missing although the subagent
+/// was asked for structured output.
+fn structured_result(
+    last_message: Option<&str>,
+    expects_structured_output: bool,
+) -> (Option<serde_json::Value>, bool) {
+    let Some(message) = last_message else {
+        return (None, false);
+    };
+    match parse_structured_result(message) {
+        Some(Ok(value)) => (Some(value), false),
+        Some(Err(_)) | None => (None, expects_structured_output),
+    }
+}
+
+/// Parse a final message that is a JSON object or array, bare or wrapped in a single fenced
+/// block. Returns `None` when the message is not meant as JSON.
+fn parse_structured_result(message: &str) -> Option<serde_json::Result<serde_json::Value>> {
+    let trimmed = message.trim();
+    let candidate = trimmed
+        .strip_prefix("```")
+        .and_then(|fenced| fenced.strip_suffix("```"))
+        .map(|fenced| {
+            let fenced = fenced.strip_prefix("json").unwrap_or(fenced);
+            fenced.trim()
+        })
+        .unwrap_or(trimmed);
+    if !(candidate.starts_with('{') || candidate.starts_with('[')) {
+        return None;
+    }
+    Some(serde_json::from_str(candidate))
+}
+
+/// Single-line excerpt of `text`, capped at `REPORT_EXCERPT_CHARS` characters.
+fn excerpt(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= REPORT_EXCERPT_CHARS {
+        return line;
+    }
+    let mut excerpt = line.chars().take(REPORT_EXCERPT_CHARS).collect::<String>();
+    excerpt.push_str("...");
+    excerpt
+}
+
+/// How [`poll_agent_status`] stopped waiting, with the status it last observed.
+enum WaitOutcome {
+    Finished(AgentStatus),
+    TimedOut(AgentStatus),
+}
+
+async fn wait_for_agent(
+    session: std::sync::Arc<crate::codex::Session>,
+    agent_id: ThreadId,
+    timeout_ms: u64,
+) -> Result<AgentStatus, FunctionCallError> {
+    match poll_agent_status(&session, agent_id, timeout_ms).await {
+        WaitOutcome::Finished(status) => Ok(status),
+        WaitOutcome::TimedOut(status) => Err(FunctionCallError::RespondToModel(format!(
+            "wait timed out; last status was {status:?}"
+        ))),
+    }
+}
+
+async fn poll_agent_status(
+    session: &crate::codex::Session,
+    agent_id: ThreadId,
+    timeout_ms: u64,
+) -> WaitOutcome {
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+
+    loop {
+        let status = session.services.agent_control.get_status(agent_id).await;
+        if !matches!(status, AgentStatus::PendingInit | AgentStatus::Running) {
+            return WaitOutcome::Finished(status);
+        }
+        if Instant::now() >= deadline {
+            session.services.agent_control.record_wait_timeout(agent_id);
+            return WaitOutcome::TimedOut(status);
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+}
+
+/// Apply the default timeout and clamp it to the host's configured ceiling.
+fn resolve_timeout_ms(
+    session: &crate::codex::Session,
+    timeout_ms: Option<i64>,
+) -> Result<u64, FunctionCallError> {
+    let limits = session.services.agent_control.collab_limits();
+    let timeout_ms = match timeout_ms {
+        Some(timeout_ms) if timeout_ms <= 0 => {
+            return Err(FunctionCallError::RespondToModel(
+                "timeout_ms must be greater than zero".to_string(),
+            ));
+        }
+        Some(timeout_ms) => timeout_ms as u64,
+        None => limits.default_wait_timeout_ms,
+    };
+    Ok(timeout_ms.min(limits.max_wait_timeout_ms))
+}
+
+fn status_payload(status: &AgentStatus) -> String {
+    serde_json::to_string(status).unwrap_or_else(|_| format!("{status:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use core_test_support::responses;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn close_agent_waits_unless_forced() {
+        let args: CloseAgentArgs =
+            parse_arguments(r#"{"id":"a"}"#).expect("parse default close args");
+        assert!(!args.force);
+        let args: CloseAgentArgs =
+            parse_arguments(r#"{"id":"a","force":true}"#).expect("parse forced close args");
+        assert!(args.force);
+    }
+
+    #[test]
+    fn wait_timeout_is_an_error_unless_partial_results_are_requested() {
+        let args: WaitArgs = parse_arguments(r#"{"id":"a"}"#).expect("parse default wait args");
+        assert!(!args.partial_on_timeout);
+
+        let response = WaitTimeoutResponse {
+            timed_out: true,
+            status: AgentStatus::Running,
+            preview: Some(excerpt("Halfway through\n  the test suite")),
+        };
+        assert_eq!(
+            serde_json::to_value(&response).expect("serialize timeout response"),
+            json!({
+                "timed_out": true,
+                "status": serde_json::to_value(AgentStatus::Running).expect("serialize status"),
+                "preview": "Halfway through the test suite",
+            })
+        );
+    }
+
+    #[test]
+    fn subagent_limit_errors_get_guidance_per_kind() {
+        let Err(FunctionCallError::RespondToModel(per_parent)) =
+            Err::<(), _>(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::PerParent,
+                limit: 8,
+            })
+            .map_err(spawn_error)
+        else {
+            panic!("limit errors should go back to the model");
+        };
+        assert!(per_parent.contains("maximum of 8 subagents"));
+        assert!(per_parent.contains("close_agent"));
+
+        let Err(FunctionCallError::RespondToModel(budget)) =
+            Err::<(), _>(CodexErr::SubagentLimitExceeded {
+                kind: SubagentLimitKind::TokenBudget,
+                limit: 1_000,
+            })
+            .map_err(spawn_error)
+        else {
+            panic!("limit errors should go back to the model");
+        };
+        assert!(budget.contains("1000 tokens"));
+    }
+
+    #[test]
+    fn target_agent_id_rejects_the_callers_own_thread() {
+        let caller_id = ThreadId::new();
+        let other_id = ThreadId::new();
+        assert_eq!(
+            target_agent_id(caller_id, &other_id.to_string()).expect("other agent"),
+            other_id
+        );
+        let Err(FunctionCallError::RespondToModel(message)) =
+            target_agent_id(caller_id, &caller_id.to_string())
+        else {
+            panic!("targeting the caller should be refused");
+        };
+        assert_eq!(
+            message,
+            format!("agent id {caller_id} is your own thread; pass the id of another agent")
+        );
+        assert_matches!(
+            target_agent_id(caller_id, "not-an-id"),
+            Err(FunctionCallError::RespondToModel(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn collab_tools_refuse_to_target_the_caller() {
+        let (session, _turn) = crate::codex::make_session_and_context().await;
+        let session = Arc::new(session);
+        let own_id = session.conversation_id().to_string();
+        let id_args = json!({ "id": own_id }).to_string();
+        let results = [
+            handle_wait(Arc::clone(&session), id_args.clone()).await,
+            handle_close_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_replay_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_pause_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_resume_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_inspect_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_agent_output(Arc::clone(&session), id_args.clone()).await,
+            handle_report_agent(Arc::clone(&session), id_args.clone()).await,
+            handle_get_agent_tree(Arc::clone(&session), id_args.clone()).await,
+            handle_clone_agent(
+                Arc::clone(&session),
+                json!({ "id": own_id, "count": 1 }).to_string(),
+            )
+            .await,
+            handle_wait_for_message(
+                Arc::clone(&session),
+                json!({ "id": own_id, "pattern": "DONE" }).to_string(),
+            )
+            .await,
+            handle_checkpoint_agent(
+                Arc::clone(&session),
+                json!({ "id": own_id, "label": "before refactor" }).to_string(),
+            )
+            .await,
+            handle_restore_checkpoint(
+                Arc::clone(&session),
+                json!({ "id": own_id, "checkpoint_id": 1, "message": "try again" }).to_string(),
+            )
+            .await,
+            handle_compare_agents(
+                Arc::clone(&session),
+                json!({ "left": own_id, "right": ThreadId::new().to_string() }).to_string(),
+            )
+            .await,
+            handle_send_and_wait(
+                Arc::clone(&session),
+                json!({ "id": own_id, "message": "status?" }).to_string(),
+            )
+            .await,
+        ];
+        for result in results {
+            let Err(FunctionCallError::RespondToModel(message)) = result else {
+                panic!("targeting the caller should be refused");
+            };
+            assert!(message.contains("is your own thread"), "{message}");
+        }
+    }
+
+    /// A manager, an orchestrator session wired to it, and a subagent config whose model turns
+    /// are answered by `server`.
+    async fn mock_model_session(
+        server: &wiremock::MockServer,
+    ) -> (
+        crate::ThreadManager,
+        Arc<crate::codex::Session>,
+        crate::config::Config,
+    ) {
+        let provider = crate::ModelProviderInfo {
+            base_url: Some(format!("{}/v1", server.uri())),
+            ..crate::built_in_model_providers()["openai"].clone()
+        };
+        let manager = crate::ThreadManager::with_models_provider(
+            crate::CodexAuth::from_api_key("Test API Key"),
+            provider.clone(),
+        );
+        let (mut session, _turn) = crate::codex::make_session_and_context().await;
+        session.services.agent_control = manager.agent_control();
+        let mut config = crate::config::test_config();
+        config.model_provider = provider;
+        (manager, Arc::new(session), config)
+    }
+
+    fn assistant_turn(id: &str, text: &str) -> String {
+        responses::sse(vec![
+            responses::ev_response_created(id),
+            responses::ev_assistant_message(&format!("{id}-msg"), text),
+            responses::ev_completed(id),
+        ])
+    }
+
+    async fn wait_until_completed(session: &crate::codex::Session, agent_id: ThreadId) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !matches!(
+                session.services.agent_control.get_status(agent_id).await,
+                AgentStatus::Completed(_)
+            ) {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the turn completes");
+    }
+
+    #[tokio::test]
+    async fn send_and_wait_returns_the_new_turns_last_message() {
+        let server = responses::start_mock_server().await;
+        responses::mount_sse_sequence(
+            &server,
+            vec![
+                assistant_turn("resp-1", "ready"),
+                assistant_turn("resp-2", "the answer is 42"),
+            ],
+        )
+        .await;
+        let (manager, session, config) = mock_model_session(&server).await;
+        let agent_id = manager
+            .spawn_subagent(
+                session.conversation_id(),
+                config,
+                "get ready".to_string(),
+                crate::SubagentSpawnOptions {
+                    display_name: Some("solver".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("spawn subagent");
+        wait_until_completed(&session, agent_id).await;
+
+        let ToolOutput::Function { content, .. } = handle_send_and_wait(
+            Arc::clone(&session),
+            json!({ "id": agent_id.to_string(), "message": "what is the answer?", "timeout_ms": 10_000 })
+                .to_string(),
+        )
+        .await
+        .expect("send_and_wait succeeds") else {
+            panic!("send_and_wait should return function output");
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&content).expect("parse send_and_wait output");
+        assert_eq!(value["last_message"], json!("the answer is 42"));
+        assert_eq!(value["status"], json!({ "completed": "the answer is 42" }));
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[tokio::test]
+    async fn send_and_wait_times_out_when_the_turn_never_starts() {
+        let server = responses::start_mock_server().await;
+        responses::mount_sse_sequence(
+            &server,
+            vec![
+                assistant_turn("resp-1", "ready"),
+                assistant_turn("resp-2", "late"),
+            ],
+        )
+        .await;
+        let (manager, session, config) = mock_model_session(&server).await;
+        // A subagent without a drain: its turns run, but none is ever recorded as started.
+        let crate::NewThread {
+            thread_id: agent_id,
+            thread,
+            ..
+        } = manager
+            .start_thread(config.clone())
+            .await
+            .expect("start thread");
+        thread
+            .submit(crate::protocol::Op::UserInput {
+                items: vec![codex_protocol::user_input::UserInput::Text {
+                    text: "get ready".to_string(),
+                }],
+                final_output_json_schema: None,
+            })
+            .await
+            .expect("submit first turn");
+        wait_until_completed(&session, agent_id).await;
+        manager
+            .seed_subagents(
+                vec![crate::SeededSubagent {
+                    id: agent_id,
+                    parent_id: session.conversation_id(),
+                    display_name: Some("silent".to_string()),
+                    ..Default::default()
+                }],
+                &config,
+            )
+            .await;
+
+        let err = handle_send_and_wait(
+            Arc::clone(&session),
+            json!({ "id": agent_id.to_string(), "message": "anyone there?", "timeout_ms": 300 })
+                .to_string(),
+        )
+        .await
+        .expect_err("the turn is never seen starting");
+        let FunctionCallError::RespondToModel(message) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(
+            message.contains("timed out before agent") && message.contains("use wait"),
+            "{message}"
+        );
+        manager.shutdown_all(Duration::from_secs(5)).await;
+    }
+
+    #[test]
+    fn spawn_output_reports_the_model_fallback() {
+        assert_eq!(spawn_output("agent_id: a".to_string(), None), "agent_id: a");
+        assert_eq!(
+            spawn_output(
+                "agent_id: a".to_string(),
+                Some("model `x` is unavailable; the subagent will use gpt-5 instead"),
+            ),
+            "agent_id: a\nmodel_fallback: model `x` is unavailable; the subagent will use gpt-5 instead"
+        );
+        let result = SpawnAgentBatchResult {
+            agent_id: None,
+            model_fallback: Some("fell back".to_string()),
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&result).expect("serialize batch result"),
+            json!({ "model_fallback": "fell back" })
+        );
+    }
+
+    #[test]
+    fn message_pattern_matches_substrings_and_regexes() {
+        let substring = MessagePattern::parse("DONE", false).expect("substring pattern");
+        assert!(substring.matches("step 3: DONE."));
+        assert!(!substring.matches("done"));
+        // Without `regex`, metacharacters are matched literally.
+        let literal = MessagePattern::parse("a+b", false).expect("literal pattern");
+        assert!(literal.matches("a+b=c"));
+        assert!(!literal.matches("aab"));
+
+        let regex = MessagePattern::parse(r"(?i)^tests? (passed|ok)$", true).expect("regex");
+        assert!(regex.matches("Tests passed"));
+        assert!(!regex.matches("tests failed"));
+    }
+
+    #[test]
+    fn message_pattern_rejects_empty_and_invalid_patterns() {
+        assert_matches!(
+            MessagePattern::parse("", false),
+            Err(FunctionCallError::RespondToModel(_))
+        );
+        assert_matches!(
+            MessagePattern::parse("(unclosed", true),
+            Err(FunctionCallError::RespondToModel(_))
+        );
+    }
+
+    #[test]
+    fn structured_result_parses_json_messages() {
+        assert_eq!(
+            parse_structured_result(r#" {"tests": "pass", "failures": 0} "#).map(Result::ok),
+            Some(Some(json!({"tests": "pass", "failures": 0})))
+        );
+        assert_eq!(
+            parse_structured_result("```json\n[1, 2]\n```").map(Result::ok),
+            Some(Some(json!([1, 2])))
+        );
+        assert_eq!(
+            parse_structured_result("All tests pass.").map(Result::ok),
+            None
+        );
+    }
+
+    #[test]
+    fn structured_result_flags_malformed_json() {
+        let result = parse_structured_result(r#"{"tests": "pass","#);
+        assert!(matches!(result, Some(Err(_))));
+    }
+
+    #[test]
+    fn structured_result_is_only_flagged_when_requested() {
+        assert_eq!(
+            structured_result(Some(r#"{"failures": 0}"#), true),
+            (Some(json!({"failures": 0})), false)
+        );
+        for message in [r#"{"failures": "#, "All tests pass."] {
+            assert_eq!(structured_result(Some(message), false), (None, false));
+            assert_eq!(structured_result(Some(message), true), (None, true));
+        }
+        // Nothing to flag before the subagent has answered.
+        assert_eq!(structured_result(None, true), (None, false));
+    }
+
+    #[test]
+    fn compare_agents_diffs_last_messages() {
+        let agent = |last_message: Option<&str>| ComparedAgent {
+            id: ThreadId::new(),
+            display_name: None,
+            status: AgentStatus::Completed(last_message.map(str::to_string)),
+            last_message: last_message.map(str::to_string),
+        };
+
+        let response = compare_agents(agent(Some("plan\nuse a\n")), agent(Some("plan\nuse b\n")));
+        assert!(!response.identical);
+        let diff = response.diff.expect("both agents have messages");
+        assert!(diff.contains("-use a\n+use b\n"), "{diff}");
+
+        let response = compare_agents(agent(Some("same\n")), agent(Some("same\n")));
+        assert!(response.identical);
+
+        let response = compare_agents(agent(Some("done\n")), agent(None));
+        assert!(!response.identical);
+        assert_eq!(response.diff, None);
+    }
+
+    #[test]
+    fn agent_report_summarizes_status_message_and_recent_tools() {
+        let output = SubagentOutputSnapshot {
+            partial: None,
+            last_message: Some("Reviewed   the\ndiff".to_string()),
+            reasoning_summary: None,
+            reasoning_raw: None,
+            tool_events: vec![
+                "exec begin: ls".to_string(),
+                "exec end: ls (exit 0)".to_string(),
+                "exec begin: cargo test".to_string(),
+                "exec end: cargo test (exit 0)".to_string(),
+            ],
+            truncated: false,
+            token_usage: None,
+            rate_limits: None,
+            turn_diff: None,
+            warnings: Vec::new(),
+            file_changes: Vec::new(),
+            last_activity: None,
+            spawned_at: None,
+            turns: 2,
+            shutdown_reason: None,
+            undelivered_messages: Vec::new(),
+        };
+
+        assert_eq!(
+            agent_report("Reviewer", &AgentStatus::Running, &output),
+            "Reviewer is running. Last message: \"Reviewed the diff\". Recent tools: exec end: ls (exit 0); exec begin: cargo test; exec end: cargo test (exit 0)."
+        );
+
+        let output = SubagentOutputSnapshot {
+            partial: Some("x".repeat(REPORT_EXCERPT_CHARS + 1)),
+            tool_events: Vec::new(),
+            spawned_at: Some(std::time::Instant::now()),
+            ..output
+        };
+        assert_eq!(
+            agent_report("Reviewer", &AgentStatus::Running, &output),
+            format!(
+                "Reviewer is running after 0m00s and 2 turn(s). Writing: \"{}...\".",
+                "x".repeat(REPORT_EXCERPT_CHARS)
+            )
+        );
+        assert_eq!(
+            agent_report(
+                "Reviewer",
+                &AgentStatus::Completed(Some("Looks good".to_string())),
+                &SubagentOutputSnapshot {
+                    spawned_at: None,
+                    ..output
+                }
+            ),
+            "Reviewer has completed. Final message: \"Looks good\"."
+        );
+    }
+
+    #[test]
+    fn list_agents_response_is_a_versioned_envelope() {
+        let id = ThreadId::new();
+        let response = ListAgentsResponse {
+            version: LIST_AGENTS_SCHEMA_VERSION,
+            agents: vec![SubagentSummary {
+                id,
+                status: AgentStatus::Running,
+                persona: Some("Reviews diffs".to_string()),
+                display_name: Some("Reviewer".to_string()),
+                correlation_id: None,
+                seconds_since_last_activity: Some(3),
+                unread_count: Some(2),
+            }],
+        };
+
+        let value = serde_json::to_value(&response).expect("serialize agent list");
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "agents": [{
+                    "id": id.to_string(),
+                    "status": "running",
+                    "persona": "Reviews diffs",
+                    "display_name": "Reviewer",
+                    "seconds_since_last_activity": 3,
+                    "unread_count": 2,
+                }],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_for_message_finds_a_match_posted_before_the_call() {
+        let manager = crate::ThreadManager::with_models_provider(
+            crate::CodexAuth::from_api_key("Test API Key"),
+            crate::built_in_model_providers()["openai"].clone(),
+        );
+        let (mut session, _turn) = crate::codex::make_session_and_context().await;
+        session.services.agent_control = manager.agent_control();
+        let session = Arc::new(session);
+        let subagent_id = ThreadId::new();
+        manager
+            .seed_subagents(
+                vec![crate::SeededSubagent {
+                    id: subagent_id,
+                    parent_id: session.conversation_id(),
+                    display_name: Some("builder".to_string()),
+                    ..Default::default()
+                }],
+                &crate::config::test_config(),
+            )
+            .await;
+        let sender = GroupChatSender::SubAgent {
+            id: subagent_id,
+            persona: None,
+            display_name: Some("builder".to_string()),
+        };
+        for text in ["BUILD DONE", "starting tests"] {
+            session
+                .process_group_chat_message(
+                    "sub".to_string(),
+                    text.to_string(),
+                    Vec::new(),
+                    sender.clone(),
+                )
+                .await;
+        }
+
+        let ToolOutput::Function { content, .. } = handle_wait_for_message(
+            Arc::clone(&session),
+            json!({ "id": subagent_id.to_string(), "pattern": "DONE", "timeout_ms": 1 })
+                .to_string(),
+        )
+        .await
+        .expect("an earlier message matches") else {
+            panic!("wait_for_message should return function output");
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&content).expect("parse wait_for_message output");
+        assert_eq!(value["text"], json!("BUILD DONE"));
+    }
+
+    #[tokio::test]
+    async fn read_group_chat_keeps_attribution_after_the_sender_is_forgotten() {
+        let manager = crate::ThreadManager::with_models_provider(
+            crate::CodexAuth::from_api_key("Test API Key"),
+            crate::built_in_model_providers()["openai"].clone(),
+        );
+        let (mut session, _turn) = crate::codex::make_session_and_context().await;
+        session.services.agent_control = manager.agent_control();
+        let session = Arc::new(session);
+        let subagent_id = ThreadId::new();
+        let sender = GroupChatSender::SubAgent {
+            id: subagent_id,
+            persona: Some("Tracks down failing tests".to_string()),
+            display_name: Some("Debugger".to_string()),
+        };
+        session
+            .process_group_chat_message(
+                "sub".to_string(),
+                "Please find the flaky test".to_string(),
+                Vec::new(),
+                GroupChatSender::Human,
+            )
+            .await;
+        session
+            .process_group_chat_message(
+                "sub".to_string(),
+                "The race is in the cache warmup".to_string(),
+                Vec::new(),
+                sender.clone(),
+            )
+            .await;
+        session
+            .services
+            .agent_control
+            .forget_subagent(subagent_id)
+            .await
+            .expect("forget subagent");
+        assert_eq!(
+            session
+                .services
+                .agent_control
+                .subagent_display_name(subagent_id)
+                .await
+                .expect("look up display name"),
+            None
+        );
+
+        let ToolOutput::Function { content, .. } =
+            handle_read_group_chat(Arc::clone(&session), json!({ "last": 1 }).to_string())
+                .await
+                .expect("read group chat")
+        else {
+            panic!("read_group_chat should return function output");
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&content).expect("parse read_group_chat output");
+        assert_eq!(value["total_messages"], json!(2));
+        assert_eq!(
+            value["messages"],
+            json!([{
+                "id": { "sender": format!("subagent:{subagent_id}"), "seq": 0 },
+                "sender": serde_json::to_value(&sender).expect("serialize sender"),
+                "text": "The race is in the cache warmup",
+                "display": true,
+            }])
+        );
+        assert_eq!(
+            value["messages"][0]["sender"]["sub_agent"]["display_name"],
+            json!("Debugger")
+        );
+    }
+
+    #[test]
+    fn agent_context_is_cut_to_the_budget_items_first() {
+        let context = InitialContext {
+            base_instructions: "You are a coding agent.".to_string(),
+            items: vec![ResponseItem::Message {
+                id: None,
+                role: "developer".to_string(),
+                content: vec![ContentItem::InputText {
+                    text: "Persona: reviewer".to_string(),
+                }],
+            }],
+        };
+
+        let full = agent_context_response(context.clone(), 1_000);
+        assert!(!full.truncated);
+        assert_eq!(
+            full.context,
+            vec![AgentContextItem {
+                role: "developer".to_string(),
+                text: "Persona: reviewer".to_string(),
+            }]
+        );
+        assert_eq!(full.base_instructions, "You are a coding agent.");
+
+        let cut = agent_context_response(context, 20);
+        assert!(cut.truncated);
+        assert_eq!(cut.context[0].text, "Persona: reviewer");
+        assert_eq!(cut.base_instructions, "You");
+    }
 }
@@ -1,12 +1,22 @@
+use crate::agent::spawn::MAX_SPAWN_ATTACHMENTS;
+use crate::agent_personas::MAX_PERSONA_CHARS;
 use crate::client_common::tools::ResponsesApiTool;
 use crate::client_common::tools::ToolSpec;
 use crate::features::Feature;
 use crate::features::Features;
+use crate::thread_manager::CollabLimits;
+use crate::thread_manager::MAX_PINNED_CONTEXT_CHARS;
+use crate::thread_manager::MAX_SCRATCHPAD_CHARS;
+use crate::thread_manager::MAX_SHARED_KEY_CHARS;
+use crate::thread_manager::MAX_SHARED_VALUE_CHARS;
+use crate::thread_manager::MAX_SHARED_VARIABLES;
+use crate::thread_manager::MAX_SUBAGENT_CHECKPOINTS;
+use crate::thread_manager::MAX_SUBAGENT_TOOL_EVENTS_LIMIT;
+use crate::thread_manager::MAX_SUBAGENT_TREE_DEPTH;
+use crate::thread_manager::MAX_SUBAGENT_TREE_NODES;
 use crate::tools::handlers::PLAN_TOOL;
 use crate::tools::handlers::apply_patch::create_apply_patch_freeform_tool;
 use crate::tools::handlers::apply_patch::create_apply_patch_json_tool;
-use crate::tools::handlers::collab::DEFAULT_WAIT_TIMEOUT_MS;
-use crate::tools::handlers::collab::MAX_WAIT_TIMEOUT_MS;
 use crate::tools::registry::ToolRegistryBuilder;
 use codex_protocol::models::VIEW_IMAGE_TOOL_NAME;
 use codex_protocol::openai_models::ApplyPatchToolType;
@@ -28,6 +38,8 @@ pub(crate) struct ToolsConfig {
     pub collab_tools: bool,
     pub experimental_supported_tools: Vec<String>,
     pub tool_policy: crate::config::types::ToolPolicy,
+    /// Limits quoted in the collab tool descriptions.
+    pub collab_limits: CollabLimits,
 }
 
 pub(crate) struct ToolsConfigParams<'a> {
@@ -80,8 +92,15 @@ impl ToolsConfig {
             collab_tools: include_collab_tools,
             experimental_supported_tools: model_info.experimental_supported_tools.clone(),
             tool_policy,
+            collab_limits: CollabLimits::default(),
         }
     }
+
+    /// Describe the collab tools with the host's `collab_limits` instead of the defaults.
+    pub fn with_collab_limits(mut self, collab_limits: CollabLimits) -> Self {
+        self.collab_limits = collab_limits;
+        self
+    }
 }
 
 /// Generic JSON‑Schema subset needed for our tool definitions
@@ -413,185 +432,1122 @@ fn create_view_image_tool() -> ToolSpec {
         JsonSchema::String {
             description: Some("Local filesystem path to an image file".to_string()),
         },
-    )]);
+    )]);
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: VIEW_IMAGE_TOOL_NAME.to_string(),
+        description: "View a local image from the filesystem (only use if given a full filepath by the user, and the image isn't already attached to the thread context within <image ...> tags)."
+            .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["path".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+/// Properties shared by `spawn_agent` and the per-agent specs of `spawn_agent_batch`.
+fn spawn_agent_properties(limits: &CollabLimits) -> BTreeMap<String, JsonSchema> {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some("Initial message to send to the new agent.".to_string()),
+        },
+    );
+    properties.insert(
+        "display_name".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Display name for the subagent (shown in status lines and group chat).".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "persona".to_string(),
+        JsonSchema::String {
+            description: Some("Optional persona instructions for the new agent.".to_string()),
+        },
+    );
+    properties.insert(
+        "model".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional model for the new agent. Defaults to this session's model, which is also used when available models cannot be checked.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "tool_allowlist".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Optional allowlist of tool names/patterns (for example \"shell\", \"mcp__server__tool\"). If set, only matching tools are exposed.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "tool_denylist".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Optional denylist of tool names/patterns. Matching tools are removed.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "shell_command_allowlist".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Optional allowlist of shell command patterns. If set, only matching commands may run.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "shell_command_denylist".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Optional denylist of shell command patterns. Matching commands are rejected."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "attachments".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(format!(
+                "Optional file paths inside the working directory to hand the new agent with its first message, at most {MAX_SPAWN_ATTACHMENTS}. Images are attached as images; other files must be UTF-8 text (at most 64 KiB) and are inlined."
+            )),
+        },
+    );
+    properties.insert(
+        "priority".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "Optional scheduling priority from 0 (default, lowest) to 255.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "evict_idle".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "When the subagent limit is reached, shut down the completed subagent with the lowest priority below this one to make room instead of failing.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "max_tool_events".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Optional number of recent tool events kept for agent_output. Defaults to {}; capped at {MAX_SUBAGENT_TOOL_EVENTS_LIMIT}.",
+                limits.max_subagent_tool_events
+            )),
+        },
+    );
+    properties.insert(
+        "include_shared".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Add your current shared variables (see set_shared) to the subagent's instructions. It can still read later changes with get_shared.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "source_label".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional label recorded as the subagent's session source for telemetry (for example \"collab/research\"). Defaults to \"collab\".".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "use_template_intro".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "When true, prepend the intro message of the built-in persona whose name matches display_name (see list_personas).".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "spawn_token".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional token naming this spawn. spawn_agent then returns as soon as the spawn is queued instead of waiting for the subagent to start, and cancel_spawn with the token aborts it until the subagent is running; find the agent with list_agents once it has started.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "correlation_id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional id tying the subagent to an upstream request; it is echoed in list_agents and subagent traces.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "output_schema".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional JSON schema, passed as a JSON string, that the subagent's final messages must follow. agent_output returns such a message parsed in structured_result and sets structured_result_invalid when it does not parse.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "auto_post".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Post the subagent's final messages to the group chat. Set to false to collect its results yourself with agent_output instead. Defaults to true.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "env".to_string(),
+        JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(JsonSchema::String { description: None }.into()),
+        },
+    );
+    properties
+}
+
+fn create_spawn_agent_tool(limits: &CollabLimits) -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "spawn_agent".to_string(),
+        description: "Spawn a new agent and return its id, plus a model_fallback note when the requested model is unavailable and the agent runs on your model instead.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: spawn_agent_properties(limits),
+            required: Some(vec!["message".to_string(), "display_name".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_spawn_agent_batch_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "agents".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::Object {
+                properties: spawn_agent_properties(limits),
+                required: Some(vec!["message".to_string(), "display_name".to_string()]),
+                additional_properties: Some(false.into()),
+            }),
+            description: Some(format!(
+                "Agents to spawn, each with the same fields as spawn_agent (at most {} active subagents in total).",
+                limits.max_subagents_per_parent
+            )),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "spawn_agent_batch".to_string(),
+        description:
+            "Spawn several agents concurrently. Returns one result per requested agent, in order, with either its id or the reason it failed."
+                .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["agents".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_clone_agent_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to clone.".to_string()),
+        },
+    );
+    properties.insert(
+        "count".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Number of clones to spawn (at most {} active subagents in total).",
+                limits.max_subagents_per_parent
+            )),
+        },
+    );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional initial message for the clones. Defaults to the source agent's initial message."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "clone_agent".to_string(),
+        description:
+            "Spawn sibling agents with the same model, persona, tool policy, and cwd as an existing subagent. Returns the new ids."
+                .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "count".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_checkpoint_agent_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to checkpoint.".to_string()),
+        },
+    );
+    properties.insert(
+        "label".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Short name for this save point, for example \"before refactor\".".to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "checkpoint_agent".to_string(),
+        description: format!(
+            "Record a labeled save point at a subagent's current position in its conversation and return its checkpoint_id. The subagent must be between turns. Each subagent keeps its {MAX_SUBAGENT_CHECKPOINTS} newest checkpoints."
+        ),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "label".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_restore_checkpoint_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent the checkpoint belongs to.".to_string()),
+        },
+    );
+    properties.insert(
+        "checkpoint_id".to_string(),
+        JsonSchema::Number {
+            description: Some("Checkpoint returned by checkpoint_agent.".to_string()),
+        },
+    );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Message the restored subagent continues with from the checkpoint.".to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "restore_checkpoint".to_string(),
+        description: "Fork a subagent from one of its checkpoints: a new subagent with the same model, persona, and tool policy starts from the conversation as it was at the checkpoint and receives message. The original subagent is left untouched. Returns the new agent_id.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec![
+                "id".to_string(),
+                "checkpoint_id".to_string(),
+                "message".to_string(),
+            ]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_send_input_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Identifier of the agent to message. Subagents may only send to their parent or to sibling subagents of the same parent."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some("Message to send to the agent.".to_string()),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "send_input".to_string(),
+        description: "Post a message to the group chat and ping a subagent.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "message".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_send_and_wait_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to message.".to_string()),
+        },
+    );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some("Message to send to the subagent.".to_string()),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Optional timeout in milliseconds for the whole turn. Defaults to {} and is capped at {}.",
+                limits.default_wait_timeout_ms, limits.max_wait_timeout_ms
+            )),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "send_and_wait".to_string(),
+        description: "Send a message to an idle subagent, wait for it to finish the turn, and return its status and last message.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "message".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_broadcast_input_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "ids".to_string(),
+        JsonSchema::Array {
+            items: Box::new(JsonSchema::String { description: None }),
+            description: Some(
+                "Identifiers of the subagents to message. Defaults to all of your subagents."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "message".to_string(),
+        JsonSchema::String {
+            description: Some("Message to send to every recipient.".to_string()),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(
+                "How long each recipient gets to accept the message before it is reported as slow (default 5000)."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "broadcast_input".to_string(),
+        description: "Post one message to the group chat and ping several subagents at once. Reports which recipients accepted it in time (accepted), which were still busy at the timeout (slow; they still receive it once free), and which could not be reached (failed, with the error).".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["message".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_wait_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the agent to wait on.".to_string()),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Optional timeout in milliseconds. Defaults to {} and is capped at {}.",
+                limits.default_wait_timeout_ms, limits.max_wait_timeout_ms
+            )),
+        },
+    );
+
+    properties.insert(
+        "partial_on_timeout".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "On timeout, return {\"timed_out\": true} with the agent's current status and a short output preview instead of an error. Defaults to false."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "wait".to_string(),
+        description: "Wait for an agent and return its status.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_wait_for_message_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent whose messages to watch.".to_string()),
+        },
+    );
+    properties.insert(
+        "pattern".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Text the message must contain, or a regular expression when regex is true."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "regex".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Treat pattern as a regular expression. Defaults to false.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Optional timeout in milliseconds. Defaults to {} and is capped at {}.",
+                limits.default_wait_timeout_ms, limits.max_wait_timeout_ms
+            )),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "wait_for_message".to_string(),
+        description: "Return the subagent's most recent group chat message matching pattern, waiting until it posts one if none of the retained messages match.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "pattern".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_close_agent_tool(limits: &CollabLimits) -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the agent to close.".to_string()),
+        },
+    );
+    properties.insert(
+        "timeout_ms".to_string(),
+        JsonSchema::Number {
+            description: Some(format!(
+                "Optional timeout in milliseconds. Defaults to {} and is capped at {}.",
+                limits.default_wait_timeout_ms, limits.max_wait_timeout_ms
+            )),
+        },
+    );
+    properties.insert(
+        "force".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "When true, do not wait for the agent to stop; forget it immediately and return its last known status."
+                    .to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "reason".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional reason for closing, for example that the work is no longer needed or the agent is being restarted. Delivered to the agent before it shuts down and kept with its final output."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "close_agent".to_string(),
+        description: "Close an agent and return its last known status.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_kill_all_agents_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "kill_all_agents".to_string(),
+        description: "Emergency stop: shut down and forget every subagent spawned by this session. Returns how many were terminated.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_pause_agent_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to pause.".to_string()),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "pause_agent".to_string(),
+        description: "Pause a subagent: stop processing its events and refuse new input until resume_agent. A turn already in flight keeps running. Returns whether it was newly paused.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_resume_agent_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to resume.".to_string()),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "resume_agent".to_string(),
+        description:
+            "Resume a subagent paused with pause_agent or pause_all. Returns whether it was paused."
+                .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_pause_all_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "pause_all".to_string(),
+        description: "Pause every subagent spawned by this session at once, e.g. while rate limited. Returns how many were newly paused.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_resume_all_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "resume_all".to_string(),
+        description:
+            "Resume every paused subagent spawned by this session. Returns how many were resumed."
+                .to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_get_agent_tree_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional id of an agent anywhere below this session (including nested subagents) to use as the root instead.".to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "get_agent_tree".to_string(),
+        description: format!(
+            "Return every subagent below this session as a nested tree of {{ id, persona, display_name, status, children }}, with node_count. Limited to {MAX_SUBAGENT_TREE_DEPTH} levels and {MAX_SUBAGENT_TREE_NODES} nodes; truncated is true when agents were left out."
+        ),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_inspect_agent_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Identifier of the agent to inspect: a direct subagent or any agent nested below one."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "inspect_agent".to_string(),
+        description: "Return the persona, display name, model, and tool and shell command allow/deny lists a subagent is running with, after any spawn-time overrides.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_inspect_agent_context_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Identifier of the agent to inspect: a direct subagent or any agent nested below one."
+                    .to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "inspect_agent_context".to_string(),
+        description: "Return the context a subagent actually started with, unredacted: its context items ahead of the first user message (developer instructions with its persona, user instructions, environment) followed by its base instructions. Use it to check a handoff when a subagent misunderstands its task. Long output is cut and flagged with truncated.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_amend_instructions_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "id".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the subagent to update.".to_string()),
+        },
+    );
+    properties.insert(
+        "text".to_string(),
+        JsonSchema::String {
+            description: Some(format!(
+                "Standing guidance to add, at most {MAX_PERSONA_CHARS} characters."
+            )),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "amend_instructions".to_string(),
+        description: "Add standing guidance to a running subagent's developer instructions without restarting it. The update applies from its next model request, follows its persona rather than replacing it, and is kept for the rest of its session and by its clones.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string(), "text".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_agent_token_usage_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "agent_token_usage".to_string(),
+        description: "Report tokens consumed by all subagents spawned from this session, and the token budget if one is configured.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_agents_summary_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "agents_summary".to_string(),
+        description: "Scoreboard for your subagents in one call: counts by status, how many errored, total tokens, total seconds alive, and turns taken.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_list_agents_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "order".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Listing order: \"id\" (default) or \"spawn_order\" (oldest first).".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "status_filter".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Only list agents with this status: pending_init, running, completed, errored, shutdown, or not_found.".to_string(),
+            ),
+        },
+    );
+    properties.insert(
+        "offset".to_string(),
+        JsonSchema::Number {
+            description: Some("Number of matching agents to skip (default 0).".to_string()),
+        },
+    );
+    properties.insert(
+        "limit".to_string(),
+        JsonSchema::Number {
+            description: Some("Maximum number of agents to return.".to_string()),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "list_agents".to_string(),
+        description: "List subagents spawned by this session. Returns `agents` with a schema `version` that changes when the entry format does.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_list_personas_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "include_orchestrator_prompt".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Also return the orchestrator instructions used for this session.".to_string(),
+            ),
+        },
+    );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: VIEW_IMAGE_TOOL_NAME.to_string(),
-        description: "View a local image from the filesystem (only use if given a full filepath by the user, and the image isn't already attached to the thread context within <image ...> tags)."
-            .to_string(),
+        name: "list_personas".to_string(),
+        description: "List the built-in subagent personas (name and description) available when spawning agents.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["path".to_string()]),
+            required: None,
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_spawn_agent_tool() -> ToolSpec {
+fn create_agent_output_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
-        "message".to_string(),
+        "id".to_string(),
         JsonSchema::String {
-            description: Some("Initial message to send to the new agent.".to_string()),
+            description: Some(
+                "Identifier of the agent to inspect: a direct subagent or any agent nested below one."
+                    .to_string(),
+            ),
         },
     );
     properties.insert(
-        "display_name".to_string(),
-        JsonSchema::String {
-            description: Some(
-                "Display name for the subagent (shown in status lines and group chat).".to_string(),
-            ),
+        "max_chars".to_string(),
+        JsonSchema::Number {
+            description: Some("Optional max chars to return from the partial output.".to_string()),
         },
     );
+
     properties.insert(
-        "persona".to_string(),
-        JsonSchema::String {
-            description: Some("Optional persona instructions for the new agent.".to_string()),
+        "recursive".to_string(),
+        JsonSchema::Boolean {
+            description: Some(
+                "Also return the status and latest output of agents this agent spawned, nested by parent (depth and count are bounded).".to_string(),
+            ),
         },
     );
     properties.insert(
-        "tool_allowlist".to_string(),
-        JsonSchema::Array {
-            items: Box::new(JsonSchema::String { description: None }),
+        "include_file_changes".to_string(),
+        JsonSchema::Boolean {
             description: Some(
-                "Optional allowlist of tool names/patterns (for example \"shell\", \"mcp__server__tool\"). If set, only matching tools are exposed.".to_string(),
+                "Also return the files changed by patches this agent applied since its last prompt, with their diffs."
+                    .to_string(),
             ),
         },
     );
     properties.insert(
-        "tool_denylist".to_string(),
-        JsonSchema::Array {
-            items: Box::new(JsonSchema::String { description: None }),
+        "include_turn_diff".to_string(),
+        JsonSchema::Boolean {
             description: Some(
-                "Optional denylist of tool names/patterns. Matching tools are removed.".to_string(),
+                "Also return the aggregated diff of the agent's current or last turn as turn_diff."
+                    .to_string(),
             ),
         },
     );
     properties.insert(
-        "shell_command_allowlist".to_string(),
-        JsonSchema::Array {
-            items: Box::new(JsonSchema::String { description: None }),
+        "include_group_chat".to_string(),
+        JsonSchema::Boolean {
             description: Some(
-                "Optional allowlist of shell command patterns. If set, only matching commands may run.".to_string(),
+                "Also return the group-chat messages this agent last read and those still unread."
+                    .to_string(),
             ),
         },
     );
     properties.insert(
-        "shell_command_denylist".to_string(),
-        JsonSchema::Array {
-            items: Box::new(JsonSchema::String { description: None }),
+        "strip_ansi".to_string(),
+        JsonSchema::Boolean {
             description: Some(
-                "Optional denylist of shell command patterns. Matching commands are rejected."
+                "Strip ANSI escape sequences and control characters from the captured output and tool events. Defaults to true; set to false to see the raw text."
                     .to_string(),
             ),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: "spawn_agent".to_string(),
-        description: "Spawn a new agent and return its id.".to_string(),
+        name: "agent_output".to_string(),
+        description:
+            "Fetch the latest partial or final output for a subagent, including reasoning summaries, raw reasoning, tool events, warnings, and rate limits when available. `truncated` is true when older output was dropped since the subagent's last prompt."
+                .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["message".to_string(), "display_name".to_string()]),
+            required: Some(vec!["id".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_send_input_tool() -> ToolSpec {
+fn create_report_agent_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
         "id".to_string(),
         JsonSchema::String {
             description: Some(
-                "Identifier of the agent to message. Subagents may only send to their parent."
+                "Identifier of the agent to report on: a direct subagent or any agent nested below one."
                     .to_string(),
             ),
         },
     );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "report_agent".to_string(),
+        description: "Summarize in one short paragraph what a subagent is doing: its status, how long it has run, turns taken, latest message, and most recent tool events. Use agent_output for full detail.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_compare_agents_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "left".to_string(),
+        JsonSchema::String {
+            description: Some("Identifier of the first agent to compare.".to_string()),
+        },
+    );
     properties.insert(
-        "message".to_string(),
+        "right".to_string(),
         JsonSchema::String {
-            description: Some("Message to send to the agent.".to_string()),
+            description: Some("Identifier of the second agent to compare.".to_string()),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: "send_input".to_string(),
-        description: "Post a message to the group chat and ping a subagent.".to_string(),
+        name: "compare_agents".to_string(),
+        description: "Compare the last messages of two of your subagents, for example clones exploring alternative approaches. Returns both agents' status and last message plus a line diff between the messages.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["id".to_string(), "message".to_string()]),
+            required: Some(vec!["left".to_string(), "right".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_wait_tool() -> ToolSpec {
+fn create_replay_agent_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
         "id".to_string(),
         JsonSchema::String {
-            description: Some("Identifier of the agent to wait on.".to_string()),
+            description: Some("Identifier of the subagent to retry.".to_string()),
         },
     );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "replay_agent".to_string(),
+        description: "Resend the last prompt a subagent received, clearing its buffered output first. Use it to retry after a transient error; it is refused while the subagent is mid-turn.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["id".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_cancel_spawn_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
     properties.insert(
-        "timeout_ms".to_string(),
-        JsonSchema::Number {
-            description: Some(format!(
-                "Optional timeout in milliseconds. Defaults to {DEFAULT_WAIT_TIMEOUT_MS} and max {MAX_WAIT_TIMEOUT_MS}."
-            )),
+        "token".to_string(),
+        JsonSchema::String {
+            description: Some("spawn_token passed to the spawn to cancel.".to_string()),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: "wait".to_string(),
-        description: "Wait for an agent and return its status.".to_string(),
+        name: "cancel_spawn".to_string(),
+        description: "Abort a spawn that is still starting its subagent, identified by the spawn_token it was given. Fails once the subagent is running; use close_agent then.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["id".to_string()]),
+            required: Some(vec!["token".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_close_agent_tool() -> ToolSpec {
+fn create_set_shared_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
-        "id".to_string(),
+        "key".to_string(),
         JsonSchema::String {
-            description: Some("Identifier of the agent to close.".to_string()),
+            description: Some(format!(
+                "Name of the variable, at most {MAX_SHARED_KEY_CHARS} characters."
+            )),
         },
     );
     properties.insert(
-        "timeout_ms".to_string(),
-        JsonSchema::Number {
+        "value".to_string(),
+        JsonSchema::String {
             description: Some(format!(
-                "Optional timeout in milliseconds. Defaults to {DEFAULT_WAIT_TIMEOUT_MS} and max {MAX_WAIT_TIMEOUT_MS}."
+                "New value, at most {MAX_SHARED_VALUE_CHARS} characters. Omit to remove the variable."
             )),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: "close_agent".to_string(),
-        description: "Close an agent and return its last known status.".to_string(),
+        name: "set_shared".to_string(),
+        description: format!(
+            "Set a shared variable (for example \"target_branch\" = \"main\") that everyone on your team can read with get_shared: the top-level orchestrator and all of its subagents, nested ones included, share one set of variables. At most {MAX_SHARED_VARIABLES} variables may be set."
+        ),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["id".to_string()]),
+            required: Some(vec!["key".to_string()]),
             additional_properties: Some(false.into()),
         },
     })
 }
 
-fn create_list_agents_tool() -> ToolSpec {
+fn create_get_shared_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "key".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Optional variable to read. Omit to return every shared variable.".to_string(),
+            ),
+        },
+    );
+
     ToolSpec::Function(ResponsesApiTool {
-        name: "list_agents".to_string(),
-        description: "List subagents spawned by this session.".to_string(),
+        name: "get_shared".to_string(),
+        description: "Read the shared variables set with set_shared by anyone on your team: the top-level orchestrator and all of its subagents, nested ones included.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_pin_context_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "text".to_string(),
+        JsonSchema::String {
+            description: Some(
+                "Context to pin, such as a spec excerpt or coding conventions.".to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "pin_context".to_string(),
+        description: format!(
+            "Pin context that every subagent you spawn from now on receives ahead of its first message. Already running subagents are not affected. Pinned items may total at most {MAX_PINNED_CONTEXT_CHARS} characters."
+        ),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["text".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_clear_pinned_context_tool() -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "clear_pinned_context".to_string(),
+        description:
+            "Remove everything pinned with pin_context, so new subagents start without it."
+                .to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties: BTreeMap::new(),
@@ -601,30 +1557,84 @@ fn create_list_agents_tool() -> ToolSpec {
     })
 }
 
-fn create_agent_output_tool() -> ToolSpec {
+fn create_scratchpad_append_tool() -> ToolSpec {
     let mut properties = BTreeMap::new();
     properties.insert(
-        "id".to_string(),
+        "text".to_string(),
         JsonSchema::String {
-            description: Some("Identifier of the agent to inspect.".to_string()),
+            description: Some(
+                "Note to add, such as a finding or a summary of a result.".to_string(),
+            ),
+        },
+    );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "scratchpad_append".to_string(),
+        description: format!(
+            "Append a note to the scratchpad your whole team shares: the top-level orchestrator and all of its subagents, nested ones included. It lasts for the whole session; once it exceeds {MAX_SCRATCHPAD_CHARS} characters the oldest notes are dropped."
+        ),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: Some(vec!["text".to_string()]),
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_scratchpad_read_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
+    properties.insert(
+        "last".to_string(),
+        JsonSchema::Number {
+            description: Some("Only return this many of the newest notes.".to_string()),
         },
     );
+
+    ToolSpec::Function(ResponsesApiTool {
+        name: "scratchpad_read".to_string(),
+        description: "Read the notes appended with scratchpad_append, oldest first, with total_entries counting all notes kept.".to_string(),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties,
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_read_group_chat_tool() -> ToolSpec {
+    let mut properties = BTreeMap::new();
     properties.insert(
-        "max_chars".to_string(),
+        "last".to_string(),
         JsonSchema::Number {
-            description: Some("Optional max chars to return from the partial output.".to_string()),
+            description: Some("Only return this many of the newest messages.".to_string()),
         },
     );
 
     ToolSpec::Function(ResponsesApiTool {
-        name: "agent_output".to_string(),
-        description:
-            "Fetch the latest partial or final output for a subagent, including reasoning and tool events when available."
-                .to_string(),
+        name: "read_group_chat".to_string(),
+        description: "Read the group chat history kept for this session, oldest first, with total_messages counting all messages kept. Each subagent sender carries the id, persona, and display_name it had when it posted, even if it has since been closed.".to_string(),
         strict: false,
         parameters: JsonSchema::Object {
             properties,
-            required: Some(vec!["id".to_string()]),
+            required: None,
+            additional_properties: Some(false.into()),
+        },
+    })
+}
+
+fn create_spawn_capacity_tool(limits: &CollabLimits) -> ToolSpec {
+    ToolSpec::Function(ResponsesApiTool {
+        name: "spawn_capacity".to_string(),
+        description: format!(
+            "Report how many more subagents you can spawn right now as remaining, with a breakdown per limit: per_parent {{ limit, active, starting, remaining }} for the {} subagents you may own at once, and token_budget {{ limit, used, remaining }} when the host set one (null otherwise).",
+            limits.max_subagents_per_parent
+        ),
+        strict: false,
+        parameters: JsonSchema::Object {
+            properties: BTreeMap::new(),
+            required: None,
             additional_properties: Some(false.into()),
         },
     })
@@ -1377,28 +2387,152 @@ pub(crate) fn build_specs(
     if config.collab_tools {
         let collab_handler = Arc::new(CollabHandler);
         if tool_allowed("spawn_agent") {
-            builder.push_spec(create_spawn_agent_tool());
+            builder.push_spec(create_spawn_agent_tool(&config.collab_limits));
             builder.register_handler("spawn_agent", collab_handler.clone());
         }
+        if tool_allowed("spawn_agent_batch") {
+            builder.push_spec(create_spawn_agent_batch_tool(&config.collab_limits));
+            builder.register_handler("spawn_agent_batch", collab_handler.clone());
+        }
+        if tool_allowed("clone_agent") {
+            builder.push_spec(create_clone_agent_tool(&config.collab_limits));
+            builder.register_handler("clone_agent", collab_handler.clone());
+        }
         if tool_allowed("send_input") {
             builder.push_spec(create_send_input_tool());
             builder.register_handler("send_input", collab_handler.clone());
         }
+        if tool_allowed("broadcast_input") {
+            builder.push_spec(create_broadcast_input_tool());
+            builder.register_handler("broadcast_input", collab_handler.clone());
+        }
         if tool_allowed("wait") {
-            builder.push_spec(create_wait_tool());
+            builder.push_spec(create_wait_tool(&config.collab_limits));
             builder.register_handler("wait", collab_handler.clone());
         }
+        if tool_allowed("wait_for_message") {
+            builder.push_spec(create_wait_for_message_tool(&config.collab_limits));
+            builder.register_handler("wait_for_message", collab_handler.clone());
+        }
         if tool_allowed("close_agent") {
-            builder.push_spec(create_close_agent_tool());
+            builder.push_spec(create_close_agent_tool(&config.collab_limits));
             builder.register_handler("close_agent", collab_handler.clone());
         }
+        if tool_allowed("kill_all_agents") {
+            builder.push_spec(create_kill_all_agents_tool());
+            builder.register_handler("kill_all_agents", collab_handler.clone());
+        }
+        if tool_allowed("pause_agent") {
+            builder.push_spec(create_pause_agent_tool());
+            builder.register_handler("pause_agent", collab_handler.clone());
+        }
+        if tool_allowed("resume_agent") {
+            builder.push_spec(create_resume_agent_tool());
+            builder.register_handler("resume_agent", collab_handler.clone());
+        }
+        if tool_allowed("pause_all") {
+            builder.push_spec(create_pause_all_tool());
+            builder.register_handler("pause_all", collab_handler.clone());
+        }
+        if tool_allowed("resume_all") {
+            builder.push_spec(create_resume_all_tool());
+            builder.register_handler("resume_all", collab_handler.clone());
+        }
+        if tool_allowed("agent_token_usage") {
+            builder.push_spec(create_agent_token_usage_tool());
+            builder.register_handler("agent_token_usage", collab_handler.clone());
+        }
+        if tool_allowed("agents_summary") {
+            builder.push_spec(create_agents_summary_tool());
+            builder.register_handler("agents_summary", collab_handler.clone());
+        }
         if tool_allowed("list_agents") {
             builder.push_spec(create_list_agents_tool());
             builder.register_handler("list_agents", collab_handler.clone());
         }
+        if tool_allowed("get_agent_tree") {
+            builder.push_spec(create_get_agent_tree_tool());
+            builder.register_handler("get_agent_tree", collab_handler.clone());
+        }
+        if tool_allowed("inspect_agent") {
+            builder.push_spec(create_inspect_agent_tool());
+            builder.register_handler("inspect_agent", collab_handler.clone());
+        }
+        if tool_allowed("inspect_agent_context") {
+            builder.push_spec(create_inspect_agent_context_tool());
+            builder.register_handler("inspect_agent_context", collab_handler.clone());
+        }
+        if tool_allowed("amend_instructions") {
+            builder.push_spec(create_amend_instructions_tool());
+            builder.register_handler("amend_instructions", collab_handler.clone());
+        }
         if tool_allowed("agent_output") {
             builder.push_spec(create_agent_output_tool());
-            builder.register_handler("agent_output", collab_handler);
+            builder.register_handler("agent_output", collab_handler.clone());
+        }
+        if tool_allowed("report_agent") {
+            builder.push_spec(create_report_agent_tool());
+            builder.register_handler("report_agent", collab_handler.clone());
+        }
+        if tool_allowed("compare_agents") {
+            builder.push_spec(create_compare_agents_tool());
+            builder.register_handler("compare_agents", collab_handler.clone());
+        }
+        if tool_allowed("replay_agent") {
+            builder.push_spec(create_replay_agent_tool());
+            builder.register_handler("replay_agent", collab_handler.clone());
+        }
+        if tool_allowed("list_personas") {
+            builder.push_spec(create_list_personas_tool());
+            builder.register_handler("list_personas", collab_handler.clone());
+        }
+        if tool_allowed("cancel_spawn") {
+            builder.push_spec(create_cancel_spawn_tool());
+            builder.register_handler("cancel_spawn", collab_handler.clone());
+        }
+        if tool_allowed("set_shared") {
+            builder.push_spec(create_set_shared_tool());
+            builder.register_handler("set_shared", collab_handler.clone());
+        }
+        if tool_allowed("get_shared") {
+            builder.push_spec(create_get_shared_tool());
+            builder.register_handler("get_shared", collab_handler.clone());
+        }
+        if tool_allowed("pin_context") {
+            builder.push_spec(create_pin_context_tool());
+            builder.register_handler("pin_context", collab_handler.clone());
+        }
+        if tool_allowed("clear_pinned_context") {
+            builder.push_spec(create_clear_pinned_context_tool());
+            builder.register_handler("clear_pinned_context", collab_handler.clone());
+        }
+        if tool_allowed("spawn_capacity") {
+            builder.push_spec(create_spawn_capacity_tool(&config.collab_limits));
+            builder.register_handler("spawn_capacity", collab_handler.clone());
+        }
+        if tool_allowed("scratchpad_append") {
+            builder.push_spec(create_scratchpad_append_tool());
+            builder.register_handler("scratchpad_append", collab_handler.clone());
+        }
+        if tool_allowed("scratchpad_read") {
+            builder.push_spec(create_scratchpad_read_tool());
+            builder.register_handler("scratchpad_read", collab_handler.clone());
+        }
+        if tool_allowed("read_group_chat") {
+            builder.push_spec(create_read_group_chat_tool());
+            builder.register_handler("read_group_chat", collab_handler.clone());
+        }
+        if tool_allowed("checkpoint_agent") {
+            builder.push_spec(create_checkpoint_agent_tool());
+            builder.register_handler("checkpoint_agent", collab_handler.clone());
+        }
+        if tool_allowed("restore_checkpoint") {
+            builder.push_spec(create_restore_checkpoint_tool());
+            builder.register_handler("restore_checkpoint", collab_handler.clone());
+        }
+        if tool_allowed("send_and_wait") {
+            builder.push_spec(create_send_and_wait_tool(&config.collab_limits));
+            builder.register_handler("send_and_wait", collab_handler);
         }
     }
 
@@ -1602,11 +2736,42 @@ mod tests {
             &tools,
             &[
                 "spawn_agent",
+                "spawn_agent_batch",
+                "clone_agent",
                 "send_input",
+                "broadcast_input",
                 "wait",
+                "wait_for_message",
                 "close_agent",
+                "kill_all_agents",
+                "pause_agent",
+                "resume_agent",
+                "pause_all",
+                "resume_all",
+                "agent_token_usage",
+                "agents_summary",
                 "list_agents",
+                "get_agent_tree",
+                "inspect_agent",
+                "inspect_agent_context",
+                "amend_instructions",
                 "agent_output",
+                "report_agent",
+                "compare_agents",
+                "replay_agent",
+                "list_personas",
+                "cancel_spawn",
+                "set_shared",
+                "get_shared",
+                "pin_context",
+                "clear_pinned_context",
+                "spawn_capacity",
+                "scratchpad_append",
+                "scratchpad_read",
+                "read_group_chat",
+                "checkpoint_agent",
+                "restore_checkpoint",
+                "send_and_wait",
             ],
         );
     }
@@ -1627,15 +2792,103 @@ mod tests {
             &tools,
             &[
                 "spawn_agent",
+                "spawn_agent_batch",
+                "clone_agent",
                 "send_input",
+                "broadcast_input",
                 "wait",
+                "wait_for_message",
                 "close_agent",
+                "kill_all_agents",
+                "pause_agent",
+                "resume_agent",
+                "pause_all",
+                "resume_all",
+                "agent_token_usage",
+                "agents_summary",
                 "list_agents",
+                "get_agent_tree",
+                "inspect_agent",
+                "inspect_agent_context",
+                "amend_instructions",
                 "agent_output",
+                "report_agent",
+                "compare_agents",
+                "replay_agent",
+                "list_personas",
+                "cancel_spawn",
+                "set_shared",
+                "get_shared",
+                "pin_context",
+                "clear_pinned_context",
+                "spawn_capacity",
+                "scratchpad_append",
+                "scratchpad_read",
+                "read_group_chat",
+                "checkpoint_agent",
+                "restore_checkpoint",
+                "send_and_wait",
             ],
         );
     }
 
+    #[test]
+    fn collab_tool_descriptions_quote_the_host_limits() {
+        let config = test_config();
+        let model_info = ModelsManager::construct_model_info_offline("gpt-5-codex", &config);
+        let mut features = Features::with_defaults();
+        features.enable(Feature::Collab);
+        let tools_config = ToolsConfig::new(&ToolsConfigParams {
+            model_info: &model_info,
+            features: &features,
+            tool_policy: &ToolPolicy::default(),
+        })
+        .with_collab_limits(CollabLimits {
+            default_wait_timeout_ms: 1_234,
+            max_wait_timeout_ms: 56_789,
+            max_subagent_tool_events: 7,
+            max_subagents_per_parent: 3,
+            ..CollabLimits::default()
+        });
+        let (tools, _) = build_specs(&tools_config, None).build();
+
+        let property_description = |tool: &str, property: &str| {
+            let ToolSpec::Function(ResponsesApiTool {
+                parameters: JsonSchema::Object { properties, .. },
+                ..
+            }) = &find_tool(&tools, tool).spec
+            else {
+                panic!("{tool} should be a function tool");
+            };
+            match properties.get(property) {
+                Some(JsonSchema::Number {
+                    description: Some(description),
+                }) => description.clone(),
+                other => panic!("unexpected {tool}.{property} schema: {other:?}"),
+            }
+        };
+        for tool in ["wait", "wait_for_message", "close_agent", "send_and_wait"] {
+            let description = property_description(tool, "timeout_ms");
+            assert!(
+                description.contains("Defaults to 1234 and is capped at 56789."),
+                "{tool}: {description}"
+            );
+        }
+        assert!(property_description("spawn_agent", "max_tool_events").contains("Defaults to 7;"));
+        assert!(
+            property_description("clone_agent", "count").contains("at most 3 active subagents")
+        );
+        let ToolSpec::Function(ResponsesApiTool { description, .. }) =
+            &find_tool(&tools, "spawn_capacity").spec
+        else {
+            panic!("spawn_capacity should be a function tool");
+        };
+        assert!(
+            description.contains("for the 3 subagents you may own at once"),
+            "{description}"
+        );
+    }
+
     fn assert_model_tools(model_slug: &str, features: &Features, expected_tools: &[&str]) {
         let config = test_config();
         let model_info = ModelsManager::construct_model_info_offline(model_slug, &config);
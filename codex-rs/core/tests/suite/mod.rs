@@ -43,6 +43,7 @@ mod models_etag_responses;
 mod otel;
 mod prompt_caching;
 mod quota_exceeded;
+mod reconfigure_thread;
 mod read_file;
 mod remote_models;
 mod request_compression;
@@ -58,6 +59,7 @@ mod shell_snapshot;
 mod skills;
 mod stream_error_allows_next_turn;
 mod stream_no_completed;
+mod subscribe_events;
 mod text_encoding_fix;
 mod tool_harness;
 mod tool_parallelism;
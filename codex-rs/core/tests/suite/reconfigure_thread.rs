@@ -0,0 +1,100 @@
+use codex_core::CodexAuth;
+use codex_core::ModelProviderInfo;
+use codex_core::NewThread;
+use codex_core::ReconfigureOutcome;
+use codex_core::ThreadManager;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::Op;
+use codex_protocol::user_input::UserInput;
+use core_test_support::load_default_config_for_test;
+use core_test_support::skip_if_no_network;
+use core_test_support::wait_for_event;
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn reconfigure_thread_hot_swaps_model_and_restarts_for_new_cwd() {
+    skip_if_no_network!();
+
+    let server = MockServer::start().await;
+    let sse =
+        core_test_support::load_sse_fixture_with_id("../fixtures/completed_template.json", "resp");
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(sse, "text/event-stream"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home).await;
+    config.model_provider = model_provider;
+
+    let thread_manager = ThreadManager::with_models_provider(
+        CodexAuth::from_api_key("dummy"),
+        config.model_provider.clone(),
+    );
+    let NewThread {
+        thread_id, thread, ..
+    } = thread_manager
+        .start_thread(config.clone())
+        .await
+        .expect("create thread");
+    thread
+        .submit(Op::UserInput {
+            items: vec![UserInput::Text {
+                text: "hello".to_string(),
+            }],
+            final_output_json_schema: None,
+        })
+        .await
+        .unwrap();
+    wait_for_event(&thread, |ev| matches!(ev, EventMsg::TurnComplete(_))).await;
+
+    let mut new_model = config.clone();
+    new_model.model = Some("o3".to_string());
+    let outcome = thread_manager
+        .reconfigure_thread(thread_id, new_model)
+        .await
+        .expect("hot-swap model");
+    assert!(matches!(outcome, ReconfigureOutcome::HotApplied));
+
+    let new_cwd = TempDir::new().unwrap();
+    let mut moved = config;
+    moved.cwd = new_cwd.path().to_path_buf();
+    let outcome = thread_manager
+        .reconfigure_thread(thread_id, moved)
+        .await
+        .expect("restart with new cwd");
+    let ReconfigureOutcome::Restarted(restarted) = outcome else {
+        panic!("changing cwd should restart the thread");
+    };
+
+    assert_ne!(restarted.thread_id, thread_id);
+    assert_eq!(restarted.session_configured.cwd, new_cwd.path());
+    assert!(thread_manager.get_thread(thread_id).await.is_err());
+    let replayed = restarted
+        .session_configured
+        .initial_messages
+        .unwrap_or_default();
+    assert!(
+        replayed
+            .iter()
+            .any(|msg| matches!(msg, EventMsg::UserMessage(user) if user.message == "hello")),
+        "restarted thread should keep the earlier history: {replayed:?}"
+    );
+}
@@ -0,0 +1,90 @@
+use codex_core::CodexAuth;
+use codex_core::ModelProviderInfo;
+use codex_core::NewThread;
+use codex_core::ThreadManager;
+use codex_core::built_in_model_providers;
+use codex_core::protocol::EventMsg;
+use codex_core::protocol::Op;
+use codex_protocol::user_input::UserInput;
+use core_test_support::load_default_config_for_test;
+use core_test_support::skip_if_no_network;
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use tokio::sync::broadcast::error::TryRecvError;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn subscribe_events_mirrors_primary_consumer_in_order() {
+    skip_if_no_network!();
+
+    let server = MockServer::start().await;
+    let sse =
+        core_test_support::load_sse_fixture_with_id("../fixtures/completed_template.json", "resp");
+    Mock::given(method("POST"))
+        .and(path("/v1/responses"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_raw(sse, "text/event-stream"),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let model_provider = ModelProviderInfo {
+        base_url: Some(format!("{}/v1", server.uri())),
+        ..built_in_model_providers()["openai"].clone()
+    };
+    let home = TempDir::new().unwrap();
+    let mut config = load_default_config_for_test(&home).await;
+    config.model_provider = model_provider;
+
+    let thread_manager = ThreadManager::with_models_provider(
+        CodexAuth::from_api_key("dummy"),
+        config.model_provider.clone(),
+    );
+    let NewThread {
+        thread_id, thread, ..
+    } = thread_manager
+        .start_thread(config)
+        .await
+        .expect("create thread");
+    let mut mirror = thread_manager
+        .subscribe_events(thread_id)
+        .await
+        .expect("subscribe to thread events");
+
+    thread
+        .submit(Op::UserInput {
+            items: vec![UserInput::Text {
+                text: "hello".to_string(),
+            }],
+            final_output_json_schema: None,
+        })
+        .await
+        .unwrap();
+
+    let mut primary = Vec::new();
+    loop {
+        let event = thread.next_event().await.expect("next event");
+        let done = matches!(event.msg, EventMsg::TurnComplete(_));
+        primary.push(serde_json::to_value(&event).unwrap());
+        if done {
+            break;
+        }
+    }
+
+    let mut mirrored = Vec::new();
+    loop {
+        match mirror.try_recv() {
+            Ok(event) => mirrored.push(serde_json::to_value(&event).unwrap()),
+            Err(TryRecvError::Empty) => break,
+            Err(err) => panic!("unexpected mirror error: {err:?}"),
+        }
+    }
+    assert_eq!(mirrored, primary);
+}
@@ -294,6 +294,7 @@ impl EventProcessor for EventProcessorWithHumanOutput {
                 sender,
                 text,
                 display,
+                ..
             }) => {
                 if !display {
                     return CodexStatus::Running;
@@ -613,6 +614,8 @@ impl EventProcessor for EventProcessorWithHumanOutput {
             | EventMsg::ReasoningContentDelta(_)
             | EventMsg::ReasoningRawContentDelta(_)
             | EventMsg::SkillsUpdateAvailable
+            | EventMsg::GroupChatCursor(_)
+            | EventMsg::SubagentToolActivity(_)
             | EventMsg::UndoCompleted(_)
             | EventMsg::UndoStarted(_)
             | EventMsg::ThreadRolledBack(_) => {}
@@ -307,7 +307,9 @@ async fn run_codex_tool_session_inner(
                     | EventMsg::ContextCompacted(_)
                     | EventMsg::ThreadRolledBack(_)
                     | EventMsg::DeprecationNotice(_)
-                    | EventMsg::GroupChatMessage(_) => {
+                    | EventMsg::GroupChatMessage(_)
+                    | EventMsg::GroupChatCursor(_)
+                    | EventMsg::SubagentToolActivity(_) => {
                         // For now, we do not do anything extra for these
                         // events. Note that
                         // send(codex_event_to_notification(&event)) above has
@@ -192,6 +192,33 @@ pub enum Op {
         sender: GroupChatSender,
     },
 
+    /// Sent by the thread manager when the subagents of this session that are mid-turn have all
+    /// gone without activity for a while. The session emits a possible-deadlock warning unless
+    /// one of them still has unread group chat messages.
+    PossibleSubagentDeadlock {
+        /// The stalled subagents.
+        subagents: Vec<ThreadId>,
+        /// How long the most recently active of them has been stalled.
+        idle_seconds: u64,
+    },
+
+    /// Sent by the thread manager to echo a subagent's tool activity into this session's event
+    /// stream as [`EventMsg::SubagentToolActivity`]. Nothing is added to the history.
+    SubagentToolActivity {
+        /// The subagent that used the tool.
+        subagent_id: ThreadId,
+        /// One-line summary such as `exec begin: cargo test`.
+        description: String,
+    },
+
+    /// Append standing guidance to this session's developer instructions. The text is recorded
+    /// in history as a developer message right away and kept in the instructions for the rest
+    /// of the session, so it also survives compaction.
+    AmendInstructions {
+        /// Instruction text to append, already framed by the sender.
+        text: String,
+    },
+
     /// Request a single history entry identified by `log_id` + `offset`.
     GetHistoryEntryRequest { offset: usize, log_id: u64 },
 
@@ -586,6 +613,14 @@ pub enum EventMsg {
     /// Group chat message from a human, team lead, or subagent.
     GroupChatMessage(GroupChatMessageEvent),
 
+    /// How far a subagent has read the group chat. Persisted so read positions survive resume;
+    /// not meant for display.
+    GroupChatCursor(GroupChatCursorEvent),
+
+    /// Tool activity of a subagent, echoed into its parent's stream when the host opts in.
+    /// Informational only; not persisted.
+    SubagentToolActivity(SubagentToolActivityEvent),
+
     /// Agent text output delta message
     AgentMessageDelta(AgentMessageDeltaEvent),
 
@@ -1129,8 +1164,35 @@ pub enum GroupChatSender {
     },
 }
 
+/// Stable identity for a group chat message so replays can be deduplicated.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash, JsonSchema, TS)]
+pub struct GroupChatMessageId {
+    /// Stable key for the sender (`human`, `team_lead`, or `subagent:<id>`).
+    pub sender: String,
+    /// Monotonic sequence number, scoped to the sender.
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
+pub struct GroupChatCursorEvent {
+    pub subagent_id: ThreadId,
+    /// Id of the last group chat message delivered to the subagent.
+    pub last_read: GroupChatMessageId,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
+pub struct SubagentToolActivityEvent {
+    pub subagent_id: ThreadId,
+    /// One-line summary such as `exec begin: cargo test`.
+    pub description: String,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
 pub struct GroupChatMessageEvent {
+    /// Assigned when the message is first appended to a group chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub id: Option<GroupChatMessageId>,
     pub sender: GroupChatSender,
     pub text: String,
     #[serde(default)]
@@ -1341,6 +1403,24 @@ pub struct SessionMeta {
     #[serde(default)]
     pub source: SessionSource,
     pub model_provider: Option<String>,
+    /// Set when this session is a collab subagent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub subagent: Option<SubagentLineage>,
+}
+
+/// Who spawned a collab subagent, recorded in its session metadata so rollout files can be
+/// traced back to the orchestrator they served.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, TS)]
+pub struct SubagentLineage {
+    /// Thread that owned the subagent when it was spawned.
+    pub parent_id: ThreadId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub persona: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub display_name: Option<String>,
 }
 
 impl Default for SessionMeta {
@@ -1354,6 +1434,7 @@ impl Default for SessionMeta {
             instructions: None,
             source: SessionSource::default(),
             model_provider: None,
+            subagent: None,
         }
     }
 }
@@ -28,6 +28,8 @@ use crate::tui::TuiEvent;
 use crate::update_action::UpdateAction;
 use codex_ansi_escape::ansi_escape_line;
 use codex_core::AuthManager;
+use codex_core::SubagentOrder;
+use codex_core::SubagentSpawnOptions;
 use codex_core::ThreadManager;
 use codex_core::config::Config;
 use codex_core::config::edit::ConfigEdit;
@@ -371,7 +373,11 @@ impl App {
             return;
         };
         let mut summaries = Vec::new();
-        for thread_id in self.server.list_subagent_ids(current_id).await {
+        for thread_id in self
+            .server
+            .list_subagent_ids(current_id, SubagentOrder::SpawnOrder)
+            .await
+        {
             let status = match self.server.get_thread(thread_id).await {
                 Ok(thread) => thread.agent_status().await,
                 Err(_) => AgentStatus::NotFound,
@@ -424,7 +430,15 @@ impl App {
         config.model = Some(self.current_model.clone());
         let subagent_id = match self
             .server
-            .spawn_subagent(parent_id, config, message, None, Some(display_name))
+            .spawn_subagent(
+                parent_id,
+                config,
+                message,
+                SubagentSpawnOptions {
+                    display_name: Some(display_name),
+                    ..SubagentSpawnOptions::default()
+                },
+            )
             .await
         {
             Ok(thread_id) => thread_id,
@@ -662,7 +676,10 @@ impl App {
         let Some(thread_id) = self.chat_widget.thread_id() else {
             return Ok(true);
         };
-        let subagent_ids = self.server.list_subagent_ids(thread_id).await;
+        let subagent_ids = self
+            .server
+            .list_subagent_ids(thread_id, SubagentOrder::SpawnOrder)
+            .await;
         if subagent_ids.is_empty() {
             self.chat_widget.update_subagent_statuses(Vec::new());
             return Ok(true);
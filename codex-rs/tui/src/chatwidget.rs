@@ -2265,6 +2265,7 @@ impl ChatWidget {
                 self.on_background_event(message)
             }
             EventMsg::GroupChatMessage(ev) => self.on_group_chat_message(ev),
+            EventMsg::GroupChatCursor(_) | EventMsg::SubagentToolActivity(_) => {}
             EventMsg::UndoStarted(ev) => self.on_undo_started(ev),
             EventMsg::UndoCompleted(ev) => self.on_undo_completed(ev),
             EventMsg::StreamError(StreamErrorEvent {
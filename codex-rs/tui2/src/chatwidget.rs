@@ -1971,7 +1971,9 @@ impl ChatWidget {
             EventMsg::BackgroundEvent(BackgroundEventEvent { message }) => {
                 self.on_background_event(message)
             }
-            EventMsg::GroupChatMessage(_) => {}
+            EventMsg::GroupChatMessage(_)
+            | EventMsg::GroupChatCursor(_)
+            | EventMsg::SubagentToolActivity(_) => {}
             EventMsg::UndoStarted(ev) => self.on_undo_started(ev),
             EventMsg::UndoCompleted(ev) => self.on_undo_completed(ev),
             EventMsg::StreamError(StreamErrorEvent {